@@ -0,0 +1,629 @@
+// =========================
+// archive.rs
+// MilterDecoder 添付アーカイブ解析モジュール
+//
+// 【このファイルで使う主なクレート】
+// - zip: ZIPのセントラルディレクトリ読み取り・メンバー展開
+// - tar: TARのエントリ読み取り
+// - flate2: gzipの展開
+// - sevenz-rust: 7zのヘッダ読み取り・メンバー展開
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: バイト列読み取り（io::Read/Cursor）
+//
+// 【役割】
+// - ZIP/RAR/7z/TAR/GZ添付のメンバー一覧（名前・サイズ・圧縮後サイズ・圧縮率）の取得
+// - パスワード保護（メンバー暗号化・ヘッダ暗号化）の検出
+// - 異常な圧縮率（zip bomb）の検出
+// - メンバーの実際のファイル種別判定と、入れ子アーカイブの再帰解析（Archive_depthで深さ制限）
+// - RARはヘッダを直接読み取る（無圧縮メンバーのみ入れ子解析）
+// =========================
+
+use serde::Serialize; // JSON出力用
+use std::io::{Cursor, Read}; // メンバーデータの読み取り
+
+use crate::filetype::FileKind; // マジックバイトによるファイル種別
+
+const MAX_ENTRIES: usize = 1000; // 1アーカイブあたりの一覧上限
+const MAX_MEMBER_READ: usize = 16 * 1024 * 1024; // 入れ子解析のために展開するメンバーの最大サイズ
+const MAX_TOTAL_READ: usize = 64 * 1024 * 1024; // 1添付あたりの展開量の上限（入れ子全体）
+const BOMB_MIN_SIZE: u64 = 1024 * 1024; // zip bomb判定の対象とする展開後サイズの下限
+
+/// アーカイブ解析の制限（Archive_depth/Archive_bomb_ratio設定）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    pub max_depth: usize, // 一覧を取る入れ子レベルの上限（0なら解析しない、1なら添付自体のみ）
+    pub bomb_ratio: u64,  // これを超える圧縮率をzip bombとみなす
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        ArchiveLimits {
+            max_depth: 3,
+            bomb_ratio: 100,
+        }
+    }
+}
+
+/// アーカイブ1件分の解析結果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveInfo {
+    pub format: FileKind,           // アーカイブ形式
+    pub entries: Vec<ArchiveEntry>, // メンバー一覧（格納順）
+    pub total_size: u64,            // 展開後サイズの合計（宣言値）
+    pub ratio: Option<f64>,         // 全体の圧縮率（展開後合計 / アーカイブサイズ）
+    pub encrypted: bool,            // 暗号化メンバーの有無（入れ子内も含む）
+    pub headers_encrypted: bool,    // 一覧自体の暗号化（RAR -hp、7z -mhe）
+    pub zip_bomb: bool,             // 異常な圧縮率か（入れ子内も含む）
+    pub entries_truncated: bool,    // 一覧上限（1000件）で打ち切ったか
+    pub error: Option<String>,      // 読み取りエラー（途中までの一覧は残す）
+}
+
+/// アーカイブのメンバー1件分
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveEntry {
+    pub name: String,                      // メンバー名（パス込み）
+    pub size: u64,                         // 展開後サイズ（宣言値）
+    pub compressed_size: Option<u64>,      // 圧縮後サイズ（形式上わからなければNone）
+    pub ratio: Option<f64>,                // 圧縮率（展開後 / 圧縮後）
+    pub directory: bool,                   // ディレクトリか
+    pub encrypted: bool,                   // パスワード保護されているか
+    pub detected_type: Option<FileKind>,   // 展開できた場合の実際のファイル種別
+    pub archive: Option<Box<ArchiveInfo>>, // 入れ子アーカイブの解析結果
+    pub depth_limited: bool,               // 深さ制限により入れ子アーカイブを解析しなかったか
+}
+
+impl ArchiveEntry {
+    fn new(
+        name: String,
+        size: u64,
+        compressed_size: Option<u64>,
+        directory: bool,
+        encrypted: bool,
+    ) -> Self {
+        ArchiveEntry {
+            name,
+            size,
+            ratio: compressed_size.and_then(|c| ratio(size, c)),
+            compressed_size,
+            directory,
+            encrypted,
+            detected_type: None,
+            archive: None,
+            depth_limited: false,
+        }
+    }
+}
+
+/// 添付データがアーカイブなら一覧を取得（アーカイブ以外・Archive_depth 0ならNone）
+///
+/// # 引数
+/// - `data`: デコード済み添付データ
+/// - `kind`: filetype::detectの判定結果
+/// - `limits`: 入れ子の深さ・zip bomb判定の圧縮率
+///
+/// # 説明
+/// - 暗号化されていないメンバーは最大16MB（1添付で合計64MB）まで展開して種別判定・入れ子解析する
+/// - 読み取りエラーはerrorに記録し、それまでの一覧は返す
+pub fn inspect(data: &[u8], kind: Option<FileKind>, limits: &ArchiveLimits) -> Option<ArchiveInfo> {
+    if limits.max_depth == 0 {
+        return None; // アーカイブ解析無効
+    }
+    let mut budget = MAX_TOTAL_READ; // 残り展開量
+    inspect_at(data, kind?, limits, 1, &mut budget)
+}
+
+/// アーカイブ形式か
+fn is_archive(kind: FileKind) -> bool {
+    matches!(
+        kind,
+        FileKind::Zip | FileKind::Rar | FileKind::SevenZip | FileKind::Tar | FileKind::Gzip
+    )
+}
+
+/// 入れ子レベルlevelのアーカイブを解析
+fn inspect_at(
+    data: &[u8],
+    kind: FileKind,
+    limits: &ArchiveLimits,
+    level: usize,
+    budget: &mut usize,
+) -> Option<ArchiveInfo> {
+    let list: fn(&[u8], &mut Lister) -> Result<(), String> = match kind {
+        FileKind::Zip => list_zip,
+        FileKind::Rar => list_rar,
+        FileKind::SevenZip => list_7z,
+        FileKind::Tar => list_tar,
+        FileKind::Gzip => list_gzip,
+        _ => return None, // アーカイブ以外
+    };
+    let mut lister = Lister {
+        info: ArchiveInfo {
+            format: kind,
+            entries: Vec::new(),
+            total_size: 0,
+            ratio: None,
+            encrypted: false,
+            headers_encrypted: false,
+            zip_bomb: false,
+            entries_truncated: false,
+            error: None,
+        },
+        limits,
+        level,
+        budget,
+    };
+    if let Err(e) = list(data, &mut lister) {
+        lister.info.error = Some(e); // 途中までの一覧は残す
+    }
+    Some(lister.finish(data.len()))
+}
+
+/// メンバー一覧の収集（形式ごとの読み取り処理から呼ばれる）
+struct Lister<'a> {
+    info: ArchiveInfo,         // 収集中の解析結果
+    limits: &'a ArchiveLimits, // 解析制限
+    level: usize,              // このアーカイブの入れ子レベル（添付自体が1）
+    budget: &'a mut usize,     // 残り展開量（入れ子全体で共有）
+}
+
+impl Lister<'_> {
+    /// 一覧上限に達したか（達したら打ち切りを記録）
+    fn full(&mut self) -> bool {
+        if self.info.entries.len() >= MAX_ENTRIES {
+            self.info.entries_truncated = true;
+            return true;
+        }
+        false
+    }
+
+    /// メンバーデータを上限内で読み取る（宣言サイズ・実サイズが上限を超えればNone）
+    fn read_content(&mut self, size: u64, reader: impl Read) -> Option<Vec<u8>> {
+        let limit = MAX_MEMBER_READ.min(*self.budget); // 今回読める上限
+        if size > limit as u64 {
+            return None;
+        }
+        let mut buf = Vec::new();
+        reader.take(limit as u64 + 1).read_to_end(&mut buf).ok()?; // 宣言サイズの偽装に備えて上限で止める
+        if buf.len() > limit {
+            return None;
+        }
+        *self.budget -= buf.len();
+        Some(buf)
+    }
+
+    /// メンバーを追加（データがあれば種別判定し、アーカイブなら深さ制限内で再帰解析）
+    fn push(&mut self, mut entry: ArchiveEntry, content: Option<&[u8]>) {
+        if let Some(data) = content {
+            entry.detected_type = crate::filetype::detect(data);
+            if let Some(kind) = entry.detected_type.filter(|k| is_archive(*k)) {
+                if self.level < self.limits.max_depth {
+                    entry.archive =
+                        inspect_at(data, kind, self.limits, self.level + 1, self.budget)
+                            .map(Box::new);
+                } else {
+                    entry.depth_limited = true; // Archive_depth超過
+                }
+            }
+        }
+        self.info.entries.push(entry);
+    }
+
+    /// 合計サイズ・圧縮率・暗号化・zip bomb判定を確定
+    fn finish(mut self, archive_size: usize) -> ArchiveInfo {
+        let bomb_ratio = self.limits.bomb_ratio as f64; // 判定しきい値
+        let info = &mut self.info;
+        info.total_size = info
+            .entries
+            .iter()
+            .fold(0u64, |sum, e| sum.saturating_add(e.size)); // 偽装サイズでの桁あふれ防止
+        info.ratio = ratio(info.total_size, archive_size as u64);
+        let over =
+            |size: u64, r: Option<f64>| size >= BOMB_MIN_SIZE && r.is_some_and(|r| r > bomb_ratio);
+        info.zip_bomb = over(info.total_size, info.ratio)
+            || info
+                .entries
+                .iter()
+                .any(|e| over(e.size, e.ratio) || e.archive.as_ref().is_some_and(|a| a.zip_bomb));
+        info.encrypted = info.headers_encrypted
+            || info
+                .entries
+                .iter()
+                .any(|e| e.encrypted || e.archive.as_ref().is_some_and(|a| a.encrypted));
+        self.info
+    }
+}
+
+/// 圧縮率（展開後 / 圧縮後、小数2桁）
+fn ratio(size: u64, compressed: u64) -> Option<f64> {
+    if compressed == 0 {
+        return None;
+    }
+    Some((size as f64 / compressed as f64 * 100.0).round() / 100.0)
+}
+
+/// ZIP: セントラルディレクトリから一覧を取得（暗号化フラグは汎用フラグのbit0）
+fn list_zip(data: &[u8], l: &mut Lister) -> Result<(), String> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    for i in 0..zip.len() {
+        if l.full() {
+            break;
+        }
+        let entry = {
+            let f = zip.by_index_raw(i).map_err(|e| e.to_string())?; // 展開せずにメタデータのみ
+            ArchiveEntry::new(
+                f.name().to_string(),
+                f.size(),
+                Some(f.compressed_size()),
+                f.is_dir(),
+                f.encrypted(),
+            )
+        };
+        let content = if entry.encrypted || entry.directory {
+            None // パスワード無しでは展開できない
+        } else {
+            match zip.by_index(i) {
+                Ok(f) => l.read_content(entry.size, f),
+                Err(_) => None, // 未対応の圧縮方式等
+            }
+        };
+        l.push(entry, content.as_deref());
+    }
+    Ok(())
+}
+
+/// TAR: エントリを順に読み取り（無圧縮のため圧縮率なし）
+fn list_tar(data: &[u8], l: &mut Lister) -> Result<(), String> {
+    let mut tar = tar::Archive::new(data);
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+        if l.full() {
+            break;
+        }
+        let mut e = entry.map_err(|e| e.to_string())?;
+        let name = String::from_utf8_lossy(&e.path_bytes()).to_string(); // パス（非UTF-8は置換）
+        let size = e.header().size().unwrap_or(0);
+        let directory = e.header().entry_type().is_dir();
+        let content = if directory {
+            None
+        } else {
+            l.read_content(size, &mut e)
+        };
+        l.push(
+            ArchiveEntry::new(name, size, None, directory, false),
+            content.as_deref(),
+        );
+    }
+    Ok(())
+}
+
+/// GZ: 単一メンバーとして扱う（展開後サイズは末尾のISIZE、名前はヘッダのFNAME）
+fn list_gzip(data: &[u8], l: &mut Lister) -> Result<(), String> {
+    let mut decoder = flate2::read::GzDecoder::new(data);
+    let name = decoder
+        .header()
+        .and_then(|h| h.filename())
+        .map(|n| String::from_utf8_lossy(n).to_string())
+        .unwrap_or_else(|| "(gzip)".to_string()); // FNAMEが無い場合
+    let size = data
+        .len()
+        .checked_sub(4)
+        .map(|at| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as u64)
+        .unwrap_or(0); // 展開後サイズ（2^32で循環）
+    let content = l.read_content(size, &mut decoder);
+    l.push(
+        ArchiveEntry::new(name, size, Some(data.len() as u64), false, false),
+        content.as_deref(),
+    );
+    Ok(())
+}
+
+/// 7z: ヘッダから一覧を取得（AES暗号化されたフォルダのメンバーは暗号化扱い）
+///
+/// # 説明
+/// - ヘッダ暗号化（-mhe）時は一覧を取れないためheaders_encryptedのみ立てる
+/// - ソリッド圧縮のためメンバー単位の圧縮後サイズは持たない（全体の圧縮率のみ）
+/// - 暗号化が無く展開後合計が16MB以下のときだけ展開して入れ子解析する（宣言された合計サイズを超えたら打ち切る）
+fn list_7z(data: &[u8], l: &mut Lister) -> Result<(), String> {
+    use sevenz_rust::{Error, SevenZMethod};
+    let archive = match sevenz_rust::Archive::read(&mut Cursor::new(data), data.len() as u64, &[]) {
+        Ok(archive) => archive,
+        Err(Error::PasswordRequired) => {
+            l.info.headers_encrypted = true;
+            return Ok(());
+        }
+        Err(Error::UnsupportedCompressionMethod(m)) if m.contains("AES") => {
+            l.info.headers_encrypted = true; // 暗号化ヘッダ（AES未対応ビルドではこちら）
+            return Ok(());
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let encrypted_folder: Vec<bool> = archive
+        .folders
+        .iter()
+        .map(|f| {
+            f.coders
+                .iter()
+                .any(|c| c.decompression_method_id() == SevenZMethod::ID_AES256SHA256)
+        })
+        .collect();
+    let encrypted = |i: usize| {
+        archive
+            .stream_map
+            .file_folder_index
+            .get(i)
+            .copied()
+            .flatten()
+            .is_some_and(|f| encrypted_folder[f])
+    };
+    let total = archive
+        .files
+        .iter()
+        .fold(0u64, |sum, f| sum.saturating_add(f.size)); // 偽装サイズでの桁あふれ防止
+    let mut contents: Vec<Option<Vec<u8>>> = vec![None; archive.files.len()]; // 展開したメンバーデータ
+    if !encrypted_folder.contains(&true) && total <= MAX_MEMBER_READ.min(*l.budget) as u64 {
+        let mut reader = sevenz_rust::SevenZReader::from_archive(
+            archive.clone(),
+            Cursor::new(data),
+            sevenz_rust::Password::empty(),
+        );
+        let mut buffers: Vec<(String, Vec<u8>)> = Vec::new(); // 展開順（フォルダ順）のメンバーデータ
+        let mut left = total as usize; // 宣言された合計サイズの残り
+        let mut overflow = false; // 宣言サイズを超えて展開されたか
+        let _ = reader.for_each_entries(|entry, r| {
+            let mut buf = Vec::new();
+            r.take(left as u64 + 1).read_to_end(&mut buf)?; // 宣言サイズの偽装に備えて上限で止める
+            if buf.len() > left {
+                overflow = true;
+                return Ok(false); // 宣言と食い違うため展開を打ち切る
+            }
+            left -= buf.len();
+            buffers.push((entry.name().to_string(), buf));
+            Ok(true)
+        });
+        if overflow {
+            buffers.clear(); // 偽装されたアーカイブの展開結果は入れ子解析に使わない
+        }
+        for (name, buf) in buffers {
+            // 同名メンバーは先に出現したものから割り当てる
+            if let Some(i) = archive
+                .files
+                .iter()
+                .enumerate()
+                .position(|(i, f)| f.name() == name && contents[i].is_none())
+            {
+                *l.budget = l.budget.saturating_sub(buf.len());
+                contents[i] = Some(buf);
+            }
+        }
+    }
+    for (i, f) in archive.files.iter().enumerate() {
+        if l.full() {
+            break;
+        }
+        let entry = ArchiveEntry::new(
+            f.name().to_string(),
+            f.size(),
+            None,
+            f.is_directory(),
+            encrypted(i),
+        );
+        l.push(entry, contents[i].as_deref());
+    }
+    Ok(())
+}
+
+/// RAR: RAR4/RAR5のヘッダを直接読み取り
+fn list_rar(data: &[u8], l: &mut Lister) -> Result<(), String> {
+    if data.starts_with(b"Rar!\x1a\x07\x01\x00") {
+        list_rar5(data, l)
+    } else {
+        list_rar4(data, l)
+    }
+}
+
+/// RAR4（1.5〜4.x）: 7バイトの共通ブロックヘッダを順に辿る
+///
+/// # 説明
+/// - メインヘッダ（0x73）のMHD_PASSWORD（0x0080）はヘッダ暗号化、ファイルヘッダ（0x74）の0x0004はメンバー暗号化
+/// - 無圧縮（method 0x30）のメンバーのみデータをそのまま入れ子解析に使う
+fn list_rar4(data: &[u8], l: &mut Lister) -> Result<(), String> {
+    let mut pos = 7; // マーカーブロックの次
+    while pos + 7 <= data.len() {
+        if l.full() {
+            break;
+        }
+        let head_type = data[pos + 2];
+        let flags = le16(data, pos + 3);
+        let head_size = le16(data, pos + 5) as usize;
+        if head_size < 7 {
+            return Err("不正なRARブロックヘッダ".to_string());
+        }
+        let mut add_size = if flags & 0x8000 != 0 && pos + 11 <= data.len() {
+            le32(data, pos + 7) as u64 // 後続データサイズ
+        } else {
+            0
+        };
+        match head_type {
+            0x73 if flags & 0x0080 != 0 => {
+                l.info.headers_encrypted = true; // -hp（一覧も暗号化）
+                return Ok(());
+            }
+            0x74 => {
+                let h = data
+                    .get(pos..pos + head_size)
+                    .filter(|h| h.len() >= 32)
+                    .ok_or("RARファイルヘッダが途中で切れています")?;
+                let mut packed = le32(h, 7) as u64;
+                let mut size = le32(h, 11) as u64;
+                let method = h[25];
+                let name_size = le16(h, 26) as usize;
+                let mut off = 32; // ファイル名の位置
+                if flags & 0x0100 != 0 && h.len() >= 40 {
+                    packed |= (le32(h, 32) as u64) << 32; // 4GB超
+                    size |= (le32(h, 36) as u64) << 32;
+                    off = 40;
+                }
+                add_size = packed;
+                let name = h.get(off..off + name_size).unwrap_or(&[]);
+                let name = name.split(|&b| b == 0).next().unwrap_or(&[]); // Unicode名の前のASCII名
+                let directory = flags & 0x00e0 == 0x00e0;
+                let encrypted = flags & 0x0004 != 0;
+                let content = if method == 0x30 && !encrypted && !directory {
+                    data.get(pos + head_size..)
+                        .and_then(|d| d.get(..packed as usize))
+                        .and_then(|d| l.read_content(size, d))
+                } else {
+                    None // 圧縮メンバーは展開しない
+                };
+                let entry = ArchiveEntry::new(
+                    String::from_utf8_lossy(name).replace('\\', "/"),
+                    size,
+                    Some(packed),
+                    directory,
+                    encrypted,
+                );
+                l.push(entry, content.as_deref());
+            }
+            0x7b => break, // 終端ブロック
+            _ => {}
+        }
+        pos = pos
+            .checked_add(head_size)
+            .and_then(|p| p.checked_add(add_size as usize))
+            .ok_or("RARブロックサイズが不正です")?;
+    }
+    Ok(())
+}
+
+/// RAR5: 可変長整数（vint）のヘッダを順に辿る
+///
+/// # 説明
+/// - 暗号化ヘッダ（type 4）があれば一覧は読めない
+/// - ファイルヘッダ（type 2）の拡張領域にファイル暗号化レコード（type 1）があればメンバー暗号化
+/// - 無圧縮（method 0）のメンバーのみデータをそのまま入れ子解析に使う
+fn list_rar5(data: &[u8], l: &mut Lister) -> Result<(), String> {
+    const BROKEN: &str = "RAR5ヘッダが途中で切れています";
+    let mut pos: usize = 8; // シグネチャの次
+    while pos.saturating_add(4) < data.len() {
+        if l.full() {
+            break;
+        }
+        let mut p = pos + 4; // CRC32の次
+        let header_size = vint(data, &mut p).ok_or(BROKEN)? as usize;
+        let header_end = p.checked_add(header_size).ok_or(BROKEN)?;
+        let h = data.get(..header_end).ok_or(BROKEN)?;
+        let head_type = vint(h, &mut p).ok_or(BROKEN)?;
+        let head_flags = vint(h, &mut p).ok_or(BROKEN)?;
+        let extra_size = if head_flags & 0x01 != 0 {
+            vint(h, &mut p).ok_or(BROKEN)?
+        } else {
+            0
+        } as usize;
+        let data_size = if head_flags & 0x02 != 0 {
+            vint(h, &mut p).ok_or(BROKEN)?
+        } else {
+            0
+        };
+        match head_type {
+            4 => {
+                l.info.headers_encrypted = true; // -hp（一覧も暗号化）
+                return Ok(());
+            }
+            2 => {
+                let file_flags = vint(h, &mut p).ok_or(BROKEN)?;
+                let size = vint(h, &mut p).ok_or(BROKEN)?;
+                vint(h, &mut p).ok_or(BROKEN)?; // 属性
+                let skip = |p: usize| p.checked_add(4).filter(|&e| e <= h.len()).ok_or(BROKEN);
+                if file_flags & 0x02 != 0 {
+                    p = skip(p)?; // 更新日時
+                }
+                if file_flags & 0x04 != 0 {
+                    p = skip(p)?; // CRC32
+                }
+                let compression = vint(h, &mut p).ok_or(BROKEN)?;
+                vint(h, &mut p).ok_or(BROKEN)?; // 作成OS
+                let name_len = vint(h, &mut p).ok_or(BROKEN)? as usize;
+                let name = p
+                    .checked_add(name_len)
+                    .and_then(|e| h.get(p..e))
+                    .ok_or(BROKEN)?;
+                let extra = h
+                    .get(header_end.saturating_sub(extra_size)..)
+                    .unwrap_or(&[]);
+                let directory = file_flags & 0x01 != 0;
+                let encrypted = rar5_has_record(extra, 0x01);
+                let content = if (compression >> 7) & 0x07 == 0 && !encrypted && !directory {
+                    data.get(header_end..)
+                        .and_then(|d| d.get(..data_size as usize))
+                        .and_then(|d| l.read_content(size, d))
+                } else {
+                    None // 圧縮メンバーは展開しない
+                };
+                let entry = ArchiveEntry::new(
+                    String::from_utf8_lossy(name).to_string(),
+                    size,
+                    Some(data_size),
+                    directory,
+                    encrypted,
+                );
+                l.push(entry, content.as_deref());
+            }
+            5 => break, // 終端ヘッダ
+            _ => {}
+        }
+        pos = header_end
+            .checked_add(data_size as usize)
+            .ok_or("RAR5データサイズが不正です")?;
+    }
+    Ok(())
+}
+
+/// RAR5拡張領域に指定typeのレコードがあるか
+fn rar5_has_record(extra: &[u8], record_type: u64) -> bool {
+    let mut p = 0;
+    while p < extra.len() {
+        let Some(size) = vint(extra, &mut p) else {
+            return false;
+        };
+        let start = p; // レコード本体の開始位置（sizeはtype以降の長さ）
+        if vint(extra, &mut p) == Some(record_type) {
+            return true;
+        }
+        // 偽装サイズで位置が戻る・桁あふれする場合は不正な拡張領域として打ち切る
+        let Some(next) = start.checked_add(size as usize).filter(|&n| n > start) else {
+            return false;
+        };
+        if next > extra.len() {
+            return false;
+        }
+        p = next;
+    }
+    false
+}
+
+/// RAR5の可変長整数（下位7ビットずつ、最上位ビットが継続）
+fn vint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..70).step_by(7) {
+        let b = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None // 10バイトを超える不正な値
+}
+
+/// リトルエンディアン16ビット値（範囲外は0）
+fn le16(data: &[u8], at: usize) -> u16 {
+    data.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or(0)
+}
+
+/// リトルエンディアン32ビット値（範囲外は0）
+fn le32(data: &[u8], at: usize) -> u32 {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(0)
+}
@@ -0,0 +1,165 @@
+// =========================
+// attachment.rs
+// MilterDecoder 添付ファイル処理モジュール
+//
+// 【このファイルで使う主なクレート】
+// - sha2 / sha1 / md-5: 添付ファイルのハッシュ値（SHA-256/SHA-1/MD5）計算
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: ファイル作成（fs::OpenOptions::create_new）、パス操作（path::Path）
+//
+// 【役割】
+// - デコード済み添付データのハッシュ値計算（脅威インテリジェンス照合用）
+// - 添付ファイル名の無害化（パス区切り・制御文字・先頭ドットの除去、長さ制限）
+// - スプールディレクトリ（Attachment_spool）への衝突しないファイル名での書き出し
+// - 添付メール（message/rfc822）内の添付も含めた一括書き出し
+// =========================
+
+use md5::Md5; // MD5
+use serde::Serialize; // JSON出力用
+use sha1::Sha1; // SHA-1
+use sha2::{Digest, Sha256}; // SHA-256・共通ハッシュトレイト
+use std::io::Write; // ファイル書き込み
+use std::path::{Path, PathBuf}; // スプールディレクトリ・保存先パス
+
+use crate::parse::ParsedMail; // 型付きの解析結果
+
+const MAX_NAME_LEN: usize = 100; // 無害化後ファイル名の最大文字数（拡張子込み）
+const MAX_COLLISION_RETRY: usize = 1000; // 同名ファイルがある場合の連番付与上限
+
+/// 添付データのハッシュ値（16進小文字）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ContentHashes {
+    pub sha256: String, // SHA-256
+    pub sha1: String,   // SHA-1
+    pub md5: String,    // MD5
+}
+
+impl ContentHashes {
+    /// デコード済みデータからハッシュ値を計算
+    pub fn compute(data: &[u8]) -> Self {
+        ContentHashes {
+            sha256: to_hex(&Sha256::digest(data)),
+            sha1: to_hex(&Sha1::digest(data)),
+            md5: to_hex(&Md5::digest(data)),
+        }
+    }
+}
+
+/// バイト列を16進小文字の文字列へ変換
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 添付ファイル名を無害化
+///
+/// # 説明
+/// - ディレクトリ部分（/ や \ 区切り）を捨ててファイル名部分のみ使う
+/// - 英数字・ドット・ハイフン・アンダースコア以外（空白・制御文字・記号・非ASCII）は「_」に置換
+/// - 先頭のドットは除去（隠しファイル・「..」対策）
+/// - 拡張子を残したままMAX_NAME_LEN文字以内に切り詰め、空なら「attachment」
+pub fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(""); // ファイル名部分
+    let cleaned = sanitize_component(base); // 危険な文字の置換・先頭ドット除去
+    if cleaned.is_empty() {
+        return "attachment".to_string();
+    }
+    if cleaned.len() <= MAX_NAME_LEN {
+        return cleaned;
+    }
+    // 拡張子（最大10文字）を残して切り詰め（ASCIIのみなのでバイト位置で切れる）
+    match cleaned.rfind('.') {
+        Some(dot) if cleaned.len() - dot <= 10 => {
+            let ext = &cleaned[dot..]; // ドット込みの拡張子
+            format!("{}{}", &cleaned[..MAX_NAME_LEN - ext.len()], ext)
+        }
+        _ => cleaned[..MAX_NAME_LEN].to_string(),
+    }
+}
+
+/// 英数字・ドット・ハイフン・アンダースコア以外を「_」に置換し、先頭のドットを除去
+fn sanitize_component(s: &str) -> String {
+    let cleaned: String = s
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_' // 危険・非ASCII文字は置換
+            }
+        })
+        .collect();
+    cleaned.trim_start_matches('.').to_string()
+}
+
+/// スプールディレクトリへ衝突しない名前で書き出し、保存先パスを返す
+///
+/// # 説明
+/// - 「<接頭辞>_<無害化ファイル名>」で新規作成し、既存なら「-1」「-2」…を拡張子の前に付与
+/// - create_newで作成するため、同時接続からの書き込みでも上書きしない
+fn write_unique(dir: &Path, prefix: &str, filename: &str, data: &[u8]) -> std::io::Result<PathBuf> {
+    let name = format!("{}_{}", prefix, filename); // 基本ファイル名
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot..]), // 拡張子を分離
+        _ => (name.as_str(), ""),
+    };
+    for n in 0..MAX_COLLISION_RETRY {
+        let candidate = if n == 0 {
+            dir.join(&name)
+        } else {
+            dir.join(format!("{}-{}{}", stem, n, ext)) // 連番付き
+        };
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(mut f) => {
+                f.write_all(data)?;
+                return Ok(candidate);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue, // 次の候補へ
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "同名ファイルが多すぎます",
+    ))
+}
+
+/// 非テキストパート（添付メール内も含む）をスプールディレクトリへ書き出す
+///
+/// # 引数
+/// - `mail`: parse_mailの解析結果（保存先パスをsaved_asに記録する）
+/// - `dir`: スプールディレクトリ（無ければ作成）
+/// - `message_id`: ファイル名の接頭辞に使うメッセージID
+///
+/// # 説明
+/// - ファイル名は「<メッセージID>_p<パート番号>[_p<添付メール内のパート番号>…]_<無害化ファイル名>」
+/// - ファイル名の無い添付は「attachment」、書き込み失敗はログに出して次の添付へ進む
+pub fn extract_attachments(mail: &mut ParsedMail, dir: &Path, message_id: &str) {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        crate::printdaytimeln!("添付保存失敗: {}: {}", dir.display(), e);
+        return;
+    }
+    let prefix = sanitize_component(message_id); // メッセージIDも無害化して使う（「/」等も置換）
+    extract_into(mail, dir, &prefix);
+}
+
+/// 1通分の非テキストパートを書き出し、添付メールへ再帰
+fn extract_into(mail: &mut ParsedMail, dir: &Path, prefix: &str) {
+    for part in &mut mail.non_text_parts {
+        let filename = sanitize_filename(part.filename.as_deref().unwrap_or(""));
+        let part_prefix = format!("{}_p{}", prefix, part.part); // パート番号で区別
+        match write_unique(dir, &part_prefix, &filename, &part.data) {
+            Ok(path) => part.saved_as = Some(path.display().to_string()),
+            Err(e) => crate::printdaytimeln!("添付保存失敗: {}: {}", filename, e),
+        }
+    }
+    for mime in &mut mail.mime_parts {
+        let index = mime.index; // 添付メールのパート番号
+        if let Some(nested) = mime.message.as_deref_mut() {
+            extract_into(nested, dir, &format!("{}_p{}", prefix, index));
+        }
+    }
+}
@@ -7,13 +7,23 @@
 // - std: 標準ライブラリ（アドレス、コレクション、時間、文字列操作など）
 // - super::milter_command: Milterプロトコルのコマンド種別定義・判定用（MilterCommand enum, as_str等）
 // - super::milter: Milterコマンドごとのペイロード分解・応答処理（decode_xxx群）
-// - crate::parse: MIMEメールのパース・構造化・本文抽出・添付抽出（parse_mail）
+// - super::session: セッション状態（フェーズ・接続/トランザクション情報）管理（Session）
+// - crate::parse: MIMEメールのパース・構造化・本文抽出・添付抽出（rebuild_message, parse_mail）
+// - crate::formatter: 解析結果のログ出力（log_mail）
+// - crate::attachment: 添付ファイルのスプール保存（extract_attachments）
+// - crate::output: 構造化（JSON Lines）出力（MessageRecord, write_record）
+// - crate::dkim: DKIM署名の検証（verify_message, method_results）
+// - crate::spf: SPFの評価（check_spf）
 // - crate::printdaytimeln!: JSTタイムスタンプ付きログ出力マクロ
 //
 // 【役割】
 // - クライアント1接続ごとのMilterプロトコル非同期処理
 // - ヘッダ受信 → コマンド判定 → ペイロード受信 → コマンド別処理 → 応答送信
-// - BODYEOB時にメールパース・出力処理の呼び出し
+// - OPTNEGで合意した応答省略フラグ（SMFIP_NR_*）に従った応答有無の制御
+// - フェーズ状態遷移によるコマンド順序管理
+// - BODYEOB時にメールパース・出力処理の呼び出し（設定があればJSON Linesも出力）
+// - BODYEOB時のSPF評価・DKIM署名検証（設定時のみ、結果はAuthentication-Resultsヘッダとしても付与）
+// - BODYEOB時のメッセージ変更アクション（X-MilterDecoder-Summaryヘッダ付与等）の送信
 // - タイムアウト・エラーハンドリング・シャットダウン通知処理
 // =========================
 
@@ -24,12 +34,21 @@ use tokio::{
 };
 
 use super::milter::{
-    decode_body, decode_connect, decode_data_macros, decode_eoh_bodyeob, decode_header,
-    decode_helo, decode_optneg,
+    decode_body, decode_connect, decode_data_macros, decode_header, decode_helo, decode_mail,
+    decode_optneg, decode_rcpt, send_modifications, send_response,
 };
-use super::milter_command::MilterCommand; // Milterコマンド種別定義・判定 // 各Milterコマンドの分解・応答処理
+use super::milter_command::{MilterCommand, MilterModification, MilterResponse}; // Milterコマンド種別定義・判定、応答・変更アクション
+use super::session::Session; // セッション状態（フェーズ・接続/トランザクション情報）管理
 
-use crate::parse::parse_mail; // メールパース・出力処理（BODYEOB時に呼び出し）
+use crate::negotiate::SMFIP_HDR_LEADSPC; // ヘッダ値先頭空白フラグ
+use crate::output::{write_record, MessageRecord}; // 構造化（JSON Lines）出力
+use crate::attachment::extract_attachments; // 添付ファイルのスプール保存
+use crate::dkim::{method_results, verify_message}; // DKIM署名の検証
+use crate::formatter::{log_dkim, log_mail, log_received, log_spf}; // 解析結果・中継経路・SPF/DKIM結果のログ出力
+use crate::parse::{parse_mail, rebuild_message}; // メール再構築・パース（BODYEOB時に呼び出し）
+use crate::policy::{evaluate, modifications, PolicyStage}; // 受理/拒否判定・変更アクション
+use crate::received::parse_chain; // Receivedヘッダの中継経路解析
+use crate::spf::check_spf; // SPFの評価
 
 /// クライアント1接続ごとの非同期処理（Milterプロトコル）
 /// 1. ヘッダ受信 → 2. コマンド判定 → 3. ペイロード受信 → 4. コマンド別処理 → 5. 応答送信
@@ -48,16 +67,9 @@ pub async fn handle_client(
     let config = crate::init::CONFIG.read().unwrap().clone(); // 設定をロックしてクローン
     let timeout_duration = std::time::Duration::from_secs(config.client_timeout); // タイムアウト値をDuration化
 
-    // BODYコマンド受信後はEOHをBODYEOB扱いにするフラグ
-    let mut is_body_eob = false; // BODY受信後にEOHをBODYEOBとして扱う
-                                 // DATAコマンドでヘッダブロック開始/終了を判定
-    let mut is_header_block = false; // ヘッダブロック中かどうか
-                                     // ヘッダ情報（複数値対応）
-    let mut header_fields: std::collections::HashMap<String, Vec<String>> =
-        std::collections::HashMap::new(); // ヘッダ格納用
-                                          // ボディ情報
-    let mut body_field = String::new(); // ボディ格納用
-                                        // メインループ: 切断・エラー・タイムアウト・シャットダウン通知以外は繰り返しコマンド受信・応答
+    // セッション状態（フェーズ・接続情報・エンベロープ・ヘッダ・ボディ）
+    let mut session = Session::new(); // 接続直後はOPTNEG待ち
+    // メインループ: 切断・エラー・タイムアウト・シャットダウン通知以外は繰り返しコマンド受信・応答
     loop {
         // メインループ: 切断・エラー・タイムアウト・シャットダウン通知以外は繰り返しコマンド受信・応答
         // --- フェーズ1: 5バイトヘッダ受信（4バイト:サイズ + 1バイト:コマンド） ---
@@ -103,35 +115,30 @@ pub async fn handle_client(
         let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]); // 4バイト:コマンド+ペイロードサイズ
         let command = header[4]; // 1バイト:コマンド種別
         let milter_cmd = MilterCommand::from_u8(command); // コマンド種別をenum化
-        match milter_cmd {
-            // コマンド種別ごとに分岐
-            Some(cmd) => {
-                // EOHコマンド時はEOH/BODYEOB名で出力、それ以外は通常名
-                if let MilterCommand::Eoh = cmd {
-                    let eoh_str = MilterCommand::Eoh.as_str_eoh(is_body_eob);
-                    crate::printdaytimeln!(
-                        "コマンド受信: {} (0x{:02X}) size={} from {} [is_body_eob={}]",
-                        eoh_str,
-                        command,
-                        size,
-                        peer_addr,
-                        is_body_eob
-                    );
-                } else {
-                    crate::printdaytimeln!(
-                        "コマンド受信: {} (0x{:02X}) size={} from {}",
-                        cmd.as_str(),
-                        command,
-                        size,
-                        peer_addr
-                    );
-                }
-            }
+        let cmd = match milter_cmd {
+            Some(cmd) => cmd, // 定義済みコマンド
             None => {
                 // 未定義コマンドは切断
                 crate::printdaytimeln!("不正コマンド: 0x{:02X} (addr: {})", command, peer_addr);
                 return;
             }
+        };
+        crate::printdaytimeln!(
+            "コマンド受信: {} (0x{:02X}) size={} from {} [phase={:?}]",
+            cmd.as_str(),
+            command,
+            size,
+            peer_addr,
+            session.phase
+        );
+        // 現フェーズで想定外のコマンドは警告のみ出力（MTA実装差を考慮し処理は継続）
+        if !session.phase.can_accept(cmd) {
+            crate::printdaytimeln!(
+                "順序外コマンド: {} (phase={:?}) from {}",
+                cmd.as_str(),
+                session.phase,
+                peer_addr
+            );
         }
 
         // --- フェーズ3: ペイロード受信（4KB単位で分割） ---
@@ -185,49 +192,240 @@ pub async fn handle_client(
             peer_addr
         ); // 受信サイズ出力
 
-        // --- コマンド別処理: OPTNEG, EOH/BODYEOB, その他 ---
-        if let Some(cmd) = milter_cmd {
-            // コマンド種別ごとに処理分岐
-            // PostfixのMilterプロトコルで送られてくる順番に分岐を並び替え
-            // 主要なMilterコマンドごとに分岐し、各処理を実行
-            if let MilterCommand::OptNeg = cmd {
+        // --- コマンド別処理: フェーズ状態遷移に沿ってコマンドごとに分岐 ---
+        // PostfixのMilterプロトコルで送られてくる順番に分岐を並べる
+        // 応答を待つコマンドは（応答, ログ用フェーズ名）を返し、応答送信は分岐後の1か所で行う
+        let reply: Option<(MilterResponse, &str)> = match cmd {
+            MilterCommand::OptNeg => {
                 // OPTNEGコマンド解析処理（ネゴシエーション情報の分解・応答）
-                decode_optneg(&mut stream, &payload).await; // ネゴシエーション応答
-            } else if let MilterCommand::Connect = cmd {
-                // CONNECTコマンド時は接続情報の分解＆応答（milter.rsに分離）
-                decode_connect(&mut stream, &payload, &peer_addr).await; // 接続情報応答
-            } else if let MilterCommand::HeLO = cmd {
-                // HELOコマンド時はHELO情報の分解＆応答（milter.rsに分離）
-                decode_helo(&mut stream, &payload, &peer_addr).await; // HELO応答
-            } else if let MilterCommand::Data = cmd {
-                // DATAコマンド時(のマクロ処理)（milter.rsに分離）
-                decode_data_macros(&payload, &mut is_header_block); // マクロ情報処理
-                                                                    // DATAコマンドではCONTINUE応答を送信しなくてもよい
-            } else if let MilterCommand::Header = cmd {
+                match decode_optneg(&mut stream, &payload, &config.macro_lists).await {
+                    Some(options) => session.options = options, // ネゴシエーション応答（合意内容を保持）
+                    None => {
+                        // 交渉失敗（ペイロード長不足）: 応答できないため切断
+                        crate::printdaytimeln!("OPTNEG交渉失敗のため切断: {}", peer_addr);
+                        return;
+                    }
+                }
+                session
+                    .header_fields
+                    .set_leading_space(session.options.has_protocol(SMFIP_HDR_LEADSPC)); // ヘッダ値先頭空白の扱い
+                None // OPTNEG応答は送信済み
+            }
+            MilterCommand::Macro => {
+                // MACROコマンド時はマクロ情報を分解・出力（milter.rsに分離）
+                if let Some((stage, pairs)) = decode_data_macros(&payload) {
+                    session.store_macros(stage, pairs); // 接続単位/トランザクション単位で保持（応答不要）
+                    if let Some(queue_id) = session.macros.queue_id() {
+                        crate::logging::update_queue_id(queue_id); // トランザクション中ならメッセージIDをキューIDに
+                    }
+                }
+                None
+            }
+            MilterCommand::Connect => {
+                // CONNECTコマンド時は接続情報の分解（milter.rsに分離）→ ホスト名・アドレスでポリシー判定
+                let connect_info = decode_connect(&payload); // 接続情報分解
+                let address = connect_info.address.map(|a| a.to_string()).unwrap_or_default(); // IPアドレス（無ければ空文字）
+                let response = evaluate(
+                    &config.policy_rules,
+                    PolicyStage::Connect,
+                    &[&connect_info.hostname, &address],
+                    &session.macros.lines(),
+                ); // ポリシー判定
+                session.connect_info = Some(connect_info); // 接続単位の情報として保持
+                Some((response, "connect"))
+            }
+            MilterCommand::HeLO => {
+                // HELOコマンド時はHELO情報の分解（milter.rsに分離）→ HELO名でポリシー判定
+                let helo = decode_helo(&payload); // HELO情報分解
+                let response = evaluate(
+                    &config.policy_rules,
+                    PolicyStage::Helo,
+                    &[&helo],
+                    &session.macros.lines(),
+                ); // ポリシー判定
+                session.helo = Some(helo); // 接続単位の情報として保持
+                Some((response, "helo"))
+            }
+            MilterCommand::Mail => {
+                // MAIL FROMコマンド時は送信者とESMTPパラメータを分解・格納（milter.rsに分離）→ 送信者でポリシー判定
+                crate::logging::begin_message(session.macros.queue_id()); // メッセージID設定（キューID優先）
+                decode_mail(&payload, &mut session.envelope); // 送信者格納（新トランザクション開始）
+                let sender = session
+                    .envelope
+                    .sender
+                    .as_ref()
+                    .map(|s| s.address.as_str())
+                    .unwrap_or(""); // 送信者アドレス
+                let response = evaluate(
+                    &config.policy_rules,
+                    PolicyStage::Mail,
+                    &[sender],
+                    &session.macros.lines(),
+                ); // ポリシー判定
+                Some((response, "mail"))
+            }
+            MilterCommand::Rcpt => {
+                // RCPT TOコマンド時は受信者とESMTPパラメータを分解・格納（milter.rsに分離）→ 受信者でポリシー判定
+                decode_rcpt(&payload, &mut session.envelope); // 受信者追加
+                let rcpt = session
+                    .envelope
+                    .recipients
+                    .last()
+                    .map(|r| r.address.as_str())
+                    .unwrap_or(""); // 今回の受信者アドレス
+                let response = evaluate(
+                    &config.policy_rules,
+                    PolicyStage::Rcpt,
+                    &[rcpt],
+                    &session.macros.lines(),
+                ); // ポリシー判定
+                Some((response, "rcpt"))
+            }
+            MilterCommand::Data => {
+                // DATAコマンドは判定しない（SMFIP_NR_DATA未合意ならCONTINUE応答）
+                Some((MilterResponse::Continue, "data"))
+            }
+            MilterCommand::Header => {
                 // SMFIC_HEADER(0x4C)コマンド時、ペイロードをヘッダ配列に格納＆出力（milter.rsに分離）
-                decode_header(&payload, &mut header_fields); // ヘッダ格納
-                                                             // HEADERコマンドではCONTINUE応答を送信しなくてもよい（Postfix互換）
-            } else if let MilterCommand::Body = cmd {
-                // BODYコマンドが来たら以降0x45はBODYEOB扱いにする
-                is_body_eob = true; // BODY受信後はEOHをBODYEOB扱い
-                is_header_block = false; // BODYコマンドでヘッダブロック終了
-                                         // BODYペイロードをデコード・保存（ヘッダ配列・ボディも渡す）
-                decode_body(&payload, &mut body_field); // ボディ格納
-                                                        // BODYコマンドではCONTINUE応答を送信しなくてもよい
-            } else if let MilterCommand::Eoh = cmd {
-                // EOH/BODYEOBの判定・応答処理をmilter.rsに分離
-                decode_eoh_bodyeob(&mut stream, is_body_eob, &peer_addr).await; // EOH/BODYEOB応答
-                                                                                // BODYEOB(=is_body_eob==true)のときのみ、直前のヘッダ情報とボディ情報を出力
-                if is_body_eob {
-                    parse_mail(&header_fields, &body_field); // メールパース・出力
-                                                             // 出力後はいろいろクリア
-                    header_fields.clear(); // ヘッダ初期化
-                    body_field.clear(); // ボディ初期化
-                    is_body_eob = false; // BODYEOB→EOH遷移
-                }
-            } else {
-                // その他のコマンドや拡張コマンド時
-                // ペイロードデータを16進表記で出力（デバッグ用）
+                decode_header(&payload, &mut session.header_fields); // ヘッダ格納
+                Some((MilterResponse::Continue, "header")) // SMFIP_NR_HDR未合意ならCONTINUE応答
+            }
+            MilterCommand::Eoh => {
+                // EOH時は各ヘッダ行でポリシー判定（本文受信前に拒否可能）
+                let lines: Vec<String> = session.header_fields.iter().map(|h| h.to_line()).collect(); // 「名前: 値」
+                let subjects: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+                let response = evaluate(
+                    &config.policy_rules,
+                    PolicyStage::Eoh,
+                    &subjects,
+                    &session.macros.lines(),
+                ); // ポリシー判定
+                Some((response, "eoh"))
+            }
+            MilterCommand::Body => {
+                // BODYペイロードをデコード・保存
+                decode_body(&payload, &mut session.body_field); // ボディ格納
+                Some((MilterResponse::Continue, "body")) // SMFIP_NR_BODY未合意ならCONTINUE応答
+            }
+            MilterCommand::BodyEob => {
+                // 直前のヘッダ情報とボディ情報を出力（本文が空のメールも対象）
+                crate::printdaytimeln!(
+                    "[session] connect: {}, helo: {}, queue_id: {}",
+                    session
+                        .connect_info
+                        .as_ref()
+                        .map(|c| c.raw.as_str())
+                        .unwrap_or("(なし)"),
+                    session.helo.as_deref().unwrap_or("(なし)"),
+                    session.macros.queue_id().unwrap_or("(なし)")
+                ); // 接続単位の情報・キューIDを出力
+                let received = parse_chain(&session.header_fields, chrono::Local::now().fixed_offset()); // Receivedヘッダの中継経路
+                log_received(&received); // 接続情報に続けて中継経路を出力
+                let mut record = MessageRecord::new(&session); // 構造化出力レコード（接続・エンベロープ・マクロ・ヘッダ）
+                record.received = received; // 接続情報と並べてJSONへ
+                if config.spf_verify || config.dkim_verify {
+                    // SPF評価・DKIM署名検証（DNS問い合わせは同期処理のためワーカースレッドを占有して行う）
+                    let now = chrono::Utc::now().timestamp(); // x=の期限判定・%{t}用
+                    let address = session.connect_info.as_ref().and_then(|c| c.address); // 接続元IP
+                    let mail_from = session.envelope.sender.as_ref().map(|s| s.address.as_str()).unwrap_or(""); // 未受信はヌル送信者扱い
+                    let (spf, dkim) = tokio::task::block_in_place(|| {
+                        // システムのリゾルバは内部に実行環境を持ち、非同期の文脈で破棄するとpanicするため生成から破棄までここで行う
+                        let resolver = config.dns_resolver.open(); // SPFレコード・公開鍵の問い合わせ先
+                        let spf = address
+                            .filter(|_| config.spf_verify)
+                            .map(|ip| check_spf(ip, mail_from, session.helo.as_deref(), resolver.as_ref(), now));
+                        let dkim = config
+                            .dkim_verify
+                            .then(|| verify_message(&session.header_fields, &session.body_field, resolver.as_ref(), now));
+                        (spf, dkim)
+                    });
+                    let mut methods = Vec::new(); // Authentication-Resultsの項目（SPF → DKIMの順）
+                    if config.spf_verify {
+                        match spf {
+                            Some(result) => {
+                                log_spf(&result); // 中継経路に続けて評価結果を出力
+                                methods.push(result.method_result());
+                                record.spf = Some(result); // JSONへ
+                            }
+                            None => crate::printdaytimeln!("[spf] 接続元IPアドレスが無いため評価しない"),
+                        }
+                    }
+                    if let Some(results) = dkim {
+                        log_dkim(&results); // SPFに続けて検証結果を出力
+                        methods.extend(method_results(&results));
+                        record.dkim = results; // JSONへ
+                    }
+                    if let Some(authserv_id) = config.authserv_id.as_ref() {
+                        // 外部から届いた同じauthserv-idのヘッダは偽装のため削除（RFC 8601 §5）
+                        // 削除で後続の出現番号がずれないよう末尾側から送る
+                        for index in session.header_fields.authentication_results_of(authserv_id).into_iter().rev() {
+                            crate::printdaytimeln!("[auth] 偽装Authentication-Resultsを削除: 出現番号{}", index);
+                            session.modifications.push(MilterModification::ChangeHeader {
+                                index,
+                                name: "Authentication-Results".to_string(),
+                                value: String::new(),
+                            });
+                        }
+                        if !methods.is_empty() {
+                            // 自サーバの結果は既存のヘッダより上（先頭）に挿入
+                            session.modifications.push(MilterModification::InsertHeader {
+                                index: 0,
+                                name: "Authentication-Results".to_string(),
+                                value: format!("{}; {}", authserv_id, methods.join("; ")),
+                            });
+                        }
+                    }
+                }
+                let mail_bytes = rebuild_message(&session.header_fields, &session.body_field); // メール全体を再構築
+                // パース・アーカイブ展開・Office/PDF解析・添付保存は同期処理のため、
+                // block_in_placeでワーカースレッドを明け渡してから実行（他の接続の処理を止めない、ログ文脈は維持）
+                let parsed = tokio::task::block_in_place(|| {
+                    let mut parsed = parse_mail(&mail_bytes, &config.parse_options()); // メールパース（添付メール・アーカイブも再帰解析）
+                    if let (Some(mail), Some(dir)) = (parsed.as_mut(), &config.attachment_spool) {
+                        let message_id = crate::logging::current_ids().1.unwrap_or_else(|| "message".to_string()); // ファイル名接頭辞
+                        extract_attachments(mail, dir, &message_id); // 添付ファイルを書き出し
+                    }
+                    parsed
+                });
+                log_mail(
+                    &session.envelope,
+                    &session.macros.lines(),
+                    &session.header_fields,
+                    &mail_bytes,
+                    parsed.as_ref(),
+                ); // 解析結果のログ出力
+                record.set_mail(parsed.as_ref()); // 解析結果をJSONレコードへ
+                if let Some(target) = &config.json_output {
+                    record.truncate_bodies(config.json_body_limit); // 本文切り詰め
+                    write_record(target, &record); // JSON Lines出力
+                }
+                if let Some(counts) = parsed.as_ref().map(|p| p.counts()) {
+                    // パート数をサマリーヘッダとして付与
+                    session.modifications.push(MilterModification::AddHeader {
+                        name: "X-MilterDecoder-Summary".to_string(),
+                        value: format!("text={}; non-text={}", counts.text, counts.non_text),
+                    });
+                }
+                // 各ヘッダ行でポリシー判定（既定はACCEPT）
+                let lines: Vec<String> = session.header_fields.iter().map(|h| h.to_line()).collect(); // 「名前: 値」
+                let subjects: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+                let macro_lines = session.macros.lines(); // 「名前=値」形式のマクロ値
+                session
+                    .modifications
+                    .extend(modifications(&config.policy_rules, &subjects, &macro_lines)); // ルールの変更アクション
+                let response = evaluate(&config.policy_rules, PolicyStage::Eom, &subjects, &macro_lines); // ポリシー判定
+                if matches!(response, MilterResponse::Accept | MilterResponse::Continue) {
+                    // 受理時のみ変更アクションを最終応答より前に送信
+                    send_modifications(&mut stream, &session.modifications, &session.options, &peer_addr)
+                        .await;
+                }
+                session.reset_transaction(); // 出力後はトランザクション状態をクリア
+                Some((response, "eom"))
+            }
+            MilterCommand::Unknown => {
+                // MTAが解釈できなかったSMTPコマンド（デバッグ用に16進表記でも出力）
+                let unknown_str = String::from_utf8_lossy(&payload).replace('\0', "<NUL>"); // NUL可視化
+                crate::printdaytimeln!("未知SMTPコマンド: {}", unknown_str);
                 if !payload.is_empty() {
                     let hexstr = payload
                         .iter()
@@ -236,8 +434,47 @@ pub async fn handle_client(
                         .join(" "); // 16進ダンプ生成
                     crate::printdaytimeln!("ペイロード: {}", hexstr); // 16進ダンプ出力
                 }
-                // その他の正式なコマンドにはCONTINUE応答を送信しない
+                Some((MilterResponse::Continue, "unknown")) // SMFIP_NR_UNKN未合意ならCONTINUE応答
+            }
+            MilterCommand::Abort => {
+                // トランザクション中断（RSET等、応答不要）: 受信途中のメールを破棄し、CONNECT/HELO情報は保持
+                crate::printdaytimeln!("トランザクション中断: {}", peer_addr);
+                session.reset_transaction(); // エンベロープ・ヘッダ・ボディ破棄
+                None
             }
+            MilterCommand::QuitNc => {
+                // セッション終了（接続は再利用されるため切断しない）: 新しい接続として状態を初期化
+                crate::printdaytimeln!("セッション終了(接続再利用): {}", peer_addr);
+                session.reset_connection(); // 接続単位の情報も含めて破棄
+                None
+            }
+            MilterCommand::Quit => {
+                // セッション終了: 接続を閉じる
+                crate::printdaytimeln!("セッション終了: {}", peer_addr);
+                return;
+            }
+        };
+        // --- 応答送信: OPTNEGで応答省略（SMFIP_NR_*）を合意したコマンド以外には必ず1回だけ応答 ---
+        if let Some((response, stage)) = reply {
+            if session.options.expects_reply(cmd) {
+                send_response(&mut stream, &response, stage, &peer_addr).await; // 応答送信
+            } else if response != MilterResponse::Continue {
+                // 応答省略合意済みのため判定結果を返せない
+                crate::printdaytimeln!(
+                    "応答省略合意済みのため判定結果を送信できません({}): {}",
+                    stage,
+                    response.as_str()
+                );
+            }
+        }
+        // トランザクション終了時はメッセージIDを解除（最終応答のログまでは付与）
+        if matches!(
+            cmd,
+            MilterCommand::BodyEob | MilterCommand::Abort | MilterCommand::QuitNc
+        ) {
+            crate::logging::end_message();
         }
+        // フェーズを次段階へ遷移
+        session.phase = session.phase.next(cmd);
     } // メインループ終端
 }
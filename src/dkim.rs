@@ -0,0 +1,519 @@
+// =========================
+// dkim.rs
+// MilterDecoder DKIM署名検証モジュール
+//
+// 【このファイルで使う主なクレート】
+// - rsa: rsa-sha256署名の検証（公開鍵はSubjectPublicKeyInfo/PKCS#1のDER）
+// - ed25519-dalek: ed25519-sha256署名（RFC 8463）の検証
+// - sha2: ヘッダ・本文のSHA-256ハッシュ計算
+// - base64: b=・bh=・p=タグのデコード
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - crate::header: 受信順ヘッダリスト（MTAから受け取ったままの値・順序）
+// - crate::resolver: 公開鍵（selector._domainkey.domain のTXT）の問い合わせ
+//
+// 【役割】
+// - DKIM-Signatureヘッダのタグ解析（v/a/c/d/s/h/i/l/x/b/bh）
+// - ヘッダ・本文の正規化（simple/relaxed）、h=による署名対象ヘッダの選択（同名は下から）、l=による本文の切り詰め
+// - 本文ハッシュ（bh=）と署名（b=）の検証、公開鍵レコード（v/k/h/s/t/p）の解析
+// - 署名ごとの結果（pass/fail/temperror/permerror）とAuthentication-Resultsヘッダ用の文字列の生成
+// =========================
+
+use base64::Engine; // base64デコード
+use rsa::pkcs1::DecodeRsaPublicKey; // PKCS#1形式の公開鍵
+use rsa::pkcs8::DecodePublicKey; // SubjectPublicKeyInfo形式の公開鍵
+use rsa::traits::PublicKeyParts; // 鍵長の取得
+use serde::Serialize; // JSON出力用
+use sha2::{Digest, Sha256}; // SHA-256
+use std::collections::HashMap; // ヘッダ名 → 使用済みの数
+
+use crate::header::HeaderList; // 受信順ヘッダリスト
+use crate::resolver::{LookupError, Resolver}; // 公開鍵の問い合わせ
+
+const MAX_SIGNATURES: usize = 10; // 検証するDKIM-Signatureヘッダの上限
+const MIN_RSA_BITS: usize = 1024; // 受け付けるRSA鍵長の下限（RFC 8301）
+
+/// 署名1件の検証結果（RFC 8601の結果値）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DkimStatus {
+    Pass,      // 検証成功
+    Fail,      // 本文ハッシュまたは署名が一致しない
+    Temperror, // 公開鍵の問い合わせが一時的に失敗
+    Permerror, // 署名・公開鍵の形式不正、未対応のアルゴリズム、期限切れ、鍵なし
+}
+
+impl DkimStatus {
+    /// ログ・Authentication-Results出力用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            DkimStatus::Pass => "pass",
+            DkimStatus::Fail => "fail",
+            DkimStatus::Temperror => "temperror",
+            DkimStatus::Permerror => "permerror",
+        }
+    }
+}
+
+/// DKIM-Signatureヘッダ1件分の検証結果
+#[derive(Debug, Clone, Serialize)]
+pub struct DkimResult {
+    pub index: usize,                     // DKIM-Signatureヘッダの連番（上から1始まり）
+    pub result: DkimStatus,               // 検証結果
+    pub domain: Option<String>,           // 署名ドメイン（d=）
+    pub selector: Option<String>,         // セレクタ（s=）
+    pub algorithm: Option<String>,        // アルゴリズム（a=）
+    pub canonicalization: Option<String>, // 正規化方式（c=、省略時はsimple/simple）
+    pub identity: Option<String>,         // 署名者の識別子（i=）
+    pub signed_headers: Vec<String>,      // 署名対象ヘッダ名（h=）
+    pub body_length: Option<usize>,       // 署名対象の本文バイト数（l=）
+    pub header_b: Option<String>,         // 署名値の先頭8文字（Authentication-Resultsのheader.b）
+    pub reason: Option<String>,           // pass以外の理由
+}
+
+impl DkimResult {
+    /// Authentication-Resultsヘッダの1項目（例: dkim=pass header.d=example.com header.s=sel header.b=abcdEFGH）
+    pub fn method_result(&self) -> String {
+        let mut out = format!("dkim={}", self.result.name());
+        if let Some(reason) = &self.reason {
+            out.push_str(&format!(" reason=\"{}\"", reason.replace(['"', '\\'], "")));
+        }
+        for (name, value) in [
+            ("header.d", &self.domain),
+            ("header.i", &self.identity),
+            ("header.s", &self.selector),
+            ("header.a", &self.algorithm),
+            ("header.b", &self.header_b),
+        ] {
+            if let Some(value) = value {
+                out.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        out
+    }
+}
+
+/// Authentication-Resultsヘッダの項目（署名ごとの結果、署名が無ければdkim=none）
+pub fn method_results(results: &[DkimResult]) -> Vec<String> {
+    if results.is_empty() {
+        return vec!["dkim=none".to_string()];
+    }
+    results.iter().map(|r| r.method_result()).collect()
+}
+
+/// 署名アルゴリズム
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    RsaSha256,     // rsa-sha256
+    Ed25519Sha256, // ed25519-sha256（RFC 8463）
+}
+
+/// 正規化方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Canon {
+    Simple,  // そのまま（本文末尾の空行のみ除く）
+    Relaxed, // 空白の圧縮・ヘッダ名の小文字化・折り返しの展開
+}
+
+/// 検証に必要なタグを取り出した署名
+struct Signature {
+    algorithm: Algorithm,       // a=
+    header_canon: Canon,        // c=のヘッダ側
+    body_canon: Canon,          // c=の本文側
+    domain: String,             // d=
+    selector: String,           // s=
+    headers: Vec<String>,       // h=（小文字化済み）
+    body_length: Option<usize>, // l=
+    body_hash: Vec<u8>,         // bh=（デコード済み）
+    signature: Vec<u8>,         // b=（デコード済み）
+}
+
+/// メッセージの全DKIM-Signatureヘッダを検証
+///
+/// # 引数
+/// - `headers`: Milterで受信したヘッダ（受信順・生の値）
+/// - `body`: Milterで受信したボディ（改行コードはここでCRLFに統一）
+/// - `resolver`: 公開鍵の問い合わせ先
+/// - `now`: 現在時刻（UNIX秒、x=の期限判定用）
+///
+/// # 説明
+/// - DKIM-Signatureヘッダを上から順にMAX_SIGNATURES件まで検証する
+/// - 署名が無ければ空のリストを返す
+pub fn verify_message(
+    headers: &HeaderList,
+    body: &[u8],
+    resolver: &dyn Resolver,
+    now: i64,
+) -> Vec<DkimResult> {
+    let body = crate::parse::normalize_crlf(body); // MTAから受け取った本文をCRLFに統一
+    let mut body_cache: HashMap<Canon, Vec<u8>> = HashMap::new(); // 正規化方式ごとの本文
+    headers
+        .get_all("DKIM-Signature")
+        .take(MAX_SIGNATURES)
+        .enumerate()
+        .map(|(i, field)| {
+            let raw = headers.raw_field(field); // 署名ヘッダ自身（b=の値を除いてハッシュ対象）
+            let mut result = DkimResult {
+                index: i + 1,
+                result: DkimStatus::Permerror,
+                domain: None,
+                selector: None,
+                algorithm: None,
+                canonicalization: None,
+                identity: None,
+                signed_headers: Vec::new(),
+                body_length: None,
+                header_b: None,
+                reason: None,
+            };
+            if let Err((status, reason)) = verify_one(
+                headers,
+                &raw,
+                &body,
+                &mut body_cache,
+                resolver,
+                now,
+                &mut result,
+            ) {
+                result.result = status;
+                result.reason = Some(reason);
+            } else {
+                result.result = DkimStatus::Pass;
+            }
+            result
+        })
+        .collect()
+}
+
+/// 署名1件を検証（タグの値は解析できた分からresultへ記録）
+fn verify_one(
+    headers: &HeaderList,
+    raw: &[u8],
+    body: &[u8],
+    body_cache: &mut HashMap<Canon, Vec<u8>>,
+    resolver: &dyn Resolver,
+    now: i64,
+    result: &mut DkimResult,
+) -> Result<(), (DkimStatus, String)> {
+    let perm = |reason: &str| (DkimStatus::Permerror, reason.to_string());
+    let value =
+        String::from_utf8_lossy(&raw[raw.iter().position(|&b| b == b':').unwrap_or(0) + 1..])
+            .to_string();
+    let tags = tag_list(&value).map_err(|e| perm(&e))?;
+    let tag = |name: &str| tags.get(name).map(|v| v.as_str());
+    // 出力用に解析前の値を記録
+    result.domain = tag("d").map(|d| d.to_lowercase());
+    result.selector = tag("s").map(|s| s.to_string());
+    result.algorithm = tag("a").map(|a| a.to_lowercase());
+    result.canonicalization = Some(tag("c").unwrap_or("simple/simple").to_lowercase());
+    result.identity = tag("i").map(|i| i.to_string());
+    result.signed_headers = tag("h")
+        .map(|h| h.split(':').map(|n| n.trim().to_string()).collect())
+        .unwrap_or_default();
+    result.header_b = tag("b").map(|b| strip_ws(b).chars().take(8).collect());
+    let sig = parse_signature(&tags, now).map_err(|e| perm(&e))?;
+    result.body_length = sig.body_length;
+
+    // 公開鍵の問い合わせ（selector._domainkey.domain のTXT）
+    let name = format!("{}._domainkey.{}", sig.selector, sig.domain);
+    let records = match resolver.txt(&name) {
+        Ok(records) => records,
+        Err(LookupError::NotFound) => return Err(perm("no key for signature")),
+        Err(LookupError::TempFail(e)) => {
+            return Err((DkimStatus::Temperror, format!("key lookup failed: {}", e)))
+        }
+    };
+    let record = records
+        .first()
+        .ok_or_else(|| perm("no key for signature"))?;
+    let key = parse_key(record, &sig, result.identity.as_deref()).map_err(|e| perm(&e))?;
+
+    // 本文ハッシュ（bh=）の検証
+    let canon_body = body_cache
+        .entry(sig.body_canon)
+        .or_insert_with(|| canonical_body(body, sig.body_canon));
+    let signed_body = match sig.body_length {
+        Some(len) if len > canon_body.len() => {
+            return Err((DkimStatus::Fail, "body shorter than l= length".to_string()))
+        }
+        Some(len) => &canon_body[..len],
+        None => &canon_body[..],
+    };
+    if Sha256::digest(signed_body).as_slice() != sig.body_hash.as_slice() {
+        return Err((DkimStatus::Fail, "body hash did not verify".to_string()));
+    }
+
+    // 署名（b=）の検証
+    let mut data = Vec::new(); // ハッシュ対象（選択したヘッダ + b=の値を除いた署名ヘッダ）
+    let mut used: HashMap<&str, usize> = HashMap::new(); // ヘッダ名ごとに下から使用した数
+    for name in &sig.headers {
+        let count = used.entry(name.as_str()).or_insert(0);
+        let instances: Vec<_> = headers.get_all(name).collect();
+        if *count < instances.len() {
+            let field = instances[instances.len() - 1 - *count]; // 同名ヘッダは下から順に使う
+            data.extend(canonical_header(
+                &headers.raw_field(field),
+                sig.header_canon,
+            ));
+            data.extend_from_slice(b"\r\n");
+        }
+        *count += 1; // 存在しない分は空として扱う
+    }
+    data.extend(canonical_header(&strip_b_value(raw), sig.header_canon)); // 末尾のCRLFは付けない
+    let digest = Sha256::digest(&data);
+    let verified = match key {
+        PublicKey::Rsa(key) => key
+            .verify(rsa::Pkcs1v15Sign::new::<Sha256>(), &digest, &sig.signature)
+            .is_ok(),
+        PublicKey::Ed25519(key) => ed25519_dalek::Signature::from_slice(&sig.signature)
+            .map(|s| key.verify_strict(&digest, &s).is_ok())
+            .unwrap_or(false),
+    };
+    if !verified {
+        return Err((DkimStatus::Fail, "signature did not verify".to_string()));
+    }
+    Ok(())
+}
+
+/// タグリスト（「名前=値; ...」）を解析（値の前後の空白は除き、同じタグの重複は不正）
+fn tag_list(value: &str) -> Result<HashMap<String, String>, String> {
+    let mut tags = HashMap::new();
+    for item in value.split(';') {
+        if item.trim().is_empty() {
+            continue; // 末尾の「;」
+        }
+        let (name, value) = item
+            .split_once('=')
+            .ok_or_else(|| format!("malformed tag: {}", item.trim()))?;
+        let name = name.trim().to_string();
+        if tags
+            .insert(name.clone(), value.trim().to_string())
+            .is_some()
+        {
+            return Err(format!("duplicate tag: {}", name));
+        }
+    }
+    Ok(tags)
+}
+
+/// DKIM-Signatureのタグを検証して必要な値を取り出す
+fn parse_signature(tags: &HashMap<String, String>, now: i64) -> Result<Signature, String> {
+    let tag = |name: &str| tags.get(name).map(|v| v.as_str());
+    let require = |name: &str| tag(name).ok_or_else(|| format!("missing {}= tag", name));
+    if require("v")? != "1" {
+        return Err("unsupported version".to_string());
+    }
+    let algorithm = match require("a")?.to_lowercase().as_str() {
+        "rsa-sha256" => Algorithm::RsaSha256,
+        "ed25519-sha256" => Algorithm::Ed25519Sha256,
+        _ => return Err("unsupported algorithm".to_string()),
+    };
+    let canon = tag("c").unwrap_or("simple").to_lowercase();
+    let (header_canon, body_canon) = canon.split_once('/').unwrap_or((canon.as_str(), "simple"));
+    let parse_canon = |c: &str| match c {
+        "simple" => Ok(Canon::Simple),
+        "relaxed" => Ok(Canon::Relaxed),
+        _ => Err("unsupported canonicalization".to_string()),
+    };
+    let domain = require("d")?.trim_end_matches('.').to_lowercase();
+    let headers: Vec<String> = require("h")?
+        .split(':')
+        .map(|n| n.trim().to_lowercase())
+        .filter(|n| !n.is_empty())
+        .collect();
+    if !headers.iter().any(|h| h == "from") {
+        return Err("From not signed".to_string());
+    }
+    if let Some(identity) = tag("i") {
+        // i=のドメインはd=と同じかそのサブドメイン
+        let auid = identity
+            .rsplit_once('@')
+            .map(|(_, d)| d)
+            .unwrap_or("")
+            .to_lowercase();
+        if auid != domain && !auid.ends_with(&format!(".{}", domain)) {
+            return Err("i= domain does not match d=".to_string());
+        }
+    }
+    let body_length = match tag("l") {
+        Some(l) => Some(
+            l.parse::<usize>()
+                .map_err(|_| "invalid l= tag".to_string())?,
+        ),
+        None => None,
+    };
+    if let Some(x) = tag("x") {
+        let expiration = x.parse::<i64>().map_err(|_| "invalid x= tag".to_string())?;
+        if expiration < now {
+            return Err("signature expired".to_string());
+        }
+    }
+    let decode = |name: &str| {
+        base64::engine::general_purpose::STANDARD
+            .decode(strip_ws(require(name)?))
+            .map_err(|_| format!("invalid {}= tag", name))
+    };
+    Ok(Signature {
+        algorithm,
+        header_canon: parse_canon(header_canon)?,
+        body_canon: parse_canon(body_canon)?,
+        domain,
+        selector: require("s")?.to_lowercase(),
+        headers,
+        body_length,
+        body_hash: decode("bh")?,
+        signature: decode("b")?,
+    })
+}
+
+/// 公開鍵
+enum PublicKey {
+    Rsa(rsa::RsaPublicKey),               // k=rsa
+    Ed25519(ed25519_dalek::VerifyingKey), // k=ed25519
+}
+
+/// 公開鍵レコード（TXT）を解析して署名のアルゴリズムに合う公開鍵を取り出す
+fn parse_key(record: &str, sig: &Signature, identity: Option<&str>) -> Result<PublicKey, String> {
+    let tags = tag_list(record).map_err(|e| format!("invalid key record: {}", e))?;
+    let tag = |name: &str| tags.get(name).map(|v| v.as_str());
+    if tag("v").is_some_and(|v| v != "DKIM1") {
+        return Err("invalid key record version".to_string());
+    }
+    if tag("h").is_some_and(|h| {
+        !h.split(':')
+            .any(|h| h.trim().eq_ignore_ascii_case("sha256"))
+    }) {
+        return Err("key does not allow sha256".to_string());
+    }
+    if tag("s").is_some_and(|s| !s.split(':').any(|s| matches!(s.trim(), "*" | "email"))) {
+        return Err("key not for email".to_string());
+    }
+    if tag("t").is_some_and(|t| t.split(':').any(|f| f.trim() == "s")) {
+        // t=s: i=のドメインはd=と完全一致が必要
+        let auid = identity
+            .and_then(|i| i.rsplit_once('@'))
+            .map(|(_, d)| d.to_lowercase());
+        if auid.is_some_and(|d| d != sig.domain) {
+            return Err("i= subdomain not allowed by key".to_string());
+        }
+    }
+    let data = strip_ws(tag("p").ok_or("missing p= tag in key")?);
+    if data.is_empty() {
+        return Err("key revoked".to_string());
+    }
+    let der = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|_| "invalid key data".to_string())?;
+    match (
+        tag("k").unwrap_or("rsa").to_lowercase().as_str(),
+        sig.algorithm,
+    ) {
+        ("rsa", Algorithm::RsaSha256) => {
+            let key = rsa::RsaPublicKey::from_public_key_der(&der)
+                .or_else(|_| rsa::RsaPublicKey::from_pkcs1_der(&der))
+                .map_err(|_| "invalid key data".to_string())?;
+            if key.size() * 8 < MIN_RSA_BITS {
+                return Err("key too short".to_string());
+            }
+            Ok(PublicKey::Rsa(key))
+        }
+        ("ed25519", Algorithm::Ed25519Sha256) => {
+            let bytes: [u8; 32] = der.try_into().map_err(|_| "invalid key data".to_string())?;
+            ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+                .map(PublicKey::Ed25519)
+                .map_err(|_| "invalid key data".to_string())
+        }
+        _ => Err("key type does not match algorithm".to_string()),
+    }
+}
+
+/// 空白・改行を全て除く（b=・bh=・p=のbase64値用）
+fn strip_ws(value: &str) -> String {
+    value.chars().filter(|c| !c.is_ascii_whitespace()).collect()
+}
+
+/// 署名ヘッダからb=タグの値を除く（タグ名と「=」、前後の空白・他のタグはそのまま）
+fn strip_b_value(raw: &[u8]) -> Vec<u8> {
+    let colon = raw.iter().position(|&b| b == b':').unwrap_or(0); // ヘッダ名の終わり
+    let mut out = raw[..=colon].to_vec();
+    for (i, item) in raw[colon + 1..].split(|&b| b == b';').enumerate() {
+        if i > 0 {
+            out.push(b';');
+        }
+        match item.iter().position(|&b| b == b'=') {
+            Some(eq) if item[..eq].trim_ascii() == b"b" => out.extend_from_slice(&item[..=eq]),
+            _ => out.extend_from_slice(item),
+        }
+    }
+    out
+}
+
+/// ヘッダ1件（「名前: 値」、行終端なし）を正規化
+///
+/// # 説明
+/// - simple: そのまま
+/// - relaxed: 名前を小文字化、折り返しを展開、連続する空白を1つに圧縮し、「:」前後と値の末尾の空白を除く
+fn canonical_header(raw: &[u8], canon: Canon) -> Vec<u8> {
+    if canon == Canon::Simple {
+        return raw.to_vec();
+    }
+    let colon = raw.iter().position(|&b| b == b':').unwrap_or(raw.len());
+    let mut out = raw[..colon].trim_ascii().to_ascii_lowercase(); // ヘッダ名
+    out.push(b':');
+    let value: Vec<u8> = raw
+        .get(colon + 1..)
+        .unwrap_or_default()
+        .iter()
+        .copied()
+        .filter(|&b| b != b'\r' && b != b'\n')
+        .collect(); // 折り返しの展開
+    out.extend(compress_wsp(value.trim_ascii()));
+    out
+}
+
+/// 本文を正規化
+///
+/// # 説明
+/// - simple: 末尾の空行を除き、最後の行をCRLFで終える（空の本文はCRLFのみ）
+/// - relaxed: 各行の連続する空白を1つに圧縮して行末の空白を除き、末尾の空行を除く（空の本文は空のまま）
+fn canonical_body(body: &[u8], canon: Canon) -> Vec<u8> {
+    let mut lines: Vec<Vec<u8>> = body
+        .split(|&b| b == b'\n')
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l).to_vec())
+        .collect();
+    if body.is_empty() || body.ends_with(b"\n") {
+        lines.pop(); // 最後のCRLFの後ろは行ではない
+    }
+    if canon == Canon::Relaxed {
+        for line in &mut lines {
+            let trimmed = compress_wsp(line);
+            *line = trimmed.trim_ascii_end().to_vec(); // 行末の空白を除く
+        }
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop(); // 末尾の空行を除く
+    }
+    if lines.is_empty() && canon == Canon::Simple {
+        return b"\r\n".to_vec();
+    }
+    let mut out = Vec::with_capacity(body.len());
+    for line in lines {
+        out.extend(line);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+/// 連続する空白（SP/HTAB）を1つのSPに圧縮
+fn compress_wsp(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        if b == b' ' || b == b'\t' {
+            if out.last() != Some(&b' ') {
+                out.push(b' ');
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
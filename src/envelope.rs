@@ -0,0 +1,89 @@
+// =========================
+// envelope.rs
+// MilterDecoder エンベロープ（MAIL FROM / RCPT TO）管理モジュール
+//
+// 【このファイルで使う主なクレート】
+// - std: 標準ライブラリ（バイト列分割、文字列変換、コレクション）
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+//
+// 【役割】
+// - SMFIC_MAIL / SMFIC_RCPT ペイロード（NUL区切り引数リスト）の分解
+// - エンベロープ送信者・全受信者とESMTPパラメータ（SIZE=, BODY=, NOTIFY=等）の保持
+// - トランザクション（1通）ごとのエンベロープ状態のリセット
+// =========================
+
+use serde::Serialize; // JSON出力用
+
+/// ESMTPパラメータ（例: SIZE=1024, BODY=8BITMIME, SMTPUTF8）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EsmtpParam {
+    pub keyword: String,       // パラメータ名（大文字化済み、例: SIZE）
+    pub value: Option<String>, // パラメータ値（値なしパラメータはNone）
+}
+
+/// エンベロープアドレス（MAIL FROM または RCPT TO の1件分）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvelopeAddress {
+    pub address: String,         // アドレス（<>除去済み、ヌル送信者は空文字）
+    pub params: Vec<EsmtpParam>, // ESMTPパラメータ（受信順）
+}
+
+impl EnvelopeAddress {
+    /// SMFIC_MAIL/SMFIC_RCPTペイロードからEnvelopeAddressを生成
+    ///
+    /// # 説明
+    /// - ペイロードはNUL区切りの引数リスト（先頭がアドレス、以降がESMTPパラメータ）
+    /// - 引数が1つも無い場合はNone
+    pub fn from_args(payload: &[u8]) -> Option<Self> {
+        let mut args = payload
+            .split(|b| *b == 0x00) // NUL区切りで分割
+            .filter(|s| !s.is_empty()) // 空要素（末尾NUL等）を除外
+            .map(|s| String::from_utf8_lossy(s).to_string()); // 各引数を文字列化
+        let raw_addr = args.next()?; // 先頭引数がアドレス
+        let address = raw_addr
+            .trim()
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .to_string(); // <>を除去（<>のみならヌル送信者として空文字）
+        let params = args
+            .map(|arg| match arg.split_once('=') {
+                Some((k, v)) => EsmtpParam {
+                    keyword: k.to_ascii_uppercase(), // KEY=VALUE形式
+                    value: Some(v.to_string()),
+                },
+                None => EsmtpParam {
+                    keyword: arg.to_ascii_uppercase(), // 値なしパラメータ（例: SMTPUTF8）
+                    value: None,
+                },
+            })
+            .collect();
+        Some(EnvelopeAddress { address, params })
+    }
+
+    /// ログ出力用に「<アドレス> KEY=VALUE ...」形式へ整形
+    pub fn display(&self) -> String {
+        let mut s = format!("<{}>", self.address); // アドレス部
+        for p in &self.params {
+            match &p.value {
+                Some(v) => s.push_str(&format!(" {}={}", p.keyword, v)), // KEY=VALUE
+                None => s.push_str(&format!(" {}", p.keyword)),          // KEYのみ
+            }
+        }
+        s
+    }
+}
+
+/// 1トランザクション分のエンベロープ情報（送信者・全受信者）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Envelope {
+    pub sender: Option<EnvelopeAddress>, // MAIL FROM（未受信ならNone）
+    pub recipients: Vec<EnvelopeAddress>, // RCPT TO（受信順、複数）
+}
+
+impl Envelope {
+    /// エンベロープ情報をクリア（次のトランザクション用）
+    pub fn clear(&mut self) {
+        self.sender = None; // 送信者初期化
+        self.recipients.clear(); // 受信者初期化
+    }
+}
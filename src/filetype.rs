@@ -0,0 +1,433 @@
+// =========================
+// filetype.rs
+// MilterDecoder ファイル種別判定（マジックバイト）モジュール
+//
+// 【このファイルで使う主なクレート】
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: バイト列・文字列操作
+//
+// 【役割】
+// - デコード済み添付データの先頭バイト（マジックバイト）による実際のファイル種別判定
+//   （PE/ELF/ZIP/OOXML/OLE2/PDF/RAR/7z/TAR/GZ/ISO/LNK/HTML/スクリプト/画像）
+// - 宣言されたContent-Type・ファイル名の拡張子と実際の種別との不一致検出
+//   （例: invoice.pdf の中身がPE、application/octet-stream の中身がZIP）
+// =========================
+
+use serde::Serialize; // JSON出力用
+
+/// マジックバイトから判定したファイル種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Pe,       // Windows実行ファイル（EXE/DLL、MZヘッダ＋PEシグネチャ）
+    Elf,      // Linux等の実行ファイル
+    Zip,      // ZIPアーカイブ（JAR/APK含む）
+    Ooxml,    // Office Open XML（docx/xlsx/pptx等、ZIP形式）
+    Ole2,     // OLE2複合文書（doc/xls/ppt/msg/msi等）
+    Pdf,      // PDF
+    Rar,      // RARアーカイブ
+    SevenZip, // 7-Zipアーカイブ
+    Tar,      // TARアーカイブ
+    Gzip,     // gzip圧縮ファイル
+    Iso,      // ISO 9660ディスクイメージ
+    Lnk,      // Windowsショートカット
+    Html,     // HTML
+    Script,   // スクリプト（シバン・バッチ・PHP・JScript・VBScript・PowerShell・HTA・WSF）
+    Png,      // PNG画像
+    Jpeg,     // JPEG画像
+    Gif,      // GIF画像
+    Bmp,      // BMP画像
+    Tiff,     // TIFF画像
+    Webp,     // WebP画像
+    Ico,      // アイコン画像
+}
+
+impl FileKind {
+    /// ログ出力用の種別名（JSONの値と同じ）
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::Pe => "pe",
+            FileKind::Elf => "elf",
+            FileKind::Zip => "zip",
+            FileKind::Ooxml => "ooxml",
+            FileKind::Ole2 => "ole2",
+            FileKind::Pdf => "pdf",
+            FileKind::Rar => "rar",
+            FileKind::SevenZip => "seven_zip",
+            FileKind::Tar => "tar",
+            FileKind::Gzip => "gzip",
+            FileKind::Iso => "iso",
+            FileKind::Lnk => "lnk",
+            FileKind::Html => "html",
+            FileKind::Script => "script",
+            FileKind::Png => "png",
+            FileKind::Jpeg => "jpeg",
+            FileKind::Gif => "gif",
+            FileKind::Bmp => "bmp",
+            FileKind::Tiff => "tiff",
+            FileKind::Webp => "webp",
+            FileKind::Ico => "ico",
+        }
+    }
+
+    /// この種別として正当なContent-Type（小文字「type/subtype」、末尾「.」は前方一致）
+    fn content_types(self) -> &'static [&'static str] {
+        match self {
+            FileKind::Pe => &[
+                "application/x-msdownload",
+                "application/x-dosexec",
+                "application/x-msdos-program",
+                "application/x-ms-dos-executable",
+                "application/vnd.microsoft.portable-executable",
+                "application/x-executable",
+            ],
+            FileKind::Elf => &[
+                "application/x-executable",
+                "application/x-elf",
+                "application/x-sharedlib",
+                "application/x-pie-executable",
+            ],
+            FileKind::Zip => &[
+                "application/zip",
+                "application/x-zip",
+                "application/x-zip-compressed",
+                "application/java-archive",
+                "application/vnd.android.package-archive",
+            ],
+            FileKind::Ooxml => &[
+                "application/vnd.openxmlformats-officedocument.",
+                "application/vnd.ms-word.",
+                "application/vnd.ms-excel.",
+                "application/vnd.ms-powerpoint.",
+            ],
+            FileKind::Ole2 => &[
+                "application/msword",
+                "application/vnd.ms-word",
+                "application/vnd.ms-excel",
+                "application/vnd.ms-powerpoint",
+                "application/vnd.ms-outlook",
+                "application/vnd.ms-office",
+                "application/vnd.visio",
+                "application/x-msi",
+                "application/x-ole-storage",
+            ],
+            FileKind::Pdf => &["application/pdf", "application/x-pdf"],
+            FileKind::Rar => &[
+                "application/vnd.rar",
+                "application/x-rar",
+                "application/x-rar-compressed",
+            ],
+            FileKind::SevenZip => &["application/x-7z-compressed"],
+            FileKind::Tar => &["application/x-tar", "application/x-gtar"],
+            FileKind::Gzip => &[
+                "application/gzip",
+                "application/x-gzip",
+                "application/x-gtar",
+                "application/x-compressed-tar",
+            ],
+            FileKind::Iso => &[
+                "application/x-iso9660-image",
+                "application/x-cd-image",
+                "application/x-iso-image",
+            ],
+            FileKind::Lnk => &["application/x-ms-shortcut"],
+            FileKind::Html => &["text/html", "application/xhtml+xml"],
+            FileKind::Script => &[
+                "application/x-sh",
+                "application/x-shellscript",
+                "text/x-sh",
+                "text/x-shellscript",
+                "text/x-python",
+                "application/x-python",
+                "text/x-perl",
+                "application/x-perl",
+                "text/x-php",
+                "application/x-httpd-php",
+                "application/x-bat",
+                "application/javascript",
+                "application/x-javascript",
+                "text/javascript",
+                "application/ecmascript",
+                "text/vbscript",
+                "application/x-vbscript",
+                "application/x-powershell",
+                "text/x-powershell",
+                "application/hta",
+                "application/x-wsf",
+            ],
+            FileKind::Png => &["image/png"],
+            FileKind::Jpeg => &["image/jpeg", "image/jpg", "image/pjpeg"],
+            FileKind::Gif => &["image/gif"],
+            FileKind::Bmp => &["image/bmp", "image/x-bmp", "image/x-ms-bmp"],
+            FileKind::Tiff => &["image/tiff"],
+            FileKind::Webp => &["image/webp"],
+            FileKind::Ico => &["image/x-icon", "image/vnd.microsoft.icon"],
+        }
+    }
+
+    /// この種別として正当な拡張子（小文字、ドット無し）
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            FileKind::Pe => &[
+                "exe", "dll", "sys", "scr", "com", "cpl", "ocx", "drv", "efi", "mui",
+            ],
+            FileKind::Elf => &["so", "elf", "bin", "o", "out"],
+            FileKind::Zip => &["zip", "jar", "apk"],
+            FileKind::Ooxml => &[
+                "docx", "docm", "dotx", "dotm", "xlsx", "xlsm", "xltx", "xltm", "xlsb", "xlam",
+                "pptx", "pptm", "potx", "potm", "ppsx", "ppsm",
+            ],
+            FileKind::Ole2 => &[
+                "doc", "dot", "xls", "xlt", "xla", "ppt", "pot", "pps", "msg", "msi", "vsd", "pub",
+            ],
+            FileKind::Pdf => &["pdf"],
+            FileKind::Rar => &["rar"],
+            FileKind::SevenZip => &["7z"],
+            FileKind::Tar => &["tar"],
+            FileKind::Gzip => &["gz", "tgz"],
+            FileKind::Iso => &["iso", "img"],
+            FileKind::Lnk => &["lnk"],
+            FileKind::Html => &["html", "htm", "xhtml", "shtml"],
+            FileKind::Script => &[
+                "sh", "bash", "py", "pl", "rb", "php", "bat", "cmd", "js", "jse", "vbs", "vbe",
+                "ps1", "psm1", "hta", "wsf", "wsh",
+            ],
+            FileKind::Png => &["png"],
+            FileKind::Jpeg => &["jpg", "jpeg", "jpe", "jfif"],
+            FileKind::Gif => &["gif"],
+            FileKind::Bmp => &["bmp", "dib"],
+            FileKind::Tiff => &["tif", "tiff"],
+            FileKind::Webp => &["webp"],
+            FileKind::Ico => &["ico", "cur"],
+        }
+    }
+
+    /// 宣言されたContent-Typeがこの種別として正当か
+    fn accepts_content_type(self, content_type: &str) -> bool {
+        self.content_types().iter().any(|t| {
+            if t.ends_with('.') {
+                content_type.starts_with(t) // ベンダー接頭辞で前方一致
+            } else {
+                content_type == *t
+            }
+        })
+    }
+}
+
+/// 宣言と実際の種別が食い違った項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchField {
+    ContentType, // Content-Typeヘッダ
+    Extension,   // ファイル名の拡張子
+}
+
+impl MismatchField {
+    /// ログ出力用の項目名（JSONの値と同じ）
+    pub fn name(self) -> &'static str {
+        match self {
+            MismatchField::ContentType => "content_type",
+            MismatchField::Extension => "extension",
+        }
+    }
+}
+
+/// 宣言された種別と実際の種別の不一致1件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TypeMismatch {
+    pub field: MismatchField, // 食い違った項目
+    pub declared: String,     // 宣言値（Content-Typeまたは拡張子）
+    pub detected: FileKind,   // マジックバイトから判定した種別
+}
+
+/// デコード済みデータのマジックバイトからファイル種別を判定（不明ならNone）
+///
+/// # 説明
+/// - バイナリ形式は先頭（ISOは0x8001等）の固定シグネチャで判定
+/// - PEはMZヘッダだけでなく、e_lfanew（0x3C）が指す位置のPEシグネチャも確認する
+/// - ZIPのうち「[Content_Types].xml」を含むものはOOXMLとする
+/// - HTML・スクリプトはBOM・先頭空白を除いた先頭文字列と、先頭部分に含まれる特徴的な記述で判定
+pub fn detect(data: &[u8]) -> Option<FileKind> {
+    const OLE2: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+    const LNK: &[u8] = &[
+        0x4C, 0x00, 0x00, 0x00, 0x01, 0x14, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x46,
+    ]; // ヘッダサイズ＋CLSID
+    if is_pe(data) {
+        return Some(FileKind::Pe);
+    }
+    if data.starts_with(b"\x7fELF") {
+        return Some(FileKind::Elf);
+    }
+    if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+        if contains(data, b"[Content_Types].xml") {
+            return Some(FileKind::Ooxml); // ZIP内にOOXMLのパーツ定義がある
+        }
+        return Some(FileKind::Zip);
+    }
+    if data.starts_with(OLE2) {
+        return Some(FileKind::Ole2);
+    }
+    if contains(&data[..data.len().min(1024)], b"%PDF-") {
+        return Some(FileKind::Pdf); // 先頭1KB以内（前置きゴミを許容するPDFリーダーに合わせる）
+    }
+    if data.starts_with(b"Rar!\x1a\x07") {
+        return Some(FileKind::Rar); // RAR4/RAR5共通部分
+    }
+    if data.starts_with(b"7z\xbc\xaf\x27\x1c") {
+        return Some(FileKind::SevenZip);
+    }
+    if data.starts_with(b"\x1f\x8b\x08") {
+        return Some(FileKind::Gzip); // deflate圧縮のgzip
+    }
+    if data.get(257..262) == Some(b"ustar") {
+        return Some(FileKind::Tar); // POSIX/GNU tarヘッダ
+    }
+    if [0x8001, 0x8801, 0x9001]
+        .iter()
+        .any(|&off| data.get(off..off + 5) == Some(b"CD001"))
+    {
+        return Some(FileKind::Iso); // ボリューム記述子
+    }
+    if data.starts_with(LNK) {
+        return Some(FileKind::Lnk);
+    }
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(FileKind::Png);
+    }
+    if data.starts_with(b"\xff\xd8\xff") {
+        return Some(FileKind::Jpeg);
+    }
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some(FileKind::Gif);
+    }
+    if data.starts_with(b"II*\x00") || data.starts_with(b"MM\x00*") {
+        return Some(FileKind::Tiff);
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        return Some(FileKind::Webp);
+    }
+    if data.starts_with(b"\x00\x00\x01\x00") {
+        return Some(FileKind::Ico);
+    }
+    if data.starts_with(b"BM") && data.get(14..16).is_some() {
+        return Some(FileKind::Bmp);
+    }
+    detect_text(data)
+}
+
+/// MZヘッダのe_lfanew（0x3C、リトルエンディアン）が指す位置にPEシグネチャがあるか
+fn is_pe(data: &[u8]) -> bool {
+    if !data.starts_with(b"MZ") {
+        return false;
+    }
+    let Some(&[a, b, c, d]) = data.get(0x3C..0x40) else {
+        return false; // DOSヘッダが途中で切れている
+    };
+    let offset = u32::from_le_bytes([a, b, c, d]) as usize; // PEヘッダ位置
+    offset
+        .checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .is_some_and(|sig| sig == b"PE\0\0")
+}
+
+/// テキスト形式（HTML・スクリプト）の判定
+fn detect_text(data: &[u8]) -> Option<FileKind> {
+    let head = &data[..data.len().min(512)]; // 先頭512バイトのみ見る
+    let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head); // UTF-8 BOM除去
+    let start = head
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(head.len());
+    let head = head[start..].to_ascii_lowercase(); // 大文字小文字を区別しない
+    if contains(&head, b"<hta:application") {
+        return Some(FileKind::Script); // HTA（HTML形式だがmshtaで実行される）
+    }
+    const HTML: [&[u8]; 6] = [
+        b"<!doctype html",
+        b"<html",
+        b"<head",
+        b"<body",
+        b"<script",
+        b"<iframe",
+    ];
+    if HTML.iter().any(|p| head.starts_with(p)) {
+        return Some(FileKind::Html);
+    }
+    const SCRIPT: [&[u8]; 3] = [b"#!", b"@echo off", b"<?php"];
+    if SCRIPT.iter().any(|p| head.starts_with(p)) {
+        return Some(FileKind::Script);
+    }
+    // 先頭記述に決まりのないWSF・JScript・VBScript・PowerShellは特徴的な記述で判定
+    const SCRIPT_MARKERS: [&[u8]; 12] = [
+        b"<job>",                // WSF
+        b"<job ",                // WSF（id属性付き）
+        b"<package>",            // WSF（複数ジョブ）
+        b"wscript.",             // JScript/VBScript（Windows Script Host）
+        b"activexobject",        // JScript
+        b"string.fromcharcode",  // JScript（難読化）
+        b"createobject(",        // VBScript
+        b"on error resume next", // VBScript
+        b"invoke-expression",    // PowerShell
+        b"frombase64string",     // PowerShell
+        b"new-object ",          // PowerShell
+        b"iex(",                 // PowerShell（Invoke-Expressionの別名）
+    ];
+    if SCRIPT_MARKERS.iter().any(|m| contains(&head, m)) {
+        return Some(FileKind::Script);
+    }
+    None
+}
+
+/// 宣言されたContent-Type・拡張子と実際の種別の不一致を検出
+///
+/// # 引数
+/// - `detected`: detectの判定結果（Noneなら不一致なし）
+/// - `content_type`: 宣言されたContent-Type（小文字「type/subtype」）
+/// - `filename`: 宣言されたファイル名（拡張子が無ければ拡張子の比較はしない）
+///
+/// # 説明
+/// - application/octet-stream等の汎用型も「実際の種別を隠している」として不一致に含める
+pub fn find_mismatches(
+    detected: Option<FileKind>,
+    content_type: &str,
+    filename: Option<&str>,
+) -> Vec<TypeMismatch> {
+    let Some(kind) = detected else {
+        return Vec::new(); // 種別不明なら比較しない
+    };
+    let mut mismatches = Vec::new();
+    if !kind.accepts_content_type(content_type) {
+        mismatches.push(TypeMismatch {
+            field: MismatchField::ContentType,
+            declared: content_type.to_string(),
+            detected: kind,
+        });
+    }
+    if let Some(ext) = filename.and_then(extension_of) {
+        if !kind.extensions().contains(&ext.as_str()) {
+            mismatches.push(TypeMismatch {
+                field: MismatchField::Extension,
+                declared: ext,
+                detected: kind,
+            });
+        }
+    }
+    mismatches
+}
+
+/// ファイル名の拡張子を小文字で取得（末尾の空白・ドットは無視、無ければNone）
+fn extension_of(filename: &str) -> Option<String> {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or(""); // ファイル名部分
+    let base = base.trim_end_matches([' ', '.']); // Windowsで無視される末尾文字
+    let (stem, ext) = base.rsplit_once('.')?;
+    if stem.is_empty() || ext.is_empty() {
+        return None; // 「.bashrc」等は拡張子なし扱い
+    }
+    Some(ext.to_ascii_lowercase())
+}
+
+/// バイト列内の部分一致検索
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
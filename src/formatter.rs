@@ -0,0 +1,496 @@
+// =========================
+// formatter.rs
+// MilterDecoder 解析結果のログ出力（整形）モジュール
+//
+// 【このファイルで使う主なクレート】
+// - crate::parse: 型付きの解析結果（ParsedMail）
+// - crate::printdaytimeln!: JSTタイムスタンプ付きログ出力マクロ
+// - std: 文字列操作（バイト列の可視化）
+//
+// 【役割】
+// - Receivedヘッダの中継経路（ホップごとのfrom/by・プロトコル・TLS・遅延・フラグ）の出力
+// - DKIM署名の検証結果（署名ごとの結果・署名ドメイン・セレクタ・理由）の出力
+// - SPFの評価結果（結果・評価した送信者・接続元IP・一致した機構・DNS問い合わせ回数）の出力
+// - エンベロープ（MAIL FROM/RCPT TO）・マクロ値・ヘッダ数の出力
+// - 再構築したメール全体の生データ出力（NULバイトは<NUL>に可視化）
+// - parse_mailの解析結果（From/To/Subject/Content-Type/本文/非テキストパート）を従来のログ形式で出力
+// - MIMEツリー（深さ・boundary）と添付メール（message/rfc822）のヘッダ・添付の出力
+// - 添付ファイルのハッシュ値・保存先の出力
+// - 添付ファイルの実際の種別（マジックバイト）と宣言との不一致の出力
+// - アーカイブ添付のメンバー一覧（入れ子アーカイブは字下げ）・暗号化・zip bombの出力
+// - Office文書添付の指標（マクロ・外部テンプレート・OLEオブジェクト・DDE・XLM）の出力
+// - PDF添付のページ数・危険なキーワードの出現数・URIアクションの出力
+// - 本文・添付から抽出したURL（抽出元・正規化後・展開したリダイレクタ）の出力
+// - フィッシング判定の指標（種別・スコア・実際の値と装っている値）の出力
+// =========================
+
+use crate::archive::ArchiveInfo; // アーカイブ添付の解析結果
+use crate::dkim::DkimResult; // DKIM署名の検証結果
+use crate::envelope::Envelope; // エンベロープ情報（MAIL FROM/RCPT TO）
+use crate::header::HeaderList; // 受信順ヘッダリスト
+use crate::parse::{MailAddress, MimePart, ParsedMail}; // 型付きの解析結果
+use crate::pdf::PdfInfo; // PDF添付の解析結果
+use crate::received::ReceivedHop; // Receivedヘッダの中継経路
+use crate::spf::SpfResult; // SPFの評価結果
+
+/// Receivedヘッダの中継経路を1ホップ1行で出力（ヘッダ順、最新のホップが先頭）
+///
+/// # 引数
+/// - `hops`: received::parse_chainの解析結果
+///
+/// # 説明
+/// - 記載の無い項目は省略し、遅延は1つ前のホップからの秒数、フラグは[名前]で末尾に付ける
+pub fn log_received(hops: &[ReceivedHop]) {
+    crate::printdaytimeln!("[received] 中継経路数: {}", hops.len());
+    for hop in hops {
+        let mut line = format!("[received] hop({}):", hop.index);
+        let from = [&hop.from_host, &hop.from_rdns, &hop.from_ip]
+            .map(|v| v.as_deref().unwrap_or("-"))
+            .join(" ");
+        line.push_str(&format!(" from={}", from)); // HELO名・逆引き名・IP
+        let by = [&hop.by_host, &hop.by_ip]
+            .map(|v| v.as_deref().unwrap_or("-"))
+            .join(" ");
+        line.push_str(&format!(" by={}", by));
+        for (name, value) in [
+            ("with", &hop.protocol),
+            ("id", &hop.id),
+            ("for", &hop.for_address),
+            ("date", &hop.timestamp),
+        ] {
+            if let Some(value) = value {
+                line.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        if let Some(delay) = hop.delay {
+            line.push_str(&format!(" delay={}s", delay));
+        }
+        if let Some(tls) = &hop.tls {
+            line.push_str(&format!(" tls=({})", tls));
+        }
+        for flag in &hop.flags {
+            line.push_str(&format!(" [{}]", flag.name()));
+        }
+        crate::printdaytimeln!("{}", line);
+    }
+}
+
+/// DKIM署名の検証結果をログ出力する関数
+///
+/// # 引数
+/// - `results`: verify_messageの検証結果（DKIM-Signatureヘッダ順）
+///
+/// # 説明
+/// - 署名ごとに結果・d=・s=・a=・c=・h=・l=を出力し、pass以外は理由を括弧で付ける
+pub fn log_dkim(results: &[DkimResult]) {
+    crate::printdaytimeln!("[dkim] 署名数: {}", results.len());
+    for r in results {
+        let mut line = format!("[dkim] 署名({}): {}", r.index, r.result.name());
+        for (name, value) in [
+            ("d", &r.domain),
+            ("s", &r.selector),
+            ("a", &r.algorithm),
+            ("c", &r.canonicalization),
+        ] {
+            if let Some(value) = value {
+                line.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        if !r.signed_headers.is_empty() {
+            line.push_str(&format!(" h={}", r.signed_headers.join(":")));
+        }
+        if let Some(len) = r.body_length {
+            line.push_str(&format!(" l={}", len));
+        }
+        if let Some(reason) = &r.reason {
+            line.push_str(&format!(" ({})", reason));
+        }
+        crate::printdaytimeln!("{}", line);
+    }
+}
+
+/// SPFの評価結果をログ出力する関数
+///
+/// # 引数
+/// - `result`: check_spfの評価結果
+///
+/// # 説明
+/// - 結果・識別子（mailfrom/helo）・送信者・接続元IP・一致した機構・DNS問い合わせ回数を出力
+/// - fail時の説明文、none/temperror/permerrorの理由を括弧で付ける
+pub fn log_spf(result: &SpfResult) {
+    let mut line = format!(
+        "[spf] 結果: {} {}={} ip={}",
+        result.result.name(),
+        result.identity,
+        result.sender,
+        result.client_ip
+    );
+    if let Some(mechanism) = &result.mechanism {
+        line.push_str(&format!(" mechanism={}", mechanism));
+    }
+    line.push_str(&format!(
+        " lookups={} void={}",
+        result.dns_lookups, result.void_lookups
+    ));
+    if let Some(explanation) = &result.explanation {
+        line.push_str(&format!(" exp=\"{}\"", explanation));
+    }
+    if let Some(reason) = &result.reason {
+        line.push_str(&format!(" ({})", reason));
+    }
+    crate::printdaytimeln!("{}", line);
+}
+
+/// BODYEOB時の解析結果をログ出力する関数
+///
+/// # 引数
+/// - `envelope`: Milterで受信したエンベロープ情報（MAIL FROM/RCPT TO）
+/// - `macros`: 受信したマクロ値（「名前=値」形式）
+/// - `header_fields`: Milterで受信したヘッダ情報（受信順）
+/// - `mail_bytes`: 再構築したメール全体の生データ
+/// - `parsed`: parse_mailの解析結果（パース失敗時はNone）
+pub fn log_mail(
+    envelope: &Envelope,
+    macros: &[String],
+    header_fields: &HeaderList,
+    mail_bytes: &[u8],
+    parsed: Option<&ParsedMail>,
+) {
+    // エンベロープ情報を出力（ヘッダFrom/Toとは別に、SMTPレベルの送受信者を記録）
+    match &envelope.sender {
+        Some(sender) => crate::printdaytimeln!("[envelope] mail from: {}", sender.display()),
+        None => crate::printdaytimeln!("[envelope] mail from: (なし)"),
+    }
+    // 受信者は全件を受信順に出力
+    for (i, rcpt) in envelope.recipients.iter().enumerate() {
+        crate::printdaytimeln!("[envelope] rcpt to({}): {}", i + 1, rcpt.display());
+    }
+    crate::printdaytimeln!("[envelope] 受信者数: {}", envelope.recipients.len()); // 受信者数出力
+
+    // マクロ値を出力（認証ユーザー・TLS情報・キューID等）
+    for m in macros {
+        crate::printdaytimeln!("[macros] {}", m);
+    }
+
+    crate::printdaytimeln!(
+        "[headers] ヘッダ数: {} (Received: {})",
+        header_fields.len(),
+        header_fields.get_all("Received").count()
+    ); // ヘッダ数と中継経路数を出力
+
+    // NULバイト（\0）を可視化文字に置換してデバッグ出力用に整形（出力用のみ文字列化）
+    let mail_string_visible = String::from_utf8_lossy(mail_bytes).replace('\0', "<NUL>");
+    crate::printdaytimeln!("--- BODYEOB時のメール全体 ---");
+    crate::printdaytimeln!("{}", mail_string_visible); // 生メールデータの可視化出力
+
+    let Some(mail) = parsed else {
+        // パース失敗時（メール構造が不正等）
+        crate::printdaytimeln!("[mail-parser] parse error"); // パース失敗ログ
+        return;
+    };
+    crate::printdaytimeln!("[mail-parser] from: {}", join_addresses(&mail.from)); // From出力
+    crate::printdaytimeln!("[mail-parser] to: {}", join_addresses(&mail.to)); // To出力
+    crate::printdaytimeln!(
+        "[mail-parser] subject: {}",
+        mail.subject.as_deref().unwrap_or("(なし)")
+    ); // 件名出力
+    if let Some(ct) = &mail.content_type {
+        crate::printdaytimeln!("[mail-parser] content-type: {:?}", ct); // MIMEタイプ出力
+    }
+    if let Some(enc) = &mail.encoding {
+        crate::printdaytimeln!("[mail-parser] encoding: {:?}", enc); // エンコーディング出力
+    }
+    if mail.multipart {
+        crate::printdaytimeln!("[mail-parser] このメールはマルチパートです"); // 複数パート
+    } else {
+        crate::printdaytimeln!("[mail-parser] このメールはシングルパートです"); // 単一パート
+    }
+    let counts = mail.counts(); // テキスト/非テキストのパート数
+    crate::printdaytimeln!("[mail-parser] テキストパート数: {}", counts.text);
+    crate::printdaytimeln!("[mail-parser] 非テキストパート数: {}", counts.non_text);
+
+    // 本文出力処理：テキスト/HTMLパートごとに内容を出力（デコード済み）
+    for (i, text) in mail.text_parts.iter().enumerate() {
+        crate::printdaytimeln!("[mail-parser] TEXT本文({}): {}", i + 1, text.content);
+    }
+    for (i, html) in mail.html_parts.iter().enumerate() {
+        crate::printdaytimeln!("[mail-parser] HTML本文({}): {}", i + 1, html.content);
+    }
+    // 添付ファイル等の非テキストパート情報を1行ずつ出力
+    log_non_text_parts("[mail-parser]", mail);
+    log_urls("[url]", mail);
+    log_phishing("[phishing]", mail);
+    // MIMEツリー・添付メールを出力
+    log_mime_tree("[mime]", &mail.mime_parts);
+    log_nested_messages(mail);
+}
+
+/// 非テキストパート情報（属性・ハッシュ値・保存先）を出力
+fn log_non_text_parts(tag: &str, mail: &ParsedMail) {
+    for (i, part) in mail.non_text_parts.iter().enumerate() {
+        crate::printdaytimeln!(
+            "{} 非テキストパート({}): content_type={:?}, encoding={}, filename={}, size={} bytes",
+            tag,
+            i + 1,
+            part.content_type,
+            part.encoding,
+            part.filename.as_deref().unwrap_or("(ファイル名なし)"),
+            part.size
+        );
+        crate::printdaytimeln!(
+            "{} 添付ハッシュ({}): sha256={}, sha1={}, md5={}",
+            tag,
+            i + 1,
+            part.hashes.sha256,
+            part.hashes.sha1,
+            part.hashes.md5
+        ); // 脅威インテリジェンス照合用
+        if let Some(kind) = part.detected_type {
+            crate::printdaytimeln!("{} ファイル種別({}): {}", tag, i + 1, kind.name());
+        }
+        for m in &part.type_mismatches {
+            crate::printdaytimeln!(
+                "{} 種別不一致({}): {}={} 実際={}",
+                tag,
+                i + 1,
+                m.field.name(),
+                m.declared,
+                m.detected.name()
+            ); // 偽装の疑い（例: invoice.pdf の中身がPE）
+        }
+        if let Some(archive) = &part.archive {
+            log_archive(&format!("{} アーカイブ({}):", tag, i + 1), archive, 0);
+        }
+        if let Some(office) = &part.office {
+            for ind in &office.indicators {
+                let mut line = format!(
+                    "{} Office指標({}): {} {}",
+                    tag,
+                    i + 1,
+                    ind.kind.name(),
+                    ind.location
+                );
+                if let Some(d) = &ind.detail {
+                    line.push_str(&format!(" ({})", d)); // 参照先URL・フィールドコード等
+                }
+                crate::printdaytimeln!("{}", line);
+            }
+            if let Some(e) = &office.error {
+                crate::printdaytimeln!("{} Office読み取りエラー({}): {}", tag, i + 1, e);
+            }
+        }
+        if let Some(pdf) = &part.pdf {
+            log_pdf(&format!("{} PDF({}):", tag, i + 1), pdf);
+        }
+        if let Some(path) = &part.saved_as {
+            crate::printdaytimeln!("{} 添付保存({}): {}", tag, i + 1, path); // Attachment_spool指定時
+        }
+    }
+}
+
+/// アーカイブの概要とメンバー一覧を出力（入れ子アーカイブは字下げして再帰）
+fn log_archive(tag: &str, archive: &ArchiveInfo, depth: usize) {
+    let indent = "  ".repeat(depth); // 入れ子ごとに字下げ
+    let mut line = format!(
+        "{} {}format={} entries={} total_size={}",
+        tag,
+        indent,
+        archive.format.name(),
+        archive.entries.len(),
+        archive.total_size
+    );
+    if let Some(r) = archive.ratio {
+        line.push_str(&format!(" ratio={:.2}", r));
+    }
+    if archive.encrypted {
+        line.push_str(" [暗号化]"); // パスワード保護
+    }
+    if archive.headers_encrypted {
+        line.push_str(" [一覧暗号化]");
+    }
+    if archive.zip_bomb {
+        line.push_str(" [zip bomb]"); // Archive_bomb_ratio超過
+    }
+    if archive.entries_truncated {
+        line.push_str(" [一覧打ち切り]");
+    }
+    if let Some(e) = &archive.error {
+        line.push_str(&format!(" [読み取りエラー: {}]", e));
+    }
+    crate::printdaytimeln!("{}", line);
+    for entry in &archive.entries {
+        let mut line = format!("{} {}  - {} size={}", tag, indent, entry.name, entry.size);
+        if let Some(c) = entry.compressed_size {
+            line.push_str(&format!(" compressed={}", c));
+        }
+        if let Some(r) = entry.ratio {
+            line.push_str(&format!(" ratio={:.2}", r));
+        }
+        if let Some(kind) = entry.detected_type {
+            line.push_str(&format!(" type={}", kind.name())); // 展開できたメンバーの実際の種別
+        }
+        if entry.directory {
+            line.push_str(" [ディレクトリ]");
+        }
+        if entry.encrypted {
+            line.push_str(" [暗号化]");
+        }
+        if entry.depth_limited {
+            line.push_str(" (深さ制限のため未解析)"); // Archive_depth超過
+        }
+        crate::printdaytimeln!("{}", line);
+        if let Some(inner) = &entry.archive {
+            log_archive(tag, inner, depth + 2); // 入れ子アーカイブ
+        }
+    }
+}
+
+/// 抽出したURLを1件1行で出力（正規化で変わった場合・リダイレクタの転送先を取り出した場合は併記）
+fn log_urls(tag: &str, mail: &ParsedMail) {
+    for (i, url) in mail.urls.iter().enumerate() {
+        let mut line = format!(
+            "{} URL({}): source={} part={} {}",
+            tag,
+            i + 1,
+            url.source.name(),
+            url.part,
+            url.url
+        );
+        if url.normalized != url.url {
+            line.push_str(&format!(" → {}", url.normalized));
+        }
+        for inner in &url.unwrapped {
+            line.push_str(&format!(" [転送先 {}]", inner.url)); // リダイレクタから取り出した転送先
+        }
+        crate::printdaytimeln!("{}", line);
+    }
+}
+
+/// フィッシング判定の指標を1件1行で出力（種別・スコア・場所・実際の値・装っている値）
+fn log_phishing(tag: &str, mail: &ParsedMail) {
+    for (i, ind) in mail.phishing.iter().enumerate() {
+        let mut line = format!(
+            "{} フィッシング指標({}): {} score={} {} actual={} claimed={}",
+            tag,
+            i + 1,
+            ind.kind.name(),
+            ind.score,
+            ind.location,
+            ind.actual,
+            ind.claimed
+        );
+        if let Some(detail) = &ind.detail {
+            line.push_str(&format!(" ({})", detail));
+        }
+        crate::printdaytimeln!("{}", line);
+    }
+}
+
+/// PDFの概要（バージョン・ページ数・キーワード）とURIアクションを出力
+fn log_pdf(tag: &str, pdf: &PdfInfo) {
+    let mut line = format!(
+        "{} version={} pages={}",
+        tag,
+        pdf.version.as_deref().unwrap_or("?"),
+        pdf.pages
+    );
+    let k = &pdf.keywords;
+    for (name, count) in [
+        ("JavaScript", k.javascript),
+        ("JS", k.js),
+        ("OpenAction", k.open_action),
+        ("AA", k.aa),
+        ("Launch", k.launch),
+        ("EmbeddedFile", k.embedded_file),
+        ("URI", k.uri),
+        ("AcroForm", k.acroform),
+        ("XFA", k.xfa),
+    ] {
+        if count > 0 {
+            line.push_str(&format!(" /{}={}", name, count)); // 出現したキーワードのみ
+        }
+    }
+    if pdf.has_javascript() && pdf.has_auto_action() {
+        line.push_str(" [JavaScript自動実行]"); // 開いただけでスクリプトが動く可能性
+    }
+    if k.launch > 0 {
+        line.push_str(" [外部プログラム起動]");
+    }
+    if pdf.encrypted {
+        line.push_str(" [暗号化]");
+    }
+    if pdf.inflate_errors > 0 {
+        line.push_str(&format!(" [展開エラー: {}]", pdf.inflate_errors));
+    }
+    crate::printdaytimeln!("{}", line);
+    for uri in &pdf.uris {
+        crate::printdaytimeln!("{}   - URI {}", tag, uri);
+    }
+}
+
+/// MIMEツリーを深さに応じて字下げして出力
+fn log_mime_tree(tag: &str, parts: &[MimePart]) {
+    for p in parts {
+        let mut line = format!(
+            "{} {}part={} {}",
+            tag,
+            "  ".repeat(p.depth), // 深さごとに字下げ
+            p.index,
+            p.content_type
+        );
+        if let Some(b) = &p.boundary {
+            line.push_str(&format!(" boundary={:?}", b));
+        }
+        if let Some(f) = &p.filename {
+            line.push_str(&format!(" filename={}", f));
+        }
+        if !p.content_type.starts_with("multipart/") {
+            line.push_str(&format!(" size={}", p.size));
+        }
+        if p.depth_limited {
+            line.push_str(" (深さ制限のため未解析)"); // Nested_message_depth超過
+        }
+        crate::printdaytimeln!("{}", line);
+    }
+}
+
+/// 添付メール（message/rfc822）のヘッダ・From/To/Subject・MIMEツリー・添付を再帰的に出力
+fn log_nested_messages(mail: &ParsedMail) {
+    for (part, nested) in mail.nested_messages() {
+        let tag = format!("[nested({}) part={}]", nested.level, part.index); // 入れ子レベルと添付位置
+        for h in &nested.headers {
+            crate::printdaytimeln!("{} header {}: {}", tag, h.name, h.value);
+        }
+        crate::printdaytimeln!("{} from: {}", tag, join_addresses(&nested.from));
+        crate::printdaytimeln!("{} to: {}", tag, join_addresses(&nested.to));
+        crate::printdaytimeln!(
+            "{} subject: {}",
+            tag,
+            nested.subject.as_deref().unwrap_or("(なし)")
+        );
+        let counts = nested.counts();
+        crate::printdaytimeln!(
+            "{} テキストパート数: {}, 非テキストパート数: {}",
+            tag,
+            counts.text,
+            counts.non_text
+        );
+        log_non_text_parts(&tag, nested);
+        log_urls(&tag, nested);
+        log_phishing(&tag, nested);
+        log_mime_tree(&tag, &nested.mime_parts);
+        log_nested_messages(nested); // さらに内側の添付メール
+    }
+}
+
+/// アドレス一覧をカンマ区切りで整形（空なら「(なし)」）
+fn join_addresses(addrs: &[MailAddress]) -> String {
+    if addrs.is_empty() {
+        return "(なし)".to_string(); // ヘッダ無し時のデフォルト
+    }
+    addrs
+        .iter()
+        .map(|a| a.display())
+        .collect::<Vec<_>>()
+        .join(", ") // 複数アドレスをカンマ区切り
+}
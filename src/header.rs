@@ -0,0 +1,141 @@
+// =========================
+// header.rs
+// MilterDecoder ヘッダ管理モジュール
+//
+// 【このファイルで使う主なクレート】
+// - std: 標準ライブラリ（ベクタ、バイト列操作）
+//
+// 【役割】
+// - SMFIC_HEADERで受信したヘッダを受信順のまま保持（名前の大文字小文字・値の折り返しも保持）
+// - ヘッダ名（大文字小文字無視）による検索
+// - 自サーバのauthserv-idを名乗るAuthentication-Resultsヘッダの検出（偽装ヘッダの削除用）
+// - MTAが見たヘッダ部の再構築（mail-parserへ渡す生データ、DKIM検証等で使用）
+// =========================
+
+/// ヘッダ1行分（名前 + 生の値）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderField {
+    pub name: String,   // ヘッダ名（受信時の大文字小文字のまま）
+    pub value: Vec<u8>, // ヘッダ値（折り返し空白を含む生バイト列）
+}
+
+impl HeaderField {
+    /// 「名前: 値」形式の1行文字列（ポリシー判定・ログ用、不正なUTF-8は置換文字に変換）
+    /// - SMFIP_HDR_LEADSPC合意時に値へ残る先頭空白は除いて整形する
+    pub fn to_line(&self) -> String {
+        let value = String::from_utf8_lossy(&self.value);
+        format!("{}: {}", self.name, value.trim_start_matches([' ', '\t']))
+    }
+}
+
+/// 受信順を保持したヘッダリスト
+#[derive(Debug, Clone, Default)]
+pub struct HeaderList {
+    fields: Vec<HeaderField>, // 受信順のヘッダ
+    leading_space: bool,      // 値が区切りの先頭空白を含むか（SMFIP_HDR_LEADSPC合意時）
+}
+
+impl HeaderList {
+    /// ヘッダを末尾に追加（受信順を保持）
+    pub fn push(&mut self, name: String, value: Vec<u8>) {
+        self.fields.push(HeaderField { name, value });
+    }
+
+    /// ヘッダ値が区切りの先頭空白を含むかを設定（OPTNEG合意時、clearでは変わらない）
+    pub fn set_leading_space(&mut self, leading_space: bool) {
+        self.leading_space = leading_space;
+    }
+
+    /// 受信順にヘッダを走査
+    pub fn iter(&self) -> std::slice::Iter<'_, HeaderField> {
+        self.fields.iter()
+    }
+
+    /// 指定名（大文字小文字無視）のヘッダを受信順に全て取得
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a HeaderField> + 'a {
+        self.fields
+            .iter()
+            .filter(move |f| f.name.eq_ignore_ascii_case(name))
+    }
+
+    /// 指定authserv-id（大文字小文字無視）のAuthentication-Resultsヘッダの出現番号を取得
+    ///
+    /// # 引数
+    /// - `authserv_id`: 自サーバのauthserv-id
+    ///
+    /// # 説明
+    /// - 出現番号は同名ヘッダ中の1始まり（SMFIR_CHGHEADERの指定番号）
+    /// - 値の先頭「;」までからコメント「(...)」を除き、最初の語をauthserv-idとする（後続の版番号は無視）
+    /// - RFC 8601 §5により、外部から届いた同じauthserv-idのヘッダは削除対象となる
+    pub fn authentication_results_of(&self, authserv_id: &str) -> Vec<u32> {
+        self.get_all("Authentication-Results")
+            .zip(1u32..)
+            .filter(|(field, _)| {
+                let value = String::from_utf8_lossy(&field.value);
+                let mut depth = 0usize; // コメントの入れ子の深さ
+                let mut id = String::new(); // コメントを除いたauthserv-id部分
+                for c in value.chars().take_while(|&c| c != ';') {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth = depth.saturating_sub(1),
+                        _ if depth == 0 => id.push(c),
+                        _ => {}
+                    }
+                }
+                id.split_whitespace()
+                    .next()
+                    .is_some_and(|id| id.eq_ignore_ascii_case(authserv_id))
+            })
+            .map(|(_, index)| index)
+            .collect()
+    }
+
+    /// ヘッダ数
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// 全ヘッダを破棄（次のトランザクション用）
+    pub fn clear(&mut self) {
+        self.fields.clear();
+    }
+
+    /// MTAが見たヘッダ1件分を再構築（「名前: 値」、行終端のCRLFは含まない）
+    ///
+    /// # 説明
+    /// - MTAは区切りの空白1つを除いてMilterへ渡すため補う
+    /// - SMFIP_HDR_LEADSPC合意時は値に元の空白が含まれるため「名前:値」で出力
+    /// - 折り返し行の改行コードはCRLFに統一する
+    pub fn raw_field(&self, field: &HeaderField) -> Vec<u8> {
+        let mut out = Vec::new(); // 再構築バッファ
+        out.extend_from_slice(field.name.as_bytes()); // ヘッダ名
+        if self.leading_space {
+            out.push(b':'); // 区切り（空白は値側に含まれる）
+        } else {
+            out.extend_from_slice(b": "); // 区切り
+        }
+        let mut prev = 0u8; // 直前のバイト（CRLF判定用）
+        for &b in &field.value {
+            if b == b'\n' && prev != b'\r' {
+                out.push(b'\r'); // 折り返しの単独LFをCRLF化
+            }
+            out.push(b);
+            prev = b;
+        }
+        out
+    }
+
+    /// MTAが見たヘッダ部を再構築（受信順・名前の大文字小文字・折り返しを保持）
+    ///
+    /// # 説明
+    /// - 各ヘッダをraw_fieldの形式 + CRLFで出力
+    /// - ヘッダ部とボディ部の区切り空行は含まない
+    pub fn to_raw_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new(); // 再構築バッファ
+        for f in &self.fields {
+            out.extend_from_slice(&self.raw_field(f)); // 「名前: 値」
+            out.extend_from_slice(b"\r\n"); // ヘッダ行終端
+        }
+        out
+    }
+}
@@ -7,7 +7,7 @@
 // - lazy_static: グローバル変数初期化（設定の静的共有）
 //
 // 【役割】
-// - サーバー設定（Listenアドレス、クライアントタイムアウト等）の読み込み・保持
+// - サーバー設定（Listenアドレス、クライアントタイムアウト、判定ルール、要求マクロ一覧等）の読み込み・保持
 // - 設定ファイル(MilterDecoder.conf)からConfig構造体を生成
 // - グローバル設定CONFIGとして全体で参照可能
 // =========================
@@ -15,24 +15,94 @@
 use lazy_static::lazy_static;
 use std::sync::RwLock; // RwLock: スレッド安全な設定共有 // lazy_static: グローバル変数初期化
 
+use crate::archive::ArchiveLimits; // アーカイブ添付の解析制限
+use crate::macros::MacroList; // フェーズごとの要求マクロ一覧
+use crate::output::JsonTarget; // 構造化出力の出力先
+use crate::parse::ParseOptions; // parse_mailの解析設定
+use crate::policy::PolicyRule; // 受理/拒否判定ルール
+use crate::resolver::DnsSource; // DNS問い合わせ先
+use std::path::PathBuf; // 添付保存先ディレクトリ
+
 /// サーバー設定情報構造体（Listen/Client_timeoutなど）
 /// - address: サーバー待受アドレス（例: 0.0.0.0:8898）
 /// - client_timeout: クライアント無通信タイムアウト秒
+/// - policy_rules: 受理/拒否判定ルール（記述順に評価）
+/// - macro_lists: OPTNEG時にSETSYMLISTで要求するフェーズごとのマクロ一覧
+/// - json_output: 構造化（JSON Lines）出力先（未指定なら出力しない）
+/// - json_body_limit: JSON出力時の本文最大バイト数（0は無制限）
+/// - nested_message_depth: 添付メール（message/rfc822）を再帰解析する最大レベル
+/// - attachment_spool: 添付ファイルの保存先ディレクトリ（未指定なら保存しない）
+/// - archive_depth: アーカイブ添付の一覧を取る入れ子レベルの上限（0なら解析しない）
+/// - archive_bomb_ratio: zip bombとみなす圧縮率
+/// - protected_domains: 類似ドメイン判定の保護対象ドメイン
+/// - dkim_verify: BODYEOB時にDKIM署名を検証するか
+/// - spf_verify: BODYEOB時にSPFを評価するか
+/// - dns_resolver: DKIM公開鍵・SPFレコード等の問い合わせ先（システムのDNS または ゾーンファイル）
+/// - authserv_id: Authentication-Resultsヘッダのauthserv-id（未指定なら付与しない）
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub address: String,     // サーバー待受アドレス（Listen）
-    pub client_timeout: u64, // クライアントタイムアウト秒（Client_timeout）
+    pub address: String,                   // サーバー待受アドレス（Listen）
+    pub client_timeout: u64,               // クライアントタイムアウト秒（Client_timeout）
+    pub policy_rules: Vec<PolicyRule>,     // 受理/拒否判定ルール（Policy_rule、複数可）
+    pub macro_lists: Vec<MacroList>,       // 要求マクロ一覧（Macro_list、複数可）
+    pub json_output: Option<JsonTarget>,   // JSON Lines出力先（Json_output）
+    pub json_body_limit: usize,            // JSON出力の本文最大バイト数（Json_body_limit）
+    pub nested_message_depth: usize,       // 添付メールの再帰解析レベル上限（Nested_message_depth）
+    pub attachment_spool: Option<PathBuf>, // 添付ファイル保存先（Attachment_spool）
+    pub archive_depth: usize,              // アーカイブ添付の入れ子レベル上限（Archive_depth）
+    pub archive_bomb_ratio: u64,           // zip bomb判定の圧縮率（Archive_bomb_ratio）
+    pub protected_domains: Vec<String>, // 類似ドメイン判定の保護対象ドメイン（Protected_domains、複数可）
+    pub dkim_verify: bool,              // DKIM署名の検証（Dkim_verify）
+    pub spf_verify: bool,               // SPFの評価（Spf_verify）
+    pub dns_resolver: DnsSource,        // DNS問い合わせ先（Dns_resolver）
+    pub authserv_id: Option<String>, // Authentication-Resultsのauthserv-id（Authentication_results）
+}
+
+impl Config {
+    /// parse_mailに渡す解析設定を生成
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            nested_message_depth: self.nested_message_depth,
+            archive: ArchiveLimits {
+                max_depth: self.archive_depth,
+                bomb_ratio: self.archive_bomb_ratio,
+            },
+            protected_domains: self.protected_domains.clone(),
+        }
+    }
 }
 
 /// 設定ファイル(MilterDecoder.conf)からConfigを生成
 ///
 /// # 説明
 /// - Listen <アドレス/ポート>、Client_timeout <秒> をパースしてConfig構造体に格納
+/// - Policy_rule <フェーズ> <パターン> <アクション> [...] を記述順に格納（書式不正の行は警告して無視）
+/// - Macro_list <フェーズ> <マクロ名> [...] を格納（書式不正の行は警告して無視）
+/// - Json_output <stdout|ファイルパス>、Json_body_limit <バイト数> を格納
+/// - Nested_message_depth <レベル> を格納（未指定時は3、0なら添付メールを解析しない）
+/// - Attachment_spool <ディレクトリ> を格納（指定時のみ添付ファイルを書き出す）
+/// - Archive_depth <レベル>（未指定時は3、0ならアーカイブを解析しない）、Archive_bomb_ratio <圧縮率>（未指定時は100）を格納
+/// - Protected_domains <ドメイン> [...] を小文字化して追加（複数行可、未指定なら類似ドメイン判定をしない）
+/// - Dkim_verify <yes|no>・Spf_verify <yes|no>（未指定時はno）、Dns_resolver <system|ゾーンファイルパス>（未指定時はsystem）を格納
+/// - Authentication_results <authserv-id> を格納（指定時のみSPF・DKIMの結果をヘッダとして付与）
 /// - Listen未指定時は[::]:8898、Client_timeout未指定時は30秒をデフォルト
 pub fn load_config() -> Config {
     let text = std::fs::read_to_string("MilterDecoder.conf").expect("設定ファイル読み込み失敗"); // 設定ファイル全体を文字列で取得
     let mut address = None; // Listenアドレス初期値
     let mut client_timeout = 30u64; // タイムアウト初期値（秒）
+    let mut policy_rules = Vec::new(); // 判定ルール初期値（ルール無し）
+    let mut macro_lists = Vec::new(); // 要求マクロ一覧初期値（要求無し）
+    let mut json_output = None; // JSON出力先初期値（出力しない）
+    let mut json_body_limit = 0usize; // 本文最大バイト数初期値（無制限）
+    let mut nested_message_depth = 3usize; // 添付メール再帰解析レベル初期値
+    let mut attachment_spool = None; // 添付保存先初期値（保存しない）
+    let mut archive_depth = 3usize; // アーカイブ入れ子レベル初期値
+    let mut archive_bomb_ratio = 100u64; // zip bomb判定の圧縮率初期値
+    let mut protected_domains: Vec<String> = Vec::new(); // 保護対象ドメイン初期値（判定しない）
+    let mut dkim_verify = false; // DKIM検証初期値（検証しない）
+    let mut spf_verify = false; // SPF評価初期値（評価しない）
+    let mut dns_resolver = DnsSource::System; // DNS問い合わせ先初期値（システムのDNS）
+    let mut authserv_id = None; // authserv-id初期値（ヘッダを付与しない）
     for line in text.lines() {
         // 設定ファイル各行をループ
         let line = line.trim(); // 前後空白除去
@@ -49,12 +119,103 @@ pub fn load_config() -> Config {
             if let Ok(val) = rest.trim().parse::<u64>() {
                 client_timeout = val; // 数値変換成功時のみ反映
             }
+        // Policy_rule設定（受理/拒否判定ルール）
+        } else if let Some(rest) = line.strip_prefix("Policy_rule ") {
+            match PolicyRule::parse(rest) {
+                Ok(rule) => policy_rules.push(rule), // 解析成功時のみ追加
+                Err(e) => crate::printdaytimeln!("Policy_rule設定不正: {} ({})", rest.trim(), e),
+            }
+        // Macro_list設定（SETSYMLISTで要求するマクロ一覧）
+        } else if let Some(rest) = line.strip_prefix("Macro_list ") {
+            match MacroList::parse(rest) {
+                Ok(list) => macro_lists.push(list), // 解析成功時のみ追加
+                Err(e) => crate::printdaytimeln!("Macro_list設定不正: {} ({})", rest.trim(), e),
+            }
+        // Json_output設定（構造化出力先）
+        } else if let Some(rest) = line.strip_prefix("Json_output ") {
+            match JsonTarget::parse(rest) {
+                Ok(target) => json_output = Some(target), // 解析成功時のみ反映
+                Err(e) => crate::printdaytimeln!("Json_output設定不正: {} ({})", rest.trim(), e),
+            }
+        // Json_body_limit設定（JSON出力の本文最大バイト数）
+        } else if let Some(rest) = line.strip_prefix("Json_body_limit ") {
+            if let Ok(val) = rest.trim().parse::<usize>() {
+                json_body_limit = val; // 数値変換成功時のみ反映
+            }
+        // Nested_message_depth設定（添付メールの再帰解析レベル上限）
+        } else if let Some(rest) = line.strip_prefix("Nested_message_depth ") {
+            if let Ok(val) = rest.trim().parse::<usize>() {
+                nested_message_depth = val; // 数値変換成功時のみ反映
+            }
+        // Attachment_spool設定（添付ファイル保存先ディレクトリ）
+        } else if let Some(rest) = line.strip_prefix("Attachment_spool ") {
+            let dir = rest.trim(); // ディレクトリ部分取得
+            if !dir.is_empty() {
+                attachment_spool = Some(PathBuf::from(dir)); // 空でなければ反映
+            }
+        // Archive_depth設定（アーカイブ添付の入れ子レベル上限）
+        } else if let Some(rest) = line.strip_prefix("Archive_depth ") {
+            if let Ok(val) = rest.trim().parse::<usize>() {
+                archive_depth = val; // 数値変換成功時のみ反映
+            }
+        // Archive_bomb_ratio設定（zip bomb判定の圧縮率）
+        } else if let Some(rest) = line.strip_prefix("Archive_bomb_ratio ") {
+            if let Ok(val) = rest.trim().parse::<u64>() {
+                archive_bomb_ratio = val; // 数値変換成功時のみ反映
+            }
+        // Protected_domains設定（類似ドメイン判定の保護対象、空白区切りで複数可）
+        } else if let Some(rest) = line.strip_prefix("Protected_domains ") {
+            for domain in rest.split_whitespace() {
+                let domain = domain.trim_end_matches('.').to_lowercase();
+                if !domain.is_empty() && !protected_domains.contains(&domain) {
+                    protected_domains.push(domain); // 重複は除く
+                }
+            }
+        // Dkim_verify設定（DKIM署名の検証）
+        } else if let Some(rest) = line.strip_prefix("Dkim_verify ") {
+            match rest.trim().to_lowercase().as_str() {
+                "yes" | "on" | "true" => dkim_verify = true,
+                "no" | "off" | "false" => dkim_verify = false,
+                _ => crate::printdaytimeln!("Dkim_verify設定不正: {}", rest.trim()),
+            }
+        // Spf_verify設定（SPFの評価）
+        } else if let Some(rest) = line.strip_prefix("Spf_verify ") {
+            match rest.trim().to_lowercase().as_str() {
+                "yes" | "on" | "true" => spf_verify = true,
+                "no" | "off" | "false" => spf_verify = false,
+                _ => crate::printdaytimeln!("Spf_verify設定不正: {}", rest.trim()),
+            }
+        // Dns_resolver設定（DKIM公開鍵・SPFレコード等の問い合わせ先）
+        } else if let Some(rest) = line.strip_prefix("Dns_resolver ") {
+            match DnsSource::parse(rest) {
+                Ok(source) => dns_resolver = source, // 解析成功時のみ反映
+                Err(e) => crate::printdaytimeln!("Dns_resolver設定不正: {} ({})", rest.trim(), e),
+            }
+        // Authentication_results設定（付与するヘッダのauthserv-id）
+        } else if let Some(rest) = line.strip_prefix("Authentication_results ") {
+            let id = rest.trim(); // authserv-id部分取得
+            if !id.is_empty() {
+                authserv_id = Some(id.to_string()); // 空でなければ反映
+            }
         }
     }
     let address = address.unwrap_or_else(|| "[::]:8898".to_string()); // Listen未指定時はIPv4/IPv6デュアルスタック8898番ポート
     Config {
-        address,        // サーバー待受アドレス
-        client_timeout, // クライアントタイムアウト秒
+        address,              // サーバー待受アドレス
+        client_timeout,       // クライアントタイムアウト秒
+        policy_rules,         // 受理/拒否判定ルール
+        macro_lists,          // 要求マクロ一覧
+        json_output,          // JSON Lines出力先
+        json_body_limit,      // JSON出力の本文最大バイト数
+        nested_message_depth, // 添付メールの再帰解析レベル上限
+        attachment_spool,     // 添付ファイル保存先
+        archive_depth,        // アーカイブ添付の入れ子レベル上限
+        archive_bomb_ratio,   // zip bomb判定の圧縮率
+        protected_domains,    // 類似ドメイン判定の保護対象ドメイン
+        dkim_verify,          // DKIM署名の検証
+        spf_verify,           // SPFの評価
+        dns_resolver,         // DNS問い合わせ先
+        authserv_id,          // Authentication-Resultsのauthserv-id
     }
 }
 
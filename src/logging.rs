@@ -5,11 +5,103 @@
 // 【このファイルで使う主なクレート】
 // - chrono: 日時操作・整形（Local::now, format）
 // - chrono-tz: タイムゾーン変換（Asia::Tokyo/JST指定）
+// - tokio: タスクローカル変数（task_local!、クライアント処理タスクごとのログ文脈）
+// - std: 連番発行（sync::atomic::AtomicU64）、内部可変性（cell::RefCell）
 //
 // 【役割】
 // - printdaytimeln!: JSTタイムスタンプ付きで標準出力にログを出すマクロ
+// - セッションID（接続ごと）・メッセージID（トランザクションごと）をログ行へ自動付与
 // =========================
 
+use std::cell::RefCell; // タスク内でのログ文脈の更新用
+use std::sync::atomic::{AtomicU64, Ordering}; // セッションID連番
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1); // 次に発行するセッションID
+
+/// クライアント処理タスクごとのログ文脈
+#[derive(Debug, Clone, Default)]
+struct LogContext {
+    session_id: u64,            // セッションID（接続ごとの連番）
+    message_seq: u64,           // セッション内のトランザクション連番
+    message_id: Option<String>, // メッセージID（キューID、無ければ「セッションID.連番」）
+}
+
+tokio::task_local! {
+    static LOG_CONTEXT: RefCell<LogContext>; // タスクローカルなログ文脈
+}
+
+/// 新しいセッションIDを発行（プロセス内で一意の連番）
+pub fn next_session_id() -> u64 {
+    NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 指定セッションIDのログ文脈でFutureを実行（クライアント処理タスクの起点で使用）
+///
+/// # 説明
+/// - 実行中のprintdaytimeln!は全て「[session=ID]」付きで出力される
+/// - メッセージIDはbegin_message/end_messageで設定・解除する
+pub async fn with_session<F: std::future::Future>(session_id: u64, fut: F) -> F::Output {
+    let context = LogContext {
+        session_id,
+        ..LogContext::default()
+    };
+    LOG_CONTEXT.scope(RefCell::new(context), fut).await
+}
+
+/// トランザクション開始（MAIL受信時）: メッセージIDを設定
+///
+/// # 説明
+/// - キューID（マクロi）が分かっていればそれを、無ければ「セッションID.連番」をメッセージIDとする
+/// - ログ文脈外では何もしない
+pub fn begin_message(queue_id: Option<&str>) {
+    let _ = LOG_CONTEXT.try_with(|c| {
+        let mut c = c.borrow_mut();
+        c.message_seq += 1;
+        c.message_id = Some(match queue_id {
+            Some(q) => q.to_string(),
+            None => format!("{}.{}", c.session_id, c.message_seq),
+        });
+    });
+}
+
+/// トランザクション途中でキューIDが判明した場合にメッセージIDを置き換える
+pub fn update_queue_id(queue_id: &str) {
+    let _ = LOG_CONTEXT.try_with(|c| {
+        let mut c = c.borrow_mut();
+        if c.message_id.is_some() {
+            c.message_id = Some(queue_id.to_string()); // トランザクション中のみ
+        }
+    });
+}
+
+/// トランザクション終了（BODYEOB応答後・ABORT・QUIT_NC時）: メッセージIDを解除
+pub fn end_message() {
+    let _ = LOG_CONTEXT.try_with(|c| c.borrow_mut().message_id = None);
+}
+
+/// 現在のセッションID・メッセージID（構造化出力用、ログ文脈外は(None, None)）
+pub fn current_ids() -> (Option<u64>, Option<String>) {
+    LOG_CONTEXT
+        .try_with(|c| {
+            let c = c.borrow();
+            (Some(c.session_id), c.message_id.clone())
+        })
+        .unwrap_or((None, None))
+}
+
+/// ログ行の接頭辞（「[session=ID message=ID] 」、ログ文脈外は空文字）
+pub fn log_prefix() -> String {
+    LOG_CONTEXT
+        .try_with(|c| {
+            let c = c.borrow();
+            match &c.message_id {
+                Some(m) => format!("[session={} message={}] ", c.session_id, m),
+                None => format!("[session={}] ", c.session_id),
+            }
+        })
+        .unwrap_or_default()
+}
+
 /// JSTタイムスタンプ付きで標準出力にログを出すマクロ
 ///
 /// # 使い方
@@ -17,12 +109,14 @@
 ///
 /// # 説明
 /// - chrono, chrono-tzでJST現在時刻を取得し、先頭に付与して出力
+/// - クライアント処理タスク内ではセッションID・メッセージIDも自動付与
 /// - 可変引数でformat!と同様に使える
 #[macro_export] // クレート全体で利用可能
 macro_rules! printdaytimeln {
     ($($arg:tt)*) => {{ // 可変引数（format!と同じ）
         let now = chrono::Local::now().with_timezone(&chrono_tz::Asia::Tokyo); // JST現在時刻取得
         let log_time = now.format("[%Y/%m/%d %H:%M:%S]"); // タイムスタンプ整形
-        println!("{} {}", log_time, format!($($arg)*)); // タイムスタンプ付きログ出力
+        let prefix = $crate::logging::log_prefix(); // セッションID・メッセージID
+        println!("{} {}{}", log_time, prefix, format!($($arg)*)); // タイムスタンプ付きログ出力
     }};
 }
@@ -0,0 +1,207 @@
+// =========================
+// macros.rs
+// MilterDecoder マクロ（Milter symbol）管理モジュール
+//
+// 【このファイルで使う主なクレート】
+// - std: 標準ライブラリ（コレクション、文字列処理）
+//
+// 【役割】
+// - SMFIR_SETSYMLISTで要求するフェーズ（SMFIM_*）の定義
+// - 設定ファイルのMacro_list行（フェーズごとの要求マクロ一覧）の解析
+// - OPTNEG応答に付加するマクロ一覧ペイロードの生成
+// - マクロ名の正規化（単一文字マクロの{}除去）
+// - 受信したマクロ値をフェーズ・マクロ名で保持するMacroStore
+// =========================
+
+use std::collections::BTreeMap; // （フェーズ, マクロ名） → 値
+
+use crate::milter_command::MilterMacro; // 1文字マクロ識別子
+
+/// マクロ一覧を要求するフェーズ（mfdef.hのSMFIM_*）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MacroStage {
+    Connect = 0, // SMFIM_CONNECT: 接続時
+    Helo = 1,    // SMFIM_HELO: HELO/EHLO時
+    EnvFrom = 2, // SMFIM_ENVFROM: MAIL FROM時
+    EnvRcpt = 3, // SMFIM_ENVRCPT: RCPT TO時
+    Data = 4,    // SMFIM_DATA: DATA時
+    Eom = 5,     // SMFIM_EOM: 本文終了時
+    Eoh = 6,     // SMFIM_EOH: ヘッダ終了時
+}
+
+impl MacroStage {
+    /// Milterプロトコル上で届く順（EOHはDATAとEOMの間）
+    pub const PROTOCOL_ORDER: [MacroStage; 7] = [
+        MacroStage::Connect,
+        MacroStage::Helo,
+        MacroStage::EnvFrom,
+        MacroStage::EnvRcpt,
+        MacroStage::Data,
+        MacroStage::Eoh,
+        MacroStage::Eom,
+    ];
+
+    /// 設定ファイル上のフェーズ名から変換（Policy_ruleと同じ名前、envfrom/envrcptも可）
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "connect" => Some(MacroStage::Connect),
+            "helo" => Some(MacroStage::Helo),
+            "mail" | "envfrom" => Some(MacroStage::EnvFrom),
+            "rcpt" | "envrcpt" => Some(MacroStage::EnvRcpt),
+            "data" => Some(MacroStage::Data),
+            "eom" => Some(MacroStage::Eom),
+            "eoh" => Some(MacroStage::Eoh),
+            _ => None,
+        }
+    }
+
+    /// SMFIC_MACROの先頭バイト（対象コマンド）から変換
+    pub fn from_command_byte(b: u8) -> Option<Self> {
+        match b {
+            b'C' => Some(MacroStage::Connect),
+            b'H' => Some(MacroStage::Helo),
+            b'M' => Some(MacroStage::EnvFrom),
+            b'R' => Some(MacroStage::EnvRcpt),
+            b'T' => Some(MacroStage::Data),
+            b'E' => Some(MacroStage::Eom),
+            b'N' => Some(MacroStage::Eoh),
+            _ => None,
+        }
+    }
+
+    /// 接続単位のマクロか（CONNECT/HELO時のマクロは同一接続の全メールで有効）
+    pub fn is_connection_scope(self) -> bool {
+        matches!(self, MacroStage::Connect | MacroStage::Helo)
+    }
+}
+
+/// フェーズごとの要求マクロ一覧（Macro_list行）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroList {
+    pub stage: MacroStage,  // 要求フェーズ
+    pub names: Vec<String>, // 要求マクロ名（例: {auth_authen}, i）
+}
+
+impl MacroList {
+    /// Macro_list行（"Macro_list "以降）を解析
+    ///
+    /// # 書式
+    /// `<stage> <macro> [<macro> ...]`
+    /// - stage: connect / helo / mail / rcpt / data / eoh / eom
+    /// - macro: 単一文字（i, j等）または{name}形式（{auth_authen}等）
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut tokens = spec.split_whitespace(); // 空白区切り
+        let stage_name = tokens.next().ok_or("フェーズ未指定")?; // フェーズ名
+        let stage = MacroStage::from_name(stage_name)
+            .ok_or_else(|| format!("不明なフェーズ: {}", stage_name))?;
+        let names: Vec<String> = tokens.map(|t| t.to_string()).collect(); // マクロ名一覧
+        if names.is_empty() {
+            return Err("マクロ未指定".to_string());
+        }
+        if let Some(bad) = names.iter().find(|n| n.contains('\0')) {
+            return Err(format!("マクロ名不正: {:?}", bad));
+        }
+        Ok(MacroList { stage, names })
+    }
+}
+
+/// OPTNEG応答に付加するSETSYMLISTペイロードを生成
+///
+/// # 説明
+/// - フェーズごとに「4バイトのフェーズ番号 + 空白区切りのマクロ名一覧 + NUL」
+/// - 同じフェーズの複数行は1つにまとめ、フェーズ番号順に並べる
+pub fn symlist_payload(lists: &[MacroList]) -> Vec<u8> {
+    let mut merged: BTreeMap<MacroStage, Vec<&str>> = BTreeMap::new(); // フェーズ → マクロ名一覧
+    for list in lists {
+        let names = merged.entry(list.stage).or_default();
+        for name in &list.names {
+            if !names.contains(&name.as_str()) {
+                names.push(name); // 重複指定は1つにまとめる
+            }
+        }
+    }
+    let mut payload = Vec::new();
+    for (stage, names) in merged {
+        payload.extend_from_slice(&(stage as u32).to_be_bytes()); // フェーズ番号（4バイト）
+        payload.extend_from_slice(names.join(" ").as_bytes()); // マクロ名一覧
+        payload.push(0x00); // NUL終端
+    }
+    payload
+}
+
+/// マクロ名を正規化（"{i}"のような単一文字の{}表記は"i"にそろえる）
+pub fn normalize_name(name: &str) -> String {
+    match name.strip_prefix('{').and_then(|n| n.strip_suffix('}')) {
+        Some(inner) if inner.chars().count() == 1 => inner.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// 受信したマクロ値の保持領域（フェーズ・マクロ名で引ける）
+///
+/// # 説明
+/// - CONNECT/HELO時の値は接続単位、MAIL以降の値はトランザクション単位で保持する
+/// - 同じフェーズで同名マクロが再送された場合は後の値で上書き（RCPTごとの値は最後の受信者のもの）
+#[derive(Debug, Clone, Default)]
+pub struct MacroStore {
+    values: BTreeMap<(MacroStage, String), String>, // （フェーズ, 正規化済みマクロ名） → 値
+}
+
+impl MacroStore {
+    /// マクロ値を格納
+    pub fn insert(&mut self, stage: MacroStage, name: &str, value: String) {
+        self.values.insert((stage, normalize_name(name)), value);
+    }
+
+    /// 指定フェーズで受信したマクロ値を取得
+    pub fn get(&self, stage: MacroStage, name: &str) -> Option<&str> {
+        self.values
+            .get(&(stage, normalize_name(name)))
+            .map(|v| v.as_str())
+    }
+
+    /// 最も新しいフェーズで受信したマクロ値を取得（フェーズを問わない参照用）
+    pub fn latest(&self, name: &str) -> Option<&str> {
+        MacroStage::PROTOCOL_ORDER
+            .iter()
+            .rev()
+            .find_map(|&stage| self.get(stage, name))
+    }
+
+    /// キューID（マクロi、Postfixは通常MAIL以降で通知）
+    pub fn queue_id(&self) -> Option<&str> {
+        let name = (MilterMacro::QueueId.to_u8() as char).to_string(); // "i"
+        self.latest(&name).filter(|v| !v.is_empty())
+    }
+
+    /// トランザクション単位（MAIL以降）の値を破棄（BODYEOB後・ABORT時）
+    pub fn clear_message(&mut self) {
+        self.values.retain(|(stage, _), _| stage.is_connection_scope());
+    }
+
+    /// 全ての値を破棄（QUIT_NC時）
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// 現在有効なマクロ値を名前順に取得（同名は最新フェーズの値）
+    pub fn latest_values(&self) -> BTreeMap<&str, &str> {
+        let mut latest: BTreeMap<&str, &str> = BTreeMap::new(); // マクロ名 → 最新値
+        for stage in MacroStage::PROTOCOL_ORDER {
+            for ((s, name), value) in &self.values {
+                if *s == stage {
+                    latest.insert(name, value); // 後のフェーズで上書き
+                }
+            }
+        }
+        latest
+    }
+
+    /// 現在有効なマクロ値を「名前=値」形式で列挙（同名は最新フェーズの値、名前順、ポリシー判定・出力用）
+    pub fn lines(&self) -> Vec<String> {
+        self.latest_values()
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect()
+    }
+}
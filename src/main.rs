@@ -5,23 +5,51 @@
 // 【このファイルで使う主なクレート】
 // - tokio: 非同期TCPサーバ・シグナル・ブロードキャスト（net::TcpListener, sync::broadcast, signal::unix）
 // - std: スレッド安全な参照カウント・ロック（Arc, RwLock）
+// - attachment: 添付ファイルのハッシュ値計算・スプール保存
 // - client: クライアント受信処理
+// - envelope: エンベロープ（MAIL FROM/RCPT TO）管理
+// - formatter: 解析結果のログ出力
+// - header: 受信順ヘッダ管理
 // - init: 設定ファイル管理
-// - logging: JSTタイムスタンプ付きログ出力
+// - logging: JSTタイムスタンプ付きログ出力（セッションID・メッセージID付与）
+// - macros: マクロ一覧（SETSYMLIST）管理
 // - milter_command: Milterコマンド定義
+// - negotiate: OPTNEG能力モデル（合意フラグ算出）
+// - output: 構造化（JSON Lines）出力
+// - policy: 受理/拒否判定
+// - session: セッションフェーズ（状態遷移）管理
 //
 // 【役割】
 // - サーバー起動・設定管理・クライアント接続受付・シグナル処理
 // =========================
 
+mod archive; // アーカイブ添付（ZIP/RAR/7z/TAR/GZ）の解析
+mod attachment; // 添付ファイルのハッシュ値計算・スプール保存
 mod client; // クライアント受信処理
+mod dkim; // DKIM署名検証
+mod envelope; // エンベロープ（MAIL FROM/RCPT TO）管理
+mod filetype; // マジックバイトによるファイル種別判定
+mod formatter; // 解析結果のログ出力
+mod header; // 受信順ヘッダ管理
 mod init; // 設定ファイル管理
 mod logging; // JSTタイムスタンプ付きログ出力
+mod macros; // マクロ一覧（SETSYMLIST）管理
 mod milter; // Milterコマンドごとのデコード・応答処理
 mod milter_command; // Milterコマンド定義
-mod parse; // メールパース・出力処理
+mod negotiate; // OPTNEG能力モデル
+mod office; // Office文書（OLE2/OOXML）のマクロ・DDE等の検出
+mod output; // 構造化（JSON Lines）出力
+mod parse; // メールパース処理（型付きの解析結果）
+mod pdf; // PDF添付のキーワード・URI抽出
+mod phishing; // フィッシング判定（表示名なりすまし・類似ドメイン・リンク偽装）
+mod policy; // 受理/拒否判定
+mod received; // Receivedヘッダの中継経路解析
+mod resolver; // DNS問い合わせ（システムのDNS・ゾーンファイル）
+mod session; // セッションフェーズ（状態遷移）管理
+mod spf; // SPF評価
+mod urls; // URL抽出・正規化
 
-use init::load_config;
+use init::{load_config, CONFIG};
 use std::sync::{Arc, RwLock}; // スレッド安全な参照カウント・ロック
 #[cfg(unix)]
 use tokio::signal::unix::{signal, SignalKind}; // Unix系: シグナル受信
@@ -32,7 +60,7 @@ use tokio::{net::TcpListener, sync::broadcast}; // 非同期TCPサーバ・ブ
 #[tokio::main]
 async fn main() {
     // 設定をスレッド安全に共有（Arc+RwLock）
-    let config = Arc::new(RwLock::new(load_config()));
+    let config = Arc::new(RwLock::new(CONFIG.read().unwrap().clone())); // グローバル設定（初回読込済み）を共有
     // サーバー再起動・終了通知用ブロードキャストチャネル
     let (shutdown_tx, _) = broadcast::channel::<()>(100);
 
@@ -48,6 +76,7 @@ async fn main() {
             while hup.recv().await.is_some() {
                 printdaytimeln!("SIGHUP受信: 設定ファイル再読込");
                 let new_config = load_config(); // 新設定読込
+                *CONFIG.write().unwrap() = new_config.clone(); // グローバル設定更新（クライアント処理が参照）
                 *config.write().unwrap() = new_config; // 設定更新
                 let _ = shutdown_tx_hup.send(()); // 全クライアントへ再起動通知
             }
@@ -55,7 +84,8 @@ async fn main() {
         // SIGTERM受信: サーバー安全終了
         tokio::spawn(async move {
             let mut term = signal(SignalKind::terminate()).expect("SIGTERM登録失敗");
-            while term.recv().await.is_some() {
+            // 受信後はprocess::exitで終了するため繰り返さない（whileはclippy::never_loopで拒否される）
+            if term.recv().await.is_some() {
                 printdaytimeln!("SIGTERM受信: サーバー安全終了");
                 let _ = shutdown_tx_term.send(()); // 全クライアントへ終了通知
                 std::process::exit(0); // プロセス終了
@@ -101,9 +131,14 @@ async fn main() {
             // クライアント受信ループ
             tokio::select! {
                 Ok((stream, addr)) = listener.accept() => {
-                    printdaytimeln!("接続: {}", addr); // 新規接続
+                    let session_id = logging::next_session_id(); // 接続ごとのセッションID
+                    printdaytimeln!("接続: {} session={}", addr, session_id); // 新規接続
                     let shutdown_rx = shutdown_tx.subscribe(); // クライアント用レシーバ
-                    tokio::spawn(client::handle_client(stream, shutdown_rx)); // クライアント処理開始
+                    // クライアント処理開始（以降のログにはセッションID・メッセージIDが付く）
+                    tokio::spawn(logging::with_session(
+                        session_id,
+                        client::handle_client(stream, shutdown_rx),
+                    ));
                 }
                 _ = shutdown_rx.recv() => {
                     printdaytimeln!("再起動のためリスナー再バインド"); // 再起動通知
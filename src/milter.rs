@@ -6,10 +6,17 @@
 // - tokio: 非同期TCP通信・I/O・応答送信などの非同期処理全般（net::TcpStream, io::AsyncWriteExt）
 // - std: 標準ライブラリ（バイト操作、コレクション、エラー処理、フォーマット等）
 // - crate::printdaytimeln!: JSTタイムスタンプ付きでログ出力する独自マクロ
-// - crate::milter_command: Milterマクロ種別enum（MilterMacro）
+// - crate::milter_command: Milterマクロ種別enum（MilterMacro）、Milter応答enum（MilterResponse）、変更アクションenum（MilterModification）
+// - crate::negotiate: OPTNEG合意内容（NegotiatedOptions）・フラグ名一覧
+// - crate::macros: 要求マクロ一覧（SETSYMLIST）・マクロフェーズ・マクロ名正規化
+// - crate::session: 接続情報（ConnectInfo, ConnectFamily）
+// - crate::envelope: エンベロープ情報（MAIL FROM/RCPT TO）の格納
+// - crate::header: 受信順ヘッダリスト（HeaderList）への格納
 //
 // 【役割】
-// - Milterコマンドごとのデコード・応答処理（OPTNEG, CONNECT, HELO, DATA, HEADER, BODY, EOH/BODYEOB）
+// - Milterコマンドごとのデコード処理（OPTNEG, CONNECT, HELO, MAIL, RCPT, MACRO, HEADER, BODY）
+// - Milter応答（CONTINUE/ACCEPT/REJECT/TEMPFAIL/DISCARD/REPLYCODE）の送信
+// - EOM時のメッセージ変更アクション（ADDHEADER等）の送信
 // - ネゴシエーション情報の分解・応答送信
 // - マクロペイロードの分解・出力
 // - ヘッダ・ボディ情報の格納・加工
@@ -20,280 +27,327 @@ use tokio::{
     net::TcpStream,    // 非同期TCPストリーム
 };
 
+use crate::envelope::{Envelope, EnvelopeAddress}; // エンベロープ情報
+use crate::header::HeaderList; // 受信順ヘッダリスト
+use crate::milter_command::{MilterModification, MilterResponse}; // Milter応答・メッセージ変更アクション
+use crate::macros::{normalize_name, symlist_payload, MacroList, MacroStage}; // マクロ一覧・フェーズ
+use crate::negotiate::{
+    flag_names, NegotiatedOptions, ACTION_FLAG_NAMES, PROTOCOL_FLAG_NAMES, SMFIF_SETSYMLIST,
+    SMFIP_HDR_LEADSPC,
+}; // OPTNEG合意内容
+use crate::session::{ConnectFamily, ConnectInfo}; // 接続情報・プロトコルファミリ
+
 /// SMFIC_OPTNEGペイロードを分解して出力し、OPTNEG応答を送信
-/// OPTNEGコマンドのデコード・応答送信処理
-/// - stream: クライアントTCPストリーム
-/// - payload: 受信ペイロード
-///   Milterプロトコルのネゴシエーション情報を分解し、内容を出力してOPTNEG応答を返す
-///   OPTNEGコマンドのデコード・応答送信処理
 ///
 /// # 引数
 /// - `stream`: クライアントTCPストリーム
-/// - `payload`: 受信ペイロード
+/// - `payload`: 受信ペイロード（4バイトバージョン + 4バイトアクション + 4バイトプロトコルフラグ）
+/// - `macro_lists`: フェーズごとの要求マクロ一覧（Macro_list設定）
 ///
 /// # 説明
-/// Milterプロトコルのネゴシエーション情報を分解し、内容を出力してOPTNEG応答を返す
-pub async fn decode_optneg(stream: &mut TcpStream, payload: &[u8]) {
+/// MTAの提示内容とサーバーが実装している能力の共通部分を算出し、OPTNEG応答として返す。
+/// SMFIF_SETSYMLISTを合意できた場合は要求マクロ一覧を応答の末尾に付加する（設定が無ければ要求しない）。
+/// 合意内容（EOM時のメッセージ変更可否・応答省略・ヘッダ先頭空白の扱いに使用）を返す。
+/// ペイロード長不足時は交渉失敗としてNoneを返す（MTAが応答待ちで停止しないよう呼び出し側で切断する）。
+pub async fn decode_optneg(
+    stream: &mut TcpStream,
+    payload: &[u8],
+    macro_lists: &[MacroList],
+) -> Option<NegotiatedOptions> {
     // OPTNEGペイロードは: 4バイトプロトコルバージョン + 4バイト機能フラグ + 4バイトサポートフラグ
-    if payload.len() >= 12 {
-        // 4バイトごとに各値を抽出
-        let protocol_ver = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]); // プロトコルバージョン
-        let actions = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]); // アクションフラグ
-        let protocol_flags = u32::from_be_bytes([payload[8], payload[9], payload[10], payload[11]]); // サポートフラグ
-                                                                                                     // 受信したOPTNEG情報を詳細に出力
-        crate::printdaytimeln!(
-            "SMFIC_OPTNEG: protocol_ver={} actions=0x{:08X} protocol_flags=0x{:08X}",
-            protocol_ver,
-            actions,
-            protocol_flags
-        );
-        // 代表的なMilterアクションフラグを分解して出力
-        let action_flags = [
-            (0x00000001, "ADD_HEADERS"),       // ヘッダ追加
-            (0x00000002, "CHANGE_BODY"),       // 本文変更
-            (0x00000004, "ADD_RECIPIENTS"),    // 宛先追加
-            (0x00000008, "DELETE_RECIPIENTS"), // 宛先削除
-            (0x00000010, "QUARANTINE"),        // 隔離
-            (0x00000020, "REPLACE_HEADERS"),   // ヘッダ置換
-            (0x00000040, "CHANGE_REPLY"),      // 応答変更
-        ];
-        // 各アクションフラグが立っていれば出力
-        for (flag, name) in &action_flags {
-            // 各アクションフラグが立っていれば出力
-            if actions & flag != 0 {
-                crate::printdaytimeln!("Milterアクション: {}", name); // アクションフラグごとに出力
-            }
-        }
-        // プロトコルフラグも分解して出力
-        let proto_flags = [
-            (0x00000001, "NO_CONNECT"), // CONNECT省略
-            (0x00000002, "NO_HELO"),    // HELO省略
-            (0x00000004, "NO_ENVFROM"), // ENVFROM省略
-            (0x00000008, "NO_ENVRCPT"), // ENVRCPT省略
-            (0x00000010, "NO_BODY"),    // BODY省略
-            (0x00000020, "NO_HDRS"),    // HDRS省略
-            (0x00000040, "NO_UNKNOWN"), // UNKNOWN省略
-            (0x00000080, "NO_DATA"),    // DATA省略
-        ];
-        // 各プロトコルフラグが立っていれば出力
-        for (flag, name) in &proto_flags {
-            // 各プロトコルフラグが立っていれば出力
-            if protocol_flags & flag != 0 {
-                crate::printdaytimeln!("Milterプロトコル: {}", name); // サポートフラグごとに出力
-            }
-        }
-        // OPTNEG応答バッファを生成（13バイト: コマンド1+ペイロード12）
-        let mut resp = Vec::with_capacity(13);
-        resp.extend_from_slice(&13u32.to_be_bytes()); // 応答サイズ（4バイト）
-        resp.push(0x4f); // コマンド: SMFIR_OPTNEG（応答コマンド）
-        resp.extend_from_slice(&protocol_ver.to_be_bytes()); // プロトコルバージョン（4バイト）
-                                                             // クライアントから受信したアクションフラグをそのまま応答にセット
-        let resp_actions = actions;
-        resp.extend_from_slice(&resp_actions.to_be_bytes()); // アクションフラグ（4バイト）
-                                                             // NO_BODY(0x10)とNO_HDRS(0x20)を立てないサポートフラグを生成（ヘッダ・ボディもMilterで渡される）
-        let resp_protocol_flags = protocol_flags & !(0x10 | 0x20);
-        resp.extend_from_slice(&resp_protocol_flags.to_be_bytes()); // サポートフラグ（4バイト）
-        // クライアントにOPTNEG応答を送信
-        match stream.write_all(&resp).await {
-            Ok(_) => crate::printdaytimeln!("SMFIR_OPTNEG応答送信完了: {:?}", resp), // 送信成功時
-            Err(e) => crate::printdaytimeln!("SMFIR_OPTNEG応答送信エラー: {}", e),   // 送信失敗時
-        }
-    } else {
+    if payload.len() < 12 {
         // ペイロード長不足時のエラー出力
-        println!("SMFIC_OPTNEGペイロード長不足: {} bytes", payload.len());
+        crate::printdaytimeln!("SMFIC_OPTNEGペイロード長不足: {} bytes", payload.len());
+        return None;
+    }
+    // 4バイトごとに各値を抽出
+    let protocol_ver = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]); // プロトコルバージョン
+    let actions = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]); // アクションフラグ
+    let protocol_flags = u32::from_be_bytes([payload[8], payload[9], payload[10], payload[11]]); // サポートフラグ
+    // 受信したOPTNEG情報を詳細に出力
+    crate::printdaytimeln!(
+        "SMFIC_OPTNEG: protocol_ver={} actions=0x{:08X} protocol_flags=0x{:08X}",
+        protocol_ver,
+        actions,
+        protocol_flags
+    );
+    crate::printdaytimeln!("MTA提示アクション: {}", flag_names(actions, &ACTION_FLAG_NAMES));
+    crate::printdaytimeln!(
+        "MTA提示プロトコル: {}",
+        flag_names(protocol_flags, &PROTOCOL_FLAG_NAMES)
+    );
+    // MTA提示内容とサーバー能力の共通部分を合意内容とする
+    let mut options = NegotiatedOptions::negotiate(protocol_ver, actions, protocol_flags);
+    if macro_lists.is_empty() {
+        options.actions &= !SMFIF_SETSYMLIST; // 要求するマクロが無ければSETSYMLISTは使わない
+    }
+    crate::printdaytimeln!(
+        "OPTNEG合意: version={} actions={} protocol={}",
+        options.version,
+        flag_names(options.actions, &ACTION_FLAG_NAMES),
+        flag_names(options.protocol, &PROTOCOL_FLAG_NAMES)
+    );
+    // OPTNEG応答ペイロード: バージョン・アクション・プロトコルフラグ（+ 要求マクロ一覧）
+    let mut resp_payload = options.to_payload();
+    if options.has_action(SMFIF_SETSYMLIST) {
+        for list in macro_lists {
+            crate::printdaytimeln!("要求マクロ一覧: {:?} {}", list.stage, list.names.join(" "));
+        }
+        resp_payload.extend_from_slice(&symlist_payload(macro_lists)); // フェーズごとのマクロ一覧
+    } else if !macro_lists.is_empty() {
+        crate::printdaytimeln!("MTAがSMFIF_SETSYMLISTを提示しないため要求マクロ一覧は送信しません");
+    }
+    // OPTNEG応答バッファを生成（4バイト長 + コマンド1バイト + ペイロード）
+    let mut resp = Vec::with_capacity(5 + resp_payload.len());
+    resp.extend_from_slice(&((resp_payload.len() + 1) as u32).to_be_bytes()); // 応答サイズ（4バイト）
+    resp.push(b'O'); // コマンド: SMFIC_OPTNEG（応答も同じコマンド）
+    resp.extend_from_slice(&resp_payload); // ペイロード
+    // クライアントにOPTNEG応答を送信
+    match stream.write_all(&resp).await {
+        Ok(_) => crate::printdaytimeln!("SMFIR_OPTNEG応答送信完了: {:?}", resp), // 送信成功時
+        Err(e) => crate::printdaytimeln!("SMFIR_OPTNEG応答送信エラー: {}", e),   // 送信失敗時
     }
+    Some(options)
 }
 
-/// CONNECTコマンドのデコード・応答送信処理
+/// CONNECTコマンドのデコード処理
 ///
 /// # 引数
-/// - `stream`: クライアントTCPストリーム
-/// - `payload`: 受信ペイロード
-/// - `peer_addr`: クライアントアドレス
+/// - `payload`: 受信ペイロード（ホスト名\0 + ファミリ1バイト + ポート2バイト + アドレス\0）
 ///
 /// # 説明
-/// 受信した接続情報を出力し、CONTINUE応答(0x06)をクライアントに返す。
-pub async fn decode_connect(stream: &mut tokio::net::TcpStream, payload: &[u8], peer_addr: &str) {
+/// 受信した接続情報を出力し、セッションに保持する接続情報を返す。
+/// 応答（CONTINUE/REJECT等）はポリシー判定後に呼び出し側で送信する。
+/// - ファミリ'4'/'6'のアドレスはIPアドレスとして解析（Sendmailの「IPv6:」接頭辞・角括弧は除く）
+/// - ファミリ'L'（UNIXドメインソケット）のアドレスはソケットのパスとして保持
+/// - ファミリ'U'（不明）はポート・アドレス無し
+pub fn decode_connect(payload: &[u8]) -> ConnectInfo {
     // ペイロードをUTF-8文字列化し、接続情報として出力
-    let connect_str = String::from_utf8_lossy(payload); // ペイロードをUTF-8文字列化
-    crate::printdaytimeln!("接続情報: {}", connect_str); // 接続情報を出力
-                                                         // CONTINUE応答（0x06）を生成
-    let resp_size: u32 = 1; // 応答サイズ（コマンドのみ）
-    let resp_cmd: u8 = 0x06; // CONTINUEコマンド
-    let mut resp = Vec::with_capacity(5); // 応答バッファ（5バイト: サイズ4+コマンド1）
-    resp.extend_from_slice(&resp_size.to_be_bytes()); // サイズ（4バイト）
-    resp.push(resp_cmd); // コマンド（1バイト）
-                         // クライアントにCONTINUE応答を送信
-                         // クライアントにCONTINUE応答を送信
-    if let Err(e) = stream.write_all(&resp).await {
-        crate::printdaytimeln!("応答送信エラー: {}: {}", peer_addr, e); // 送信失敗時
-    } else {
-        crate::printdaytimeln!("応答送信(connect): CONTINUE (0x06) to {}", peer_addr);
-        // 送信成功時
+    let connect_str = String::from_utf8_lossy(payload).replace('\0', " "); // NUL区切りを空白に置換
+    crate::printdaytimeln!("接続情報: {}", connect_str.trim()); // 接続情報を出力
+    let host_end = payload.iter().position(|&b| b == 0x00).unwrap_or(payload.len()); // ホスト名終端
+    let hostname = String::from_utf8_lossy(&payload[..host_end]).to_string(); // ホスト名
+    let family = match payload.get(host_end + 1).copied().unwrap_or(b'U') {
+        b'4' => ConnectFamily::Inet,
+        b'6' => ConnectFamily::Inet6,
+        b'L' => ConnectFamily::Unix,
+        _ => ConnectFamily::Unknown,
+    }; // ファミリ（'4','6','L','U'）
+    let mut info = ConnectInfo {
+        hostname,
+        family,
+        port: None,
+        address: None,
+        path: None,
+        raw: connect_str.trim().to_string(),
+    };
+    if family == ConnectFamily::Unknown || payload.len() < host_end + 4 {
+        return info; // 不明時はポート・アドレス無し
+    }
+    let port = u16::from_be_bytes([payload[host_end + 2], payload[host_end + 3]]); // ポート（ネットワークバイトオーダー）
+    let addr = &payload[host_end + 4..]; // ポート2バイトの後ろがアドレス文字列（NUL終端）
+    let addr = String::from_utf8_lossy(addr.strip_suffix(&[0x00]).unwrap_or(addr)).to_string();
+    if family == ConnectFamily::Unix {
+        info.path = Some(addr); // UNIXドメインソケットのパス
+        return info;
+    }
+    info.port = Some(port);
+    let ip = addr.trim_start_matches('[').trim_end_matches(']'); // 角括弧付きの表記
+    let ip = ip
+        .get(..5)
+        .filter(|p| p.eq_ignore_ascii_case("IPv6:"))
+        .map_or(ip, |_| &ip[5..]); // Sendmailの「IPv6:」接頭辞
+    info.address = ip.parse().ok();
+    if info.address.is_none() {
+        crate::printdaytimeln!("接続元アドレス解析失敗: {}", addr);
     }
+    info
 }
 
-/// HELOコマンドのデコード・応答送信処理
+/// HELOコマンドのデコード処理
 ///
 /// # 引数
-/// - `stream`: クライアントTCPストリーム
-/// - `payload`: 受信ペイロード
-/// - `peer_addr`: クライアントアドレス
+/// - `payload`: 受信ペイロード（HELO/EHLOホスト名\0）
 ///
 /// # 説明
-/// 受信したHELO情報を出力し、CONTINUE応答(0x06)をクライアントに返す。
-pub async fn decode_helo(stream: &mut tokio::net::TcpStream, payload: &[u8], peer_addr: &str) {
+/// 受信したHELO情報を出力し、HELOホスト名（セッションに保持する用）を返す。
+/// 応答（CONTINUE/REJECT等）はポリシー判定後に呼び出し側で送信する。
+pub fn decode_helo(payload: &[u8]) -> String {
     // ペイロードをUTF-8文字列化し、HELO情報として出力
-    let helo_str = String::from_utf8_lossy(payload); // ペイロードをUTF-8文字列化
-    crate::printdaytimeln!("HELO: {}", helo_str); // HELO情報を出力
-                                                  // CONTINUE応答（0x06）を生成
-    let resp_size: u32 = 1; // 応答サイズ（コマンドのみ）
-    let resp_cmd: u8 = 0x06; // CONTINUEコマンド
-    let mut resp = Vec::with_capacity(5); // 応答バッファ（5バイト: サイズ4+コマンド1）
-    resp.extend_from_slice(&resp_size.to_be_bytes()); // サイズ（4バイト）
-    resp.push(resp_cmd); // コマンド（1バイト）
-                         // クライアントにCONTINUE応答を送信
-                         // クライアントにCONTINUE応答を送信
-    if let Err(e) = stream.write_all(&resp).await {
-        crate::printdaytimeln!("応答送信エラー: {}: {}", peer_addr, e); // 送信失敗時
-    } else {
-        crate::printdaytimeln!("応答送信(helo): CONTINUE (0x06) to {}", peer_addr);
-        // 送信成功時
+    let helo_str = String::from_utf8_lossy(payload).to_string(); // ペイロードをUTF-8文字列化
+    crate::printdaytimeln!("HELO: {}", helo_str.replace('\0', "")); // HELO情報を出力
+    helo_str.trim_end_matches('\0').to_string() // 末尾NULを除去して返却
+}
+
+/// MAIL FROMコマンドのデコード処理
+///
+/// # 引数
+/// - `payload`: 受信ペイロード（NUL区切り: 送信者アドレス + ESMTPパラメータ）
+/// - `envelope`: 現トランザクションのエンベロープ情報
+///
+/// # 説明
+/// MAIL FROMは新しいトランザクションの開始なので、エンベロープを初期化してから送信者を格納する。
+pub fn decode_mail(payload: &[u8], envelope: &mut Envelope) {
+    envelope.clear(); // 新トランザクション開始: 前回のエンベロープを破棄
+    match EnvelopeAddress::from_args(payload) {
+        Some(sender) => {
+            crate::printdaytimeln!("MAIL FROM: {}", sender.display()); // 送信者情報を出力
+            envelope.sender = Some(sender); // 送信者を格納
+        }
+        None => crate::printdaytimeln!("MAIL FROMペイロード不正: {} bytes", payload.len()),
     }
 }
 
-/// DATAコマンドのマクロペイロードを分解・出力する
+/// RCPT TOコマンドのデコード処理
 ///
 /// # 引数
-/// - `payload`: DATAコマンドのペイロード
+/// - `payload`: 受信ペイロード（NUL区切り: 受信者アドレス + ESMTPパラメータ）
+/// - `envelope`: 現トランザクションのエンベロープ情報
 ///
 /// # 説明
-/// DATAコマンドのペイロードを0x00区切りで分割し、マクロ名・値を出力する。
-/// 先頭バイトでマクロ種別を判定し、各マクロ名・値を詳細に出力。
+/// 受信者アドレスとESMTPパラメータ（NOTIFY=, ORCPT=等）を分解し、受信者リストに追加する。
+pub fn decode_rcpt(payload: &[u8], envelope: &mut Envelope) {
+    match EnvelopeAddress::from_args(payload) {
+        Some(rcpt) => {
+            crate::printdaytimeln!("RCPT TO: {}", rcpt.display()); // 受信者情報を出力
+            envelope.recipients.push(rcpt); // 受信者リストに追加
+        }
+        None => crate::printdaytimeln!("RCPT TOペイロード不正: {} bytes", payload.len()),
+    }
+}
+
+/// MACROコマンドのデコード処理
 ///
-/// 【この関数で使う主なクレート】
-/// - crate::milter_command::MilterMacro: マクロ種別enum（Postfix/Sendmail互換）
-/// - std: バイトスライス分割・文字列変換
-pub fn decode_data_macros(payload: &[u8], is_header_block: &mut bool) {
-    // DATAコマンドのマクロペイロードを0x00区切りで分割し、マクロ名・値を出力する。
+/// # 引数
+/// - `payload`: 受信ペイロード（対象コマンド1バイト + NUL区切りの「マクロ名, 値」の繰り返し）
+///
+/// # 説明
+/// マクロ名・値を出力し、対象フェーズと（正規化済みマクロ名, 値）の一覧を返す。
+/// 対象コマンドがマクロ一覧のフェーズ（SMFIM_*）に当たらない場合・マクロ無しの場合はNone。
+pub fn decode_data_macros(payload: &[u8]) -> Option<(MacroStage, Vec<(String, String)>)> {
     use crate::milter_command::MilterMacro;
-    let parts: Vec<&[u8]> = payload
-        .split(|b| *b == 0x00)
-        .filter(|s| !s.is_empty())
-        .collect();
-    if parts.is_empty() {
-        // マクロ無し
-        return;
-    }
     // 先頭バイトでマクロ種別（DATA/CONNECT/HELO/SOH等）を判定
-    let phase_macro_val = parts[0].first().copied().unwrap_or(0);
-    let phase_macro = MilterMacro::from_u8(phase_macro_val);
-    let phase_macro_str = phase_macro.as_str().to_string();
-    if phase_macro == MilterMacro::Soh {
-        *is_header_block = true;
-    }
-
-    // 先頭マクロ名（parts[0]の2バイト目以降）と値（parts[1]）
-    if parts[0].len() > 1 && parts.len() > 1 {
-        let macro_name_bytes = &parts[0][1..];
-        let macro_val_bytes = parts[1];
-        let macro_name = if let Some(&b'{') = macro_name_bytes.first() {
-            // {name}形式の拡張マクロ
-            if let Some(close_idx) = macro_name_bytes[1..].iter().position(|&b| b == b'}') {
-                let name = String::from_utf8_lossy(&macro_name_bytes[1..1 + close_idx]);
-                format!("{}({})", MilterMacro::Vender.as_str(), name)
-            } else {
-                format!("{}(Unknown)", MilterMacro::Vender.as_str())
-            }
-        } else {
-            macro_name_bytes
-                .first()
-                .map(|&b| MilterMacro::from_u8(b).as_str().to_string())
-                .unwrap_or(MilterMacro::Unknown(0).as_str().to_string())
+    let (&phase_macro_val, rest) = payload.split_first()?;
+    let phase_macro_str = MilterMacro::from_u8(phase_macro_val).as_str(); // ログ用フェーズ名
+    // 残りを0x00区切りで分割（マクロ名, 値, マクロ名, 値, ...）
+    let parts: Vec<&[u8]> = rest.split(|b| *b == 0x00).collect();
+    let mut pairs = Vec::new(); // （マクロ名, 値）一覧
+    for pair in parts.chunks_exact(2) {
+        let (name_bytes, val_bytes) = (pair[0], pair[1]);
+        if name_bytes.is_empty() {
+            continue; // 名前無しは無視
+        }
+        let name = normalize_name(&String::from_utf8_lossy(name_bytes)); // {i} → i
+        // ログ用表記（{name}形式はVender拡張、1文字はMilterMacro名）
+        let label = match name.strip_prefix('{').and_then(|n| n.strip_suffix('}')) {
+            Some(inner) => format!("{}({})", MilterMacro::Vender.as_str(), inner),
+            None => MilterMacro::from_u8(name.as_bytes()[0]).as_str().to_string(),
         };
-        let macro_val = String::from_utf8_lossy(macro_val_bytes).to_string();
-        crate::printdaytimeln!("マクロ[{}][{}]={}", phase_macro_str, macro_name, macro_val);
+        let value = String::from_utf8_lossy(val_bytes).to_string();
+        crate::printdaytimeln!("マクロ[{}][{}]={}", phase_macro_str, label, value);
+        pairs.push((name, value));
     }
-    // 以降は2つずつ: parts[n]=マクロ名, parts[n+1]=値
-    let mut idx = 2;
-    while idx + 1 < parts.len() {
-        let macro_name_bytes = parts[idx];
-        let macro_val_bytes = parts[idx + 1];
-        let macro_name = if let Some(&b'{') = macro_name_bytes.first() {
-            // {name}形式の拡張マクロ
-            if let Some(close_idx) = macro_name_bytes[1..].iter().position(|&b| b == b'}') {
-                let name = String::from_utf8_lossy(&macro_name_bytes[1..1 + close_idx]);
-                format!("{}({})", MilterMacro::Vender.as_str(), name)
-            } else {
-                format!("{}(Unknown)", MilterMacro::Vender.as_str())
-            }
-        } else {
-            macro_name_bytes
-                .first()
-                .map(|&b| MilterMacro::from_u8(b).as_str().to_string())
-                .unwrap_or(MilterMacro::Unknown(0).as_str().to_string())
-        };
-        let macro_val = String::from_utf8_lossy(macro_val_bytes).to_string();
-        crate::printdaytimeln!("マクロ[{}][{}]={}", phase_macro_str, macro_name, macro_val);
-        idx += 2;
+    let stage = MacroStage::from_command_byte(phase_macro_val)?; // 保持対象フェーズ
+    if pairs.is_empty() {
+        return None;
     }
+    Some((stage, pairs))
 }
 
-/// ヘッダペイロードをNUL区切りで分割し、header_fieldsに格納＆内容を可視化出力
-pub fn decode_header(
-    payload: &[u8],
-    header_fields: &mut std::collections::HashMap<String, Vec<String>>,
-) {
-    let header_str = String::from_utf8_lossy(payload); // ペイロードをUTF-8文字列化
-    let header_str_visible = header_str.replace('\0', "<NUL>"); // NULバイトを可視化（デバッグ用）
+/// ヘッダペイロードをNUL区切りで分割し、header_fieldsに受信順のまま格納＆内容を可視化出力
+///
+/// # 説明
+/// - ペイロードは「ヘッダ名\0値\0」形式
+/// - ヘッダ名の大文字小文字、値の折り返し空白は一切加工せずに保持する（DKIM検証・Received解析用）
+pub fn decode_header(payload: &[u8], header_fields: &mut HeaderList) {
+    let header_str_visible = String::from_utf8_lossy(payload).replace('\0', "<NUL>"); // NULバイトを可視化（デバッグ用）
     crate::printdaytimeln!("ヘッダ内容: {}", header_str_visible); // ヘッダ内容をログ出力
-    let mut parts = header_str.splitn(2, '\0'); // NUL区切りでヘッダ名と値に分割
-    let key = parts
-        .next()
-        .unwrap_or("")
-        .trim()
-        .trim_end_matches('\0')
-        .to_string(); // ヘッダ名（前後空白・末尾NUL除去）
-    let val = parts
-        .next()
-        .unwrap_or("")
-        .trim()
-        .trim_end_matches('\0')
-        .to_string(); // ヘッダ値（前後空白・末尾NUL除去）
-    header_fields.entry(key).or_default().push(val); // ヘッダ名ごとに値を配列で追加
+    let (name, value) = match payload.iter().position(|&b| b == 0x00) {
+        Some(idx) => (&payload[..idx], &payload[idx + 1..]), // 最初のNULでヘッダ名と値に分割
+        None => (payload, &payload[payload.len()..]),        // NUL無しは値なしとして扱う
+    };
+    let value = value.strip_suffix(&[0x00]).unwrap_or(value); // 値の終端NULのみ除去
+    let name = String::from_utf8_lossy(name).to_string(); // ヘッダ名（ASCII想定）
+    header_fields.push(name, value.to_vec()); // 受信順に追加
 }
 
-/// BODYコマンドのペイロード（ボディ本体）をデコードしてbody_fieldに格納
-pub fn decode_body(payload: &[u8], body_field: &mut String) {
-    // デコードや文字コード変換は行わず、BODYコマンドのたびにペイロードをbody_fieldへ追記する
-    let s = String::from_utf8_lossy(payload); // ペイロードをUTF-8文字列化
-    body_field.push_str(&s); // 既存body_fieldに追記
+/// BODYコマンドのペイロード（ボディ本体）をbody_fieldにバイト列のまま格納
+///
+/// # 説明
+/// - 8bit本文（Shift_JIS、ISO-2022-JPの8bit亜種、生バイナリMIME等）を壊さないよう文字列化しない
+/// - チャンク境界（最大64KB）でマルチバイト文字が分割されても、連結後のバイト列は元のまま
+pub fn decode_body(payload: &[u8], body_field: &mut Vec<u8>) {
+    body_field.extend_from_slice(payload); // 既存body_fieldにバイト列のまま追記
 }
 
-/// EOH(0x45)またはBODYEOB(0x45)コマンドの判定・応答送信処理
+/// Milter応答の送信処理
 ///
 /// # 引数
 /// - `stream`: クライアントTCPストリーム
-/// - `is_body_eob`: trueならBODYEOBとしてACCEPT応答（0x61）、falseならEOHとしてCONTINUE応答（0x06）
+/// - `response`: 送信する応答（CONTINUE/ACCEPT/REJECT/TEMPFAIL/DISCARD/REPLYCODE）
+/// - `stage`: 応答対象のフェーズ名（ログ用）
 /// - `peer_addr`: クライアントアドレス
 ///
 /// # 説明
-/// EOH/BODYEOBコマンドを判定し、適切な応答（ACCEPT/CONTINUE）をクライアントに送信する。
-pub async fn decode_eoh_bodyeob(stream: &mut TcpStream, is_body_eob: bool, peer_addr: &str) {
-    // 応答コマンド・サイズを決定（BODYEOBなら0x61, EOHなら0x06）
-    let (resp_cmd, resp_size) = if is_body_eob {
-        (0x61u8, 1u32) // BODYEOB時はACCEPT応答
-    } else {
-        (0x06u8, 1u32) // EOH時はCONTINUE応答
-    };
-    let mut resp = Vec::with_capacity(5); // 応答バッファ（5バイト: サイズ4+コマンド1）
-    resp.extend_from_slice(&resp_size.to_be_bytes()); // サイズ（4バイト）
-    resp.push(resp_cmd); // コマンド（1バイト）
-                         // クライアントに応答を送信（非同期）
+/// 各フェーズの判定結果をMTAへ送信する。
+pub async fn send_response(
+    stream: &mut TcpStream,
+    response: &MilterResponse,
+    stage: &str,
+    peer_addr: &str,
+) {
+    let resp = response.to_packet(); // 応答パケット生成
     if let Err(e) = stream.write_all(&resp).await {
         crate::printdaytimeln!("応答送信エラー: {}: {}", peer_addr, e); // 送信失敗時はエラーログ
     } else {
-        crate::printdaytimeln!("応答送信(eoh/eob): (0x{:02X}) to {}", resp_cmd, peer_addr);
-        // 送信成功時は詳細ログ
+        crate::printdaytimeln!(
+            "応答送信({}): {} (0x{:02X}) to {}",
+            stage,
+            response.as_str(),
+            response.command_byte(),
+            peer_addr
+        ); // 送信成功時は詳細ログ
+    }
+}
+
+/// EOM時のメッセージ変更アクションを順に送信（最終応答より前に呼び出す）
+///
+/// # 引数
+/// - `stream`: クライアントTCPストリーム
+/// - `modifications`: 変更アクションリスト（送信順）
+/// - `options`: OPTNEGで合意した内容（アクションフラグ・ヘッダ先頭空白の扱い）
+/// - `peer_addr`: ログ用の接続元アドレス
+///
+/// # 説明
+/// 合意していないアクションはMTA側でエラーとなるため送信せずにログのみ出力する。
+/// SMFIP_HDR_LEADSPC合意時はヘッダ値の先頭に区切りの空白を付けて送る。
+pub async fn send_modifications(
+    stream: &mut TcpStream,
+    modifications: &[MilterModification],
+    options: &NegotiatedOptions,
+    peer_addr: &str,
+) {
+    let leading_space = options.has_protocol(SMFIP_HDR_LEADSPC); // ヘッダ値に先頭空白を含めるか
+    for modification in modifications {
+        if !options.has_action(modification.required_action()) {
+            crate::printdaytimeln!(
+                "変更アクション未合意のため送信省略: {} (必要フラグ=0x{:02X})",
+                modification.as_str(),
+                modification.required_action()
+            );
+            continue;
+        }
+        for packet in modification.to_packets(leading_space) {
+            if let Err(e) = stream.write_all(&packet).await {
+                crate::printdaytimeln!("変更アクション送信エラー: {}: {}", peer_addr, e);
+                return; // 以降の送信も失敗するため中断
+            }
+        }
+        crate::printdaytimeln!(
+            "変更アクション送信: {} (0x{:02X}) to {}",
+            modification.as_str(),
+            modification.command_byte(),
+            peer_addr
+        );
     }
 }
@@ -4,13 +4,21 @@
 //
 // 【このファイルで使う主なクレート】
 // - std: 標準ライブラリ（列挙型・マッチ分岐・デバッグ用）
+// - crate::negotiate: アクションフラグ定義（SMFIF_*）
 //
 // 【役割】
 // - MilterMacro: マクロ種別（Postfix/Sendmail互換）
 // - MilterCommand: コマンド種別（mfdef.h互換）
+// - MilterResponse: 受理/拒否判定の応答種別
+// - MilterModification: EOM時のメッセージ変更アクション（ヘッダ追加・受信者変更・本文置換等）
 // - 各種変換・用途名取得メソッド
 // =========================
 
+use crate::negotiate::{
+    SMFIF_ADDHDRS, SMFIF_ADDRCPT, SMFIF_ADDRCPT_PAR, SMFIF_CHGBODY, SMFIF_CHGFROM, SMFIF_CHGHDRS,
+    SMFIF_DELRCPT, SMFIF_QUARANTINE,
+}; // アクションフラグ
+
 /// Milterマクロ識別子（Postfix/Sendmail準拠）
 /// 1バイト目でどのタイミングのマクロかを示す（例: Connect, Helo, Mail, ...）
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -66,6 +74,31 @@ impl MilterMacro {
             other => MilterMacro::Unknown(other),
         }
     }
+    /// MilterMacroを1バイト識別子に変換（from_u8の逆変換）
+    pub fn to_u8(self) -> u8 {
+        match self {
+            MilterMacro::Connect => b'C',
+            MilterMacro::Helo => b'H',
+            MilterMacro::Mail => b'M',
+            MilterMacro::Rcpt => b'R',
+            MilterMacro::Eob => b'E',
+            MilterMacro::Data => b'D',
+            MilterMacro::Soh => b'T',
+            MilterMacro::Header => b'L',
+            MilterMacro::Body => b'B',
+            MilterMacro::Quit => b'Q',
+            MilterMacro::Hostname => b'j',
+            MilterMacro::QueueId => b'i',
+            MilterMacro::DaemonName => b'n',
+            MilterMacro::ClientName => b's',
+            MilterMacro::ClientAddr => b'r',
+            MilterMacro::BodyType => b'b',
+            MilterMacro::Version => b'v',
+            MilterMacro::Space => b'_',
+            MilterMacro::Vender => b'{',
+            MilterMacro::Unknown(b) => b,
+        }
+    }
     /// MilterMacroを説明的な文字列（用途名）に変換
     /// 例: MACRO_Connect, MACRO_Helo ...
     pub fn as_str(&self) -> &'static str {
@@ -95,63 +128,50 @@ impl MilterMacro {
 }
 
 // =========================
-// Milterコマンド定義（mfdef.hより抜粋）
-// - Postfix/Sendmail互換のMilterプロトコルコマンドを列挙
-// - 主要なコマンド種別（Abort, Accept, AddHeader, ...）を網羅
-// - as_str/as_str_eohで用途名を取得可能
+// Milterコマンド定義（mfdef.hのSMFIC_*を網羅）
+// - MTA(Postfix/Sendmail)からMilterへ送られるコマンドを列挙
+// - 応答コード（SMFIR_*）はMTA→Milter方向のコマンドではないためここには含めない
+// - as_strで用途名を取得可能
 // =========================
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MilterCommand {
-    Abort = 0x41,     // SMFIC_ABORT ('A'): セッション中断
-    Accept = 0x61,    // SMFIC_ACCEPT ('a'): メール受理
-    AddHeader = 0x68, // SMFIC_ADDHEADER ('h'): ヘッダ追加
-    // AddRcpt = 0x61,    // SMFIC_ADDRCPT ('a', Acceptと重複): 宛先追加
-    Body = 0x42,         // SMFIC_BODY ('B'): 本文受信
-    Connect = 0x43,      // SMFIC_CONNECT ('C'): 接続情報
-    Data = 0x44,         // SMFIC_DATA ('D'): DATAコマンド
-    DeleteHeader = 0x64, // SMFIC_DELHEADER ('d'): ヘッダ削除
-    DeleteRcpt = 0x72,   // SMFIC_DELRCPT ('r'): 宛先削除
-    Eoh = 0x45,          // SMFIC_EOH ('E'): ヘッダ終了、もしくは SMFIC_BODYEOB ('E'): ボディ終了
-    Eom = 0x4d,          // SMFIC_EOM ('M'): メール終了
-    Header = 0x4c,       // SMFIC_HEADER ('L'): ヘッダ受信
-    HeLO = 0x48,         // SMFIC_HELO ('H'): HELO受信
-    // Macro = 0x4d,      // SMFIC_MACRO ('M', EOMと重複): マクロ情報
-    OptNeg = 0x4f, // SMFIC_OPTNEG ('O'): オプション交渉　Postfixからの最初の接続でこれがくる
-    Quit = 0x51,   // SMFIC_QUIT ('Q'): セッション終了
-    Rcpt = 0x52,   // SMFIC_RCPT ('R'): 宛先受信
+    Abort = 0x41,   // SMFIC_ABORT ('A'): トランザクション中断（RSET等）
+    Body = 0x42,    // SMFIC_BODY ('B'): 本文チャンク受信
+    Connect = 0x43, // SMFIC_CONNECT ('C'): 接続情報
+    Macro = 0x44,   // SMFIC_MACRO ('D'): マクロ定義（次に来るコマンド用）
+    BodyEob = 0x45, // SMFIC_BODYEOB ('E'): 本文終了（メール終了）
+    HeLO = 0x48,    // SMFIC_HELO ('H'): HELO/EHLO受信
+    QuitNc = 0x4b,  // SMFIC_QUIT_NC ('K'): セッション終了（接続は再利用）
+    Header = 0x4c,  // SMFIC_HEADER ('L'): ヘッダ受信
+    Mail = 0x4d,    // SMFIC_MAIL ('M'): MAIL FROM受信
+    Eoh = 0x4e,     // SMFIC_EOH ('N'): ヘッダ終了
+    OptNeg = 0x4f,  // SMFIC_OPTNEG ('O'): オプション交渉　Postfixからの最初の接続でこれがくる
+    Quit = 0x51,    // SMFIC_QUIT ('Q'): セッション終了
+    Rcpt = 0x52,    // SMFIC_RCPT ('R'): RCPT TO受信
+    Data = 0x54,    // SMFIC_DATA ('T'): DATAコマンド
+    Unknown = 0x55, // SMFIC_UNKNOWN ('U'): MTAが知らないSMTPコマンド
 }
 
 impl MilterCommand {
-    /// EOH(0x45)を文脈に応じてSMFIC_EOHまたはSMFIC_BODYEOBとして返す補助関数
-    /// is_body_eob=trueならBODYEOB、falseならEOHとして用途名を返す
-    pub fn as_str_eoh(&self, is_body_eob: bool) -> &'static str {
-        match (self, is_body_eob) {
-            (MilterCommand::Eoh, true) => "SMFIC_BODYEOB",
-            (MilterCommand::Eom, false) => "SMFIC_EOH",
-            _ => self.as_str(),
-        }
-    }
     /// MilterCommandをコマンド名文字列（用途名）に変換
-    /// 例: SMFIC_ABORT, SMFIC_ACCEPT ...
+    /// 例: SMFIC_ABORT, SMFIC_BODY ...
     pub fn as_str(&self) -> &'static str {
         match self {
             MilterCommand::Abort => "SMFIC_ABORT",
-            MilterCommand::Accept => "SMFIC_ACCEPT",
-            MilterCommand::AddHeader => "SMFIC_ADDHEADER",
-            // MilterCommand::AddRcpt => "SMFIC_ADDRCPT", // Acceptと重複
             MilterCommand::Body => "SMFIC_BODY",
             MilterCommand::Connect => "SMFIC_CONNECT",
-            MilterCommand::Data => "SMFIC_DATA",
-            MilterCommand::DeleteHeader => "SMFIC_DELHEADER",
-            MilterCommand::DeleteRcpt => "SMFIC_DELRCPT",
-            MilterCommand::Eoh => "SMFIC_EOH",
-            MilterCommand::Eom => "SMFIC_EOM",
-            MilterCommand::Header => "SMFIC_HEADER",
+            MilterCommand::Macro => "SMFIC_MACRO",
+            MilterCommand::BodyEob => "SMFIC_BODYEOB",
             MilterCommand::HeLO => "SMFIC_HELO",
-            // MilterCommand::Macro => "SMFIC_MACRO", // EOMと重複
+            MilterCommand::QuitNc => "SMFIC_QUIT_NC",
+            MilterCommand::Header => "SMFIC_HEADER",
+            MilterCommand::Mail => "SMFIC_MAIL",
+            MilterCommand::Eoh => "SMFIC_EOH",
             MilterCommand::OptNeg => "SMFIC_OPTNEG",
             MilterCommand::Quit => "SMFIC_QUIT",
             MilterCommand::Rcpt => "SMFIC_RCPT",
+            MilterCommand::Data => "SMFIC_DATA",
+            MilterCommand::Unknown => "SMFIC_UNKNOWN",
         }
     }
     /// 1バイト値からMilterCommandへ変換（Postfix/Sendmail互換）
@@ -159,21 +179,274 @@ impl MilterCommand {
     pub fn from_u8(val: u8) -> Option<Self> {
         match val {
             b'A' => Some(MilterCommand::Abort),
-            b'a' => Some(MilterCommand::Accept), // AddRcptはAcceptと重複
-            b'h' => Some(MilterCommand::AddHeader),
             b'B' => Some(MilterCommand::Body),
             b'C' => Some(MilterCommand::Connect),
-            b'D' => Some(MilterCommand::Data),
-            b'd' => Some(MilterCommand::DeleteHeader),
-            b'r' => Some(MilterCommand::DeleteRcpt),
-            b'E' => Some(MilterCommand::Eoh),
-            b'M' => Some(MilterCommand::Eom), // MacroはEOMと重複
-            b'L' => Some(MilterCommand::Header),
+            b'D' => Some(MilterCommand::Macro),
+            b'E' => Some(MilterCommand::BodyEob),
             b'H' => Some(MilterCommand::HeLO),
+            b'K' => Some(MilterCommand::QuitNc),
+            b'L' => Some(MilterCommand::Header),
+            b'M' => Some(MilterCommand::Mail),
+            b'N' => Some(MilterCommand::Eoh),
             b'O' => Some(MilterCommand::OptNeg),
             b'Q' => Some(MilterCommand::Quit),
             b'R' => Some(MilterCommand::Rcpt),
+            b'T' => Some(MilterCommand::Data),
+            b'U' => Some(MilterCommand::Unknown),
             _ => None,
         }
     }
 }
+
+// =========================
+// Milter応答定義（mfdef.hのSMFIR_*より、受理/拒否判定に使うもの）
+// - 各フェーズ（CONNECT, HELO, MAIL, RCPT, EOH, EOM）の判定結果としてMTAへ返す
+// - to_packetで送信用バイト列（4バイト長 + 1バイトコマンド + ペイロード）を生成
+// =========================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MilterResponse {
+    Continue, // SMFIR_CONTINUE ('c'): 処理継続
+    Accept,   // SMFIR_ACCEPT ('a'): メール受理（以降のコマンドは送られない）
+    Reject,   // SMFIR_REJECT ('r'): 拒否（5xx）
+    TempFail, // SMFIR_TEMPFAIL ('t'): 一時エラー（4xx）
+    Discard,  // SMFIR_DISCARD ('d'): 受理したうえで破棄
+    ReplyCode {
+        code: u16,             // SMTP応答コード（4xx/5xx）
+        xcode: Option<String>, // 拡張ステータスコード（例: 5.7.1）
+        text: String,          // 応答テキスト
+    }, // SMFIR_REPLYCODE ('y'): 任意のSMTP応答
+}
+
+impl MilterResponse {
+    /// SMFIR_REPLYCODE応答を検証付きで生成
+    ///
+    /// # 説明
+    /// - codeは400〜599のみ許可
+    /// - xcodeは「クラス.サブジェクト.詳細」形式で、クラスがcodeの先頭桁と一致すること
+    /// - textに改行は含められない
+    pub fn reply_code(code: u16, xcode: Option<&str>, text: &str) -> Result<Self, String> {
+        if !(400..600).contains(&code) {
+            return Err(format!("応答コードは4xx/5xxのみ指定可能: {}", code));
+        }
+        if let Some(x) = xcode {
+            let parts: Vec<&str> = x.split('.').collect(); // クラス.サブジェクト.詳細
+            let valid = parts.len() == 3
+                && parts
+                    .iter()
+                    .all(|p| !p.is_empty() && p.len() <= 3 && p.bytes().all(|b| b.is_ascii_digit()));
+            if !valid || parts[0] != (code / 100).to_string() {
+                return Err(format!("拡張ステータスコード不正: {} (code={})", x, code));
+            }
+        }
+        if text.contains('\r') || text.contains('\n') {
+            return Err("応答テキストに改行は含められません".to_string());
+        }
+        Ok(MilterResponse::ReplyCode {
+            code,
+            xcode: xcode.map(|x| x.to_string()),
+            text: text.to_string(),
+        })
+    }
+
+    /// 応答コマンドの1バイト値
+    pub fn command_byte(&self) -> u8 {
+        match self {
+            MilterResponse::Continue => b'c',
+            MilterResponse::Accept => b'a',
+            MilterResponse::Reject => b'r',
+            MilterResponse::TempFail => b't',
+            MilterResponse::Discard => b'd',
+            MilterResponse::ReplyCode { .. } => b'y',
+        }
+    }
+
+    /// 応答名文字列（ログ用）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MilterResponse::Continue => "SMFIR_CONTINUE",
+            MilterResponse::Accept => "SMFIR_ACCEPT",
+            MilterResponse::Reject => "SMFIR_REJECT",
+            MilterResponse::TempFail => "SMFIR_TEMPFAIL",
+            MilterResponse::Discard => "SMFIR_DISCARD",
+            MilterResponse::ReplyCode { .. } => "SMFIR_REPLYCODE",
+        }
+    }
+
+    /// 送信用パケット（4バイト長 + 1バイトコマンド + ペイロード）を生成
+    /// - SMFIR_REPLYCODEのペイロードは「code xcode text\0」（textの%は%%にエスケープ）
+    pub fn to_packet(&self) -> Vec<u8> {
+        let payload = match self {
+            MilterResponse::ReplyCode { code, xcode, text } => {
+                let mut line = code.to_string(); // 応答コード
+                if let Some(x) = xcode {
+                    line.push(' ');
+                    line.push_str(x); // 拡張ステータスコード
+                }
+                line.push(' ');
+                line.push_str(&text.replace('%', "%%")); // MTA側の書式展開対策
+                let mut bytes = line.into_bytes();
+                bytes.push(0x00); // NUL終端
+                bytes
+            }
+            _ => Vec::new(), // その他の応答はペイロード無し
+        };
+        let mut packet = Vec::with_capacity(5 + payload.len()); // 応答バッファ
+        packet.extend_from_slice(&((payload.len() + 1) as u32).to_be_bytes()); // サイズ（4バイト）
+        packet.push(self.command_byte()); // コマンド（1バイト）
+        packet.extend_from_slice(&payload); // ペイロード
+        packet
+    }
+}
+
+// =========================
+// メッセージ変更アクション定義（mfdef.hのSMFIR_*より、EOM時に最終応答より前に送るもの）
+// - OPTNEGで合意したアクションフラグ（SMFIF_*）が必要
+// - to_packetsで送信用バイト列を生成（REPLBODYは最大チャンクサイズで分割）
+// =========================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MilterModification {
+    AddHeader {
+        name: String,  // ヘッダ名
+        value: String, // ヘッダ値
+    }, // SMFIR_ADDHEADER ('h'): ヘッダ末尾追加
+    ChangeHeader {
+        index: u32,    // 同名ヘッダ中の出現番号（1始まり）
+        name: String,  // ヘッダ名
+        value: String, // 新しい値（空文字でヘッダ削除）
+    }, // SMFIR_CHGHEADER ('m'): ヘッダ変更・削除
+    InsertHeader {
+        index: u32,    // 挿入位置（0で先頭）
+        name: String,  // ヘッダ名
+        value: String, // ヘッダ値
+    }, // SMFIR_INSHEADER ('i'): ヘッダ挿入
+    AddRcpt {
+        rcpt: String, // 追加する受信者
+    }, // SMFIR_ADDRCPT ('+'): 受信者追加
+    DelRcpt {
+        rcpt: String, // 削除する受信者
+    }, // SMFIR_DELRCPT ('-'): 受信者削除
+    AddRcptPar {
+        rcpt: String,         // 追加する受信者
+        args: Option<String>, // ESMTPパラメータ（例: NOTIFY=NEVER）
+    }, // SMFIR_ADDRCPT_PAR ('2'): ESMTPパラメータ付き受信者追加
+    ReplaceBody {
+        body: Vec<u8>, // 新しい本文（生バイト列）
+    }, // SMFIR_REPLBODY ('b'): 本文置換
+    ChangeFrom {
+        sender: String,       // 新しいエンベロープ送信者
+        args: Option<String>, // ESMTPパラメータ（例: SIZE=1024）
+    }, // SMFIR_CHGFROM ('e'): エンベロープ送信者変更
+    Quarantine {
+        reason: String, // 隔離理由
+    }, // SMFIR_QUARANTINE ('q'): 隔離
+}
+
+impl MilterModification {
+    /// SMFIR_REPLBODYの1パケットあたりの最大本文サイズ（libmilterのMILTER_CHUNK_SIZE）
+    const BODY_CHUNK_SIZE: usize = 65535;
+
+    /// 応答コマンドの1バイト値
+    pub fn command_byte(&self) -> u8 {
+        match self {
+            MilterModification::AddHeader { .. } => b'h',
+            MilterModification::ChangeHeader { .. } => b'm',
+            MilterModification::InsertHeader { .. } => b'i',
+            MilterModification::AddRcpt { .. } => b'+',
+            MilterModification::DelRcpt { .. } => b'-',
+            MilterModification::AddRcptPar { .. } => b'2',
+            MilterModification::ReplaceBody { .. } => b'b',
+            MilterModification::ChangeFrom { .. } => b'e',
+            MilterModification::Quarantine { .. } => b'q',
+        }
+    }
+
+    /// 応答名文字列（ログ用）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MilterModification::AddHeader { .. } => "SMFIR_ADDHEADER",
+            MilterModification::ChangeHeader { .. } => "SMFIR_CHGHEADER",
+            MilterModification::InsertHeader { .. } => "SMFIR_INSHEADER",
+            MilterModification::AddRcpt { .. } => "SMFIR_ADDRCPT",
+            MilterModification::DelRcpt { .. } => "SMFIR_DELRCPT",
+            MilterModification::AddRcptPar { .. } => "SMFIR_ADDRCPT_PAR",
+            MilterModification::ReplaceBody { .. } => "SMFIR_REPLBODY",
+            MilterModification::ChangeFrom { .. } => "SMFIR_CHGFROM",
+            MilterModification::Quarantine { .. } => "SMFIR_QUARANTINE",
+        }
+    }
+
+    /// 送信に必要なアクションフラグ（SMFIF_*）
+    pub fn required_action(&self) -> u32 {
+        match self {
+            MilterModification::AddHeader { .. } => SMFIF_ADDHDRS,
+            MilterModification::ReplaceBody { .. } => SMFIF_CHGBODY,
+            MilterModification::AddRcpt { .. } => SMFIF_ADDRCPT,
+            MilterModification::DelRcpt { .. } => SMFIF_DELRCPT,
+            MilterModification::ChangeHeader { .. } | MilterModification::InsertHeader { .. } => {
+                SMFIF_CHGHDRS
+            }
+            MilterModification::Quarantine { .. } => SMFIF_QUARANTINE,
+            MilterModification::ChangeFrom { .. } => SMFIF_CHGFROM,
+            MilterModification::AddRcptPar { .. } => SMFIF_ADDRCPT_PAR,
+        }
+    }
+
+    /// 送信用パケット列（各4バイト長 + 1バイトコマンド + ペイロード）を生成
+    /// - 文字列引数はNUL終端、CHGHEADER/INSHEADERは先頭に4バイトのインデックス
+    /// - REPLBODYはBODY_CHUNK_SIZEごとに複数パケットへ分割（空の本文は空のパケット1つ）
+    /// - `leading_space`: SMFIP_HDR_LEADSPC合意時はヘッダ値の先頭に空白を付ける
+    pub fn to_packets(&self, leading_space: bool) -> Vec<Vec<u8>> {
+        // NUL終端文字列を連結したペイロードを生成
+        fn nul_terminated(items: &[&str]) -> Vec<u8> {
+            let mut buf = Vec::new();
+            for item in items {
+                buf.extend_from_slice(item.as_bytes());
+                buf.push(0x00);
+            }
+            buf
+        }
+        // ヘッダ値（HDR_LEADSPC合意時は区切りの空白を値側に含める、空値=削除はそのまま）
+        let header_value = |value: &str| -> String {
+            if leading_space && !value.is_empty() {
+                format!(" {}", value)
+            } else {
+                value.to_string()
+            }
+        };
+        let payloads: Vec<Vec<u8>> = match self {
+            MilterModification::AddHeader { name, value } => {
+                vec![nul_terminated(&[name, &header_value(value)])]
+            }
+            MilterModification::ChangeHeader { index, name, value }
+            | MilterModification::InsertHeader { index, name, value } => {
+                let mut buf = index.to_be_bytes().to_vec(); // インデックス（4バイト）
+                buf.extend_from_slice(&nul_terminated(&[name, &header_value(value)]));
+                vec![buf]
+            }
+            MilterModification::AddRcpt { rcpt } | MilterModification::DelRcpt { rcpt } => {
+                vec![nul_terminated(&[rcpt])]
+            }
+            MilterModification::AddRcptPar { rcpt: addr, args }
+            | MilterModification::ChangeFrom { sender: addr, args } => match args {
+                Some(a) => vec![nul_terminated(&[addr, a])],
+                None => vec![nul_terminated(&[addr])],
+            },
+            MilterModification::ReplaceBody { body } if body.is_empty() => vec![Vec::new()], // 0パケットでは本文が置換されない
+            MilterModification::ReplaceBody { body } => body
+                .chunks(Self::BODY_CHUNK_SIZE)
+                .map(|c| c.to_vec())
+                .collect(),
+            MilterModification::Quarantine { reason } => vec![nul_terminated(&[reason])],
+        };
+        payloads
+            .into_iter()
+            .map(|payload| {
+                let mut packet = Vec::with_capacity(5 + payload.len()); // 応答バッファ
+                packet.extend_from_slice(&((payload.len() + 1) as u32).to_be_bytes()); // サイズ（4バイト）
+                packet.push(self.command_byte()); // コマンド（1バイト）
+                packet.extend_from_slice(&payload); // ペイロード
+                packet
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,209 @@
+// =========================
+// negotiate.rs
+// MilterDecoder OPTNEG（オプション交渉）能力モデル
+//
+// 【このファイルで使う主なクレート】
+// - std: 標準ライブラリ（ビット演算、比較）
+// - crate::milter_command: Milterコマンド種別（応答要否の判定に使用）
+//
+// 【役割】
+// - Milterプロトコルv6のアクションフラグ（SMFIF_*）・プロトコルフラグ（SMFIP_*）定義
+// - MTAの提示内容とサーバーが実装している能力の共通部分（合意内容）の算出
+// - 合意内容（バージョン・アクション・プロトコルフラグ）の保持と問い合わせ
+// - 応答省略フラグ（SMFIP_NR_*）に基づくコマンドごとの応答要否判定
+// - ログ出力用のフラグ名一覧
+// =========================
+
+use crate::milter_command::MilterCommand; // Milterコマンド種別
+
+// --- アクションフラグ（SMFIF_*: Milterが行うメッセージ変更の種類） ---
+pub const SMFIF_ADDHDRS: u32 = 0x0000_0001; // ヘッダ追加
+pub const SMFIF_CHGBODY: u32 = 0x0000_0002; // 本文置換
+pub const SMFIF_ADDRCPT: u32 = 0x0000_0004; // 受信者追加
+pub const SMFIF_DELRCPT: u32 = 0x0000_0008; // 受信者削除
+pub const SMFIF_CHGHDRS: u32 = 0x0000_0010; // ヘッダ変更・挿入
+pub const SMFIF_QUARANTINE: u32 = 0x0000_0020; // 隔離
+pub const SMFIF_CHGFROM: u32 = 0x0000_0040; // エンベロープ送信者変更
+pub const SMFIF_ADDRCPT_PAR: u32 = 0x0000_0080; // ESMTPパラメータ付き受信者追加
+pub const SMFIF_SETSYMLIST: u32 = 0x0000_0100; // マクロ一覧指定
+
+// --- プロトコルフラグ（SMFIP_*: 送信省略・応答省略・拡張動作） ---
+pub const SMFIP_NOCONNECT: u32 = 0x0000_0001; // CONNECTを送らない
+pub const SMFIP_NOHELO: u32 = 0x0000_0002; // HELOを送らない
+pub const SMFIP_NOMAIL: u32 = 0x0000_0004; // MAIL FROMを送らない
+pub const SMFIP_NORCPT: u32 = 0x0000_0008; // RCPT TOを送らない
+pub const SMFIP_NOBODY: u32 = 0x0000_0010; // BODYを送らない
+pub const SMFIP_NOHDRS: u32 = 0x0000_0020; // HEADERを送らない
+pub const SMFIP_NOEOH: u32 = 0x0000_0040; // EOHを送らない
+pub const SMFIP_NR_HDR: u32 = 0x0000_0080; // HEADERに応答しない
+pub const SMFIP_NOUNKNOWN: u32 = 0x0000_0100; // UNKNOWNを送らない
+pub const SMFIP_NODATA: u32 = 0x0000_0200; // DATAを送らない
+pub const SMFIP_SKIP: u32 = 0x0000_0400; // SMFIR_SKIPを受け付ける
+pub const SMFIP_RCPT_REJ: u32 = 0x0000_0800; // 拒否済みRCPTも送る
+pub const SMFIP_NR_CONN: u32 = 0x0000_1000; // CONNECTに応答しない
+pub const SMFIP_NR_HELO: u32 = 0x0000_2000; // HELOに応答しない
+pub const SMFIP_NR_MAIL: u32 = 0x0000_4000; // MAIL FROMに応答しない
+pub const SMFIP_NR_RCPT: u32 = 0x0000_8000; // RCPT TOに応答しない
+pub const SMFIP_NR_DATA: u32 = 0x0001_0000; // DATAに応答しない
+pub const SMFIP_NR_UNKN: u32 = 0x0002_0000; // UNKNOWNに応答しない
+pub const SMFIP_NR_EOH: u32 = 0x0004_0000; // EOHに応答しない
+pub const SMFIP_NR_BODY: u32 = 0x0008_0000; // BODYに応答しない
+pub const SMFIP_HDR_LEADSPC: u32 = 0x0010_0000; // ヘッダ値の先頭空白を削らずに送る
+pub const SMFIP_MDS_256K: u32 = 0x1000_0000; // 最大データサイズ256KB
+pub const SMFIP_MDS_1M: u32 = 0x2000_0000; // 最大データサイズ1MB
+
+/// サーバーがサポートする最大プロトコルバージョン
+pub const SMFI_VERSION: u32 = 6;
+
+/// サーバーが実装しているアクション（MilterModificationで送信可能なもの + 要求マクロ一覧）
+const SERVER_ACTIONS: u32 = SMFIF_ADDHDRS
+    | SMFIF_CHGBODY
+    | SMFIF_ADDRCPT
+    | SMFIF_DELRCPT
+    | SMFIF_CHGHDRS
+    | SMFIF_QUARANTINE
+    | SMFIF_CHGFROM
+    | SMFIF_ADDRCPT_PAR
+    | SMFIF_SETSYMLIST;
+
+/// サーバーが要求するプロトコルフラグ（MTAが提示した場合のみ合意）
+/// - DATA/HEADER/BODY/UNKNOWNには判定を返さないため応答省略を要求
+/// - ヘッダ値は先頭空白も含めた生の値で受け取る
+/// - NO*（送信省略）は要求しない（全コマンドを受信して解析する）
+const SERVER_PROTOCOL: u32 =
+    SMFIP_NR_DATA | SMFIP_NR_HDR | SMFIP_NR_BODY | SMFIP_NR_UNKN | SMFIP_HDR_LEADSPC;
+
+/// ログ出力用アクションフラグ名一覧
+pub const ACTION_FLAG_NAMES: [(u32, &str); 9] = [
+    (SMFIF_ADDHDRS, "SMFIF_ADDHDRS"),
+    (SMFIF_CHGBODY, "SMFIF_CHGBODY"),
+    (SMFIF_ADDRCPT, "SMFIF_ADDRCPT"),
+    (SMFIF_DELRCPT, "SMFIF_DELRCPT"),
+    (SMFIF_CHGHDRS, "SMFIF_CHGHDRS"),
+    (SMFIF_QUARANTINE, "SMFIF_QUARANTINE"),
+    (SMFIF_CHGFROM, "SMFIF_CHGFROM"),
+    (SMFIF_ADDRCPT_PAR, "SMFIF_ADDRCPT_PAR"),
+    (SMFIF_SETSYMLIST, "SMFIF_SETSYMLIST"),
+];
+
+/// ログ出力用プロトコルフラグ名一覧
+pub const PROTOCOL_FLAG_NAMES: [(u32, &str); 23] = [
+    (SMFIP_NOCONNECT, "SMFIP_NOCONNECT"),
+    (SMFIP_NOHELO, "SMFIP_NOHELO"),
+    (SMFIP_NOMAIL, "SMFIP_NOMAIL"),
+    (SMFIP_NORCPT, "SMFIP_NORCPT"),
+    (SMFIP_NOBODY, "SMFIP_NOBODY"),
+    (SMFIP_NOHDRS, "SMFIP_NOHDRS"),
+    (SMFIP_NOEOH, "SMFIP_NOEOH"),
+    (SMFIP_NR_HDR, "SMFIP_NR_HDR"),
+    (SMFIP_NOUNKNOWN, "SMFIP_NOUNKNOWN"),
+    (SMFIP_NODATA, "SMFIP_NODATA"),
+    (SMFIP_SKIP, "SMFIP_SKIP"),
+    (SMFIP_RCPT_REJ, "SMFIP_RCPT_REJ"),
+    (SMFIP_NR_CONN, "SMFIP_NR_CONN"),
+    (SMFIP_NR_HELO, "SMFIP_NR_HELO"),
+    (SMFIP_NR_MAIL, "SMFIP_NR_MAIL"),
+    (SMFIP_NR_RCPT, "SMFIP_NR_RCPT"),
+    (SMFIP_NR_DATA, "SMFIP_NR_DATA"),
+    (SMFIP_NR_UNKN, "SMFIP_NR_UNKN"),
+    (SMFIP_NR_EOH, "SMFIP_NR_EOH"),
+    (SMFIP_NR_BODY, "SMFIP_NR_BODY"),
+    (SMFIP_HDR_LEADSPC, "SMFIP_HDR_LEADSPC"),
+    (SMFIP_MDS_256K, "SMFIP_MDS_256K"),
+    (SMFIP_MDS_1M, "SMFIP_MDS_1M"),
+];
+
+/// フラグ値を名前の一覧（" | "区切り）に変換（ログ用）
+pub fn flag_names(value: u32, table: &[(u32, &str)]) -> String {
+    let names: Vec<&str> = table
+        .iter()
+        .filter(|(flag, _)| value & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        "(なし)".to_string()
+    } else {
+        names.join(" | ")
+    }
+}
+
+/// OPTNEGで合意した内容（接続単位）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NegotiatedOptions {
+    pub version: u32,  // 合意したプロトコルバージョン
+    pub actions: u32,  // 合意したアクションフラグ（SMFIF_*）
+    pub protocol: u32, // 合意したプロトコルフラグ（SMFIP_*）
+}
+
+impl NegotiatedOptions {
+    /// MTAの提示内容とサーバー能力の共通部分を算出
+    ///
+    /// # 引数
+    /// - `version`: MTAのプロトコルバージョン
+    /// - `actions`: MTAが許可するアクションフラグ
+    /// - `protocol`: MTAがサポートするプロトコルフラグ
+    ///
+    /// # 説明
+    /// - バージョンはMTAとサーバーの低い方
+    /// - アクション・プロトコルフラグはMTAが提示したもののうちサーバーが実装しているもののみ
+    /// - 最大データサイズは提示された中で大きい方を1つだけ選ぶ
+    pub fn negotiate(version: u32, actions: u32, protocol: u32) -> Self {
+        let mut agreed = protocol & SERVER_PROTOCOL; // 提示されたものだけを要求
+        if protocol & SMFIP_MDS_1M != 0 {
+            agreed |= SMFIP_MDS_1M; // 受信側はサイズ上限なしのため最大を選択
+        } else if protocol & SMFIP_MDS_256K != 0 {
+            agreed |= SMFIP_MDS_256K;
+        }
+        NegotiatedOptions {
+            version: version.min(SMFI_VERSION),
+            actions: actions & SERVER_ACTIONS,
+            protocol: agreed,
+        }
+    }
+
+    /// 指定アクションフラグ（SMFIF_*）が合意済みか
+    pub fn has_action(&self, flag: u32) -> bool {
+        self.actions & flag == flag
+    }
+
+    /// 指定プロトコルフラグ（SMFIP_*）が合意済みか
+    pub fn has_protocol(&self, flag: u32) -> bool {
+        self.protocol & flag == flag
+    }
+
+    /// 指定コマンドに応答（SMFIR_*）が必要か
+    ///
+    /// # 説明
+    /// - CONNECT/HELO/MAIL/RCPT/DATA/HEADER/EOH/BODY/UNKNOWNは対応するSMFIP_NR_*合意時のみ応答不要
+    /// - BODYEOBは常に応答が必要
+    /// - OPTNEG（専用応答）・MACRO・ABORT・QUIT・QUIT_NCには応答しない
+    pub fn expects_reply(&self, cmd: MilterCommand) -> bool {
+        let no_reply_flag = match cmd {
+            MilterCommand::Connect => SMFIP_NR_CONN,
+            MilterCommand::HeLO => SMFIP_NR_HELO,
+            MilterCommand::Mail => SMFIP_NR_MAIL,
+            MilterCommand::Rcpt => SMFIP_NR_RCPT,
+            MilterCommand::Data => SMFIP_NR_DATA,
+            MilterCommand::Header => SMFIP_NR_HDR,
+            MilterCommand::Eoh => SMFIP_NR_EOH,
+            MilterCommand::Body => SMFIP_NR_BODY,
+            MilterCommand::Unknown => SMFIP_NR_UNKN,
+            MilterCommand::BodyEob => return true,
+            MilterCommand::OptNeg
+            | MilterCommand::Macro
+            | MilterCommand::Abort
+            | MilterCommand::Quit
+            | MilterCommand::QuitNc => return false,
+        };
+        !self.has_protocol(no_reply_flag)
+    }
+
+    /// OPTNEG応答ペイロード（バージョン + アクション + プロトコルフラグ、各4バイト）
+    pub fn to_payload(self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(12);
+        payload.extend_from_slice(&self.version.to_be_bytes()); // プロトコルバージョン
+        payload.extend_from_slice(&self.actions.to_be_bytes()); // アクションフラグ
+        payload.extend_from_slice(&self.protocol.to_be_bytes()); // プロトコルフラグ
+        payload
+    }
+}
@@ -0,0 +1,537 @@
+// =========================
+// office.rs
+// MilterDecoder Office文書（OLE2/OOXML）解析モジュール
+//
+// 【このファイルで使う主なクレート】
+// - cfb: OLE2（Compound File Binary）形式のストレージ・ストリーム読み取り（.doc/.xls）
+// - zip: OOXML（.docx/.docm/.xlsx/.xlsm等）のメンバー読み取り
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: バイト列読み取り（io::Read/Cursor）
+//
+// 【役割】
+// - VBAプロジェクト（マクロ）の検出
+// - 外部テンプレート参照（リモートattachedTemplate）の検出
+// - 埋め込みOLEオブジェクト・外部リンクOLEオブジェクトの検出
+// - DDEフィールド（Wordのフィールドコード、Excelの外部DDEリンク）の検出
+// - Excel 4.0マクロシート（XLM）の検出
+// - 検出結果は種別・場所・詳細を持つ指標（OfficeIndicator）として添付レコードに付与
+// =========================
+
+use serde::Serialize; // JSON出力用
+use std::io::{Cursor, Read}; // ストリーム・メンバーの読み取り
+
+use crate::filetype::FileKind; // マジックバイトによるファイル種別
+
+const MAX_STREAM_READ: usize = 16 * 1024 * 1024; // 解析のために読み取るストリーム・メンバーの最大サイズ
+const MAX_INDICATORS: usize = 100; // 1文書あたりの指標の上限
+const MAX_INSTRUCTION: usize = 512; // フィールドコードとして記録する最大文字数
+
+/// 指標の種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndicatorKind {
+    VbaProject,     // VBAプロジェクト（マクロ）
+    RemoteTemplate, // 外部テンプレート参照（attachedTemplate）
+    OleObject,      // 埋め込み・外部リンクのOLEオブジェクト
+    DdeField,       // DDEフィールド・DDEリンク
+    Excel4Macro,    // Excel 4.0マクロシート（XLM）
+}
+
+impl IndicatorKind {
+    /// ログ・JSON出力用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndicatorKind::VbaProject => "vba_project",
+            IndicatorKind::RemoteTemplate => "remote_template",
+            IndicatorKind::OleObject => "ole_object",
+            IndicatorKind::DdeField => "dde_field",
+            IndicatorKind::Excel4Macro => "excel4_macro",
+        }
+    }
+}
+
+/// 指標1件分
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OfficeIndicator {
+    pub kind: IndicatorKind,    // 指標の種別
+    pub location: String,       // 検出場所（OLE2のストレージ・ストリームのパス、OOXMLのメンバー名）
+    pub detail: Option<String>, // 詳細（参照先URL、DDEのフィールドコード、シート名等）
+}
+
+/// Office文書1件分の解析結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OfficeInfo {
+    pub format: FileKind,                 // 文書形式（ole2/ooxml）
+    pub indicators: Vec<OfficeIndicator>, // 検出した指標（検出順）
+    pub error: Option<String>,            // 読み取りエラー（途中までの指標は残す）
+}
+
+impl OfficeInfo {
+    /// 指標を追加（上限を超えた分は捨てる）
+    fn push(&mut self, kind: IndicatorKind, location: &str, detail: Option<String>) {
+        if self.indicators.len() < MAX_INDICATORS {
+            self.indicators.push(OfficeIndicator {
+                kind,
+                location: location.to_string(),
+                detail,
+            });
+        }
+    }
+}
+
+/// 添付データがOffice文書なら指標を収集（OLE2/OOXML以外はNone）
+///
+/// # 引数
+/// - `data`: デコード済み添付データ
+/// - `kind`: filetype::detectの判定結果
+///
+/// # 説明
+/// - 指標が無い文書もSome（indicatorsは空）で返し、解析したことを区別できるようにする
+/// - 読み取りエラーはerrorに記録し、それまでの指標は返す
+pub fn inspect(data: &[u8], kind: Option<FileKind>) -> Option<OfficeInfo> {
+    let kind = kind?;
+    let analyze: fn(&[u8], &mut OfficeInfo) -> Result<(), String> = match kind {
+        FileKind::Ole2 => inspect_ole2,
+        FileKind::Ooxml => inspect_ooxml,
+        _ => return None, // Office文書以外
+    };
+    let mut info = OfficeInfo {
+        format: kind,
+        indicators: Vec::new(),
+        error: None,
+    };
+    if let Err(e) = analyze(data, &mut info) {
+        info.error = Some(e);
+    }
+    Some(info)
+}
+
+/// OLE2: ストレージ構成とWordDocument/Workbookストリームから指標を収集
+///
+/// # 説明
+/// - VBAストレージ（Macros/VBA、_VBA_PROJECT_CUR/VBA）があればVBAプロジェクト
+/// - \x01Ole・\x01Ole10Native・\x01CompObjを持つルート以外のストレージは埋め込みOLEオブジェクト
+/// - WordDocumentストリームのフィールドコード（0x13で始まる）がDDE/DDEAUTOならDDEフィールド
+/// - Workbook（BIFF8）のBOUNDSHEETがマクロシートならXLM、SUPBOOKがDDEリンクならDDEフィールド
+fn inspect_ole2(data: &[u8], info: &mut OfficeInfo) -> Result<(), String> {
+    let mut file = cfb::CompoundFile::open(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let mut streams = Vec::new(); // (親ストレージのパス, ストリーム名)
+    for entry in file.walk() {
+        let path = entry.path().to_string_lossy().to_string();
+        if entry.is_storage() && !entry.is_root() && entry.name().eq_ignore_ascii_case("VBA") {
+            info.push(IndicatorKind::VbaProject, &path, None);
+        }
+        if entry.is_stream() {
+            let parent = entry
+                .path()
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            streams.push((parent, entry.name().to_string()));
+        }
+    }
+
+    // 埋め込みOLEオブジェクト（ストレージ単位で1件）
+    let mut objects: Vec<&str> = Vec::new();
+    for (parent, name) in &streams {
+        let ole_stream = matches!(
+            name.as_str(),
+            "\u{1}Ole" | "\u{1}Ole10Native" | "\u{1}CompObj"
+        );
+        if ole_stream && parent != "/" && !objects.contains(&parent.as_str()) {
+            objects.push(parent);
+        }
+    }
+    for parent in objects {
+        let label = read_stream(&mut file, &format!("{}/\u{1}Ole10Native", parent))
+            .and_then(|s| ole10_label(&s)); // パッケージの表示名（元のファイル名）
+        info.push(IndicatorKind::OleObject, parent, label);
+    }
+
+    // Word: フィールドコードのDDE
+    if let Some(stream) = read_stream(&mut file, "/WordDocument") {
+        for instruction in word_field_instructions(&stream) {
+            if is_dde(&instruction) {
+                info.push(IndicatorKind::DdeField, "/WordDocument", Some(instruction));
+            }
+        }
+    }
+
+    // Excel: BIFF8レコード
+    for name in ["/Workbook", "/Book"] {
+        if let Some(stream) = read_stream(&mut file, name) {
+            scan_biff(&stream, name, info);
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// OLE2のストリームを上限内で読み取る（無い・大きすぎる場合はNone）
+fn read_stream(file: &mut cfb::CompoundFile<Cursor<&[u8]>>, path: &str) -> Option<Vec<u8>> {
+    if !file.is_stream(path) {
+        return None;
+    }
+    let stream = file.open_stream(path).ok()?;
+    read_limited(stream)
+}
+
+/// 上限（16MB）内で全体を読み取る
+fn read_limited(reader: impl Read) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader
+        .take(MAX_STREAM_READ as u64 + 1)
+        .read_to_end(&mut buf)
+        .ok()?;
+    if buf.len() > MAX_STREAM_READ {
+        return None;
+    }
+    Some(buf)
+}
+
+/// \x01Ole10Nativeストリームから表示名（ラベル）を取得
+///
+/// # 説明
+/// - 先頭4バイトのサイズ、2バイトのフラグの後にNUL終端のラベルが続く
+fn ole10_label(stream: &[u8]) -> Option<String> {
+    let rest = stream.get(6..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    let label = String::from_utf8_lossy(&rest[..end]).trim().to_string();
+    (!label.is_empty()).then_some(label)
+}
+
+/// WordDocumentストリームからフィールドコードを抽出
+///
+/// # 説明
+/// - フィールドは0x13（開始）〜0x14（区切り）/0x15（終了）で囲まれる
+/// - 本文が8bit（cp1252）かUTF-16LEかはピーステーブル次第のため、0x13直後の並びで判定する
+fn word_field_instructions(stream: &[u8]) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < stream.len() {
+        if stream[i] != 0x13 {
+            i += 1;
+            continue;
+        }
+        let wide = stream.get(i + 1) == Some(&0) && stream.get(i + 3) == Some(&0); // UTF-16LE
+        let step = if wide { 2 } else { 1 };
+        let mut pos = i + step; // フィールドコードの先頭
+        let mut text = String::new();
+        while pos < stream.len() && text.len() < MAX_INSTRUCTION {
+            let c = if wide {
+                match stream.get(pos..pos + 2) {
+                    Some(b) => u16::from_le_bytes([b[0], b[1]]) as u32,
+                    None => break,
+                }
+            } else {
+                stream[pos] as u32
+            };
+            if c == 0x13 || c == 0x14 || c == 0x15 || c == 0 {
+                break; // 入れ子フィールド・区切り・終了
+            }
+            text.push(char::from_u32(c).unwrap_or('\u{fffd}'));
+            pos += step;
+        }
+        if !text.trim().is_empty() {
+            found.push(collapse_spaces(&text));
+        }
+        i = pos.max(i + 1);
+    }
+    found
+}
+
+/// フィールドコードがDDE（DDE/DDEAUTO）か
+fn is_dde(instruction: &str) -> bool {
+    let word = instruction
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_ascii_uppercase();
+    word == "DDE" || word == "DDEAUTO"
+}
+
+/// 連続する空白を1つにまとめ、前後の空白を除去
+fn collapse_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Excel（BIFF8）のレコードを走査してマクロシート・DDEリンクを検出
+///
+/// # 説明
+/// - BOUNDSHEET（0x0085）: hsState（表示状態）とdt（シート種別、1がマクロシート）
+/// - SUPBOOK（0x01AE）: 外部参照先。DDEリンクは「アプリケーション\x03トピック」形式
+fn scan_biff(stream: &[u8], location: &str, info: &mut OfficeInfo) {
+    let mut pos = 0;
+    while pos + 4 <= stream.len() {
+        let record = le16(stream, pos); // レコード種別
+        let len = le16(stream, pos + 2) as usize; // レコード長
+        let Some(body) = stream.get(pos + 4..pos + 4 + len) else {
+            break; // 途中で切れたストリーム
+        };
+        match record {
+            0x0085 if body.len() >= 8 && body[5] == 0x01 => {
+                let name = short_xl_string(&body[6..]).unwrap_or_default(); // シート名
+                let detail = match body[4] & 0x03 {
+                    1 => format!("{} (hidden)", name),
+                    2 => format!("{} (very hidden)", name), // VBA以外からは再表示できない
+                    _ => name,
+                };
+                info.push(IndicatorKind::Excel4Macro, location, Some(detail));
+            }
+            0x01AE if body.len() >= 4 => {
+                // ctab（2バイト）の後に文字数・フラグ・参照先パス
+                if let Some(path) = xl_string(&body[2..]).filter(|p| p.contains('\u{3}')) {
+                    let link = path.replace('\u{3}', "|"); // 「cmd|'/c ...'」形式で記録
+                    info.push(IndicatorKind::DdeField, location, Some(link));
+                }
+            }
+            _ => {}
+        }
+        pos += 4 + len;
+    }
+}
+
+/// BIFF8のShortXLUnicodeString（文字数1バイト＋フラグ1バイト＋文字列）
+fn short_xl_string(data: &[u8]) -> Option<String> {
+    let count = *data.first()? as usize;
+    biff_chars(data.get(1..)?, count)
+}
+
+/// BIFF8のXLUnicodeString（文字数2バイト＋フラグ1バイト＋文字列）
+fn xl_string(data: &[u8]) -> Option<String> {
+    if data.len() < 2 {
+        return None;
+    }
+    biff_chars(&data[2..], le16(data, 0) as usize)
+}
+
+/// フラグのbit0で1バイト文字（Latin-1）/UTF-16LEを切り替えて文字列を取得
+fn biff_chars(data: &[u8], count: usize) -> Option<String> {
+    let high_byte = data.first()? & 0x01 != 0;
+    let chars = &data[1..];
+    if high_byte {
+        let units: Vec<u16> = chars
+            .get(..count * 2)?
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        Some(String::from_utf16_lossy(&units))
+    } else {
+        Some(chars.get(..count)?.iter().map(|&b| b as char).collect())
+    }
+}
+
+/// 2バイトのリトルエンディアン整数（範囲外は0）
+fn le16(data: &[u8], at: usize) -> u16 {
+    data.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or(0)
+}
+
+/// OOXML: ZIPのメンバー名とリレーションシップ・本文XMLから指標を収集
+///
+/// # 説明
+/// - vbaProject.binがあればVBAプロジェクト、xl/macrosheets/配下があればXLM
+/// - */embeddings/配下のメンバーは埋め込みOLEオブジェクト（中身の種別を詳細に記録）
+/// - *.relsの外部（TargetMode="External"）attachedTemplateはリモートテンプレート、oleObjectは外部リンクOLE
+/// - word/*.xmlのフィールドコード（w:instrText・w:fldSimple）がDDE/DDEAUTOならDDEフィールド
+/// - xl/externalLinks/*.xmlのddeLinkはDDEリンク
+fn inspect_ooxml(data: &[u8], info: &mut OfficeInfo) -> Result<(), String> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    for i in 0..zip.len() {
+        let (name, skip) = {
+            let f = zip.by_index_raw(i).map_err(|e| e.to_string())?; // 展開せずにメタデータのみ
+            (f.name().to_string(), f.is_dir() || f.encrypted())
+        };
+        if skip {
+            continue;
+        }
+        let lower = name.to_ascii_lowercase();
+        if lower.ends_with("vbaproject.bin") {
+            info.push(IndicatorKind::VbaProject, &name, None);
+            continue;
+        }
+        if lower.starts_with("xl/macrosheets/") && lower.ends_with(".xml") {
+            info.push(IndicatorKind::Excel4Macro, &name, None);
+            continue;
+        }
+        let embedded = lower.contains("/embeddings/");
+        let xml = lower.ends_with(".rels")
+            || (lower.starts_with("word/") && lower.ends_with(".xml"))
+            || (lower.starts_with("xl/externallinks/") && lower.ends_with(".xml"));
+        if !embedded && !xml {
+            continue; // 指標の対象外のメンバー
+        }
+        let Some(content) = zip.by_index(i).ok().and_then(read_limited) else {
+            continue; // 未対応の圧縮方式・上限超過
+        };
+        if embedded {
+            let kind = crate::filetype::detect(&content).map(|k| k.name().to_string());
+            info.push(IndicatorKind::OleObject, &name, kind);
+            continue;
+        }
+        let text = String::from_utf8_lossy(&content);
+        if lower.ends_with(".rels") {
+            scan_relationships(&text, &name, info);
+        } else if lower.starts_with("word/") {
+            for instruction in xml_field_instructions(&text) {
+                if is_dde(&instruction) {
+                    info.push(IndicatorKind::DdeField, &name, Some(instruction));
+                }
+            }
+        } else {
+            for tag in xml_tags(&text).filter(|t| tag_name(t) == "ddeLink") {
+                let service = xml_attr(tag, "ddeService").unwrap_or_default();
+                let topic = xml_attr(tag, "ddeTopic").unwrap_or_default();
+                info.push(
+                    IndicatorKind::DdeField,
+                    &name,
+                    Some(format!("{}|{}", service, topic)),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// リレーションシップ（*.rels）の外部参照からリモートテンプレート・外部リンクOLEを検出
+fn scan_relationships(text: &str, location: &str, info: &mut OfficeInfo) {
+    for tag in xml_tags(text).filter(|t| tag_name(t) == "Relationship") {
+        let rel_type = xml_attr(tag, "Type").unwrap_or_default();
+        let Some(target) = xml_attr(tag, "Target") else {
+            continue;
+        };
+        let external = xml_attr(tag, "TargetMode").is_some_and(|m| m == "External");
+        if rel_type.ends_with("/attachedTemplate") && (external || target.contains("://")) {
+            info.push(IndicatorKind::RemoteTemplate, location, Some(target));
+        } else if rel_type.ends_with("/oleObject") && external {
+            info.push(IndicatorKind::OleObject, location, Some(target)); // 外部リンクのOLEオブジェクト
+        }
+    }
+}
+
+/// WordprocessingMLからフィールドコードを抽出
+///
+/// # 説明
+/// - 複合フィールドはw:fldChar（begin/separate/end）の間のw:instrTextを連結（ランをまたいで分割されるため）
+/// - 単純フィールドはw:fldSimpleのw:instr属性
+fn xml_field_instructions(text: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut stack: Vec<Option<String>> = Vec::new(); // 入れ子フィールドごとの連結中のコード
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + len];
+        rest = &rest[start + len + 1..];
+        match tag_name(tag) {
+            "w:fldChar" => match xml_attr(tag, "w:fldCharType").as_deref() {
+                Some("begin") => stack.push(Some(String::new())),
+                Some("separate") => {
+                    if let Some(code) = stack.last_mut().and_then(|c| c.take()) {
+                        found.push(collapse_spaces(&code)); // 以降は表示結果
+                    }
+                }
+                Some("end") => {
+                    if let Some(Some(code)) = stack.pop() {
+                        found.push(collapse_spaces(&code)); // separate無しのフィールド
+                    }
+                }
+                _ => {}
+            },
+            "w:instrText" if !tag.ends_with('/') => {
+                let content = &rest[..rest.find('<').unwrap_or(rest.len())];
+                if let Some(Some(code)) = stack.last_mut() {
+                    if code.len() < MAX_INSTRUCTION {
+                        code.push_str(&xml_unescape(content));
+                    }
+                }
+            }
+            "w:fldSimple" => {
+                if let Some(instr) = xml_attr(tag, "w:instr") {
+                    found.push(collapse_spaces(&instr));
+                }
+            }
+            _ => {}
+        }
+    }
+    found.retain(|c| !c.is_empty());
+    found
+}
+
+/// XMLの開始タグ（「<」「>」を除いた中身）を順に取得
+fn xml_tags(text: &str) -> impl Iterator<Item = &str> {
+    text.split('<')
+        .skip(1)
+        .filter_map(|s| s.split_once('>').map(|(tag, _)| tag))
+        .filter(|t| !t.starts_with('/') && !t.starts_with('?') && !t.starts_with('!'))
+}
+
+/// タグ名（名前空間接頭辞込み）
+fn tag_name(tag: &str) -> &str {
+    tag.split(|c: char| c.is_ascii_whitespace() || c == '/')
+        .next()
+        .unwrap_or("")
+}
+
+/// タグ内の属性値を取得（実体参照は展開）
+fn xml_attr(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(at) = rest.find(name) {
+        let before = rest[..at].chars().last(); // 属性名の直前は空白であること
+        let after = rest[at + name.len()..].trim_start();
+        rest = &rest[at + name.len()..];
+        if !before.is_some_and(|c| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=').map(str::trim_start) else {
+            continue;
+        };
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let end = value[1..].find(quote)?;
+        return Some(xml_unescape(&value[1..1 + end]));
+    }
+    None
+}
+
+/// XMLの実体参照（&amp;等・数値文字参照）を展開
+fn xml_unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|h| u32::from_str_radix(h, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&'); // 不明な参照はそのまま
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,224 @@
+// =========================
+// output.rs
+// MilterDecoder 構造化（JSON Lines）出力モジュール
+//
+// 【このファイルで使う主なクレート】
+// - serde: 出力レコードのシリアライズ定義（Serialize）
+// - serde_json: レコードのJSON文字列化
+// - chrono / chrono-tz: 出力時刻（JST、RFC3339形式）
+// - lazy_static: 書き込み排他用のグローバルMutex
+// - std: ファイル追記（fs::OpenOptions）、排他（sync::Mutex）、コレクション
+//
+// 【役割】
+// - 1メール1行のJSONレコード（MessageRecord）の定義
+// - セッション状態（接続情報・エンベロープ・マクロ・受信順ヘッダ）とparse_mailの解析結果からのレコード生成
+// - Receivedヘッダの中継経路を接続情報と並べて出力
+// - DKIM署名の検証結果・SPFの評価結果の出力
+// - 本文の切り詰め（Json_body_limit）
+// - Json_output設定（ファイル/標準出力）に従ったJSON Lines書き出し
+// =========================
+
+use lazy_static::lazy_static;
+use serde::Serialize; // JSONシリアライズ
+use std::collections::BTreeMap; // マクロ名 → 値
+use std::io::Write; // ファイル・標準出力への書き込み
+use std::path::PathBuf; // 出力先ファイルパス
+use std::sync::Mutex; // 書き込み排他
+
+use crate::dkim::DkimResult; // DKIM署名の検証結果
+use crate::envelope::Envelope; // エンベロープ情報
+use crate::parse::{MailAddress, MimePart, NonTextPart, ParsedMail, TextPart}; // 型付きの解析結果
+use crate::phishing::PhishingIndicator; // フィッシング判定の指標
+use crate::received::ReceivedHop; // Receivedヘッダの中継経路
+use crate::session::{ConnectInfo, Session}; // セッション状態・接続情報
+use crate::spf::SpfResult; // SPFの評価結果
+use crate::urls::ExtractedUrl; // 抽出したURL
+
+lazy_static! {
+    static ref WRITE_LOCK: Mutex<()> = Mutex::new(()); // 同時接続からの書き込みで行が混ざらないよう排他
+}
+
+/// JSON Linesの出力先（Json_output設定）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonTarget {
+    Stdout,        // 標準出力（ログ行と混在）
+    File(PathBuf), // ファイルに追記
+}
+
+impl JsonTarget {
+    /// Json_output行（"Json_output "以降）を解析（stdout または - で標準出力、それ以外はファイルパス）
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.trim() {
+            "" => Err("出力先未指定".to_string()),
+            "stdout" | "-" => Ok(JsonTarget::Stdout),
+            path => Ok(JsonTarget::File(PathBuf::from(path))),
+        }
+    }
+}
+
+/// 受信順ヘッダ1件（名前は受信時のまま、値は折り返しを含む生の値）
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderRecord {
+    pub name: String,  // ヘッダ名
+    pub value: String, // ヘッダ値（UTF-8として不正なバイトは置換）
+}
+
+/// テキスト/HTML本文1件
+#[derive(Debug, Clone, Serialize)]
+pub struct BodyRecord {
+    pub index: usize, // 本文の連番（種別ごとに1から、ログ出力のTEXT本文(n)/HTML本文(n)と同じ）
+    pub part: usize,  // MIMEパート番号
+    pub kind: String, // "text" または "html"
+    pub content: String, // デコード済み本文（Json_body_limitで切り詰め）
+    pub size: usize,  // 切り詰め前のサイズ（バイト数）
+    pub truncated: bool, // 切り詰めたか
+}
+
+impl BodyRecord {
+    /// テキスト/HTMLパートからレコードを生成（切り詰めは出力時にtruncate_bodiesで行う）
+    pub fn new(index: usize, kind: &str, text: &TextPart) -> Self {
+        BodyRecord {
+            index,
+            part: text.part,
+            kind: kind.to_string(),
+            content: text.content.clone(),
+            size: text.content.len(),
+            truncated: false,
+        }
+    }
+}
+
+/// 1メール分の構造化出力レコード（JSON Linesの1行）
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageRecord {
+    pub timestamp: String,                // 出力時刻（JST、RFC3339）
+    pub session_id: Option<u64>,          // セッションID（ログ行の[session=]と同じ）
+    pub message_id: Option<String>,       // メッセージID（キューID、無ければ「セッションID.連番」）
+    pub connect: Option<ConnectInfo>,     // CONNECT情報（ホスト名・ファミリ・ポート・アドレス）
+    pub received: Vec<ReceivedHop>,       // Received中継経路（最新が先頭）
+    pub dkim: Vec<DkimResult>,            // DKIM署名の検証結果
+    pub spf: Option<SpfResult>,           // SPFの評価結果
+    pub helo: Option<String>,             // HELO/EHLOホスト名
+    pub envelope: Envelope,               // エンベロープ（MAIL FROM/RCPT TO）
+    pub macros: BTreeMap<String, String>, // 受信マクロ値（同名は最新フェーズの値）
+    pub headers: Vec<HeaderRecord>,       // 受信順ヘッダ
+    pub parsed: bool,                     // mail-parserでのパース成否
+    pub from: Vec<MailAddress>,           // ヘッダFrom
+    pub to: Vec<MailAddress>,             // ヘッダTo
+    pub subject: Option<String>,          // 件名（デコード済み）
+    pub mime: Vec<MimePart>,              // MIMEパート一覧
+    pub bodies: Vec<BodyRecord>,          // テキスト/HTML本文
+    pub attachments: Vec<NonTextPart>,    // 添付ファイル等のメタデータ
+    pub urls: Vec<ExtractedUrl>,          // 本文・添付から抽出したURL
+    pub phishing: Vec<PhishingIndicator>, // フィッシング判定の指標
+}
+
+impl MessageRecord {
+    /// セッション状態からレコードを生成（中継経路・DKIM検証結果・SPF評価結果は呼び出し側で設定、MIME・本文・添付はset_mailで追加）
+    pub fn new(session: &Session) -> Self {
+        let now = chrono::Local::now().with_timezone(&chrono_tz::Asia::Tokyo); // JST現在時刻
+        let (session_id, message_id) = crate::logging::current_ids(); // ログと同じID
+        MessageRecord {
+            timestamp: now.to_rfc3339(),
+            session_id,
+            message_id,
+            connect: session.connect_info.clone(),
+            received: Vec::new(),
+            dkim: Vec::new(),
+            spf: None,
+            helo: session.helo.clone(),
+            envelope: session.envelope.clone(),
+            macros: session
+                .macros
+                .latest_values()
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            headers: session
+                .header_fields
+                .iter()
+                .map(|h| HeaderRecord {
+                    name: h.name.clone(),
+                    value: String::from_utf8_lossy(&h.value).to_string(),
+                })
+                .collect(),
+            parsed: false,
+            from: Vec::new(),
+            to: Vec::new(),
+            subject: None,
+            mime: Vec::new(),
+            bodies: Vec::new(),
+            attachments: Vec::new(),
+            urls: Vec::new(),
+            phishing: Vec::new(),
+        }
+    }
+
+    /// parse_mailの解析結果を格納（パース失敗時はNoneを渡し、parsed=falseのまま）
+    pub fn set_mail(&mut self, mail: Option<&ParsedMail>) {
+        let Some(mail) = mail else {
+            return;
+        };
+        self.parsed = true;
+        self.from = mail.from.clone();
+        self.to = mail.to.clone();
+        self.subject = mail.subject.clone();
+        self.mime = mail.mime_parts.clone();
+        self.bodies = Vec::new();
+        for (i, text) in mail.text_parts.iter().enumerate() {
+            self.bodies.push(BodyRecord::new(i + 1, "text", text)); // TEXT本文
+        }
+        for (i, html) in mail.html_parts.iter().enumerate() {
+            self.bodies.push(BodyRecord::new(i + 1, "html", html)); // HTML本文
+        }
+        self.attachments = mail.non_text_parts.clone();
+        self.urls = mail.urls.clone();
+        self.phishing = mail.phishing.clone();
+    }
+
+    /// 本文を指定バイト数以内に切り詰め（0は無制限、UTF-8の文字境界で切る）
+    pub fn truncate_bodies(&mut self, limit: usize) {
+        if limit == 0 {
+            return;
+        }
+        for body in &mut self.bodies {
+            if body.content.len() > limit {
+                let mut end = limit; // 切り詰め位置
+                while !body.content.is_char_boundary(end) {
+                    end -= 1; // マルチバイト文字の途中なら手前へ
+                }
+                body.content.truncate(end);
+                body.truncated = true;
+            }
+        }
+    }
+}
+
+/// レコードをJSON Lines形式（1行1レコード）で出力
+///
+/// # 説明
+/// - ファイル出力は毎回追記オープン（ログローテーション後も新ファイルへ書ける）
+/// - 書き込み失敗はログに出すだけでMilter処理は継続する
+pub fn write_record(target: &JsonTarget, record: &MessageRecord) {
+    let line = match serde_json::to_string(record) {
+        Ok(line) => line,
+        Err(e) => {
+            crate::printdaytimeln!("JSON出力失敗: {}", e);
+            return;
+        }
+    };
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner()); // 行単位で排他
+    match target {
+        JsonTarget::Stdout => println!("{}", line), // ログ行と並べて出力
+        JsonTarget::File(path) => {
+            let result = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut f| writeln!(f, "{}", line));
+            if let Err(e) = result {
+                crate::printdaytimeln!("JSON出力失敗: {}: {}", path.display(), e);
+            }
+        }
+    }
+}
@@ -1,251 +1,423 @@
 // =========================
 // parse.rs
-// MilterDecoder メールパース・出力処理モジュール
+// MilterDecoder メールパース処理モジュール
 //
 // 【このファイルで使う主なクレート】
 // - mail_parser: MIMEメールのパース・構造化・本文抽出・添付抽出（MessageParser, MimeHeaders）
+// - serde: 構造化（JSON）出力用のシリアライズ定義
 // - std: 標準ライブラリ（コレクション・文字列操作・イテレータ等）
-// - crate::printdaytimeln!: JSTタイムスタンプ付きログ出力マクロ
 //
 // 【役割】
-// - BODYEOB時にヘッダ＋ボディを合体してメール全体をパース
-// - From/To/Subject/Content-Type/エンコーディング/本文の構造化出力
-// - パートごとのテキスト/非テキスト判定・出力
-// - 添付ファイル名抽出・属性出力
-// - NULバイト混入の可視化・除去
+// - BODYEOB時にヘッダ＋ボディを合体してメール全体の生データを再構築
+// - mail-parserでパースし、型付きの解析結果（ParsedMail）を返却
+// - From/To/Subject/Content-Type/エンコーディング/マルチパート判定の抽出
+// - パートごとのテキスト/HTML/非テキスト分類・添付ファイル名抽出・ハッシュ値計算
+// - 非テキストパートの実際のファイル種別判定（マジックバイト）と宣言との不一致検出
+// - アーカイブ添付（ZIP/RAR/7z/TAR/GZ）のメンバー一覧・暗号化・zip bomb検出（Archive_depthで入れ子制限）
+// - Office文書（OLE2/OOXML）のマクロ・外部テンプレート・OLEオブジェクト・DDE・XLMの検出
+// - PDF添付のページ数・危険なキーワード（JavaScript・自動実行・Launch等）・URIアクションの抽出
+// - 本文・HTML・PDF・Office文書からのURL抽出と正規化（urls.rs）
+// - 表示名なりすまし・類似ドメイン・リンク文字列偽装のフィッシング判定（phishing.rs）
+// - MIMEツリーの再帰走査（親子リンク・深さ・boundary付きのMIMEパート一覧）
+// - 添付メール（message/rfc822）の再帰解析（Nested_message_depthで深さ制限）
+// - ログ出力はformatter.rs、JSON出力はoutput.rsが担当（本モジュールは出力しない）
 // =========================
 
 // mail_parserクレートからメールパーサー本体とMimeHeadersトレイトをインポート
-use mail_parser::{MessageParser, MimeHeaders}; // メールパース・MIMEヘッダアクセス用
-                                               // ヘッダ情報格納用のHashMapをインポート
-use std::collections::HashMap; // ヘッダ格納用
+use mail_parser::{HeaderName, Message, MessageParser, MessagePart, MimeHeaders, PartType}; // メールパース・MIMEヘッダアクセス用
+use serde::Serialize; // JSON出力用
+use std::sync::Arc; // 添付データの共有（解析結果の複製でデータを複製しない）
 
-/// BODYEOB時にヘッダ＋ボディを合体してメール全体をパース・出力する関数
+use crate::archive::{ArchiveInfo, ArchiveLimits}; // アーカイブ添付の解析
+use crate::attachment::ContentHashes; // 添付データのハッシュ値
+use crate::filetype::{FileKind, TypeMismatch}; // マジックバイトによるファイル種別
+use crate::office::OfficeInfo; // Office文書の解析
+use crate::pdf::PdfInfo; // PDFの解析
+use crate::phishing::PhishingIndicator; // フィッシング判定の指標
+use crate::urls::ExtractedUrl; // 抽出したURL
+
+use crate::header::HeaderList; // 受信順ヘッダリスト
+
+/// パース結果のパート数（EOM時のX-MilterDecoder-Summaryヘッダ付与等に使用）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartCounts {
+    pub text: usize,     // 本文出力対象のテキストパート数
+    pub non_text: usize, // 添付ファイル等の非テキストパート数
+}
+
+/// メールアドレス（From/To等の1件分）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MailAddress {
+    pub name: Option<String>,    // 表示名（無ければNone）
+    pub address: Option<String>, // アドレス（グループ名のみ等はNone）
+}
+
+impl MailAddress {
+    /// ログ出力用に「表示名 <アドレス>」形式へ整形（表示名が無ければアドレスのみ）
+    pub fn display(&self) -> String {
+        let name = self.name.as_deref().unwrap_or(""); // 差出人名
+        let address = self.address.as_deref().unwrap_or(""); // アドレス
+        if !name.is_empty() {
+            format!("{} <{}>", name, address) // 名前付きフォーマット
+        } else {
+            address.to_string() // アドレスのみ
+        }
+    }
+}
+
+/// テキストパート（text/plain等）・HTMLパート1件分
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextPart {
+    pub part: usize,          // MIMEパート番号
+    pub content_type: String, // 「type/subtype」
+    pub content: String,      // デコード済み本文（ISO-2022-JP・quoted-printable等から変換済み）
+}
+
+/// 非テキストパート（添付ファイル等）1件分
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NonTextPart {
+    pub part: usize,                        // MIMEパート番号
+    pub content_type: String,               // 「type/subtype」
+    pub encoding: String,                   // Content-Transfer-Encoding（mail-parserの判定結果）
+    pub filename: Option<String>, // ファイル名（Content-Dispositionのfilename、またはContent-Typeのname）
+    pub size: usize,              // デコード後のサイズ（バイト数）
+    pub hashes: ContentHashes,    // デコード後データのSHA-256/SHA-1/MD5
+    pub detected_type: Option<FileKind>, // 実際の種別（マジックバイト判定、不明ならNone）
+    pub type_mismatches: Vec<TypeMismatch>, // 宣言（Content-Type・拡張子）との不一致
+    pub archive: Option<ArchiveInfo>, // アーカイブならメンバー一覧等
+    pub office: Option<OfficeInfo>, // Office文書ならマクロ・DDE等の指標
+    pub pdf: Option<PdfInfo>,     // PDFならページ数・キーワード・URI
+    pub saved_as: Option<String>, // スプールディレクトリへの保存先（Attachment_spool指定時）
+    #[serde(skip)]
+    pub data: Arc<Vec<u8>>, // デコード後データ（添付保存用、パート本文から移したもの、JSONには出さない）
+}
+
+/// MIMEパート1件分（ルートからの深さ優先順、0がルート）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MimePart {
+    pub index: usize,                     // パート番号
+    pub parent: Option<usize>,            // 親パート番号（ルートはNone）
+    pub depth: usize,                     // MIMEツリー上の深さ（ルートは0）
+    pub content_type: String,             // 「type/subtype」（Content-Type無しはtext/plain）
+    pub boundary: Option<String>,         // multipart/*のboundary
+    pub encoding: String,                 // Content-Transfer-Encoding
+    pub filename: Option<String>,         // ファイル名
+    pub size: usize,                      // デコード後のサイズ（バイト数、multipart/*は0）
+    pub children: Vec<usize>,             // 子パート番号（multipart/*のみ）
+    pub message: Option<Box<ParsedMail>>, // 添付メール（message/rfc822）の解析結果
+    pub depth_limited: bool,              // 深さ制限により添付メールを解析しなかったか
+}
+
+/// ヘッダ1件分（添付メールのヘッダ出力用、値は生の値から前後空白を除いたもの）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MailHeader {
+    pub name: String,  // ヘッダ名
+    pub value: String, // ヘッダ値（折り返しを含む）
+}
+
+/// parse_mailの解析設定（Nested_message_depth/Archive_depth/Archive_bomb_ratio/Protected_domains設定から生成）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub nested_message_depth: usize, // 添付メール（message/rfc822）を再帰解析する最大レベル（0なら解析しない）
+    pub archive: ArchiveLimits,      // アーカイブ添付の入れ子深さ・zip bomb判定
+    pub protected_domains: Vec<String>, // 類似ドメイン判定の保護対象ドメイン（小文字）
+}
+
+/// parse_mailの解析結果（ログ出力・JSON出力・ポリシー判定等で共用）
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ParsedMail {
+    pub level: usize,                 // 添付メールの入れ子レベル（受信メール本体は0）
+    pub headers: Vec<MailHeader>,     // ヘッダ（出現順）
+    pub from: Vec<MailAddress>,       // Fromアドレス（複数可）
+    pub to: Vec<MailAddress>,         // Toアドレス（複数可）
+    pub subject: Option<String>,      // 件名（デコード済み）
+    pub content_type: Option<String>, // ルートのContent-Type（ヘッダ値そのまま）
+    pub encoding: Option<String>,     // ルートのContent-Transfer-Encoding（ヘッダ値そのまま）
+    pub multipart: bool,              // マルチパートか（パート数が2以上）
+    pub text_parts: Vec<TextPart>,    // テキストパート（添付を除くtext/html以外のtext/*）
+    pub html_parts: Vec<TextPart>,    // HTMLパート（添付を除くtext/html）
+    pub non_text_parts: Vec<NonTextPart>, // 非テキストパート・添付のtext/*パート（multipart/*の親パートは除く）
+    pub mime_parts: Vec<MimePart>,        // 全MIMEパート（ツリーの深さ優先順）
+    pub urls: Vec<ExtractedUrl>,          // 本文・添付から抽出したURL（正規化済み）
+    pub phishing: Vec<PhishingIndicator>, // フィッシング判定の指標
+}
+
+impl ParsedMail {
+    /// 添付メール（message/rfc822）の解析結果を出現順に取得（直下のみ）
+    pub fn nested_messages(&self) -> impl Iterator<Item = (&MimePart, &ParsedMail)> {
+        self.mime_parts
+            .iter()
+            .filter_map(|p| p.message.as_deref().map(|m| (p, m)))
+    }
+
+    /// テキスト（TEXT/HTML）・非テキストのパート数
+    pub fn counts(&self) -> PartCounts {
+        PartCounts {
+            text: self.text_parts.len() + self.html_parts.len(),
+            non_text: self.non_text_parts.len(),
+        }
+    }
+}
+
+/// ヘッダ＋ボディを合体してRFC準拠のメール全体バイト列を再構築
 ///
 /// # 引数
-/// - `header_fields`: Milterで受信したヘッダ情報（HashMap<String, Vec<String>>）
-/// - `body_field`: Milterで受信したボディ情報（文字列）
+/// - `header_fields`: Milterで受信したヘッダ情報（受信順・生の値を保持したHeaderList）
+/// - `body_field`: Milterで受信したボディ情報（生バイト列）
 ///
 /// # 説明
-/// 1. ヘッダ＋ボディを合体してメール全体の生データを構築
-/// 2. mail-parserでMIME構造をパース
-/// 3. From/To/Subject/Content-Type/エンコーディング等の情報を出力
-/// 4. パートごとのテキスト/非テキスト判定・出力
-/// 5. 添付ファイル名抽出・属性出力
-/// 6. NULバイト混入の可視化・除去
-pub fn parse_mail(header_fields: &HashMap<String, Vec<String>>, body_field: &str) {
-    // ヘッダ情報とボディ情報を合体し、RFC準拠のメール全体文字列を作成
-    let mut mail_string = String::new(); // メール全体の文字列構築用バッファ
-    
-    // Milterで受信した各ヘッダを「ヘッダ名: 値」形式でメール文字列に追加
-    for (k, vlist) in header_fields {
-        // 同一ヘッダ名で複数値がある場合（Received等）も全て処理
-        for v in vlist {
-            mail_string.push_str(&format!("{}: {}\r\n", k, v)); // RFC準拠のCRLF改行
-        }
-    }
-    
-    mail_string.push_str("\r\n"); // ヘッダ部とボディ部の区切り空行（RFC必須）
-    
-    // ボディ部の改行コードをCRLFに統一（OS依存の改行コード差異を吸収）
-    let body_crlf = body_field.replace("\r\n", "\n").replace('\n', "\r\n");
-    mail_string.push_str(&body_crlf); // 正規化されたボディを追加
-    
-    // NULバイト（\0）を可視化文字に置換してデバッグ出力用に整形
-    let mail_string_visible = mail_string.replace("\0", "<NUL>");
-    crate::printdaytimeln!("--- BODYEOB時のメール全体 ---");
-    crate::printdaytimeln!("{}", mail_string_visible); // 生メールデータの可視化出力
-
-    // mail-parserでメール全体をパース
+/// - 各ヘッダを受信順のまま「ヘッダ名: 値」形式で並べる（MTAが見た順序・大文字小文字・折り返しを再現）
+/// - ボディ部の改行コードはCRLFに統一する
+pub fn rebuild_message(header_fields: &HeaderList, body_field: &[u8]) -> Vec<u8> {
+    let mut mail_bytes: Vec<u8> = Vec::new(); // メール全体のバイト列構築用バッファ
+    mail_bytes.extend_from_slice(&header_fields.to_raw_bytes()); // 受信順ヘッダ
+    mail_bytes.extend_from_slice(b"\r\n"); // ヘッダ部とボディ部の区切り空行（RFC必須）
+    mail_bytes.extend_from_slice(&normalize_crlf(body_field)); // 正規化されたボディを追加
+    mail_bytes
+}
+
+/// メール全体の生データをパースして解析結果を返す関数
+///
+/// # 引数
+/// - `mail_bytes`: rebuild_messageで再構築したメール全体のバイト列
+/// - `options`: 添付メールの再帰解析レベル・アーカイブ解析の制限・保護対象ドメイン
+///
+/// # 説明
+/// 1. mail-parserでMIME構造をパース（文字コード判定・デコードはmail-parserに任せる）
+/// 2. From/To/Subject/Content-Type/エンコーディング/マルチパート判定・ヘッダを抽出
+/// 3. ルートパートからMIMEツリーを深さ優先で走査し、親子リンク・深さ・boundaryを記録
+/// 4. パートごとにテキスト/HTML/非テキストを分類（multipart/*の親パートはどれにも含めない）
+/// 5. 非テキストパートはファイル種別を判定し、アーカイブならメンバー一覧、Office文書・PDFなら指標を取得
+/// 6. 本文・HTMLのhref/src・PDF・Office文書の外部参照からURLを抽出して正規化
+/// 7. From表示名・ドメイン・HTMLリンクからフィッシングの指標を収集
+/// 8. 添付メールはnested_message_depthまで同じ手順で再帰解析
+/// 9. パース失敗時（メール構造が不正等）はNone
+pub fn parse_mail(mail_bytes: &[u8], options: &ParseOptions) -> Option<ParsedMail> {
     let parser = MessageParser::default(); // パーサーインスタンス生成
-    if let Some(msg) = parser.parse(mail_string.as_bytes()) {
-        // パース成功時
-        // Fromアドレスを文字列化（複数対応）
-        let from = msg
-            .from()
-            .map(|addrs| {
-                addrs
-                    .iter()
-                    .map(|addr| {
-                        let name = addr.name().unwrap_or(""); // 差出人名
-                        let address = addr.address().unwrap_or(""); // アドレス
-                        if !name.is_empty() {
-                            format!("{} <{}>", name, address) // 名前付きフォーマット
-                        } else {
-                            address.to_string() // アドレスのみ
-                        }
-                    })
-                    .collect::<Vec<_>>()
-                    .join(", ") // 複数アドレスをカンマ区切り
-            })
-            .unwrap_or_else(|| "(なし)".to_string()); // From無し時のデフォルト
-                                                      // Toアドレスを文字列化（複数対応）
-        let to = msg
-            .to()
-            .map(|addrs| {
-                addrs
-                    .iter()
-                    .map(|addr| {
-                        let name = addr.name().unwrap_or(""); // 宛先名
-                        let address = addr.address().unwrap_or(""); // 宛先アドレス
-                        if !name.is_empty() {
-                            format!("{} <{}>", name, address) // 名前付きフォーマット
-                        } else {
-                            address.to_string() // アドレスのみ
-                        }
-                    })
-                    .collect::<Vec<_>>()
-                    .join(", ") // 複数アドレスをカンマ区切り
-            })
-            .unwrap_or_else(|| "(なし)".to_string()); // To無し時のデフォルト
-                                                      // 件名取得
-        let subject = msg.subject().unwrap_or("(なし)"); // 件名無し時のデフォルト
-        crate::printdaytimeln!("[mail-parser] from: {}", from); // From出力
-        crate::printdaytimeln!("[mail-parser] to: {}", to); // To出力
-        crate::printdaytimeln!("[mail-parser] subject: {}", subject); // 件名出力
-                                                                      // Content-Type（MIMEタイプ）があれば出力
-        if let Some(ct) = msg
-            .headers()
-            .iter()
-            .find(|h| h.name().eq_ignore_ascii_case("Content-Type")) // ヘッダ名がContent-Typeか判定
-            .map(|h| h.value())
-        // ヘッダ値を取得
-        {
-            crate::printdaytimeln!("[mail-parser] content-type: {:?}", ct); // MIMEタイプ出力
-        }
-        // Content-Transfer-Encoding（エンコーディング方式）があれば出力
-        if let Some(enc) = msg
+    let mut msg = parser.parse(mail_bytes)?; // 生バイト列をそのまま渡す
+    Some(analyze_message(&mut msg, 0, options)) // 添付データはパート本文から移す
+}
+
+/// パース済みメッセージ1通分を解析（受信メール本体・添付メール共通）
+fn analyze_message(msg: &mut Message, level: usize, options: &ParseOptions) -> ParsedMail {
+    let mut parsed = ParsedMail {
+        level,
+        headers: msg
             .headers()
             .iter()
-            .find(|h| h.name().eq_ignore_ascii_case("Content-Transfer-Encoding")) // ヘッダ名がContent-Transfer-Encodingか判定
-            .map(|h| h.value())
-        // ヘッダ値を取得
-        {
-            crate::printdaytimeln!("[mail-parser] encoding: {:?}", enc); // エンコーディング出力
-        }
-        // マルチパートかどうか判定（パート数で判定）
-        if msg.parts.len() > 1 {
-            crate::printdaytimeln!("[mail-parser] このメールはマルチパートです"); // 複数パート
-        } else {
-            crate::printdaytimeln!("[mail-parser] このメールはシングルパートです"); // 単一パート
-        }
-        // テキストパート数・非テキストパート数をカウント
-        let mut text_count = 0; // 実際に本文出力対象となるテキストパート数
-        let mut non_text_count = 0; // 添付ファイル等の非テキストパート数
-        
-        // テキストパートのインデックスリストを作成（本文出力で使用）
-        let mut text_indices = Vec::new(); // 本文出力対象パートのインデックス記録用
-        
-        // 各パートを順番に調べてテキスト/非テキストを分類
-        for (i, part) in msg.parts.iter().enumerate() {
-            // パートがテキスト系かどうか判定（text/plain, text/html, multipart/alternative等）
-            if part.is_text() {
-                // multipart/*系パートは親パートなので本文出力対象から除外
-                // （実際の本文はその子パートに格納されている）
-                let is_multipart = part.content_type()
-                    .is_some_and(|ct| ct.c_type.eq_ignore_ascii_case("multipart"));
-                
-                if !is_multipart {
-                    // text/plain, text/html等の実体のあるテキストパート
-                    text_count += 1; // テキストパート数をカウント
-                    text_indices.push(i); // 本文出力用にインデックスを記録
-                } else {
-                    // multipart/alternative等の親パートは本文出力対象外
-                    // （コメントのみで処理は何もしない）
+            .map(|h| MailHeader {
+                name: h.name.as_str().to_string(),
+                value: raw_header_value(msg, h.offset_start, h.offset_end),
+            })
+            .collect(),
+        from: addresses(msg.from()),
+        to: addresses(msg.to()),
+        subject: msg.subject().map(|s| s.to_string()),
+        content_type: root_header(msg, HeaderName::ContentType),
+        encoding: root_header(msg, HeaderName::ContentTransferEncoding),
+        multipart: msg.parts.len() > 1, // パート数で判定
+        ..ParsedMail::default()
+    };
+    if !msg.parts.is_empty() {
+        walk_part(msg, 0, None, 0, &mut parsed, options); // ルートから走査
+    }
+    parsed.urls = crate::urls::extract(&parsed); // 走査結果（本文・PDF・Office文書）からURLを抽出
+    parsed.phishing = crate::phishing::analyze(&parsed, &options.protected_domains); // 抽出済みURLも判定に使う
+    parsed
+}
+
+/// MIMEツリーを深さ優先で走査し、パートを分類・記録（multipart/*は子パートへ再帰）
+fn walk_part(
+    msg: &mut Message,
+    id: usize,
+    parent: Option<usize>,
+    depth: usize,
+    parsed: &mut ParsedMail,
+    options: &ParseOptions,
+) {
+    let Some(part) = msg.parts.get(id) else {
+        return; // 範囲外の子パート番号は無視
+    };
+    let slot = parsed.mime_parts.len(); // このパートの記録位置
+    parsed.mime_parts.push(MimePart {
+        index: id,
+        parent,
+        depth,
+        content_type: content_type_of(part),
+        boundary: part
+            .content_type()
+            .and_then(|ct| ct.attribute("boundary"))
+            .map(|b| b.to_string()),
+        encoding: format!("{:?}", part.encoding),
+        filename: filename_of(part),
+        size: part.body.len(),
+        children: Vec::new(),
+        message: None,
+        depth_limited: false,
+    });
+    let text_attachment = is_text_attachment(msg, id, part); // 添付として送られたtext/*か
+    match &part.body {
+        PartType::Multipart(ids) => {
+            // 実際の本文・添付はその子パートに格納されている
+            for child in ids.clone() {
+                let child = child as usize; // 子パート番号
+                if child <= id {
+                    continue; // 自身・祖先への参照（不正な構造）は辿らない
                 }
-            } else {
-                // application/octet-stream等の非テキストパート（添付ファイル等）
-                non_text_count += 1; // 非テキストパート数をカウント
+                parsed.mime_parts[slot].children.push(child);
+                walk_part(msg, child, Some(id), depth + 1, parsed, options);
             }
         }
-        crate::printdaytimeln!("[mail-parser] テキストパート数: {}", text_count); // テキストパート数出力
-        crate::printdaytimeln!("[mail-parser] 非テキストパート数: {}", non_text_count); // 非テキストパート数出力
-
-        // 本文出力処理：テキストパートごとに内容を出力
-        for (idx, _) in text_indices.iter().enumerate() {
-            // text/plain, text/html以外のテキストパートは本文出力をスキップ
-            // （例：text/calendar等の特殊なテキストパート）
-            let part = &msg.parts[text_indices[idx]]; // 対象パートを取得
-            
-            // Content-Typeのサブタイプ（plain, html等）を小文字で取得
-            let subtype = part.content_type().and_then(|ct| ct.c_subtype.as_deref().map(|s| s.to_ascii_lowercase()));
-            
-            if let Some(subtype) = subtype {
-                // サブタイプがplainまたはhtmlの場合のみ本文出力
-                if subtype == "plain" || subtype == "html" {
-                    // mail-parserの本文抽出メソッドでテキスト/HTML本文を取得
-                    let text = msg.body_text(idx); // プレーンテキスト本文
-                    let html = msg.body_html(idx); // HTML本文
-                    
-                    // テキスト本文があれば出力（ISO-2022-JP等からデコード済み）
-                    if let Some(body) = text {
-                        crate::printdaytimeln!("[mail-parser] TEXT本文({}): {}", idx + 1, body);
-                    }
-                    
-                    // HTML本文があれば出力（quoted-printable等からデコード済み）
-                    if let Some(html_body) = html {
-                        crate::printdaytimeln!("[mail-parser] HTML本文({}): {}", idx + 1, html_body);
-                    }
+        PartType::Html(html) if !text_attachment => parsed.html_parts.push(TextPart {
+            part: id,
+            content_type: content_type_of(part),
+            content: html.to_string(), // quoted-printable等からデコード済み
+        }),
+        PartType::Text(text) if !text_attachment => parsed.text_parts.push(TextPart {
+            part: id,
+            content_type: content_type_of(part),
+            content: text.to_string(), // ISO-2022-JP等からデコード済み
+        }),
+        _ => {
+            // 非テキストパート、または添付として送られたtext/*パート
+            let content_type = content_type_of(part); // 宣言されたContent-Type
+            let filename = filename_of(part); // 宣言されたファイル名
+            let detected_type = crate::filetype::detect(part.contents()); // 実際の種別
+            parsed.non_text_parts.push(NonTextPart {
+                part: id,
+                type_mismatches: crate::filetype::find_mismatches(
+                    detected_type,
+                    &content_type,
+                    filename.as_deref(),
+                ),
+                content_type,
+                encoding: format!("{:?}", part.encoding),
+                filename,
+                size: part.body.len(), // パートのデコード後サイズ（バイト数）
+                hashes: ContentHashes::compute(part.contents()),
+                detected_type,
+                archive: crate::archive::inspect(part.contents(), detected_type, &options.archive),
+                office: crate::office::inspect(part.contents(), detected_type),
+                pdf: crate::pdf::inspect(part.contents(), detected_type),
+                saved_as: None,       // 保存はextract_attachmentsで行う
+                data: Arc::default(), // 解析後にパート本文から移す
+            });
+            let index = parsed.non_text_parts.len() - 1; // このパートの記録位置
+            if let PartType::Message(inner) = &mut msg.parts[id].body {
+                // 添付メール（message/rfc822）: 深さ制限内なら再帰解析
+                if parsed.level < options.nested_message_depth {
+                    let nested = analyze_message(inner, parsed.level + 1, options);
+                    parsed.mime_parts[slot].message = Some(Box::new(nested));
+                } else {
+                    parsed.mime_parts[slot].depth_limited = true;
                 }
-                // text/plain, text/html以外は本文出力しない（スキップ）
             }
-            // Content-Typeが不明な場合も本文出力しない（スキップ）
+            parsed.non_text_parts[index].data = Arc::new(take_contents(&mut msg.parts[id].body));
         }
-        // 添付ファイル等の非テキストパート情報出力処理
-        let mut non_text_idx = 0; // 非テキストパートの出力用連番（1から開始）
-        
-        // 全パートを再度走査して非テキストパートの詳細情報を出力
-        for part in msg.parts.iter() {
-            // テキストパート以外（添付ファイル、画像等）のみ処理
-            if !part.is_text() {
-                // Content-Type情報をヘッダから抽出（MIMEタイプ特定用）
-                let ct = part
-                    .headers
+    }
+}
+
+/// 解析済みパートの本文の所有権を取り出してバイト列にする
+///
+/// # 説明
+/// - base64・quoted-printable等でデコード済みの本文はそのまま移す（複製しない）
+/// - 転送エンコーディングの無い本文は受信データを借用しているため、この時点で1回だけ複製する
+fn take_contents(body: &mut PartType) -> Vec<u8> {
+    match std::mem::take(body) {
+        PartType::Text(text) | PartType::Html(text) => text.into_owned().into_bytes(),
+        PartType::Binary(data) | PartType::InlineBinary(data) => data.into_owned(),
+        PartType::Message(message) => message.raw_message.into_owned(),
+        PartType::Multipart(_) => Vec::new(),
+    }
+}
+
+/// text/*パートが本文ではなく添付として送られたか
+///
+/// # 説明
+/// - Content-Disposition: attachment、ファイル名付き、mail-parserの添付一覧に含まれる（本文として選ばれなかった）場合は添付
+/// - 添付とされたtext/*パートは非テキストパートと同様にハッシュ値計算・保存・種別判定の対象になる
+fn is_text_attachment(msg: &Message, id: usize, part: &MessagePart) -> bool {
+    part.content_disposition()
+        .is_some_and(|cd| cd.is_attachment())
+        || filename_of(part).is_some()
+        || msg.attachments.contains(&(id as u32))
+}
+
+/// アドレスヘッダ（From/To）をMailAddressの一覧へ変換（グループ内のアドレスも展開）
+fn addresses(addr: Option<&mail_parser::Address>) -> Vec<MailAddress> {
+    addr.map(|addrs| {
+        addrs
+            .iter()
+            .map(|a| MailAddress {
+                name: a.name().map(|n| n.to_string()),
+                address: a.address().map(|a| a.to_string()),
+            })
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// ヘッダ値を生データから取得（前後空白・末尾改行を除去、UTF-8として不正なバイトは置換）
+fn raw_header_value(msg: &Message, start: u32, end: u32) -> String {
+    msg.raw_message
+        .get(start as usize..end as usize)
+        .map(|v| String::from_utf8_lossy(v).trim().to_string())
+        .unwrap_or_default()
+}
+
+/// ルートパートのヘッダ値をそのまま取得（前後空白を除去）
+fn root_header(msg: &Message, name: HeaderName) -> Option<String> {
+    msg.header_raw(name).map(|v| v.trim().to_string())
+}
+
+/// パートの「type/subtype」を取得（Content-Type無しはRFC 2045の既定値text/plain）
+fn content_type_of(part: &MessagePart) -> String {
+    match part.content_type() {
+        Some(ct) => match ct.subtype() {
+            Some(sub) => format!("{}/{}", ct.ctype(), sub).to_ascii_lowercase(),
+            None => ct.ctype().to_ascii_lowercase(),
+        },
+        None => "text/plain".to_string(),
+    }
+}
+
+/// パートのファイル名を取得（Content-Dispositionのfilename、無ければContent-Typeのname）
+fn filename_of(part: &MessagePart) -> Option<String> {
+    part.content_disposition() // Content-Disposition属性を取得
+        .and_then(|cd| {
+            // filename属性を検索（一般的な添付ファイル名指定）
+            cd.attributes()
+                .unwrap_or(&[])
+                .iter()
+                .find(|attr| attr.name.eq_ignore_ascii_case("filename"))
+                .map(|attr| attr.value.to_string())
+        })
+        .or_else(|| {
+            // Content-Typeのname属性も補助的にチェック（古い形式対応）
+            part.content_type().and_then(|ct| {
+                ct.attributes()
+                    .unwrap_or(&[])
                     .iter()
-                    .find(|h| h.name().eq_ignore_ascii_case("content-type")) // Content-Typeヘッダを検索
-                    .map(|h| format!("{:?}", h.value())) // ヘッダ値を文字列として整形
-                    .unwrap_or("(不明)".to_string()); // Content-Typeが無い場合のデフォルト値
-                
-                // Content-Transfer-Encodingの種別を文字列化（base64, quoted-printable等）
-                let encoding_str = format!("{:?}", part.encoding); // エンコーディング情報
-                
-                // ファイル名情報を複数のヘッダから抽出
-                let fname = part
-                    .content_disposition() // Content-Disposition属性を取得
-                    .and_then(|cd| {
-                        // filename属性を検索（一般的な添付ファイル名指定）
-                        cd.attributes()
-                            .unwrap_or(&[])
-                            .iter()
-                            .find(|attr| attr.name.eq_ignore_ascii_case("filename"))
-                            .map(|attr| attr.value.to_string())
-                    })
-                    .or_else(|| {
-                        // Content-Typeのname属性も補助的にチェック（古い形式対応）
-                        part.content_type()
-                            .and_then(|ct| {
-                                ct.attributes()
-                                    .unwrap_or(&[])
-                                    .iter()
-                                    .find(|attr| attr.name.eq_ignore_ascii_case("name"))
-                                    .map(|attr| attr.value.to_string())
-                            })
-                    })
-                    .unwrap_or_else(|| "(ファイル名なし)".to_string()); // どちらにもファイル名が無い場合
-                
-                let size = part.body.len(); // パートの生データサイズ（バイト数）
-                
-                // 非テキストパートの詳細情報を1行で出力
-                crate::printdaytimeln!(
-                    "[mail-parser] 非テキストパート({}): content_type={}, encoding={}, filename={}, size={} bytes",
-                    non_text_idx + 1, ct, encoding_str, fname, size
-                );
-                
-                non_text_idx += 1; // 次の非テキストパート用に連番を進める
-            }
+                    .find(|attr| attr.name.eq_ignore_ascii_case("name"))
+                    .map(|attr| attr.value.to_string())
+            })
+        })
+}
+
+/// 改行コードをCRLFに統一（バイト列のまま処理）
+///
+/// # 説明
+/// - 単独のLFをCRLFに変換し、既存のCRLFはそのまま残す
+/// - 文字列化しないため、8bit本文やバイナリデータを壊さない
+pub fn normalize_crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 32); // 変換後バッファ（LF増加分を見込む）
+    let mut prev = 0u8; // 直前のバイト
+    for &b in data {
+        if b == b'\n' && prev != b'\r' {
+            out.push(b'\r'); // 単独LFの前にCRを補う
         }
-    } else {
-        // パース失敗時（メール構造が不正等）
-        crate::printdaytimeln!("[mail-parser] parse error"); // パース失敗ログ
+        out.push(b);
+        prev = b;
     }
-} // parse_mail関数終端
+    out
+}
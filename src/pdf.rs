@@ -0,0 +1,350 @@
+// =========================
+// pdf.rs
+// MilterDecoder PDF添付解析モジュール
+//
+// 【このファイルで使う主なクレート】
+// - flate2: FlateDecodeストリーム（オブジェクトストリーム等）の展開
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: バイト列走査・文字列変換
+//
+// 【役割】
+// - PDFのバージョン・ページ数・暗号化の有無の取得
+// - 危険なキーワード（/JavaScript・/JS・/OpenAction・/AA・/Launch・/EmbeddedFile・/URI・/AcroForm・/XFA）の出現数
+// - URIアクションの参照先（/URI）の抽出
+// - 名前の#xxエスケープによる難読化と、圧縮されたオブジェクトストリーム内のキーワードにも対応
+// =========================
+
+use serde::Serialize; // JSON出力用
+use std::io::Read; // FlateDecodeの展開
+
+use crate::filetype::FileKind; // マジックバイトによるファイル種別
+
+const MAX_INFLATE: usize = 16 * 1024 * 1024; // 1ストリームあたりの展開上限
+const MAX_TOTAL_INFLATE: usize = 64 * 1024 * 1024; // 1添付あたりの展開量の上限
+const MAX_URIS: usize = 1000; // 抽出するURIの上限
+
+/// 危険なキーワードの出現数（0なら無し）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PdfKeywords {
+    pub javascript: usize,    // /JavaScript（JavaScriptアクション・名前ツリー）
+    pub js: usize,            // /JS（JavaScriptコード本体）
+    pub open_action: usize,   // /OpenAction（文書を開いたときのアクション）
+    pub aa: usize,            // /AA（ページ表示・フォーム操作時の追加アクション）
+    pub launch: usize,        // /Launch（外部プログラム起動アクション）
+    pub embedded_file: usize, // /EmbeddedFile（埋め込みファイル）
+    pub uri: usize,           // /URI（URIアクション）
+    pub acroform: usize,      // /AcroForm（フォーム）
+    pub xfa: usize,           // /XFA（AcroForm内のXFAフォーム）
+}
+
+/// PDF1件分の解析結果
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PdfInfo {
+    pub version: Option<String>, // ヘッダのバージョン（%PDF-x.y）
+    pub pages: usize,            // ページ数（/Type /Pageのオブジェクト数）
+    pub encrypted: bool,         // /Encryptの有無（暗号化時は文字列を読めないためURIは取れない）
+    pub keywords: PdfKeywords,   // 危険なキーワードの出現数
+    pub uris: Vec<String>,       // URIアクションの参照先（出現順・重複除去）
+    pub inflate_errors: usize,   // 展開に失敗したFlateDecodeストリーム数
+}
+
+impl PdfInfo {
+    /// JavaScriptを含むか（/JavaScript・/JS）
+    pub fn has_javascript(&self) -> bool {
+        self.keywords.javascript > 0 || self.keywords.js > 0
+    }
+
+    /// 開いただけで実行されるアクション（/OpenAction・/AA）を持つか
+    pub fn has_auto_action(&self) -> bool {
+        self.keywords.open_action > 0 || self.keywords.aa > 0
+    }
+}
+
+/// 添付データがPDFなら解析（PDF以外はNone）
+///
+/// # 引数
+/// - `data`: デコード済み添付データ
+/// - `kind`: filetype::detectの判定結果
+///
+/// # 説明
+/// - ファイル全体と、FlateDecodeのストリームを展開した中身（最大16MB、1添付で合計64MB）を走査する
+/// - 構文解析はせず名前トークンを数える軽量な方式のため、壊れたPDFでも途中まで結果を返す
+pub fn inspect(data: &[u8], kind: Option<FileKind>) -> Option<PdfInfo> {
+    if kind != Some(FileKind::Pdf) {
+        return None;
+    }
+    let mut info = PdfInfo {
+        version: version_of(data),
+        ..PdfInfo::default()
+    };
+    scan(data, &mut info);
+    let mut budget = MAX_TOTAL_INFLATE; // 残り展開量
+    for stream in flate_streams(data) {
+        if budget == 0 {
+            break;
+        }
+        match inflate(stream, MAX_INFLATE.min(budget)) {
+            Some(content) => {
+                budget -= content.len();
+                scan(&content, &mut info);
+            }
+            None => info.inflate_errors += 1,
+        }
+    }
+    Some(info)
+}
+
+/// ヘッダ（先頭1KB内の%PDF-x.y）からバージョンを取得
+fn version_of(data: &[u8]) -> Option<String> {
+    let head = &data[..data.len().min(1024)];
+    let at = head.windows(5).position(|w| w == b"%PDF-")?;
+    let version: String = head[at + 5..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit() || **b == b'.')
+        .map(|&b| b as char)
+        .collect();
+    (!version.is_empty()).then_some(version)
+}
+
+/// 名前トークンを走査してキーワード・ページ・URIを数える
+fn scan(data: &[u8], info: &mut PdfInfo) {
+    let mut pos = 0;
+    while let Some(at) = data[pos..].iter().position(|&b| b == b'/') {
+        let (name, end) = read_name(data, pos + at + 1);
+        pos = end;
+        let k = &mut info.keywords;
+        match name.as_slice() {
+            b"JavaScript" => k.javascript += 1,
+            b"JS" => k.js += 1,
+            b"OpenAction" => k.open_action += 1,
+            b"AA" => k.aa += 1,
+            b"Launch" => k.launch += 1,
+            b"EmbeddedFile" => k.embedded_file += 1,
+            b"AcroForm" => k.acroform += 1,
+            b"XFA" => k.xfa += 1,
+            b"Encrypt" => info.encrypted = true,
+            b"Type" => {
+                let value_at = skip_whitespace(data, pos);
+                if data.get(value_at) == Some(&b'/') {
+                    let (value, _) = read_name(data, value_at + 1); // 値の名前は次の周回でも走査する
+                    if value == b"Page" {
+                        info.pages += 1; // /Pages（ページツリー）は数えない
+                    }
+                }
+            }
+            b"URI" => {
+                k.uri += 1;
+                let value_at = skip_whitespace(data, pos);
+                if let Some((uri, end)) = read_string(data, value_at) {
+                    pos = end;
+                    if !uri.is_empty() && !info.uris.contains(&uri) && info.uris.len() < MAX_URIS {
+                        info.uris.push(uri);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// PDFの区切り文字か（空白・括弧類・/・%）
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace()
+        || b == 0
+        || matches!(
+            b,
+            b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+        )
+}
+
+/// 空白（NULを含む）を読み飛ばす
+fn skip_whitespace(data: &[u8], mut pos: usize) -> usize {
+    while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == 0) {
+        pos += 1;
+    }
+    pos
+}
+
+/// 名前（/の直後から区切り文字まで）を読み取り、#xxエスケープを展開
+fn read_name(data: &[u8], mut pos: usize) -> (Vec<u8>, usize) {
+    let mut name = Vec::new();
+    while pos < data.len() && !is_delimiter(data[pos]) {
+        if data[pos] == b'#' {
+            if let Some(b) = data.get(pos + 1..pos + 3).and_then(hex_byte) {
+                name.push(b); // 難読化（例: /J#61vaScript）
+                pos += 3;
+                continue;
+            }
+        }
+        name.push(data[pos]);
+        pos += 1;
+    }
+    (name, pos)
+}
+
+/// 16進2桁を1バイトに変換
+fn hex_byte(pair: &[u8]) -> Option<u8> {
+    let s = std::str::from_utf8(pair).ok()?;
+    u8::from_str_radix(s, 16).ok()
+}
+
+/// 文字列オブジェクト（リテラル「(...)」・16進「<...>」）を読み取る
+fn read_string(data: &[u8], pos: usize) -> Option<(String, usize)> {
+    match data.get(pos)? {
+        b'(' => read_literal(data, pos + 1),
+        b'<' if data.get(pos + 1) != Some(&b'<') => read_hex(data, pos + 1),
+        _ => None, // 間接参照（/URI 12 0 R）や/S /URIの種別名
+    }
+}
+
+/// リテラル文字列（括弧の入れ子・バックスラッシュエスケープ・8進表記に対応）
+fn read_literal(data: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut out = Vec::new();
+    let mut depth = 0; // 入れ子の括弧
+    while let Some(&b) = data.get(pos) {
+        pos += 1;
+        match b {
+            b'\\' => {
+                let Some(&e) = data.get(pos) else { break };
+                pos += 1;
+                match e {
+                    b'n' => out.push(b'\n'),
+                    b'r' => out.push(b'\r'),
+                    b't' => out.push(b'\t'),
+                    b'b' => out.push(0x08),
+                    b'f' => out.push(0x0c),
+                    b'\r' | b'\n' => {
+                        if e == b'\r' && data.get(pos) == Some(&b'\n') {
+                            pos += 1; // 行継続
+                        }
+                    }
+                    b'0'..=b'7' => {
+                        let mut value = (e - b'0') as u32;
+                        for _ in 0..2 {
+                            match data.get(pos) {
+                                Some(d @ b'0'..=b'7') => {
+                                    value = value * 8 + (d - b'0') as u32;
+                                    pos += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        out.push(value as u8);
+                    }
+                    other => out.push(other), // \( \) \\ 等
+                }
+            }
+            b'(' => {
+                depth += 1;
+                out.push(b);
+            }
+            b')' if depth == 0 => return Some((pdf_text(&out), pos)),
+            b')' => {
+                depth -= 1;
+                out.push(b);
+            }
+            _ => out.push(b),
+        }
+    }
+    None // 閉じ括弧が無い
+}
+
+/// 16進文字列（空白は無視、奇数桁なら末尾に0を補う）
+fn read_hex(data: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut digits = Vec::new();
+    while let Some(&b) = data.get(pos) {
+        pos += 1;
+        if b == b'>' {
+            if digits.len() % 2 == 1 {
+                digits.push(b'0');
+            }
+            let bytes: Vec<u8> = digits.chunks(2).filter_map(hex_byte).collect();
+            return Some((pdf_text(&bytes), pos));
+        }
+        if b.is_ascii_hexdigit() {
+            digits.push(b);
+        } else if !b.is_ascii_whitespace() {
+            return None; // 16進文字列ではない
+        }
+    }
+    None
+}
+
+/// 文字列のバイト列をテキストに変換（BOM付きUTF-16BE、それ以外はUTF-8として不正なバイトを置換）
+fn pdf_text(bytes: &[u8]) -> String {
+    let text = match bytes.strip_prefix(&[0xfe, 0xff]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => String::from_utf8_lossy(bytes).to_string(),
+    };
+    text.trim().to_string()
+}
+
+/// FlateDecodeフィルタのストリーム本体を列挙
+///
+/// # 説明
+/// - 「stream」キーワード直前の辞書（直近の「obj」以降）に/FlateDecode（略記/Fl）があるものが対象
+/// - 本体は「stream」直後の改行から「endstream」まで（/Lengthは間接参照があるため使わない）
+fn flate_streams(data: &[u8]) -> Vec<&[u8]> {
+    let mut streams = Vec::new();
+    let mut pos = 0;
+    while let Some(at) = find(&data[pos..], b"stream") {
+        let start = pos + at;
+        pos = start + 6;
+        if start >= 3 && &data[start - 3..start] == b"end" {
+            continue; // endstream
+        }
+        let window = start.saturating_sub(4096); // 辞書を探す範囲（直前4KB）
+        let dict_from = rfind(&data[window..start], b"obj").map_or(window, |o| window + o);
+        let dict = &data[dict_from..start];
+        if !(contains(dict, b"/FlateDecode") || contains(dict, b"/Fl ") || contains(dict, b"/Fl/"))
+        {
+            continue;
+        }
+        let mut body_from = pos;
+        if data.get(body_from) == Some(&b'\r') {
+            body_from += 1;
+        }
+        if data.get(body_from) == Some(&b'\n') {
+            body_from += 1;
+        }
+        let body_to = find(&data[body_from..], b"endstream")
+            .map(|e| body_from + e)
+            .unwrap_or(data.len());
+        streams.push(&data[body_from..body_to]);
+        pos = body_to;
+    }
+    streams
+}
+
+/// zlib形式で展開（上限まで、途中で壊れていても展開できた分は返す）
+fn inflate(stream: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let result = flate2::read::ZlibDecoder::new(stream)
+        .take(limit as u64)
+        .read_to_end(&mut out);
+    if result.is_err() && out.is_empty() {
+        return None;
+    }
+    Some(out)
+}
+
+/// バイト列の検索（先頭位置）
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+/// バイト列の検索（末尾から）
+fn rfind(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).rposition(|w| w == needle)
+}
+
+/// バイト列を含むか
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    find(data, needle).is_some()
+}
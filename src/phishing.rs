@@ -0,0 +1,366 @@
+// =========================
+// phishing.rs
+// MilterDecoder フィッシング判定（ヒューリスティック）モジュール
+//
+// 【このファイルで使う主なクレート】
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - crate::urls: HTMLの字句分割・実体参照の展開・URLの正規化（punycodeのデコード）
+// - crate::parse: 解析結果（Fromアドレス、HTMLパート、抽出済みURL）
+//
+// 【役割】
+// - 表示名に実際のFromアドレスと異なるメールアドレスを含むなりすましの検出
+// - 保護対象ドメイン（Protected_domains）に似せたドメインの検出（ホモグリフ・punycode・編集距離・埋め込み）
+// - HTMLのリンク文字列がURLなのに、実際のhrefが別ホストを指すリンクの検出
+// - 検出結果は名前とスコアを持つ指標（PhishingIndicator）としてメッセージ単位に一覧化
+// =========================
+
+use serde::Serialize; // JSON出力用
+
+use crate::parse::ParsedMail; // 解析結果
+use crate::urls::{HtmlToken, UrlSource}; // HTMLの字句・URLの抽出元
+
+const MAX_INDICATORS: usize = 100; // 1メッセージあたりの指標の上限
+const MAX_LINK_TEXT: usize = 2048; // リンク文字列として保持する最大バイト数
+
+/// 指標の種別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhishingKind {
+    DisplayNameSpoof, // 表示名に別のメールアドレスを含む
+    LookalikeDomain,  // 保護対象ドメインに似せたドメイン
+    LinkTextMismatch, // リンク文字列のURLと実際のリンク先のホストが異なる
+}
+
+impl PhishingKind {
+    /// ログ・JSON出力用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            PhishingKind::DisplayNameSpoof => "display_name_spoof",
+            PhishingKind::LookalikeDomain => "lookalike_domain",
+            PhishingKind::LinkTextMismatch => "link_text_mismatch",
+        }
+    }
+}
+
+/// 指標1件分
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PhishingIndicator {
+    pub kind: PhishingKind,     // 指標の種別
+    pub score: u32,             // 危険度（0〜100、大きいほど疑わしい）
+    pub location: String,       // 検出場所（From、URL(n)、part=n）
+    pub actual: String,         // 実際の値（Fromアドレス、ドメイン、リンク先URL）
+    pub claimed: String, // 装っている値（表示名中のアドレス、保護対象ドメイン、リンク文字列）
+    pub detail: Option<String>, // 補足（類似判定の方法、ホストの対応等）
+}
+
+/// 解析結果からフィッシングの指標を収集（From表示名、ドメイン、HTMLリンクの順）
+///
+/// # 引数
+/// - `mail`: parse_mailの解析結果（urlsは抽出済みであること）
+/// - `protected_domains`: 保護対象ドメイン（Protected_domains設定、小文字化済み）
+///
+/// # 説明
+/// - 表示名のアドレスが実アドレスと別ドメインならスコア60、同じドメインで別アドレスなら30
+/// - 類似ドメインはFromアドレスと抽出済みURLのホストが対象（保護対象ドメインとそのサブドメインは除く）
+/// - リンク文字列のホストとhrefのホスト（リダイレクタ展開後）が異なれば（一方が他方のサブドメインの場合を除く）スコア50
+pub fn analyze(mail: &ParsedMail, protected_domains: &[String]) -> Vec<PhishingIndicator> {
+    let mut found = Vec::new();
+    display_name_spoofs(mail, &mut found);
+    lookalike_domains(mail, protected_domains, &mut found);
+    link_text_mismatches(mail, &mut found);
+    found.truncate(MAX_INDICATORS);
+    found
+}
+
+/// 表示名に含まれるメールアドレスと実際のFromアドレスを比較
+fn display_name_spoofs(mail: &ParsedMail, found: &mut Vec<PhishingIndicator>) {
+    for from in &mail.from {
+        let (Some(name), Some(address)) = (&from.name, &from.address) else {
+            continue;
+        };
+        for claimed in embedded_addresses(name) {
+            if claimed.eq_ignore_ascii_case(address) {
+                continue; // 「山田 <yamada@example.com>」形式の表示名は対象外
+            }
+            let same_domain = domain_of(&claimed).eq_ignore_ascii_case(domain_of(address));
+            found.push(PhishingIndicator {
+                kind: PhishingKind::DisplayNameSpoof,
+                score: if same_domain { 30 } else { 60 },
+                location: "From".to_string(),
+                actual: address.clone(),
+                claimed,
+                detail: Some(name.clone()),
+            });
+        }
+    }
+}
+
+/// 文字列中のメールアドレスらしい部分を抽出（ドメインに「.」を含むもののみ）
+fn embedded_addresses(text: &str) -> Vec<String> {
+    let is_local = |c: char| c.is_ascii_alphanumeric() || "._%+-".contains(c);
+    let is_domain = |c: char| c.is_ascii_alphanumeric() || ".-".contains(c);
+    let mut addresses = Vec::new();
+    for (at, _) in text.match_indices('@') {
+        let start = text[..at]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_local(c))
+            .last()
+            .map_or(at, |(i, _)| i);
+        let end = text[at + 1..]
+            .find(|c: char| !is_domain(c))
+            .map_or(text.len(), |e| at + 1 + e);
+        let local = text[start..at].trim_start_matches('.');
+        let domain = text[at + 1..end].trim_end_matches(['.', '-']);
+        if !local.is_empty() && domain.contains('.') && !domain.starts_with('.') {
+            addresses.push(format!("{}@{}", local, domain));
+        }
+    }
+    addresses
+}
+
+/// メールアドレスのドメイン部分
+fn domain_of(address: &str) -> &str {
+    address.rsplit_once('@').map_or("", |(_, d)| d)
+}
+
+/// Fromアドレスと抽出済みURLのホスト（外側のURLとリダイレクタの各転送先）を保護対象ドメインと比較（同じホストは最初の1件のみ）
+fn lookalike_domains(mail: &ParsedMail, protected: &[String], found: &mut Vec<PhishingIndicator>) {
+    if protected.is_empty() {
+        return;
+    }
+    let protected: Vec<String> = protected
+        .iter()
+        .map(|d| crate::urls::display_host(d.trim_end_matches('.')))
+        .collect();
+    let mut candidates: Vec<(String, String)> = Vec::new(); // (検出場所, ホスト)
+    for from in &mail.from {
+        if let Some(address) = &from.address {
+            let domain = domain_of(address).trim_end_matches('.').to_lowercase();
+            candidates.push(("From".to_string(), crate::urls::display_host(&domain)));
+        }
+    }
+    for (i, url) in mail.urls.iter().enumerate() {
+        for host in url.hosts() {
+            candidates.push((format!("URL({})", i + 1), host.to_string())); // リダイレクタの外側も転送先も対象
+        }
+    }
+    let mut seen: Vec<String> = Vec::new();
+    for (location, host) in candidates {
+        if host.is_empty() || seen.contains(&host) {
+            continue;
+        }
+        seen.push(host.clone());
+        if protected.iter().any(|p| is_same_or_subdomain(&host, p)) {
+            continue; // 保護対象ドメイン自身・そのサブドメインは正規
+        }
+        for p in &protected {
+            if let Some((method, score)) = resemblance(&host, p) {
+                found.push(PhishingIndicator {
+                    kind: PhishingKind::LookalikeDomain,
+                    score,
+                    location: location.clone(),
+                    actual: host.clone(),
+                    claimed: p.clone(),
+                    detail: Some(method),
+                });
+                break; // 1ホストにつき最初に一致した保護対象ドメインのみ
+            }
+        }
+    }
+}
+
+/// hostがdomainそのもの、またはそのサブドメインか
+fn is_same_or_subdomain(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// ホストが保護対象ドメインに似ているかを判定し、（判定方法, スコア）を返す
+///
+/// # 説明
+/// - ホストの末尾から保護対象ドメインと同じラベル数を取り出して比較する（login.paypa1.com → paypa1.com）
+/// - 紛らわしい文字を正規化して一致: 非ASCII（punycode）なら「punycode」80、ASCIIのみ（0/o、rn/m等）なら「homoglyph」70
+/// - 編集距離が1（10文字以上は2）以内なら「edit_distance(n)」60
+/// - 保護対象ドメインをラベルとして含む（paypal.com.evil.example等）なら「embedded」50
+fn resemblance(host: &str, protected: &str) -> Option<(String, u32)> {
+    let labels = protected.split('.').count();
+    let host_labels: Vec<&str> = host.split('.').collect();
+    let tail = host_labels[host_labels.len().saturating_sub(labels)..].join(".");
+    if tail != protected && skeleton(&tail) == skeleton(protected) {
+        return Some(if tail.is_ascii() {
+            ("homoglyph".to_string(), 70)
+        } else {
+            ("punycode".to_string(), 80)
+        });
+    }
+    let limit = if protected.chars().count() >= 10 {
+        2
+    } else {
+        1
+    };
+    let distance = edit_distance(&tail, protected);
+    if distance > 0 && distance <= limit {
+        return Some((format!("edit_distance({})", distance), 60));
+    }
+    let dotted = format!(".{}", host);
+    if dotted.contains(&format!(".{}.", protected)) || dotted.contains(&format!(".{}-", protected))
+    {
+        return Some(("embedded".to_string(), 50));
+    }
+    None
+}
+
+/// 見た目が紛らわしい文字をASCIIの代表文字に寄せた文字列（比較専用）
+fn skeleton(text: &str) -> String {
+    let mapped: String = text
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '\u{ff01}'..='\u{ff5e}' => {
+                char::from_u32(c as u32 - 0xfee0).map_or(c, |a| a.to_ascii_lowercase())
+                // 全角英数字
+            }
+            _ => c,
+        })
+        .map(|c| match c {
+            '0' | 'о' | 'ο' | 'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => 'o',
+            '1' | 'ӏ' | 'ⅼ' | 'ł' => 'l',
+            'а' | 'α' | 'ɑ' | 'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => 'a',
+            'е' | 'è' | 'é' | 'ê' | 'ë' | 'ē' => 'e',
+            'і' | 'ι' | 'ı' | 'ì' | 'í' | 'î' | 'ï' | 'ī' => 'i',
+            'р' | 'ρ' => 'p',
+            'с' | 'ϲ' | 'ç' | 'ć' | 'č' => 'c',
+            'у' | 'ý' | 'ÿ' => 'y',
+            'х' => 'x',
+            'ѕ' | 'ş' | 'ș' | 'š' => 's',
+            'ј' => 'j',
+            'ԁ' | 'đ' => 'd',
+            'ɡ' | 'ğ' => 'g',
+            'ν' => 'v',
+            'κ' => 'k',
+            'ո' | 'ñ' => 'n',
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' => 'u',
+            'ţ' | 'ț' => 't',
+            'ž' | 'ź' | 'ż' => 'z',
+            _ => c,
+        })
+        .collect();
+    mapped.replace("rn", "m").replace("vv", "w")
+}
+
+/// 編集距離（挿入・削除・置換・隣接文字の入れ替えをそれぞれ1とする）
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1); // 隣接文字の入れ替え
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// HTMLのリンク文字列（URL表記）とhrefのホストを比較
+///
+/// # 説明
+/// - hrefは外側のURLから既知のリダイレクタの各転送先までたどり、最終的な転送先のホストをリンク文字列のホストと比較する
+/// - リンク文字列自体がリダイレクタのURLなら、その最終的な転送先のホストを装っている値とみなす
+/// - 補足（detail）には表示上のホストとhrefの外側から最終的な転送先までのホストを並べる
+fn link_text_mismatches(mail: &ParsedMail, found: &mut Vec<PhishingIndicator>) {
+    for html in &mail.html_parts {
+        for (href, text) in anchors(&html.content) {
+            let Some(shown) = link_text_url(&text) else {
+                continue; // リンク文字列がURLでなければ対象外
+            };
+            let (Some(target), Some(shown)) = (
+                crate::urls::normalize(html.part, UrlSource::Html, &href),
+                crate::urls::normalize(html.part, UrlSource::Html, &shown),
+            ) else {
+                continue; // mailto:等
+            };
+            let hops: Vec<&str> = target.hosts().collect(); // 外側のURLと各転送先のホスト
+            let (Some(&target_host), Some(shown_host)) = (hops.last(), shown.hosts().last()) else {
+                continue;
+            };
+            if is_same_or_subdomain(target_host, shown_host)
+                || is_same_or_subdomain(shown_host, target_host)
+            {
+                continue; // www.の有無等、同じドメイン配下なら一致とみなす
+            }
+            found.push(PhishingIndicator {
+                kind: PhishingKind::LinkTextMismatch,
+                score: 50,
+                location: format!("part={}", html.part),
+                actual: target.normalized.clone(),
+                claimed: text,
+                detail: Some(format!("{} → {}", shown_host, hops.join(" → "))),
+            });
+        }
+    }
+}
+
+/// HTMLの<a href>を（href, 表示文字列）の一覧にする（表示文字列は実体参照を展開し、空白を1つにまとめる）
+fn anchors(html: &str) -> Vec<(String, String)> {
+    let mut anchors = Vec::new();
+    let mut current: Option<(String, String)> = None; // 閉じていない<a>
+    for token in crate::urls::html_tokens(html) {
+        match token {
+            HtmlToken::Start(name, attrs) if name == "a" => {
+                let href = attrs.into_iter().find(|(n, _)| n == "href").map(|(_, v)| v);
+                anchors.extend(current.take()); // 閉じ忘れの<a>はここで終わりとする
+                current = href.map(|h| (h, String::new()));
+            }
+            HtmlToken::End(name) if name == "a" => anchors.extend(current.take()),
+            HtmlToken::Start(..) | HtmlToken::End(_) => {
+                if let Some((_, text)) = &mut current {
+                    text.push(' '); // <br>・<span>等の区切り
+                }
+            }
+            HtmlToken::Text(t) => {
+                if let Some((_, text)) = &mut current {
+                    if text.len() < MAX_LINK_TEXT {
+                        text.push_str(&crate::urls::decode_entities(t));
+                    }
+                }
+            }
+        }
+    }
+    anchors.extend(current);
+    anchors
+        .into_iter()
+        .map(|(href, text)| (href, text.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .collect()
+}
+
+/// リンク文字列全体がURL（スキーム付き・「www.」始まり・「ドメイン/パス」形式）ならURLとして返す
+fn link_text_url(text: &str) -> Option<String> {
+    if text.is_empty() || text.contains(' ') {
+        return None;
+    }
+    if let Some(url) = crate::urls::text_urls(text).first() {
+        return (text.starts_with(url) && text.len() - url.len() <= 1).then(|| url.to_string());
+    }
+    let host = text.split(['/', '?', '#']).next()?;
+    let labels: Vec<&str> = host.split('.').collect();
+    let tld = labels.last()?;
+    let valid = labels.len() >= 2
+        && labels
+            .iter()
+            .all(|l| !l.is_empty() && l.chars().all(|c| c.is_alphanumeric() || c == '-'))
+        && tld.chars().count() >= 2
+        && tld.chars().all(char::is_alphabetic);
+    valid.then(|| format!("http://{}", text))
+}
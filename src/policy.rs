@@ -0,0 +1,318 @@
+// =========================
+// policy.rs
+// MilterDecoder 受理/拒否判定モジュール
+//
+// 【このファイルで使う主なクレート】
+// - crate::milter_command: Milter応答enum（MilterResponse）、変更アクションenum（MilterModification）
+// - std: 文字列処理（分割・大文字小文字無視比較）
+//
+// 【役割】
+// - 設定ファイルのPolicy_rule行の解析
+// - フェーズ（CONNECT, HELO, MAIL, RCPT, EOH, EOM）ごとの判定対象に対するルール照合
+// - 「macro:」接頭辞付きルールのみ受信済みマクロ値（名前=値）と照合
+// - 判定結果をMilter応答（CONTINUE/ACCEPT/REJECT/TEMPFAIL/DISCARD/REPLYCODE）として返す
+// - EOMで一致したルールのメッセージ変更アクション（ヘッダ・受信者・送信者・本文の変更、隔離）を返す
+// =========================
+
+use crate::milter_command::{MilterModification, MilterResponse}; // Milter応答・変更アクション
+
+/// 判定フェーズ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyStage {
+    Connect, // CONNECT: クライアントホスト名・アドレス
+    Helo,    // HELO: HELO/EHLOホスト名
+    Mail,    // MAIL: エンベロープ送信者
+    Rcpt,    // RCPT: エンベロープ受信者
+    Eoh,     // EOH: 各ヘッダ行（本文受信前に判定）
+    Eom,     // EOM: 各ヘッダ行（本文受信後に判定）
+}
+
+impl PolicyStage {
+    /// 設定ファイル上のフェーズ名から変換
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "connect" => Some(PolicyStage::Connect),
+            "helo" => Some(PolicyStage::Helo),
+            "mail" => Some(PolicyStage::Mail),
+            "rcpt" => Some(PolicyStage::Rcpt),
+            "eoh" => Some(PolicyStage::Eoh),
+            "eom" => Some(PolicyStage::Eom),
+            _ => None,
+        }
+    }
+
+    /// ルールに一致しなかった場合の応答（EOMのみACCEPT、それ以外はCONTINUE）
+    pub fn default_response(self) -> MilterResponse {
+        match self {
+            PolicyStage::Eom => MilterResponse::Accept,
+            _ => MilterResponse::Continue,
+        }
+    }
+}
+
+/// ルールの照合対象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyTarget {
+    Subject, // フェーズの判定対象（ホスト名、送信者、ヘッダ行等）
+    Macro,   // 受信済みマクロ値（「名前=値」形式、パターンに「macro:」接頭辞）
+}
+
+/// ルール一致時の動作
+#[derive(Debug, Clone)]
+pub enum PolicyAction {
+    Respond(MilterResponse),    // 応答を返す（最初に一致したルールのみ）
+    Modify(MilterModification), // EOMで変更アクションを追加（一致したルールすべて）
+}
+
+/// 判定ルール1件（Policy_rule行）
+#[derive(Debug, Clone)]
+pub struct PolicyRule {
+    pub stage: PolicyStage,   // 判定フェーズ
+    pub target: PolicyTarget, // 照合対象
+    pub pattern: String,      // 照合パターン（*と?のワイルドカード、大文字小文字無視、接頭辞除く）
+    pub action: PolicyAction, // 一致時の動作
+}
+
+impl PolicyRule {
+    /// 照合対象に応じて判定対象またはマクロ値からパターンに一致したものを返す
+    fn find_match<'a>(&self, subjects: &[&'a str], macros: &'a [String]) -> Option<&'a str> {
+        match self.target {
+            PolicyTarget::Subject => subjects
+                .iter()
+                .copied()
+                .find(|s| glob_match(&self.pattern, s)),
+            PolicyTarget::Macro => macros
+                .iter()
+                .map(|m| m.as_str())
+                .find(|m| glob_match(&self.pattern, m)),
+        }
+    }
+}
+
+impl PolicyRule {
+    /// Policy_rule行（"Policy_rule "以降）を解析
+    ///
+    /// # 書式
+    /// `<stage> <pattern> <action> [<code> [<xcode>] <text...>]`
+    /// - stage: connect / helo / mail / rcpt / eoh / eom
+    /// - pattern: 「macro:」で始まる場合はマクロ値（例: `macro:{auth_authen}=*`）、それ以外はフェーズの判定対象と照合
+    /// - action: continue / accept / reject / tempfail / discard / reply
+    /// - replyの場合のみ code（4xx/5xx）、xcode（省略可）、text を続けて指定
+    /// - eomのみ変更アクションも指定可能（受理時に最終応答より前に送信）
+    ///   - addheader <name> <value...> / chgheader <name> <index> [<value...>]（値省略で削除）/ insheader <index> <name> <value...>
+    ///   - addrcpt <rcpt> [<esmtp args...>] / delrcpt <rcpt> / chgfrom <sender> [<esmtp args...>]
+    ///   - replbody [<text...>]（省略で本文を空にする）/ quarantine <reason...>
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut tokens = spec.split_whitespace(); // 空白区切り
+        let stage_name = tokens.next().ok_or("フェーズ未指定")?; // フェーズ名
+        let stage = PolicyStage::from_name(stage_name)
+            .ok_or_else(|| format!("不明なフェーズ: {}", stage_name))?;
+        let pattern = tokens.next().ok_or("パターン未指定")?; // 照合パターン
+        let (target, pattern) = match pattern.strip_prefix("macro:") {
+            Some("") => return Err("マクロのパターン未指定".to_string()),
+            Some(rest) => (PolicyTarget::Macro, rest.to_string()),
+            None => (PolicyTarget::Subject, pattern.to_string()),
+        };
+        let action = tokens.next().ok_or("アクション未指定")?; // アクション名
+        let action = action.to_ascii_lowercase();
+        if let Some(modification) = parse_modification(&action, &mut tokens)? {
+            if stage != PolicyStage::Eom {
+                return Err(format!("変更アクションはeomのみ指定可能: {}", action));
+            }
+            return Ok(PolicyRule {
+                stage,
+                target,
+                pattern,
+                action: PolicyAction::Modify(modification),
+            });
+        }
+        let response = match action.as_str() {
+            "continue" => MilterResponse::Continue,
+            "accept" => MilterResponse::Accept,
+            "reject" => MilterResponse::Reject,
+            "tempfail" => MilterResponse::TempFail,
+            "discard" => MilterResponse::Discard,
+            "reply" => {
+                // reply <code> [<xcode>] <text...>
+                let code = tokens
+                    .next()
+                    .and_then(|c| c.parse::<u16>().ok())
+                    .ok_or("応答コード未指定")?;
+                let rest: Vec<&str> = tokens.collect(); // xcode + text
+                let (xcode, text_tokens) = match rest.first() {
+                    Some(x) if x.contains('.') && x.starts_with(|c: char| c.is_ascii_digit()) => {
+                        (Some(*x), &rest[1..]) // 先頭が拡張ステータスコード
+                    }
+                    _ => (None, &rest[..]),
+                };
+                MilterResponse::reply_code(code, xcode, &text_tokens.join(" "))?
+            }
+            other => return Err(format!("不明なアクション: {}", other)),
+        };
+        Ok(PolicyRule {
+            stage,
+            target,
+            pattern,
+            action: PolicyAction::Respond(response),
+        })
+    }
+}
+
+/// 変更アクション名と引数からMilterModificationを生成（変更アクション以外はNone）
+fn parse_modification<'a>(
+    action: &str,
+    tokens: &mut impl Iterator<Item = &'a str>,
+) -> Result<Option<MilterModification>, String> {
+    let mut arg = |what: &str| {
+        tokens
+            .next()
+            .map(str::to_string)
+            .ok_or(format!("{}未指定", what))
+    };
+    let modification = match action {
+        "addheader" => MilterModification::AddHeader {
+            name: arg("ヘッダ名")?,
+            value: tokens.collect::<Vec<_>>().join(" "),
+        },
+        "chgheader" => MilterModification::ChangeHeader {
+            name: arg("ヘッダ名")?,
+            index: arg("出現番号")?.parse().map_err(|_| "出現番号不正")?,
+            value: tokens.collect::<Vec<_>>().join(" "),
+        },
+        "insheader" => MilterModification::InsertHeader {
+            index: arg("挿入位置")?.parse().map_err(|_| "挿入位置不正")?,
+            name: arg("ヘッダ名")?,
+            value: tokens.collect::<Vec<_>>().join(" "),
+        },
+        "addrcpt" => {
+            let rcpt = arg("受信者")?;
+            let args: Vec<&str> = tokens.collect();
+            if args.is_empty() {
+                MilterModification::AddRcpt { rcpt }
+            } else {
+                MilterModification::AddRcptPar {
+                    rcpt,
+                    args: Some(args.join(" ")),
+                }
+            }
+        }
+        "delrcpt" => MilterModification::DelRcpt {
+            rcpt: arg("受信者")?,
+        },
+        "chgfrom" => {
+            let sender = arg("送信者")?;
+            let args: Vec<&str> = tokens.collect();
+            MilterModification::ChangeFrom {
+                sender,
+                args: (!args.is_empty()).then(|| args.join(" ")),
+            }
+        }
+        "replbody" => {
+            let text = tokens.collect::<Vec<_>>().join(" ");
+            let body = if text.is_empty() {
+                Vec::new() // 本文を空にする
+            } else {
+                format!("{}\r\n", text).into_bytes()
+            };
+            MilterModification::ReplaceBody { body }
+        }
+        "quarantine" => {
+            let reason = tokens.collect::<Vec<_>>().join(" ");
+            if reason.is_empty() {
+                return Err("隔離理由未指定".to_string());
+            }
+            MilterModification::Quarantine { reason }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(modification))
+}
+
+/// フェーズの判定対象（複数可）をルールと照合し、応答を決定
+///
+/// # 引数
+/// - `subjects`: フェーズごとの判定対象（ホスト名、送信者、ヘッダ行等）
+/// - `macros`: 現在有効なマクロ値（「名前=値」形式、例: {auth_authen}=user）
+///
+/// # 説明
+/// - 設定順に評価し、最初に一致したルールの応答を返す
+/// - マクロ値は「macro:」接頭辞付きのルールとのみ照合する（判定対象のルールには混ぜない）
+/// - 一致しなければフェーズ既定の応答（EOMはACCEPT、それ以外はCONTINUE）
+pub fn evaluate(
+    rules: &[PolicyRule],
+    stage: PolicyStage,
+    subjects: &[&str],
+    macros: &[String],
+) -> MilterResponse {
+    for rule in rules.iter().filter(|r| r.stage == stage) {
+        let PolicyAction::Respond(response) = &rule.action else {
+            continue; // 変更アクションはmodificationsで適用
+        };
+        if let Some(hit) = rule.find_match(subjects, macros) {
+            crate::printdaytimeln!(
+                "ポリシー一致: stage={:?} pattern={} subject={} → {}",
+                stage,
+                rule.pattern,
+                hit,
+                response.as_str()
+            );
+            return response.clone();
+        }
+    }
+    stage.default_response()
+}
+
+/// EOMの判定対象（ヘッダ行）に一致した変更アクションルールの変更アクションを返す
+///
+/// # 引数
+/// - `subjects`: EOMの判定対象（「名前: 値」形式のヘッダ行）
+/// - `macros`: 現在有効なマクロ値（「macro:」接頭辞付きルールの照合対象）
+///
+/// # 説明
+/// - 一致したルールすべての変更アクションを設定順に返す（応答ルールと異なり最初の一致で止めない）
+pub fn modifications(
+    rules: &[PolicyRule],
+    subjects: &[&str],
+    macros: &[String],
+) -> Vec<MilterModification> {
+    let mut out = Vec::new();
+    for rule in rules.iter().filter(|r| r.stage == PolicyStage::Eom) {
+        let PolicyAction::Modify(modification) = &rule.action else {
+            continue;
+        };
+        if let Some(hit) = rule.find_match(subjects, macros) {
+            crate::printdaytimeln!(
+                "ポリシー一致: stage=Eom pattern={} subject={} → {}",
+                rule.pattern,
+                hit,
+                modification.as_str()
+            );
+            out.push(modification.clone());
+        }
+    }
+    out
+}
+
+/// ワイルドカード照合（*: 任意の文字列、?: 任意の1文字、ASCII大文字小文字無視）
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.to_ascii_lowercase().chars().collect(); // パターン
+    let t: Vec<char> = text.to_ascii_lowercase().chars().collect(); // 対象文字列
+    let (mut pi, mut ti) = (0usize, 0usize); // 照合位置
+    let mut star: Option<(usize, usize)> = None; // 直近の*位置（パターン位置, 対象位置）
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1; // 1文字一致
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti)); // *の位置を記録（0文字一致から試す）
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1; // *の一致範囲を1文字伸ばして再試行
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false; // 不一致
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*') // 残りパターンが*のみなら一致
+}
@@ -0,0 +1,310 @@
+// =========================
+// received.rs
+// MilterDecoder Receivedヘッダ（中継経路）解析モジュール
+//
+// 【このファイルで使う主なクレート】
+// - chrono: Receivedヘッダの日時（RFC 5322形式）のパース・ホップ間の遅延計算
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: IPアドレスの検証（net::IpAddr）
+// - crate::header: 受信順ヘッダリスト（MTAから受け取ったままの値）
+//
+// 【役割】
+// - Receivedヘッダを受信順（上が新しいホップ）のまま1ホップずつ解析
+// - from/byのホスト名・逆引き名・IPアドレス、with（ESMTP/ESMTPS/ESMTPA等）、TLS情報、id、for、日時の抽出
+// - 1つ前（1行下）のホップからの遅延秒の計算
+// - 時計のずれ（負の遅延・未来の日時）や偽装が疑われるホップ（経路の不連続・HELOのIPと接続元IPの不一致等）の検出
+// =========================
+
+use chrono::{DateTime, FixedOffset}; // 日時のパース・差分計算
+use serde::Serialize; // JSON出力用
+use std::net::IpAddr; // IPアドレスの検証
+
+use crate::header::HeaderList; // 受信順ヘッダリスト
+
+const MAX_HOPS: usize = 100; // 解析するReceivedヘッダの上限
+const SKEW_TOLERANCE: i64 = 60; // 時計のずれとみなさない誤差（秒）
+
+/// ホップに付けるフラグ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HopFlag {
+    Malformed,        // from・byのどちらも無い（Receivedヘッダとして不正）
+    MissingTimestamp, // 「;」以降の日時が無い、またはパースできない
+    FutureTimestamp,  // 日時が受信時刻より未来
+    ClockSkew,        // 1つ前のホップより前の日時（負の遅延）
+    HeloMismatch,     // fromのHELO名がIPリテラルで、接続元IPと異なる
+    ChainBreak,       // fromのホスト・IPが1つ前のホップのbyと一致しない
+}
+
+impl HopFlag {
+    /// ログ・JSON出力用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            HopFlag::Malformed => "malformed",
+            HopFlag::MissingTimestamp => "missing_timestamp",
+            HopFlag::FutureTimestamp => "future_timestamp",
+            HopFlag::ClockSkew => "clock_skew",
+            HopFlag::HeloMismatch => "helo_mismatch",
+            HopFlag::ChainBreak => "chain_break",
+        }
+    }
+}
+
+/// Receivedヘッダ1件（1ホップ）分の解析結果
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReceivedHop {
+    pub index: usize,                // ヘッダ上の順番（1が最上段＝最後に中継したホップ）
+    pub from_host: Option<String>,   // fromのホスト名（送信側が名乗ったHELO名）
+    pub from_rdns: Option<String>,   // fromのコメント内の逆引きホスト名
+    pub from_ip: Option<String>,     // fromの接続元IPアドレス
+    pub by_host: Option<String>,     // byのホスト名（受信したサーバー）
+    pub by_ip: Option<String>,       // byのIPアドレス（記載があれば）
+    pub protocol: Option<String>,    // withのプロトコル（大文字、ESMTP/ESMTPS/ESMTPA/ESMTPSA等）
+    pub tls: Option<String>,         // TLS情報（コメント内のバージョン・暗号スイート等）
+    pub encrypted: bool,             // TLSで受信したか（ESMTPS/ESMTPSA、またはTLS情報あり）
+    pub authenticated: bool,         // SMTP認証付きか（ESMTPA/ESMTPSA等）
+    pub id: Option<String>,          // id（受信側のキューID等）
+    pub for_address: Option<String>, // forの宛先アドレス（山括弧は除く）
+    pub timestamp: Option<String>,   // 日時（RFC3339、元のタイムゾーンのまま）
+    pub delay: Option<i64>,          // 1つ前（1行下）のホップからの遅延秒（日時が無ければNone）
+    pub flags: Vec<HopFlag>,         // 時計のずれ・偽装が疑われる点
+    pub raw: String,                 // ヘッダ値（折り返しを空白1つにまとめたもの）
+    #[serde(skip)]
+    parsed_time: Option<DateTime<FixedOffset>>, // 遅延計算用の日時
+}
+
+/// 受信順ヘッダからReceivedヘッダの中継経路を解析
+///
+/// # 引数
+/// - `headers`: Milterで受信したヘッダ（受信順）
+/// - `now`: 受信時刻（未来の日時の判定に使う）
+///
+/// # 説明
+/// - 戻り値はヘッダの出現順（最上段＝最新のホップが先頭）
+/// - 遅延は各ホップの日時から1行下（1つ前）のホップの日時を引いた秒数（最下段はNone）
+/// - 遅延が-60秒より小さければclock_skew、受信時刻より60秒以上未来ならfuture_timestampを付ける
+/// - fromのホスト名・逆引き名・IPのいずれも1行下のホップのby（ホスト名・IP）と一致しなければchain_breakを付ける
+pub fn parse_chain(headers: &HeaderList, now: DateTime<FixedOffset>) -> Vec<ReceivedHop> {
+    let mut hops: Vec<ReceivedHop> = headers
+        .get_all("Received")
+        .take(MAX_HOPS)
+        .enumerate()
+        .map(|(i, h)| parse_hop(i + 1, &String::from_utf8_lossy(&h.value)))
+        .collect();
+    for i in 0..hops.len() {
+        if let Some(time) = hops[i].parsed_time {
+            if (time - now).num_seconds() > SKEW_TOLERANCE {
+                hops[i].flags.push(HopFlag::FutureTimestamp);
+            }
+        }
+        let Some(older) = hops.get(i + 1).cloned() else {
+            continue; // 最下段（最初のホップ）は比較対象なし
+        };
+        if let (Some(time), Some(prev)) = (hops[i].parsed_time, older.parsed_time) {
+            let delay = (time - prev).num_seconds();
+            hops[i].delay = Some(delay);
+            if delay < -SKEW_TOLERANCE {
+                hops[i].flags.push(HopFlag::ClockSkew);
+            }
+        }
+        if breaks_chain(&hops[i], &older) {
+            hops[i].flags.push(HopFlag::ChainBreak);
+        }
+    }
+    hops
+}
+
+/// Receivedヘッダ1件を解析
+fn parse_hop(index: usize, value: &str) -> ReceivedHop {
+    let raw = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (clauses, date) = match raw.rsplit_once(';') {
+        Some((c, d)) => (c, Some(d.trim())),
+        None => (raw.as_str(), None),
+    };
+    let mut hop = ReceivedHop {
+        index,
+        ..ReceivedHop::default()
+    };
+    let mut clause = ""; // 直前のキーワード（from/by/with/id/for/via）
+    for token in tokenize(clauses) {
+        match token {
+            Token::Word(word) => {
+                if let Some(keyword) = ["from", "by", "with", "id", "for", "via"]
+                    .into_iter()
+                    .find(|k| word.eq_ignore_ascii_case(k))
+                {
+                    clause = keyword;
+                    continue;
+                }
+                match clause {
+                    "from" if hop.from_host.is_none() => hop.from_host = Some(word.to_string()),
+                    "from" if hop.from_ip.is_none() => hop.from_ip = ip_literal(word), // 「from host [IP]」形式
+                    "by" if hop.by_host.is_none() => hop.by_host = Some(word.to_string()),
+                    "with" if hop.protocol.is_none() => {
+                        hop.protocol = Some(word.to_ascii_uppercase())
+                    }
+                    "id" if hop.id.is_none() => hop.id = Some(word.to_string()),
+                    "for" if hop.for_address.is_none() => {
+                        hop.for_address = Some(
+                            word.trim_start_matches('<')
+                                .trim_end_matches('>')
+                                .to_string(),
+                        )
+                    }
+                    _ => {}
+                }
+            }
+            Token::Comment(comment) => {
+                if hop.tls.is_none() && is_tls_comment(comment) {
+                    hop.tls = Some(comment.to_string());
+                }
+                match clause {
+                    "from" => read_from_comment(&mut hop, comment),
+                    "by" if hop.by_ip.is_none() => {
+                        hop.by_ip = comment.split_whitespace().find_map(ip_literal);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    // HELO名がIPリテラル（[192.0.2.1]等）ならIPとしても扱う
+    let helo_ip = hop.from_host.as_deref().and_then(ip_literal);
+    match (&helo_ip, &hop.from_ip) {
+        (Some(helo), Some(ip)) if helo != ip => hop.flags.push(HopFlag::HeloMismatch),
+        (Some(helo), None) => hop.from_ip = Some(helo.clone()),
+        _ => {}
+    }
+    let protocol = hop.protocol.as_deref().unwrap_or("");
+    let smtp = protocol.contains("SMTP");
+    hop.encrypted = hop.tls.is_some() || (smtp && protocol.trim_end_matches('A').ends_with('S'));
+    hop.authenticated = smtp && protocol.ends_with('A');
+    if hop.from_host.is_none() && hop.by_host.is_none() {
+        hop.flags.push(HopFlag::Malformed);
+    }
+    hop.parsed_time = date.and_then(parse_date);
+    hop.timestamp = hop.parsed_time.map(|t| t.to_rfc3339());
+    if hop.parsed_time.is_none() {
+        hop.flags.push(HopFlag::MissingTimestamp);
+    }
+    hop.raw = raw;
+    hop
+}
+
+/// Receivedヘッダの字句
+enum Token<'a> {
+    Word(&'a str),    // 空白区切りの語（[IP]・<アドレス>を含む）
+    Comment(&'a str), // 括弧内（入れ子の括弧を含む、外側の括弧は除く）
+}
+
+/// 語とコメント（括弧）に分ける
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if rest.starts_with('(') {
+            let mut depth = 0;
+            let mut end = rest.len();
+            for (i, c) in rest.char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = i;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            tokens.push(Token::Comment(rest[1..end].trim()));
+            rest = rest.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '(')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    tokens
+}
+
+/// fromのコメント（「rdns [IP]」「helo=...」「HELO ...」等）から逆引き名とIPを取得
+fn read_from_comment(hop: &mut ReceivedHop, comment: &str) {
+    let mut words = comment.split_whitespace();
+    while let Some(word) = words.next() {
+        if let Some(ip) = ip_literal(word) {
+            if hop.from_ip.is_none() {
+                hop.from_ip = Some(ip);
+            }
+        } else if word.eq_ignore_ascii_case("helo") {
+            words.next(); // 「HELO 名前」の名前は逆引き名ではない
+        } else if word.to_ascii_lowercase().starts_with("helo=") {
+            continue; // Eximの「helo=名前」も同様
+        } else if hop.from_rdns.is_none() && hop.from_ip.is_none() && is_hostname(word) {
+            hop.from_rdns = Some(word.to_ascii_lowercase()); // IPより前にある名前を逆引き名とする
+        }
+    }
+}
+
+/// 「[192.0.2.1]」「[IPv6:2001:db8::1]」「192.0.2.1」形式ならIPアドレス文字列を返す
+fn ip_literal(word: &str) -> Option<String> {
+    let inner = word
+        .trim_end_matches([',', ';'])
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let inner = inner
+        .strip_prefix("IPv6:")
+        .or_else(|| inner.strip_prefix("ipv6:"))
+        .unwrap_or(inner);
+    inner.parse::<IpAddr>().ok().map(|ip| ip.to_string())
+}
+
+/// ホスト名らしい語か（「.」を含み、英数字・「-」・「.」のみ、または「unknown」「localhost」）
+fn is_hostname(word: &str) -> bool {
+    word.eq_ignore_ascii_case("unknown")
+        || word.eq_ignore_ascii_case("localhost")
+        || (word.contains('.')
+            && !word.starts_with('.')
+            && word
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.'))
+}
+
+/// TLS情報のコメントか（「using TLSv1.3 with cipher ...」「version=TLS1_3 cipher=...」等）
+fn is_tls_comment(comment: &str) -> bool {
+    let upper = comment.to_ascii_uppercase();
+    upper.contains("TLS") || upper.contains("SSL") || upper.contains("CIPHER")
+}
+
+/// hopのfrom（ホスト名・逆引き名・IP）がolderのby（ホスト名・IP）と食い違うか（どちらかの情報が無ければfalse）
+fn breaks_chain(hop: &ReceivedHop, older: &ReceivedHop) -> bool {
+    let from: Vec<String> = [&hop.from_host, &hop.from_rdns, &hop.from_ip]
+        .into_iter()
+        .flatten()
+        .map(|s| {
+            s.trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_ascii_lowercase()
+        })
+        .filter(|s| !s.eq_ignore_ascii_case("unknown"))
+        .collect();
+    let by: Vec<String> = [&older.by_host, &older.by_ip]
+        .into_iter()
+        .flatten()
+        .map(|s| s.to_ascii_lowercase())
+        .collect();
+    !from.is_empty() && !by.is_empty() && !from.iter().any(|f| by.contains(f))
+}
+
+/// Receivedヘッダの日時（RFC 5322形式、末尾のタイムゾーン名コメントは除く）をパース
+fn parse_date(text: &str) -> Option<DateTime<FixedOffset>> {
+    let text = match text.find('(') {
+        Some(comment) => text[..comment].trim(),
+        None => text.trim(),
+    };
+    DateTime::parse_from_rfc2822(text).ok()
+}
@@ -0,0 +1,390 @@
+// =========================
+// resolver.rs
+// MilterDecoder DNS問い合わせモジュール
+//
+// 【このファイルで使う主なクレート】
+// - trust-dns-resolver: システムのリゾルバ設定（/etc/resolv.conf）を使ったDNS問い合わせ
+// - std: ゾーンファイル読み込み（fs::read_to_string）、コレクション（HashMap）、パス、IPアドレス（net）
+//
+// 【役割】
+// - DKIM公開鍵・SPFレコード等の問い合わせ先を差し替えられるリゾルバトレイト（Resolver、TXT/A/AAAA/MX/PTR）の定義
+// - システムのDNSへ問い合わせるリゾルバ（SystemResolver）
+// - メモリ上のレコード表を引くリゾルバ（StaticResolver、ゾーンファイルからも生成）
+// - Dns_resolver設定（system または ゾーンファイルパス）からのリゾルバ生成
+// =========================
+
+use std::collections::HashMap; // (名前, 型) → レコード一覧
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr}; // A/AAAA/PTRレコード
+use std::path::PathBuf; // ゾーンファイルパス
+
+/// 問い合わせ失敗の種別
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupError {
+    NotFound,         // 名前またはレコードが存在しない（NXDOMAIN/NODATA）
+    TempFail(String), // 一時的な失敗（タイムアウト・サーバー障害・ゾーンファイル読み込み失敗等）
+}
+
+/// DNS問い合わせのトレイト（テストではゾーンファイルやメモリ上の表に差し替える）
+pub trait Resolver {
+    /// TXTレコードを取得（1レコード内の複数の文字列は連結済み）
+    fn txt(&self, name: &str) -> Result<Vec<String>, LookupError>;
+
+    /// Aレコードを取得
+    fn a(&self, name: &str) -> Result<Vec<Ipv4Addr>, LookupError>;
+
+    /// AAAAレコードを取得
+    fn aaaa(&self, name: &str) -> Result<Vec<Ipv6Addr>, LookupError>;
+
+    /// MXレコードを取得（優先度, メールサーバー名）
+    fn mx(&self, name: &str) -> Result<Vec<(u16, String)>, LookupError>;
+
+    /// IPアドレスの逆引き（PTRレコードのホスト名、末尾の「.」なし）
+    fn ptr(&self, ip: IpAddr) -> Result<Vec<String>, LookupError>;
+}
+
+/// IPアドレスの逆引き名（d.c.b.a.in-addr.arpa / ニブル逆順.ip6.arpa）
+pub fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let o = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        }
+        IpAddr::V6(v6) => {
+            let mut labels: Vec<String> = v6
+                .octets()
+                .iter()
+                .flat_map(|b| [b >> 4, b & 0x0f])
+                .map(|n| format!("{:x}", n))
+                .collect();
+            labels.reverse();
+            labels.push("ip6.arpa".to_string());
+            labels.join(".")
+        }
+    }
+}
+
+/// 問い合わせ先の指定（Dns_resolver設定）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsSource {
+    System,        // システムのリゾルバ設定でDNSへ問い合わせ
+    Zone(PathBuf), // ゾーンファイルのレコードのみを引く
+}
+
+impl DnsSource {
+    /// Dns_resolver行（"Dns_resolver "以降）を解析（system で実DNS、それ以外はゾーンファイルパス）
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.trim() {
+            "" => Err("問い合わせ先未指定".to_string()),
+            "system" => Ok(DnsSource::System),
+            path => Ok(DnsSource::Zone(PathBuf::from(path))),
+        }
+    }
+
+    /// 指定に従ってリゾルバを生成
+    ///
+    /// # 説明
+    /// - ゾーンファイルは呼び出しごとに読み込む（再起動なしで鍵を差し替えられる）
+    /// - 生成に失敗した場合はログに出し、全ての問い合わせが一時的な失敗になるリゾルバを返す
+    /// - システムのリゾルバは非同期の文脈で破棄するとpanicするため、tokioのワーカー上では生成から破棄までblock_in_place内で扱う
+    pub fn open(&self) -> Box<dyn Resolver> {
+        let result = match self {
+            DnsSource::System => SystemResolver::new().map(|r| Box::new(r) as Box<dyn Resolver>),
+            DnsSource::Zone(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))
+                .and_then(|text| StaticResolver::from_zone(&text))
+                .map(|r| Box::new(r) as Box<dyn Resolver>),
+        };
+        result.unwrap_or_else(|e| {
+            crate::printdaytimeln!("リゾルバ初期化失敗: {}", e);
+            Box::new(StaticResolver::unavailable(e))
+        })
+    }
+}
+
+/// システムのリゾルバ設定でDNSへ問い合わせるリゾルバ
+pub struct SystemResolver {
+    inner: trust_dns_resolver::Resolver, // 同期版リゾルバ（内部に実行環境を持つ、呼び出し側でblock_in_placeする）
+}
+
+impl SystemResolver {
+    /// /etc/resolv.conf等のシステム設定からリゾルバを生成
+    pub fn new() -> Result<Self, String> {
+        trust_dns_resolver::Resolver::from_system_conf()
+            .map(|inner| SystemResolver { inner })
+            .map_err(|e| format!("システムのリゾルバ設定: {}", e))
+    }
+}
+
+/// 問い合わせ名を絶対名に（検索ドメインを付けない）
+fn fqdn(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+/// trust-dns-resolverのエラーを問い合わせ失敗の種別に変換
+fn lookup_error(e: trust_dns_resolver::error::ResolveError) -> LookupError {
+    match e.kind() {
+        trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. } => LookupError::NotFound,
+        _ => LookupError::TempFail(e.to_string()),
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn txt(&self, name: &str) -> Result<Vec<String>, LookupError> {
+        let lookup = self.inner.txt_lookup(fqdn(name)).map_err(lookup_error)?;
+        Ok(lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|s| String::from_utf8_lossy(s))
+                    .collect::<String>()
+            })
+            .collect())
+    }
+
+    fn a(&self, name: &str) -> Result<Vec<Ipv4Addr>, LookupError> {
+        let lookup = self.inner.ipv4_lookup(fqdn(name)).map_err(lookup_error)?;
+        Ok(lookup.iter().map(|a| a.0).collect())
+    }
+
+    fn aaaa(&self, name: &str) -> Result<Vec<Ipv6Addr>, LookupError> {
+        let lookup = self.inner.ipv6_lookup(fqdn(name)).map_err(lookup_error)?;
+        Ok(lookup.iter().map(|aaaa| aaaa.0).collect())
+    }
+
+    fn mx(&self, name: &str) -> Result<Vec<(u16, String)>, LookupError> {
+        let lookup = self.inner.mx_lookup(fqdn(name)).map_err(lookup_error)?;
+        Ok(lookup
+            .iter()
+            .map(|mx| {
+                let exchange = mx.exchange().to_string();
+                (mx.preference(), exchange.trim_end_matches('.').to_string())
+            })
+            .collect())
+    }
+
+    fn ptr(&self, ip: IpAddr) -> Result<Vec<String>, LookupError> {
+        let lookup = self.inner.reverse_lookup(ip).map_err(lookup_error)?;
+        Ok(lookup
+            .iter()
+            .map(|ptr| ptr.0.to_string().trim_end_matches('.').to_string())
+            .collect())
+    }
+}
+
+/// メモリ上のレコード表を引くリゾルバ
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    records: HashMap<(String, String), Vec<Vec<String>>>, // (小文字の名前, 大文字の型) → 各レコードの文字列一覧
+    failure: Option<String>, // 設定時は全ての問い合わせを一時的な失敗にする
+}
+
+impl StaticResolver {
+    /// 全ての問い合わせが一時的な失敗になるリゾルバ（ゾーンファイル読み込み失敗時）
+    pub fn unavailable(reason: String) -> Self {
+        StaticResolver {
+            failure: Some(reason),
+            ..Default::default()
+        }
+    }
+
+    /// レコードを1件追加（名前は末尾の「.」を除いて小文字化、TXTの文字列は分割されたまま保持）
+    pub fn insert(&mut self, name: &str, rtype: &str, data: Vec<String>) {
+        let key = (
+            name.trim_end_matches('.').to_lowercase(),
+            rtype.to_uppercase(),
+        );
+        self.records.entry(key).or_default().push(data);
+    }
+
+    /// ゾーンファイル（マスターファイル形式）からレコード表を生成
+    ///
+    /// # 説明
+    /// - 1行1レコード「名前 [TTL] [IN] 型 データ...」（「;」以降はコメント、括弧内は複数行に分けられる）
+    /// - 名前が「@」ならオリジン、末尾が「.」でなければオリジンを補う（$ORIGINで指定、$TTLは無視）
+    /// - 行頭が空白なら直前のレコードと同じ名前
+    /// - データの「"..."」は1つの文字列（\" と \\ と \DDD のエスケープに対応）
+    /// - MX・PTRのホスト名も末尾が「.」でなければオリジンを補う
+    pub fn from_zone(text: &str) -> Result<Self, String> {
+        let mut resolver = StaticResolver::default();
+        let mut origin = String::new(); // $ORIGIN（末尾の「.」なし）
+        let mut last_name: Option<String> = None; // 直前のレコードの名前
+        let mut pending: Vec<String> = Vec::new(); // 括弧が閉じるまでのトークン
+        let mut pending_blank = false; // 括弧を含むレコードの行頭が空白だったか
+        let mut depth = 0i32; // 括弧の深さ
+        for (lineno, line) in text.lines().enumerate() {
+            let blank = depth == 0 && line.starts_with([' ', '\t']); // 名前の省略
+            let tokens = zone_tokens(line, &mut depth)
+                .map_err(|e| format!("ゾーンファイル{}行目: {}", lineno + 1, e))?;
+            if pending.is_empty() {
+                pending_blank = blank;
+            }
+            pending.extend(tokens);
+            if depth > 0 || pending.is_empty() {
+                continue; // 括弧の途中・空行
+            }
+            let tokens = std::mem::take(&mut pending);
+            if tokens[0].eq_ignore_ascii_case("$ORIGIN") {
+                origin = tokens
+                    .get(1)
+                    .map(|o| o.trim_end_matches('.').to_lowercase())
+                    .unwrap_or_default();
+                continue;
+            }
+            if tokens[0].starts_with('$') {
+                continue; // $TTL等は無視
+            }
+            let mut rest = tokens.as_slice();
+            let name = if pending_blank {
+                last_name
+                    .clone()
+                    .ok_or_else(|| format!("ゾーンファイル{}行目: 名前がありません", lineno + 1))?
+            } else {
+                let name = absolute_name(&rest[0], &origin);
+                rest = &rest[1..];
+                name
+            };
+            while let Some(first) = rest.first() {
+                if first.bytes().all(|b| b.is_ascii_digit()) || first.eq_ignore_ascii_case("IN") {
+                    rest = &rest[1..]; // TTL・クラスは読み飛ばす
+                } else {
+                    break;
+                }
+            }
+            let Some((rtype, data)) = rest.split_first() else {
+                return Err(format!("ゾーンファイル{}行目: 型がありません", lineno + 1));
+            };
+            let mut data = data.to_vec();
+            if rtype.eq_ignore_ascii_case("MX") && data.len() == 2 {
+                data[1] = absolute_name(&data[1], &origin); // メールサーバー名
+            } else if rtype.eq_ignore_ascii_case("PTR") && data.len() == 1 {
+                data[0] = absolute_name(&data[0], &origin); // 逆引きのホスト名
+            }
+            resolver.insert(&name, rtype, data);
+            last_name = Some(name);
+        }
+        if depth != 0 {
+            return Err("ゾーンファイル: 括弧が閉じていません".to_string());
+        }
+        Ok(resolver)
+    }
+
+    /// 指定名・型のレコード一覧
+    fn lookup(&self, name: &str, rtype: &str) -> Result<&Vec<Vec<String>>, LookupError> {
+        if let Some(reason) = &self.failure {
+            return Err(LookupError::TempFail(reason.clone()));
+        }
+        let key = (name.trim_end_matches('.').to_lowercase(), rtype.to_string());
+        self.records.get(&key).ok_or(LookupError::NotFound)
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn txt(&self, name: &str) -> Result<Vec<String>, LookupError> {
+        Ok(self
+            .lookup(name, "TXT")?
+            .iter()
+            .map(|strings| strings.concat())
+            .collect())
+    }
+
+    fn a(&self, name: &str) -> Result<Vec<Ipv4Addr>, LookupError> {
+        Ok(self
+            .lookup(name, "A")?
+            .iter()
+            .filter_map(|data| data.first()?.parse().ok())
+            .collect())
+    }
+
+    fn aaaa(&self, name: &str) -> Result<Vec<Ipv6Addr>, LookupError> {
+        Ok(self
+            .lookup(name, "AAAA")?
+            .iter()
+            .filter_map(|data| data.first()?.parse().ok())
+            .collect())
+    }
+
+    fn mx(&self, name: &str) -> Result<Vec<(u16, String)>, LookupError> {
+        Ok(self
+            .lookup(name, "MX")?
+            .iter()
+            .filter_map(|data| Some((data.first()?.parse().ok()?, data.get(1)?.clone())))
+            .collect())
+    }
+
+    fn ptr(&self, ip: IpAddr) -> Result<Vec<String>, LookupError> {
+        Ok(self
+            .lookup(&reverse_name(ip), "PTR")?
+            .iter()
+            .filter_map(|data| data.first().cloned())
+            .collect())
+    }
+}
+
+/// ゾーンファイルの名前を絶対名（末尾の「.」なし・小文字）に変換
+fn absolute_name(name: &str, origin: &str) -> String {
+    if name == "@" {
+        origin.to_string()
+    } else if let Some(name) = name.strip_suffix('.') {
+        name.to_lowercase()
+    } else if origin.is_empty() {
+        name.to_lowercase()
+    } else {
+        format!("{}.{}", name.to_lowercase(), origin)
+    }
+}
+
+/// ゾーンファイル1行をトークンに分割（引用符の文字列はエスケープを解いて1トークン、括弧の深さを更新）
+fn zone_tokens(line: &str, depth: &mut i32) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break, // コメント
+            ' ' | '\t' => {}
+            '(' => *depth += 1,
+            ')' => {
+                *depth -= 1;
+                if *depth < 0 {
+                    return Err("対応しない「)」".to_string());
+                }
+            }
+            '"' => {
+                let mut s = String::new(); // 引用符内の文字列
+                loop {
+                    match chars.next() {
+                        None => return Err("引用符が閉じていません".to_string()),
+                        Some('"') => break,
+                        Some('\\') => {
+                            let digits: String = (0..3)
+                                .map_while(|_| chars.next_if(|c| c.is_ascii_digit()))
+                                .collect();
+                            if digits.is_empty() {
+                                s.extend(chars.next()); // \" や \\ 等は次の1文字
+                            } else {
+                                let code = digits
+                                    .parse::<u8>()
+                                    .map_err(|_| "不正な\\DDD".to_string())?;
+                                s.push(code as char); // \DDD（10進のバイト値）
+                            }
+                        }
+                        Some(c) => s.push(c),
+                    }
+                }
+                tokens.push(s);
+            }
+            c => {
+                let mut s = String::from(c); // 空白・括弧・コメントまでの1語
+                while let Some(&c) = chars.peek() {
+                    if matches!(c, ' ' | '\t' | '(' | ')' | ';' | '"') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(s);
+            }
+        }
+    }
+    Ok(tokens)
+}
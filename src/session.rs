@@ -0,0 +1,181 @@
+// =========================
+// session.rs
+// MilterDecoder セッション状態管理モジュール
+//
+// 【このファイルで使う主なクレート】
+// - crate::milter_command: Milterコマンド種別enum（MilterCommand）
+// - crate::envelope: エンベロープ情報（MAIL FROM/RCPT TO）
+// - crate::header: 受信順ヘッダリスト（HeaderList）
+// - crate::milter_command: メッセージ変更アクション（MilterModification）
+// - crate::negotiate: OPTNEG合意内容（NegotiatedOptions）
+// - crate::macros: マクロフェーズ（MacroStage）・受信マクロ値（MacroStore）
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: 接続元IPアドレス（net::IpAddr）
+//
+// 【役割】
+// - Milterセッションのフェーズ（OPTNEG → CONNECT → HELO → MAIL → RCPT → DATA → HEADER → EOH → BODY → BODYEOB）管理
+// - コマンド受信順序の妥当性判定・次フェーズへの遷移
+// - 接続単位・トランザクション単位のセッション状態保持とリセット（ABORT/QUIT_NC対応）
+// =========================
+
+use crate::envelope::Envelope; // エンベロープ情報
+use crate::header::HeaderList; // 受信順ヘッダリスト
+use crate::milter_command::{MilterCommand, MilterModification}; // Milterコマンド種別・メッセージ変更アクション
+use crate::macros::{MacroStage, MacroStore}; // マクロフェーズ・受信マクロ値
+use crate::negotiate::NegotiatedOptions; // OPTNEG合意内容
+use serde::Serialize; // JSON出力用
+use std::net::IpAddr; // 接続元IPアドレス
+
+/// Milterセッションのフェーズ（最後に受信した段階）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MilterPhase {
+    Start,      // 接続直後（OPTNEG待ち）
+    Negotiated, // OPTNEG完了（CONNECT待ち）
+    Connected,  // CONNECT受信済み
+    Helo,       // HELO/EHLO受信済み
+    Mail,       // MAIL FROM受信済み（トランザクション開始）
+    Rcpt,       // RCPT TO受信済み
+    Data,       // DATA受信済み
+    Header,     // HEADER受信中
+    Eoh,        // EOH受信済み
+    Body,       // BODY受信中
+    Idle,       // トランザクション終了（BODYEOB/ABORT後、次のMAIL待ち）
+}
+
+impl MilterPhase {
+    /// 現フェーズでコマンドを受け付けてよいか判定
+    ///
+    /// # 説明
+    /// - NO_*フラグで省略されるコマンドがあるため、後段フェーズへの飛び越しは許容する
+    /// - MACRO/UNKNOWN/ABORT/QUIT/QUIT_NCはどのフェーズでも受け付ける
+    pub fn can_accept(self, cmd: MilterCommand) -> bool {
+        use MilterPhase::*;
+        match cmd {
+            MilterCommand::OptNeg => self == Start,
+            MilterCommand::Connect => self == Negotiated,
+            MilterCommand::HeLO | MilterCommand::Mail => {
+                matches!(self, Negotiated | Connected | Helo | Idle)
+            }
+            MilterCommand::Rcpt => matches!(self, Mail | Rcpt),
+            MilterCommand::Data => self == Rcpt,
+            MilterCommand::Header | MilterCommand::Eoh => matches!(self, Rcpt | Data | Header),
+            MilterCommand::Body | MilterCommand::BodyEob => {
+                matches!(self, Rcpt | Data | Header | Eoh | Body)
+            }
+            MilterCommand::Macro
+            | MilterCommand::Unknown
+            | MilterCommand::Abort
+            | MilterCommand::Quit
+            | MilterCommand::QuitNc => true,
+        }
+    }
+
+    /// コマンド受信後の次フェーズを返す
+    pub fn next(self, cmd: MilterCommand) -> Self {
+        use MilterPhase::*;
+        match cmd {
+            MilterCommand::OptNeg => Negotiated,
+            MilterCommand::Connect => Connected,
+            MilterCommand::HeLO => Helo,
+            MilterCommand::Mail => Mail,
+            MilterCommand::Rcpt => Rcpt,
+            MilterCommand::Data => Data,
+            MilterCommand::Header => Header,
+            MilterCommand::Eoh => Eoh,
+            MilterCommand::Body => Body,
+            MilterCommand::BodyEob | MilterCommand::Abort => Idle, // トランザクション終了
+            MilterCommand::QuitNc => Negotiated,                   // 接続再利用: 新しいCONNECT待ち
+            MilterCommand::Quit => Start,                          // セッション終了
+            MilterCommand::Macro | MilterCommand::Unknown => self, // フェーズは変化しない
+        }
+    }
+}
+
+/// CONNECTのプロトコルファミリ（SMFIA_*）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectFamily {
+    Unknown, // 'U': 不明
+    Unix,    // 'L': UNIXドメインソケット
+    Inet,    // '4': IPv4
+    Inet6,   // '6': IPv6
+}
+
+/// CONNECTで受信した接続情報
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectInfo {
+    pub hostname: String,        // クライアントホスト名（逆引き結果、不明時は[IP]形式）
+    pub family: ConnectFamily,   // プロトコルファミリ
+    pub port: Option<u16>,       // クライアントのポート番号（IPv4/IPv6のみ）
+    pub address: Option<IpAddr>, // クライアントIPアドレス（UNIXソケット・解析失敗時はNone）
+    pub path: Option<String>,    // UNIXドメインソケットのパス
+    #[serde(skip)]
+    pub raw: String, // ペイロード全体（NULを空白に置換、ログ用）
+}
+
+/// クライアント1接続分のMilterセッション状態
+///
+/// # 説明
+/// - 接続単位の状態（CONNECT/HELO情報）とトランザクション単位の状態（エンベロープ・ヘッダ・ボディ）を保持
+/// - ABORTではトランザクション単位のみ、QUIT_NCでは接続単位も含めてリセットする
+#[derive(Debug)]
+pub struct Session {
+    pub phase: MilterPhase,                // セッションフェーズ
+    pub options: NegotiatedOptions,        // OPTNEG合意内容（接続単位）
+    pub connect_info: Option<ConnectInfo>, // CONNECT情報（接続単位）
+    pub helo: Option<String>,              // HELO/EHLOホスト名（接続単位）
+    pub macros: MacroStore,                // 受信マクロ値（CONNECT/HELO分は接続単位、以降はトランザクション単位）
+    pub envelope: Envelope,                // エンベロープ情報（トランザクション単位）
+    pub header_fields: HeaderList,         // ヘッダ情報（トランザクション単位、受信順）
+    pub body_field: Vec<u8>,               // ボディ情報（トランザクション単位）
+    pub modifications: Vec<MilterModification>, // EOM時に送るメッセージ変更アクション（トランザクション単位）
+}
+
+impl Session {
+    /// 接続直後（OPTNEG待ち）のセッションを生成
+    pub fn new() -> Self {
+        Session {
+            phase: MilterPhase::Start,            // OPTNEG待ち
+            options: NegotiatedOptions::default(), // OPTNEG未受信（何も合意していない）
+            connect_info: None,                   // CONNECT未受信
+            helo: None,                           // HELO未受信
+            macros: MacroStore::default(),        // マクロ空
+            envelope: Envelope::default(),        // エンベロープ空
+            header_fields: HeaderList::default(), // ヘッダ空
+            body_field: Vec::new(),               // ボディ空
+            modifications: Vec::new(),            // 変更アクション無し
+        }
+    }
+
+    /// トランザクション単位の状態を破棄（BODYEOB後・ABORT時）
+    /// - CONNECT/HELO情報は保持する
+    pub fn reset_transaction(&mut self) {
+        self.envelope.clear(); // エンベロープ初期化
+        self.header_fields.clear(); // ヘッダ初期化
+        self.body_field.clear(); // ボディ初期化
+        self.modifications.clear(); // 変更アクション初期化
+        self.macros.clear_message(); // トランザクション単位マクロ初期化
+    }
+
+    /// 接続単位も含めて全状態を破棄（QUIT_NC時）
+    /// - 同一ソケット上で新しいCONNECTから再開できる状態にする
+    pub fn reset_connection(&mut self) {
+        self.reset_transaction(); // トランザクション状態破棄
+        self.connect_info = None; // CONNECT情報破棄
+        self.helo = None; // HELO情報破棄
+        self.macros.clear(); // 接続単位マクロも含めて破棄
+    }
+
+    /// SMFIC_MACROで受信したマクロ値を格納
+    pub fn store_macros(&mut self, stage: MacroStage, pairs: Vec<(String, String)>) {
+        for (name, value) in pairs {
+            self.macros.insert(stage, &name, value);
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,752 @@
+// =========================
+// spf.rs
+// MilterDecoder SPF評価モジュール
+//
+// 【このファイルで使う主なクレート】
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - std: IPアドレス・プレフィックス比較（net::IpAddr/Ipv4Addr/Ipv6Addr）
+// - crate::resolver: SPFレコード（TXT）・A/AAAA/MX/PTRの問い合わせ
+//
+// 【役割】
+// - CONNECTの接続元IP・MAIL FROM（ヌル送信者ならHELO）からのSPF評価（RFC 7208 check_host）
+// - SPFレコードの構文解析（all/include/a/mx/ptr/ip4/ip6/exists、redirect=/exp=、修飾子+-~?）
+// - マクロ展開（%{s} %{l} %{o} %{d} %{i} %{p} %{v} %{h}、exp=用の%{c} %{r} %{t}、桁数・逆順・区切り文字）
+// - DNS問い合わせ回数（10回）・空応答回数（2回）・MX/PTRの名前数（10件）の上限
+// - 評価結果（none/neutral/pass/fail/softfail/temperror/permerror）とAuthentication-Resultsヘッダ用の文字列の生成
+// =========================
+
+use serde::Serialize; // JSON出力用
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr}; // 接続元IP・ip4/ip6機構
+
+use crate::resolver::{LookupError, Resolver}; // DNS問い合わせ
+
+const MAX_DNS_LOOKUPS: usize = 10; // DNS問い合わせを伴う機構・修飾子の上限（include/a/mx/ptr/exists/redirect）
+const MAX_VOID_LOOKUPS: usize = 2; // 空応答（NXDOMAIN・レコードなし）の上限
+const MAX_NAMES: usize = 10; // mx機構のMX・ptr機構のPTRで調べる名前の上限
+const MAX_DOMAIN_LEN: usize = 253; // マクロ展開後のドメイン名の最大長
+
+/// SPF評価結果（RFC 7208 2.6）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpfStatus {
+    None,      // SPFレコードが無い、または評価対象のドメインが無い
+    Neutral,   // ?修飾子に一致、またはどの機構にも一致しない
+    Pass,      // +修飾子に一致（送信を許可）
+    Fail,      // -修飾子に一致（送信を許可しない）
+    Softfail,  // ~修飾子に一致（おそらく許可しない）
+    Temperror, // DNSの一時的な失敗
+    Permerror, // レコードの構文エラー・問い合わせ回数の超過等
+}
+
+impl SpfStatus {
+    /// ログ・Authentication-Results出力用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpfStatus::None => "none",
+            SpfStatus::Neutral => "neutral",
+            SpfStatus::Pass => "pass",
+            SpfStatus::Fail => "fail",
+            SpfStatus::Softfail => "softfail",
+            SpfStatus::Temperror => "temperror",
+            SpfStatus::Permerror => "permerror",
+        }
+    }
+}
+
+/// 1メール分のSPF評価結果
+#[derive(Debug, Clone, Serialize)]
+pub struct SpfResult {
+    pub result: SpfStatus,           // 評価結果
+    pub identity: String,            // 評価した識別子（"mailfrom" または "helo"）
+    pub sender: String,              // 評価した送信者（ヌル送信者は postmaster@HELO名）
+    pub domain: String,              // 評価したドメイン
+    pub client_ip: IpAddr,           // 接続元IPアドレス
+    pub helo: Option<String>,        // HELO/EHLOホスト名
+    pub mechanism: Option<String>,   // 一致した機構（どれにも一致しなければNone）
+    pub explanation: Option<String>, // fail時のexp=による説明文
+    pub reason: Option<String>,      // none/temperror/permerrorの理由
+    pub dns_lookups: usize,          // DNS問い合わせを伴った機構・修飾子の数
+    pub void_lookups: usize,         // 空応答の数
+}
+
+impl SpfResult {
+    /// Authentication-Resultsヘッダの1項目（例: spf=pass smtp.mailfrom=user@example.com）
+    pub fn method_result(&self) -> String {
+        let mut out = format!("spf={}", self.result.name());
+        if let Some(reason) = &self.reason {
+            out.push_str(&format!(" reason=\"{}\"", reason.replace(['"', '\\'], "")));
+        }
+        if self.identity == "helo" {
+            out.push_str(&format!(" smtp.helo={}", self.domain));
+        } else {
+            out.push_str(&format!(" smtp.mailfrom={}", self.sender));
+        }
+        out
+    }
+}
+
+/// 評価の中断（temperror/permerror）
+struct Abort {
+    status: SpfStatus, // Temperror または Permerror
+    reason: String,    // 理由
+}
+
+impl Abort {
+    /// permerrorで中断
+    fn perm(reason: impl Into<String>) -> Self {
+        Abort {
+            status: SpfStatus::Permerror,
+            reason: reason.into(),
+        }
+    }
+
+    /// temperrorで中断
+    fn temp(reason: impl Into<String>) -> Self {
+        Abort {
+            status: SpfStatus::Temperror,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// 機構（ドメイン指定はマクロ展開前のまま保持）
+#[derive(Debug, Clone)]
+enum Mechanism {
+    All,                        // all
+    Include(String),            // include:<domain-spec>
+    A(Option<String>, u8, u8),  // a[:<domain-spec>][/<ip4-cidr>][//<ip6-cidr>]
+    Mx(Option<String>, u8, u8), // mx[:<domain-spec>][/<ip4-cidr>][//<ip6-cidr>]
+    Ptr(Option<String>),        // ptr[:<domain-spec>]
+    Ip4(Ipv4Addr, u8),          // ip4:<アドレス>[/<プレフィックス長>]
+    Ip6(Ipv6Addr, u8),          // ip6:<アドレス>[/<プレフィックス長>]
+    Exists(String),             // exists:<domain-spec>
+}
+
+/// 修飾子付きの機構1件
+#[derive(Debug, Clone)]
+struct Directive {
+    qualifier: SpfStatus, // 一致時の結果（+ → Pass、- → Fail、~ → Softfail、? → Neutral）
+    mechanism: Mechanism, // 機構
+    text: String,         // レコード上の表記（ログ・JSON出力用）
+}
+
+/// 構文解析したSPFレコード
+#[derive(Debug, Clone, Default)]
+struct Record {
+    directives: Vec<Directive>, // 記述順の機構
+    redirect: Option<String>,   // redirect=
+    exp: Option<String>,        // exp=
+}
+
+/// 評価中の状態（問い合わせ回数はinclude・redirect先も含めて通算）
+struct Context<'a> {
+    resolver: &'a dyn Resolver, // DNS問い合わせ先
+    ip: IpAddr,                 // 接続元IP（IPv4射影アドレスはIPv4に変換済み）
+    sender: String,             // %{s}
+    helo: String,               // %{h}
+    now: i64,                   // %{t}
+    lookups: usize,             // DNS問い合わせを伴った機構・修飾子の数
+    voids: usize,               // 空応答の数
+}
+
+/// 接続元IP・MAIL FROM・HELOからSPFを評価
+///
+/// # 引数
+/// - `ip`: CONNECTで受信した接続元IPアドレス
+/// - `mail_from`: MAIL FROMのアドレス（ヌル送信者は空文字）
+/// - `helo`: HELO/EHLOホスト名
+/// - `resolver`: DNS問い合わせ先
+/// - `now`: 現在時刻（UNIX秒、%{t}用）
+///
+/// # 説明
+/// - MAIL FROMのドメインでcheck_hostを評価（ヌル送信者はHELO名をドメインとし、送信者を postmaster@HELO名 とする）
+/// - ローカル部の無いMAIL FROMは postmaster@ドメイン として扱う
+/// - 評価対象のドメインが無い・1ラベルのみの場合はnone
+pub fn check_spf(
+    ip: IpAddr,
+    mail_from: &str,
+    helo: Option<&str>,
+    resolver: &dyn Resolver,
+    now: i64,
+) -> SpfResult {
+    let helo_name = helo.unwrap_or("").trim_end_matches('.').to_string();
+    let (identity, sender) = if mail_from.is_empty() {
+        ("helo", format!("postmaster@{}", helo_name))
+    } else if let Some((local, domain)) = mail_from.rsplit_once('@') {
+        let local = if local.is_empty() {
+            "postmaster"
+        } else {
+            local
+        };
+        ("mailfrom", format!("{}@{}", local, domain))
+    } else {
+        ("mailfrom", format!("postmaster@{}", mail_from))
+    };
+    let domain = sender
+        .rsplit_once('@')
+        .map(|(_, d)| d.trim_end_matches('.').to_lowercase())
+        .unwrap_or_default();
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip), // IPv4射影アドレス
+        v4 => v4,
+    };
+    let mut result = SpfResult {
+        result: SpfStatus::None,
+        identity: identity.to_string(),
+        sender: sender.clone(),
+        domain: domain.clone(),
+        client_ip: ip,
+        helo: helo.map(|h| h.to_string()),
+        mechanism: None,
+        explanation: None,
+        reason: None,
+        dns_lookups: 0,
+        void_lookups: 0,
+    };
+    if !valid_domain(&domain) {
+        result.reason = Some("no valid domain to check".to_string());
+        return result;
+    }
+    let mut ctx = Context {
+        resolver,
+        ip,
+        sender,
+        helo: helo_name,
+        now,
+        lookups: 0,
+        voids: 0,
+    };
+    match check_host(&mut ctx, &domain) {
+        Ok((status, mechanism, explanation)) => {
+            result.result = status;
+            result.mechanism = mechanism;
+            result.explanation = explanation;
+            if status == SpfStatus::None {
+                result.reason = Some("no SPF record".to_string());
+            }
+        }
+        Err(abort) => {
+            result.result = abort.status;
+            result.reason = Some(abort.reason);
+        }
+    }
+    result.dns_lookups = ctx.lookups;
+    result.void_lookups = ctx.voids;
+    result
+}
+
+/// check_host（RFC 7208 4）: 指定ドメインのSPFレコードで接続元IPを評価
+///
+/// # 説明
+/// - 戻り値は（結果, 一致した機構, fail時の説明文）
+fn check_host(
+    ctx: &mut Context,
+    domain: &str,
+) -> Result<(SpfStatus, Option<String>, Option<String>), Abort> {
+    if !valid_domain(domain) {
+        return Ok((SpfStatus::None, None, None));
+    }
+    let Some(record) = fetch_record(ctx, domain)? else {
+        return Ok((SpfStatus::None, None, None));
+    };
+    for directive in &record.directives {
+        if matches_mechanism(ctx, domain, &directive.mechanism)? {
+            let explanation = match (directive.qualifier, &record.exp) {
+                (SpfStatus::Fail, Some(exp)) => explanation(ctx, domain, exp),
+                _ => None,
+            };
+            return Ok((
+                directive.qualifier,
+                Some(directive.text.clone()),
+                explanation,
+            ));
+        }
+    }
+    if let Some(redirect) = &record.redirect {
+        ctx.count_lookup()?;
+        let target = expand(ctx, redirect, domain, false)?;
+        let (status, mechanism, explanation) = check_host(ctx, &target)?;
+        if status == SpfStatus::None {
+            return Err(Abort::perm(format!(
+                "redirect target {} has no SPF record",
+                target
+            )));
+        }
+        return Ok((status, mechanism, explanation));
+    }
+    Ok((SpfStatus::Neutral, None, None))
+}
+
+/// SPFレコード（"v=spf1"で始まるTXT）を取得して構文解析（無ければNone、複数ならpermerror）
+fn fetch_record(ctx: &mut Context, domain: &str) -> Result<Option<Record>, Abort> {
+    let records = match ctx.resolver.txt(domain) {
+        Ok(records) => records,
+        Err(LookupError::NotFound) => return Ok(None),
+        Err(LookupError::TempFail(e)) => {
+            return Err(Abort::temp(format!(
+                "SPF record lookup for {} failed: {}",
+                domain, e
+            )))
+        }
+    };
+    let spf: Vec<&String> = records
+        .iter()
+        .filter(|r| {
+            let lower = r.to_ascii_lowercase();
+            lower == "v=spf1" || lower.starts_with("v=spf1 ")
+        })
+        .collect();
+    match spf.as_slice() {
+        [] => Ok(None),
+        [record] => parse_record(record).map(Some),
+        _ => Err(Abort::perm(format!("multiple SPF records for {}", domain))),
+    }
+}
+
+/// SPFレコードの構文解析（未知の機構・不正な値・redirect=/exp=の重複はpermerror、未知の修飾子は無視）
+fn parse_record(text: &str) -> Result<Record, Abort> {
+    let mut record = Record::default();
+    for term in text.split_ascii_whitespace().skip(1) {
+        // 修飾子（名前=値、名前は英字で始まる）
+        if let Some((name, value)) = term.split_once('=') {
+            let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+            if is_name {
+                let slot = match name.to_ascii_lowercase().as_str() {
+                    "redirect" => &mut record.redirect,
+                    "exp" => &mut record.exp,
+                    _ => continue, // 未知の修飾子は無視
+                };
+                if slot.replace(value.to_string()).is_some() {
+                    return Err(Abort::perm(format!("duplicate {} modifier", name)));
+                }
+                continue;
+            }
+        }
+        // 修飾子 + 機構
+        let (qualifier, rest) = match term.chars().next() {
+            Some('+') => (SpfStatus::Pass, &term[1..]),
+            Some('-') => (SpfStatus::Fail, &term[1..]),
+            Some('~') => (SpfStatus::Softfail, &term[1..]),
+            Some('?') => (SpfStatus::Neutral, &term[1..]),
+            _ => (SpfStatus::Pass, term),
+        };
+        let name_end = rest.find([':', '/']).unwrap_or(rest.len());
+        let (name, arg) = rest.split_at(name_end);
+        let mechanism = parse_mechanism(&name.to_ascii_lowercase(), arg)
+            .ok_or_else(|| Abort::perm(format!("invalid term: {}", term)))?;
+        record.directives.push(Directive {
+            qualifier,
+            mechanism,
+            text: term.to_string(),
+        });
+    }
+    Ok(record)
+}
+
+/// 機構名と引数（「:」または「/」以降）から機構を生成（不正ならNone）
+fn parse_mechanism(name: &str, arg: &str) -> Option<Mechanism> {
+    let domain_spec = |arg: &str| -> Option<Option<String>> {
+        match arg.strip_prefix(':') {
+            Some(spec) if !spec.is_empty() => Some(Some(spec.to_string())),
+            Some(_) => None,
+            None if arg.is_empty() => Some(None),
+            None => None,
+        }
+    };
+    match name {
+        "all" if arg.is_empty() => Some(Mechanism::All),
+        "include" => Some(Mechanism::Include(domain_spec(arg)??)),
+        "exists" => Some(Mechanism::Exists(domain_spec(arg)??)),
+        "ptr" => Some(Mechanism::Ptr(domain_spec(arg)?)),
+        "a" | "mx" => {
+            let (spec, v4, v6) = split_dual_cidr(arg)?;
+            let spec = domain_spec(spec)?;
+            Some(if name == "a" {
+                Mechanism::A(spec, v4, v6)
+            } else {
+                Mechanism::Mx(spec, v4, v6)
+            })
+        }
+        "ip4" => {
+            let value = arg.strip_prefix(':')?;
+            let (addr, len) = split_prefix(value, 32)?;
+            Some(Mechanism::Ip4(addr.parse().ok()?, len))
+        }
+        "ip6" => {
+            let value = arg.strip_prefix(':')?;
+            let (addr, len) = split_prefix(value, 128)?;
+            Some(Mechanism::Ip6(addr.parse().ok()?, len))
+        }
+        _ => None,
+    }
+}
+
+/// 「アドレス/プレフィックス長」を分割（省略時は最大長、最大長を超えればNone）
+fn split_prefix(value: &str, max: u8) -> Option<(&str, u8)> {
+    match value.rsplit_once('/') {
+        Some((addr, len)) if !len.is_empty() && len.bytes().all(|b| b.is_ascii_digit()) => {
+            let len: u8 = len.parse().ok()?;
+            (len <= max).then_some((addr, len))
+        }
+        Some(_) => None,
+        None => Some((value, max)),
+    }
+}
+
+/// a/mx機構の引数を「ドメイン指定」「/ip4-cidr」「//ip6-cidr」に分割
+fn split_dual_cidr(arg: &str) -> Option<(&str, u8, u8)> {
+    let mut rest = arg;
+    let mut v6 = 128u8;
+    if let Some((head, len)) = rest.rsplit_once("//") {
+        if !len.is_empty() && len.bytes().all(|b| b.is_ascii_digit()) {
+            v6 = len.parse().ok().filter(|&n| n <= 128)?;
+            rest = head;
+        }
+    }
+    let mut v4 = 32u8;
+    if let Some((head, len)) = rest.rsplit_once('/') {
+        if !len.is_empty() && len.bytes().all(|b| b.is_ascii_digit()) {
+            v4 = len.parse().ok().filter(|&n| n <= 32)?;
+            rest = head;
+        }
+    }
+    Some((rest, v4, v6))
+}
+
+/// 機構が接続元IPに一致するか
+fn matches_mechanism(
+    ctx: &mut Context,
+    domain: &str,
+    mechanism: &Mechanism,
+) -> Result<bool, Abort> {
+    match mechanism {
+        Mechanism::All => Ok(true),
+        Mechanism::Include(spec) => {
+            ctx.count_lookup()?;
+            let target = expand(ctx, spec, domain, false)?;
+            match check_host(ctx, &target)?.0 {
+                SpfStatus::Pass => Ok(true),
+                SpfStatus::Fail | SpfStatus::Softfail | SpfStatus::Neutral => Ok(false),
+                SpfStatus::None => Err(Abort::perm(format!(
+                    "include target {} has no SPF record",
+                    target
+                ))),
+                SpfStatus::Temperror | SpfStatus::Permerror => {
+                    unreachable!("check_hostはエラーをErrで返す")
+                }
+            }
+        }
+        Mechanism::A(spec, v4, v6) => {
+            ctx.count_lookup()?;
+            let target = target_domain(ctx, spec, domain)?;
+            let addresses = ctx.addresses(&target, true)?;
+            Ok(addresses.iter().any(|a| in_network(ctx.ip, *a, *v4, *v6)))
+        }
+        Mechanism::Mx(spec, v4, v6) => {
+            ctx.count_lookup()?;
+            let target = target_domain(ctx, spec, domain)?;
+            let mut exchanges = match ctx.resolver.mx(&target) {
+                Ok(mx) => mx,
+                Err(LookupError::NotFound) => Vec::new(),
+                Err(LookupError::TempFail(e)) => {
+                    return Err(Abort::temp(format!(
+                        "MX lookup for {} failed: {}",
+                        target, e
+                    )))
+                }
+            };
+            if exchanges.is_empty() {
+                ctx.count_void()?;
+            }
+            if exchanges.len() > MAX_NAMES {
+                return Err(Abort::perm(format!("too many MX records for {}", target)));
+            }
+            exchanges.sort();
+            for (_, exchange) in exchanges {
+                let addresses = ctx.addresses(&exchange, false)?;
+                if addresses.iter().any(|a| in_network(ctx.ip, *a, *v4, *v6)) {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        Mechanism::Ptr(spec) => {
+            ctx.count_lookup()?;
+            let target = target_domain(ctx, spec, domain)?;
+            Ok(ctx
+                .validated_names()?
+                .iter()
+                .any(|name| name == &target || name.ends_with(&format!(".{}", target))))
+        }
+        Mechanism::Ip4(network, len) => Ok(in_network(ctx.ip, IpAddr::V4(*network), *len, 0)),
+        Mechanism::Ip6(network, len) => Ok(in_network(ctx.ip, IpAddr::V6(*network), 0, *len)),
+        Mechanism::Exists(spec) => {
+            ctx.count_lookup()?;
+            let target = expand(ctx, spec, domain, false)?;
+            match ctx.resolver.a(&target) {
+                Ok(addresses) if !addresses.is_empty() => Ok(true),
+                Ok(_) | Err(LookupError::NotFound) => {
+                    ctx.count_void()?;
+                    Ok(false)
+                }
+                Err(LookupError::TempFail(e)) => Err(Abort::temp(format!(
+                    "A lookup for {} failed: {}",
+                    target, e
+                ))),
+            }
+        }
+    }
+}
+
+/// ドメイン指定を展開（省略時は評価中のドメイン）
+fn target_domain(ctx: &mut Context, spec: &Option<String>, domain: &str) -> Result<String, Abort> {
+    match spec {
+        Some(spec) => expand(ctx, spec, domain, false),
+        None => Ok(domain.to_string()),
+    }
+}
+
+/// 接続元IPがネットワーク（IPv4はv4プレフィックス長、IPv6はv6プレフィックス長）に含まれるか
+fn in_network(ip: IpAddr, network: IpAddr, v4: u8, v6: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - v4 as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - v6 as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+/// exp=の説明文を取得（失敗時はNone、問い合わせ回数には数えない）
+fn explanation(ctx: &mut Context, domain: &str, exp: &str) -> Option<String> {
+    let target = expand(ctx, exp, domain, false).ok()?;
+    let records = ctx.resolver.txt(&target).ok()?;
+    let [text] = records.as_slice() else {
+        return None; // 説明文のTXTはちょうど1件のみ有効
+    };
+    expand(ctx, text, domain, true).ok()
+}
+
+impl Context<'_> {
+    /// DNS問い合わせを伴う機構・修飾子を数える（上限超過はpermerror）
+    fn count_lookup(&mut self) -> Result<(), Abort> {
+        self.lookups += 1;
+        if self.lookups > MAX_DNS_LOOKUPS {
+            return Err(Abort::perm("too many DNS lookups"));
+        }
+        Ok(())
+    }
+
+    /// 空応答を数える（上限超過はpermerror）
+    fn count_void(&mut self) -> Result<(), Abort> {
+        self.voids += 1;
+        if self.voids > MAX_VOID_LOOKUPS {
+            return Err(Abort::perm("too many void DNS lookups"));
+        }
+        Ok(())
+    }
+
+    /// 接続元IPと同じファミリのアドレス（A または AAAA）を取得（`void`がtrueなら空応答を数える）
+    fn addresses(&mut self, name: &str, void: bool) -> Result<Vec<IpAddr>, Abort> {
+        let result = match self.ip {
+            IpAddr::V4(_) => self
+                .resolver
+                .a(name)
+                .map(|v| v.into_iter().map(IpAddr::V4).collect::<Vec<_>>()),
+            IpAddr::V6(_) => self
+                .resolver
+                .aaaa(name)
+                .map(|v| v.into_iter().map(IpAddr::V6).collect::<Vec<_>>()),
+        };
+        match result {
+            Ok(addresses) if !addresses.is_empty() => Ok(addresses),
+            Ok(_) | Err(LookupError::NotFound) => {
+                if void {
+                    self.count_void()?;
+                }
+                Ok(Vec::new())
+            }
+            Err(LookupError::TempFail(e)) => Err(Abort::temp(format!(
+                "address lookup for {} failed: {}",
+                name, e
+            ))),
+        }
+    }
+
+    /// 接続元IPの逆引き名のうち、正引きで接続元IPに戻るもの（PTRは先頭MAX_NAMES件まで）
+    fn validated_names(&mut self) -> Result<Vec<String>, Abort> {
+        let names = match self.resolver.ptr(self.ip) {
+            Ok(names) => names,
+            Err(LookupError::NotFound) => return Ok(Vec::new()),
+            Err(LookupError::TempFail(_)) => return Ok(Vec::new()), // ptr機構はDNS失敗時に一致しない扱い
+        };
+        let mut validated = Vec::new();
+        for name in names.into_iter().take(MAX_NAMES) {
+            let name = name.trim_end_matches('.').to_lowercase();
+            if self
+                .addresses(&name, false)
+                .unwrap_or_default()
+                .contains(&self.ip)
+            {
+                validated.push(name);
+            }
+        }
+        Ok(validated)
+    }
+}
+
+/// マクロ展開（RFC 7208 7）
+///
+/// # 説明
+/// - %{文字[桁数][r][区切り文字]}、%%（%）、%_（空白）、%-（%20）
+/// - 文字が大文字ならURLエンコードする
+/// - `exp`がtrueの場合のみ%{c} %{r} %{t}を使える（exp=の説明文用）
+/// - ドメイン名として使う場合は253文字を超えないよう左側のラベルを除く
+fn expand(ctx: &mut Context, spec: &str, domain: &str, exp: bool) -> Result<String, Abort> {
+    let mut out = String::new();
+    let mut chars = spec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some('_') => out.push(' '),
+            Some('-') => out.push_str("%20"),
+            Some('{') => {
+                let body: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                out.push_str(&expand_macro(ctx, &body, domain, exp)?);
+            }
+            _ => return Err(Abort::perm(format!("invalid macro in {}", spec))),
+        }
+    }
+    if exp {
+        return Ok(out);
+    }
+    let mut name = out.trim_end_matches('.').to_string();
+    while name.len() > MAX_DOMAIN_LEN {
+        match name.split_once('.') {
+            Some((_, rest)) => name = rest.to_string(), // 左側のラベルを除く
+            None => break,
+        }
+    }
+    Ok(name)
+}
+
+/// %{...}の中身を展開
+fn expand_macro(ctx: &mut Context, body: &str, domain: &str, exp: bool) -> Result<String, Abort> {
+    let invalid = || Abort::perm(format!("invalid macro %{{{}}}", body));
+    let mut chars = body.chars();
+    let letter = chars.next().ok_or_else(invalid)?;
+    let rest = chars.as_str();
+    let digits_end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let (digits, rest) = rest.split_at(digits_end);
+    let (reverse, delimiters) = match rest.strip_prefix(['r', 'R']) {
+        Some(delimiters) => (true, delimiters),
+        None => (false, rest),
+    };
+    if !delimiters.chars().all(|c| ".-+,/_=".contains(c)) {
+        return Err(invalid());
+    }
+    let value = match letter.to_ascii_lowercase() {
+        's' => ctx.sender.clone(),
+        'l' => ctx
+            .sender
+            .rsplit_once('@')
+            .map(|(l, _)| l.to_string())
+            .unwrap_or_default(),
+        'o' => ctx
+            .sender
+            .rsplit_once('@')
+            .map(|(_, d)| d.to_string())
+            .unwrap_or_default(),
+        'd' => domain.to_string(),
+        'i' => dotted_ip(ctx.ip),
+        'p' => {
+            let names = ctx.validated_names()?;
+            names
+                .iter()
+                .find(|n| *n == domain || n.ends_with(&format!(".{}", domain)))
+                .or(names.first())
+                .cloned()
+                .unwrap_or_else(|| "unknown".to_string())
+        }
+        'v' => match ctx.ip {
+            IpAddr::V4(_) => "in-addr".to_string(),
+            IpAddr::V6(_) => "ip6".to_string(),
+        },
+        'h' => ctx.helo.clone(),
+        'c' if exp => ctx.ip.to_string(),
+        'r' if exp => "unknown".to_string(),
+        't' if exp => ctx.now.to_string(),
+        _ => return Err(invalid()),
+    };
+    // 区切り文字で分割 → 逆順 → 右からN個 → 「.」で連結
+    let delimiters = if delimiters.is_empty() {
+        "."
+    } else {
+        delimiters
+    };
+    let mut parts: Vec<&str> = value.split(|c| delimiters.contains(c)).collect();
+    if reverse {
+        parts.reverse();
+    }
+    if !digits.is_empty() {
+        let n: usize = digits.parse().map_err(|_| invalid())?;
+        if n == 0 {
+            return Err(invalid());
+        }
+        let skip = parts.len().saturating_sub(n);
+        parts.drain(..skip);
+    }
+    let expanded = parts.join(".");
+    if letter.is_ascii_uppercase() {
+        return Ok(url_encode(&expanded));
+    }
+    Ok(expanded)
+}
+
+/// %{i}用のIPアドレス表記（IPv4はドット区切り、IPv6はニブルをドット区切り）
+fn dotted_ip(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => v6
+            .octets()
+            .iter()
+            .flat_map(|b| [b >> 4, b & 0x0f])
+            .map(|n| format!("{:x}", n))
+            .collect::<Vec<_>>()
+            .join("."),
+    }
+}
+
+/// 非予約文字以外をパーセントエンコード（大文字マクロ用）
+fn url_encode(value: &str) -> String {
+    let mut out = String::new();
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// 評価対象として有効なドメイン名か（2ラベル以上、各ラベル1〜63文字、全体253文字以内）
+fn valid_domain(domain: &str) -> bool {
+    let labels: Vec<&str> = domain.split('.').collect();
+    domain.len() <= MAX_DOMAIN_LEN
+        && labels.len() >= 2
+        && labels.iter().all(|l| !l.is_empty() && l.len() <= 63)
+}
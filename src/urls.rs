@@ -0,0 +1,602 @@
+// =========================
+// urls.rs
+// MilterDecoder URL抽出・正規化モジュール
+//
+// 【このファイルで使う主なクレート】
+// - idna: ホスト名のpunycode（xn--）デコード
+// - percent-encoding: パス・クエリ・リダイレクタ引数のパーセントデコード
+// - serde: 構造化（JSON）出力用のシリアライズ定義
+// - crate::parse: 解析結果（テキスト/HTMLパート、PDF・Office文書の指標）
+//
+// 【役割】
+// - text/plain本文中のURL、text/htmlのhref/src属性、PDFのURIアクション、Office文書の外部参照からURLを抽出
+// - URLの正規化（スキーム・ホストの小文字化、punycodeのデコード、既定ポートの除去、パーセントデコード）
+// - 既知のリダイレクタ（Google・Outlook Safe Links・Proofpoint・Facebook・YouTube）の転送先の取り出し（外側のURLが主）
+// - 抽出元（種別・MIMEパート番号）付きでメッセージ単位に一覧化
+// =========================
+
+use percent_encoding::percent_decode_str; // パーセントデコード
+use serde::Serialize; // JSON出力用
+
+use crate::office::IndicatorKind; // Office文書の指標種別
+use crate::parse::ParsedMail; // 解析結果
+
+const MAX_URLS: usize = 1000; // 1メッセージあたりの抽出上限
+const MAX_UNWRAP: usize = 5; // リダイレクタを展開する最大回数（入れ子のリダイレクタ対策）
+
+/// URLの抽出元
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlSource {
+    Text,   // text/plain本文
+    Html,   // text/htmlのhref/src属性
+    Pdf,    // PDFのURIアクション
+    Office, // Office文書の外部参照（リモートテンプレート・外部リンクOLE）
+}
+
+impl UrlSource {
+    /// ログ・JSON出力用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            UrlSource::Text => "text",
+            UrlSource::Html => "html",
+            UrlSource::Pdf => "pdf",
+            UrlSource::Office => "office",
+        }
+    }
+}
+
+/// 抽出したURL1件分
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExtractedUrl {
+    pub part: usize,                  // 抽出元のMIMEパート番号
+    pub source: UrlSource,            // 抽出元の種別
+    pub url: String,                  // 抽出したままのURL（HTMLは実体参照のみ展開）
+    pub normalized: String,           // 正規化したURL（リダイレクタは展開しない）
+    pub host: Option<String>,         // 正規化後のホスト（Unicode表記）
+    pub unwrapped: Vec<UnwrappedUrl>, // 既知のリダイレクタから取り出した転送先（外側から順、最後が最終的な転送先）
+}
+
+/// リダイレクタから取り出した転送先1件分
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnwrappedUrl {
+    pub url: String,          // 転送先のURL（正規化済み）
+    pub host: Option<String>, // 転送先のホスト（Unicode表記）
+}
+
+impl ExtractedUrl {
+    /// 外側のURLと各転送先のホスト（外側から順、ホストの無いものは除く）
+    pub fn hosts(&self) -> impl Iterator<Item = &str> {
+        self.host
+            .iter()
+            .chain(self.unwrapped.iter().flat_map(|u| &u.host))
+            .map(String::as_str)
+    }
+}
+
+/// 解析結果から全URLを抽出（抽出元の順: TEXT本文、HTML本文、PDF、Office文書）
+///
+/// # 引数
+/// - `mail`: parse_mailの解析結果（添付メールは各ParsedMailで個別に抽出する）
+///
+/// # 説明
+/// - 同じパート・抽出元で同じURLは1件にまとめる
+/// - http/https/ftpと「www.」で始まるもののみ対象（mailto:・cid:・相対パス等は除く）
+pub fn extract(mail: &ParsedMail) -> Vec<ExtractedUrl> {
+    let mut found: Vec<ExtractedUrl> = Vec::new();
+    let mut add = |part: usize, source: UrlSource, raw: &str| {
+        if found.len() >= MAX_URLS
+            || found
+                .iter()
+                .any(|u| u.part == part && u.source == source && u.url == raw)
+        {
+            return;
+        }
+        if let Some(url) = normalize(part, source, raw) {
+            found.push(url);
+        }
+    };
+    for text in &mail.text_parts {
+        for raw in text_urls(&text.content) {
+            add(text.part, UrlSource::Text, raw);
+        }
+    }
+    for html in &mail.html_parts {
+        for raw in html_urls(&html.content) {
+            add(html.part, UrlSource::Html, &raw);
+        }
+    }
+    for part in &mail.non_text_parts {
+        for uri in part.pdf.iter().flat_map(|p| &p.uris) {
+            add(part.part, UrlSource::Pdf, uri);
+        }
+        for ind in part.office.iter().flat_map(|o| &o.indicators) {
+            let external = matches!(
+                ind.kind,
+                IndicatorKind::RemoteTemplate | IndicatorKind::OleObject
+            );
+            if let Some(target) = ind.detail.as_deref().filter(|_| external) {
+                add(part.part, UrlSource::Office, target);
+            }
+        }
+    }
+    found
+}
+
+/// プレーンテキストからURLらしい文字列を抽出
+///
+/// # 説明
+/// - 「http://」「https://」「ftp://」「www.」で始まる箇所から、空白・引用符・山括弧・非ASCII文字の直前までを取る
+/// - 日本語本文では直後に全角文字が続くことが多いため、非ASCII文字でURLを終える
+/// - 末尾の句読点と、対応の取れない閉じ括弧は除く
+/// - 先頭文字（h/f/w）の位置でのみ接頭辞を確認して1回の走査で進め、MAX_URLS件見つけた時点で打ち切る
+pub fn text_urls(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut urls = Vec::new();
+    let mut pos = 0;
+    while pos < text.len() && urls.len() < MAX_URLS {
+        let Some(at) = bytes[pos..]
+            .iter()
+            .position(|b| matches!(b.to_ascii_lowercase(), b'h' | b'f' | b'w'))
+        else {
+            break;
+        };
+        let start = pos + at;
+        let rest = &bytes[start..];
+        let prefixed = ["http://", "https://", "ftp://", "www."]
+            .iter()
+            .any(|p| rest.len() >= p.len() && rest[..p.len()].eq_ignore_ascii_case(p.as_bytes()));
+        if !prefixed {
+            pos = start + 1; // ASCII文字の次なので文字境界
+            continue;
+        }
+        let before = text[..start].chars().next_back(); // 直前の文字（単語やメールアドレスの途中なら対象外）
+        let len = text[start..]
+            .find(|c: char| {
+                !c.is_ascii()
+                    || c.is_ascii_whitespace()
+                    || c.is_ascii_control()
+                    || "<>\"'`{}|\\^".contains(c)
+            })
+            .unwrap_or(text.len() - start);
+        pos = start + len.max(1);
+        if before.is_some_and(|c| c.is_ascii_alphanumeric() || ".@/".contains(c)) {
+            continue;
+        }
+        let url = trim_trailing(&text[start..start + len]);
+        if url.len() > 7 && !url.ends_with("://") {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+/// URL末尾の句読点・対応の無い閉じ括弧を除く
+fn trim_trailing(url: &str) -> &str {
+    let count = |c: u8| url.bytes().filter(|&b| b == c).count() as isize;
+    let mut paren = count(b')') - count(b'('); // 閉じ括弧の過剰数（末尾を除くたびに更新し、再計数しない）
+    let mut bracket = count(b']') - count(b'[');
+    let mut url = url;
+    loop {
+        let Some(last) = url.chars().next_back() else {
+            return url;
+        };
+        let unbalanced = match last {
+            ')' => paren > 0,
+            ']' => bracket > 0,
+            '.' | ',' | ';' | ':' | '!' | '?' => true,
+            _ => false,
+        };
+        if !unbalanced {
+            return url;
+        }
+        match last {
+            ')' => paren -= 1,
+            ']' => bracket -= 1,
+            _ => {}
+        }
+        url = &url[..url.len() - 1];
+    }
+}
+
+/// HTMLのhref/src属性値を抽出（実体参照は展開済み、コメント内は除く）
+pub fn html_urls(html: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    for token in html_tokens(html) {
+        if let HtmlToken::Start(_, attrs) = token {
+            for (name, value) in attrs {
+                if name == "href" || name == "src" {
+                    urls.push(value);
+                }
+            }
+        }
+    }
+    urls
+}
+
+/// HTMLを字句単位に分けた1件分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HtmlToken<'a> {
+    Start(String, Vec<(String, String)>), // 開始タグ（小文字のタグ名, 属性一覧）
+    End(String),                          // 終了タグ（小文字のタグ名）
+    Text(&'a str),                        // タグ間のテキスト（実体参照は未展開）
+}
+
+/// HTMLを開始タグ・終了タグ・テキストに分ける（属性名は小文字化、値は実体参照を展開、コメント・<!DOCTYPE>は除く）
+pub fn html_tokens(html: &str) -> Vec<HtmlToken<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while let Some(at) = rest.find('<') {
+        if at > 0 {
+            tokens.push(HtmlToken::Text(&rest[..at]));
+        }
+        rest = &rest[at + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |e| &comment[e + 3..]); // コメントを飛ばす
+            continue;
+        }
+        let end = tag_end(rest);
+        let tag = &rest[..end];
+        rest = &rest[(end + 1).min(rest.len())..];
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(t) => (true, t),
+            None => (false, tag),
+        };
+        let name_len = tag
+            .find(|c: char| c.is_ascii_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_len].to_ascii_lowercase();
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue; // <!DOCTYPE>・<?xml?>等
+        }
+        if closing {
+            tokens.push(HtmlToken::End(name));
+        } else {
+            tokens.push(HtmlToken::Start(name, tag_attributes(&tag[name_len..])));
+        }
+    }
+    if !rest.is_empty() {
+        tokens.push(HtmlToken::Text(rest));
+    }
+    tokens
+}
+
+/// タグの終わり（引用符内の「>」は無視）
+fn tag_end(tag: &str) -> usize {
+    let mut quote: Option<char> = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '>') => return i,
+            _ => {}
+        }
+    }
+    tag.len()
+}
+
+/// タグ内の属性を（小文字の名前, 値）の一覧にする（値無しの属性は空文字列）
+fn tag_attributes(mut rest: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        let name_len = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        if name_len == 0 {
+            return attrs;
+        }
+        let name = rest[..name_len].to_ascii_lowercase();
+        rest = rest[name_len..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, next) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let end = after[1..].find(q).map_or(after.len(), |e| e + 1);
+                    (&after[1..end], &after[(end + 1).min(after.len())..])
+                }
+                _ => {
+                    let end = after
+                        .find(|c: char| c.is_ascii_whitespace())
+                        .unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(raw.trim());
+            rest = next;
+        }
+        attrs.push((name, value));
+    }
+}
+
+/// HTMLの実体参照（&amp;・&nbsp;等の主なもの、数値文字参照）を展開
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let end = rest[1..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '#')
+            .map_or(rest.len(), |e| e + 1);
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|h| u32::from_str_radix(h, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                let semicolon = rest[end..].starts_with(';') as usize; // 「;」は省略されることがある
+                rest = &rest[end + semicolon..];
+            }
+            None => {
+                out.push('&'); // 不明な参照はそのまま
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 分解したURL（パーセントデコード前）
+#[derive(Clone)]
+struct RawUrl {
+    scheme: String,           // スキーム（小文字）
+    userinfo: Option<String>, // 「ユーザー@」部分（フィッシングで正規ドメインを装うのに使われる）
+    host: String,             // ホスト（小文字、末尾の「.」除去）
+    port: Option<String>,     // ポート（既定ポートは除去済み）
+    path: String,             // パス（先頭の「/」を含む）
+    query: Option<String>,    // クエリ（「?」の後）
+    fragment: Option<String>, // フラグメント（「#」の後）
+}
+
+/// URLを抽出結果1件に変換（正規化と既知のリダイレクタの転送先の取り出し、対象外のスキームはNone）
+pub fn normalize(part: usize, source: UrlSource, raw: &str) -> Option<ExtractedUrl> {
+    let outer = split_url(&with_scheme(raw.trim())?)?;
+    let mut unwrapped = Vec::new();
+    let mut current = outer.clone();
+    for _ in 0..MAX_UNWRAP {
+        let Some(inner) = unwrap_redirector(&current).and_then(|u| split_url(&u)) else {
+            break; // 既知のリダイレクタでない、または転送先がURLとして不正ならそこで止める
+        };
+        unwrapped.push(UnwrappedUrl {
+            url: assemble(&inner),
+            host: host_of(&inner),
+        });
+        current = inner;
+    }
+    Some(ExtractedUrl {
+        part,
+        source,
+        url: raw.trim().to_string(),
+        normalized: assemble(&outer),
+        host: host_of(&outer),
+        unwrapped,
+    })
+}
+
+/// 分解済みURLのホスト（Unicode表記、空ならNone）
+fn host_of(url: &RawUrl) -> Option<String> {
+    (!url.host.is_empty()).then(|| display_host(&url.host))
+}
+
+/// スキームを補ってhttp/https/ftpのURLにする（「www.」はhttp、「//」はhttp扱い、それ以外はNone）
+fn with_scheme(url: &str) -> Option<String> {
+    let lower = url.to_ascii_lowercase();
+    if ["http://", "https://", "ftp://"]
+        .iter()
+        .any(|s| lower.starts_with(s))
+    {
+        Some(url.to_string())
+    } else if lower.starts_with("www.") {
+        Some(format!("http://{}", url))
+    } else {
+        url.strip_prefix("//").map(|u| format!("http://{}", u))
+    }
+}
+
+/// URLをスキーム・ユーザー情報・ホスト・ポート・パス・クエリ・フラグメントに分解
+fn split_url(url: &str) -> Option<RawUrl> {
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(authority_end);
+    let (userinfo, hostport) = match authority.rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, authority),
+    };
+    let (host, port) = match hostport.rfind(':') {
+        Some(c) if !hostport[c..].contains(']') => (&hostport[..c], Some(&hostport[c + 1..])),
+        _ => (hostport, None),
+    };
+    let default_port = match scheme.as_str() {
+        "http" => "80",
+        "https" => "443",
+        _ => "21",
+    };
+    let port = port.filter(|p| !p.is_empty() && *p != default_port);
+    let host = decode(host).to_lowercase();
+    let (before_fragment, fragment) = match tail.split_once('#') {
+        Some((b, f)) => (b, Some(f)),
+        None => (tail, None),
+    };
+    let (path, query) = match before_fragment.split_once('?') {
+        Some((p, q)) => (p, Some(q)),
+        None => (before_fragment, None),
+    };
+    Some(RawUrl {
+        scheme,
+        userinfo: userinfo.map(str::to_string),
+        host: host.trim_end_matches('.').to_string(),
+        port: port.map(str::to_string),
+        path: path.to_string(),
+        query: query.map(str::to_string),
+        fragment: fragment.map(str::to_string),
+    })
+}
+
+/// 正規化したURL文字列を組み立てる（ホストはUnicode表記、パス・クエリ・フラグメントはパーセントデコード）
+fn assemble(url: &RawUrl) -> String {
+    let mut out = format!("{}://", url.scheme);
+    if let Some(user) = &url.userinfo {
+        out.push_str(&decode(user));
+        out.push('@');
+    }
+    out.push_str(&display_host(&url.host));
+    if let Some(port) = &url.port {
+        out.push(':');
+        out.push_str(port);
+    }
+    let path = if url.path.is_empty() {
+        "/".to_string()
+    } else {
+        decode(&url.path)
+    };
+    out.push_str(&path);
+    if let Some(q) = &url.query {
+        out.push('?');
+        out.push_str(&decode(q));
+    }
+    if let Some(f) = &url.fragment {
+        out.push('#');
+        out.push_str(&decode(f));
+    }
+    out
+}
+
+/// ホスト名をUnicode表記に（punycodeのxn--ラベルをデコード、デコードできなければそのまま）
+pub fn display_host(host: &str) -> String {
+    if !host.split('.').any(|l| l.starts_with("xn--")) {
+        return host.to_string();
+    }
+    match idna::domain_to_unicode(host) {
+        (unicode, Ok(())) => unicode,
+        _ => host.to_string(),
+    }
+}
+
+/// パーセントデコード（UTF-8として不正になる場合は元のまま）
+fn decode(text: &str) -> String {
+    match percent_decode_str(text).decode_utf8() {
+        Ok(decoded) => decoded.to_string(),
+        Err(_) => text.to_string(),
+    }
+}
+
+/// クエリから引数の値を取得（パーセントデコード、「+」は空白）
+fn query_param(query: Option<&str>, names: &[&str]) -> Option<String> {
+    query?.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        names
+            .iter()
+            .any(|n| name.eq_ignore_ascii_case(n))
+            .then(|| decode(&value.replace('+', " ")))
+    })
+}
+
+/// 既知のリダイレクタのURLなら転送先を取り出す（該当しなければNone）
+///
+/// # 説明
+/// - Google（/url?q=）、Outlook Safe Links（?url=）、Facebook（l.php?u=）、YouTube（/redirect?q=）
+/// - Proofpoint URL Defense v2（?u=、「-」→「%」「_」→「/」で復号）・v3（/v3/__URL__;置換文字!!…、「*」を置換文字で復元）
+/// - 上記以外のホストは、url=等の引数にURLを含んでいても展開しない（攻撃者のサイトを転送元に見せかけられるため）
+fn unwrap_redirector(url: &RawUrl) -> Option<String> {
+    let host = url.host.as_str();
+    let query = url.query.as_deref();
+    let inner = if is_google_host(host) && url.path == "/url" {
+        query_param(query, &["q", "url"])
+    } else if host.ends_with(".safelinks.protection.outlook.com") {
+        query_param(query, &["url"])
+    } else if matches!(host, "l.facebook.com" | "lm.facebook.com") && url.path == "/l.php" {
+        query_param(query, &["u"])
+    } else if matches!(host, "www.youtube.com" | "youtube.com") && url.path == "/redirect" {
+        query_param(query, &["q"])
+    } else if host == "urldefense.proofpoint.com" && url.path == "/v2/url" {
+        query_param(query, &["u"]).map(|u| decode(&u.replace('-', "%").replace('_', "/")))
+    } else if host == "urldefense.com" && url.path.starts_with("/v3/__") {
+        // 転送先の「?」「#」以降は外側のクエリ・フラグメントに分かれているためつなぎ直す
+        let mut rest = url.path["/v3/__".len()..].to_string();
+        if let Some(q) = &url.query {
+            rest.push('?');
+            rest.push_str(q);
+        }
+        if let Some(f) = &url.fragment {
+            rest.push('#');
+            rest.push_str(f);
+        }
+        proofpoint_v3(&rest)
+    } else {
+        None
+    }?;
+    let lower = inner.to_ascii_lowercase();
+    (lower.starts_with("http://") || lower.starts_with("https://")).then_some(inner)
+}
+
+/// Proofpoint URL Defense v3の「URL__;置換文字!!…」から転送先を復元
+///
+/// # 説明
+/// - 置換文字はbase64url（パディングなし）のUTF-8で、URL中の「*」に1文字ずつ、「**X」にXが表す長さ（A=2〜_=65）ずつ順に当てはめる
+/// - 置換文字が足りない・デコードできない場合はNone
+fn proofpoint_v3(encoded: &str) -> Option<String> {
+    use base64::Engine;
+    let (inner, tail) = encoded.split_once("__;").unwrap_or((encoded, ""));
+    if !inner.contains('*') {
+        return Some(inner.to_string());
+    }
+    let token = tail
+        .split("!!")
+        .next()
+        .unwrap_or(tail)
+        .trim_end_matches('=');
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(token)
+        .ok()?;
+    let replacements: Vec<char> = String::from_utf8(bytes).ok()?.chars().collect();
+    let mut next = replacements.iter();
+    let mut out = String::new();
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '*' {
+            out.push(c);
+            continue;
+        }
+        let run = if chars.peek() == Some(&'*') {
+            chars.next();
+            let marker = chars.next()?;
+            const RUN: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+            RUN.find(marker)? + 2 // 「**X」はXの位置+2文字分
+        } else {
+            1
+        };
+        for _ in 0..run {
+            out.push(*next.next()?);
+        }
+    }
+    Some(out)
+}
+
+/// Googleのホストか（google.com・google.co.jp・google.com.au・google.de等、www.の有無は問わない）
+fn is_google_host(host: &str) -> bool {
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let Some(suffix) = host.strip_prefix("google.") else {
+        return false;
+    };
+    let country = |label: &str| label.len() == 2 && label.bytes().all(|b| b.is_ascii_lowercase());
+    match suffix.split_once('.') {
+        None => suffix == "com" || country(suffix),
+        Some((second, cc)) => matches!(second, "co" | "com") && country(cc),
+    }
+}
@@ -0,0 +1,238 @@
+// =========================
+// tests/archive_listing.rs
+// アーカイブ添付（ZIP/RAR/TAR/GZ）解析の結合テスト
+//
+// 【役割】
+// - メンバー名・サイズ・圧縮率の一覧がログ・JSONに出力されることを確認
+// - 入れ子アーカイブ（ZIP内ZIP、tar.gz）の再帰解析とArchive_depthによる深さ制限を確認
+// - パスワード保護（ZIP・RAR4のメンバー暗号化、RAR5のヘッダ暗号化）とzip bombの検出を確認
+// - サイズを偽装したRAR5拡張領域で解析が止まらない（桁あふれ・無限ループしない）ことを確認
+// =========================
+
+mod common;
+
+use common::{attachment, base64, multipart, run_one, Message, MIXED};
+use serde_json::Value;
+use std::io::Write;
+
+/// `zip -P secret` で作成した invoice.exe 入りのZIP（ZipCrypto）
+const ENCRYPTED_ZIP: &str = "UEsDBAoACQAAAEW5UF2M7U6PGAAAAAwAAAALABwAaW52b2ljZS5leGVVVAkAA9Gu0mrRrtJqdXgLAAEEAAAAAAQAAAAAS05NIfo6M8jQbhZ4/E5DEUXEyMrrHUMDUEsHCIztTo8YAAAADAAAAFBLAQIeAwoACQAAAEW5UF2M7U6PGAAAAAwAAAALABgAAAAAAAAAAACkgQAAAABpbnZvaWNlLmV4ZVVUBQAD0a7SanV4CwABBAAAAAAEAAAAAFBLBQYAAAAAAQABAFEAAABtAAAAAAA=";
+/// RAR4: 暗号化メンバー docs\secret.exe と無圧縮メンバー note.txt
+const RAR4: &str = "UmFyIRoHAAAAcwAADQAAAAAAAAAAAHQEgC8AEAAAAAwAAAACAAAAAAAAAAAdMw8AIAAAAGRvY3Ncc2VjcmV0LmV4ZREREREREREREREREREREREAAHQAgCgACwAAAAsAAAACAAAAAAAAAAAdMAgAIAAAAG5vdGUudHh0cGxhaW4gbm90ZQoAAHsAQAcA";
+/// RAR5: 暗号化ヘッダ（-hp）のみ
+const RAR5_LOCKED: &str = "UmFyIRoHAQAAAAAAIQQAAAAPAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA==";
+
+/// PEヘッダ風の中身（e_lfanew=0x40の位置にPEシグネチャ）
+const EXE: &[u8] = &{
+    let mut data = [0u8; 0x44];
+    data[0] = b'M';
+    data[1] = b'Z';
+    data[0x3C] = 0x40; // e_lfanew
+    data[0x40] = b'P';
+    data[0x41] = b'E';
+    data
+};
+
+/// RAR5の可変長整数（下位7ビットずつ、最上位ビットが継続）
+fn vint(mut value: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+        let b = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(b);
+            return out;
+        }
+        out.push(b | 0x80);
+    }
+}
+
+/// 拡張領域のレコードサイズを偽装したRAR5（位置が桁あふれして先頭に戻るサイズ）
+fn rar5_looping_extra() -> Vec<u8> {
+    let size = vint(u64::MAX - 9); // レコード本体の開始位置（10）に足すと0に戻る
+    assert_eq!(size.len(), 10);
+    let mut extra = size;
+    extra.push(0x02); // ファイル暗号化（0x01）以外のレコード種別
+    let mut header = vec![2, 0x01, extra.len() as u8]; // ファイルヘッダ、拡張領域あり
+    header.extend_from_slice(&[0, 0, 0, 0, 0, 1, b'a']); // フラグ・サイズ・属性・圧縮・OS・名前
+    header.extend_from_slice(&extra);
+    let mut rar = b"Rar!\x1a\x07\x01\x00".to_vec();
+    rar.extend_from_slice(&[0; 4]); // CRC32（検証しない）
+    rar.extend_from_slice(&vint(header.len() as u64));
+    rar.extend_from_slice(&header);
+    rar
+}
+
+/// (名前, データ)の一覧からdeflate圧縮のZIPを作成
+fn zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut w = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (name, data) in members {
+        w.start_file(*name, options).unwrap();
+        w.write_all(data).unwrap();
+    }
+    w.finish().unwrap().into_inner()
+}
+
+/// a.txt と b.exe を含むtarをgzip圧縮
+fn tar_gz() -> Vec<u8> {
+    let mut tar = tar::Builder::new(Vec::new());
+    for (name, data) in [("a.txt", &b"text\n"[..]), ("b.exe", EXE)] {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, name, data).unwrap();
+    }
+    let tar = tar.into_inner().unwrap();
+    let mut gz = flate2::GzBuilder::new()
+        .filename("bundle.tar")
+        .write(Vec::new(), flate2::Compression::default());
+    gz.write_all(&tar).unwrap();
+    gz.finish().unwrap()
+}
+
+/// アーカイブ添付（ZIP内ZIP・zip bomb・暗号化ZIP・tar.gz・RAR4・一覧暗号化RAR5）を並べた本文
+fn archives() -> Vec<u8> {
+    let inner = zip(&[("payload.exe", EXE)]);
+    let outer = zip(&[
+        ("docs/readme.txt", b"hello hello hello hello hello hello\n"),
+        ("inner.zip", &inner),
+    ]);
+    let bomb = zip(&[("zeros.bin", &vec![0u8; 2 * 1024 * 1024])]);
+    multipart(&[
+        attachment("application/zip", Some("archive.zip"), &base64(&outer)),
+        attachment("application/zip", Some("bomb.zip"), &base64(&bomb)),
+        attachment("application/zip", Some("invoice.zip"), ENCRYPTED_ZIP),
+        attachment(
+            "application/gzip",
+            Some("bundle.tar.gz"),
+            &base64(&tar_gz()),
+        ),
+        attachment("application/vnd.rar", Some("files.rar"), RAR4),
+        attachment("application/vnd.rar", Some("locked.rar"), RAR5_LOCKED),
+    ])
+}
+
+/// アーカイブ添付を含むメールを1通送り、ログとJSONレコードを返す
+fn run_archives(conf: &str) -> (String, Value) {
+    let body = archives();
+    run_one(
+        conf,
+        &Message {
+            headers: MIXED,
+            body: &body,
+            ..Message::default()
+        },
+    )
+}
+
+#[test]
+fn archives_are_listed_recursively_with_encryption_and_bomb_flags() {
+    let (log, record) = run_archives("");
+    let attachments = &record["attachments"];
+
+    // ZIP内ZIP: メンバー一覧と入れ子のPE
+    let outer = &attachments[0]["archive"];
+    assert_eq!(outer["format"], "zip");
+    assert_eq!(outer["entries"][0]["name"], "docs/readme.txt");
+    assert_eq!(outer["entries"][0]["size"], 36);
+    assert!(outer["entries"][0]["ratio"].as_f64().unwrap() > 1.0);
+    assert_eq!(outer["entries"][1]["detected_type"], "zip");
+    let nested = &outer["entries"][1]["archive"];
+    assert_eq!(nested["entries"][0]["name"], "payload.exe");
+    assert_eq!(nested["entries"][0]["detected_type"], "pe");
+    assert_eq!(outer["encrypted"], false);
+    assert_eq!(outer["zip_bomb"], false);
+    assert!(
+        log.contains("[mail-parser] アーカイブ(1): format=zip entries=2 total_size="),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains("[mail-parser] アーカイブ(1):       - payload.exe size=68 compressed="),
+        "{}",
+        log
+    );
+
+    // zip bomb（2MBのゼロ埋め）
+    let bomb = &attachments[1]["archive"];
+    assert_eq!(bomb["zip_bomb"], true);
+    assert!(bomb["entries"][0]["ratio"].as_f64().unwrap() > 100.0);
+    assert!(log.contains("[zip bomb]"), "{}", log);
+
+    // パスワード付きZIP
+    let encrypted = &attachments[2]["archive"];
+    assert_eq!(encrypted["encrypted"], true);
+    assert_eq!(encrypted["entries"][0]["name"], "invoice.exe");
+    assert_eq!(encrypted["entries"][0]["encrypted"], true);
+    assert!(encrypted["entries"][0]["detected_type"].is_null());
+    assert!(
+        log.contains("[mail-parser] アーカイブ(3):   - invoice.exe size=12 compressed=24 ratio=0.50 [暗号化]"),
+        "{}",
+        log
+    );
+
+    // tar.gz: gzip → tar → メンバー
+    let gz = &attachments[3]["archive"];
+    assert_eq!(gz["format"], "gzip");
+    assert_eq!(gz["entries"][0]["name"], "bundle.tar");
+    let tar = &gz["entries"][0]["archive"];
+    assert_eq!(tar["format"], "tar");
+    assert_eq!(tar["entries"][0]["name"], "a.txt");
+    assert_eq!(tar["entries"][1]["name"], "b.exe");
+    assert_eq!(tar["entries"][1]["detected_type"], "pe");
+    assert!(tar["entries"][1]["compressed_size"].is_null());
+
+    // RAR4: メンバー暗号化
+    let rar4 = &attachments[4]["archive"];
+    assert_eq!(rar4["format"], "rar");
+    assert_eq!(rar4["encrypted"], true);
+    assert_eq!(rar4["headers_encrypted"], false);
+    assert_eq!(rar4["entries"][0]["name"], "docs/secret.exe");
+    assert_eq!(rar4["entries"][0]["encrypted"], true);
+    assert_eq!(rar4["entries"][1]["name"], "note.txt");
+    assert_eq!(rar4["entries"][1]["encrypted"], false);
+    assert_eq!(rar4["entries"][1]["compressed_size"], 11);
+
+    // RAR5: ヘッダ暗号化（一覧は読めない）
+    let rar5 = &attachments[5]["archive"];
+    assert_eq!(rar5["headers_encrypted"], true);
+    assert_eq!(rar5["encrypted"], true);
+    assert_eq!(rar5["entries"], Value::Array(vec![]));
+    assert!(log.contains("[暗号化] [一覧暗号化]"), "{}", log);
+}
+
+#[test]
+fn archive_depth_limits_nested_listing() {
+    let (log, record) = run_archives("Archive_depth 1");
+    let outer = &record["attachments"][0]["archive"];
+    assert_eq!(outer["entries"][1]["depth_limited"], true);
+    assert!(outer["entries"][1]["archive"].is_null());
+    assert!(log.contains("inner.zip size="), "{}", log);
+    assert!(log.contains("(深さ制限のため未解析)"), "{}", log);
+
+    let (_, record) = run_archives("Archive_depth 0");
+    assert!(record["attachments"][0]["archive"].is_null());
+}
+
+#[test]
+fn rar5_extra_area_with_forged_record_size_does_not_hang() {
+    let body = multipart(&[attachment(
+        "application/vnd.rar",
+        Some("loop.rar"),
+        &base64(&rar5_looping_extra()),
+    )]);
+    let (_, record) = run_one(
+        "",
+        &Message {
+            headers: MIXED,
+            body: &body,
+            ..Message::default()
+        },
+    );
+    let rar5 = &record["attachments"][0]["archive"];
+    assert_eq!(rar5["format"], "rar");
+    assert_eq!(rar5["entries"][0]["name"], "a");
+    assert_eq!(rar5["entries"][0]["encrypted"], false);
+}
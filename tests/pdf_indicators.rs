@@ -0,0 +1,166 @@
+// =========================
+// tests/pdf_indicators.rs
+// PDF添付の危険キーワード・URI抽出の結合テスト
+//
+// 【役割】
+// - ページ数と/JavaScript・/JS・/OpenAction・/AA・/Launch・/EmbeddedFile・/URI・/AcroForm・/XFAの出現数を確認
+// - URIアクションの参照先（リテラル・16進文字列、圧縮されたオブジェクトストリーム内）の抽出を確認
+// - 名前の#xxエスケープによる難読化と暗号化PDFの検出を確認
+// =========================
+
+mod common;
+
+use common::{attachment, base64, multipart, run_one, Message, MIXED};
+use serde_json::Value;
+use std::io::Write;
+
+/// オブジェクト本体の一覧からPDFを組み立てる（xrefは省略、解析側は使わない）
+fn pdf(objects: &[Vec<u8>], trailer: &str) -> Vec<u8> {
+    let mut out = b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    for (i, body) in objects.iter().enumerate() {
+        out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        out.extend_from_slice(body);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+    out.extend_from_slice(format!("trailer\n<< /Root 1 0 R {}>>\n%%EOF\n", trailer).as_bytes());
+    out
+}
+
+/// FlateDecodeで圧縮したストリームオブジェクト
+fn flate_object(dict: &str, content: &str) -> Vec<u8> {
+    let mut z = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    z.write_all(content.as_bytes()).unwrap();
+    let data = z.finish().unwrap();
+    let mut out = format!(
+        "<< {} /Filter /FlateDecode /Length {} >>\nstream\n",
+        dict,
+        data.len()
+    )
+    .into_bytes();
+    out.extend_from_slice(&data);
+    out.extend_from_slice(b"\nendstream");
+    out
+}
+
+/// 自動実行JavaScript・Launch・XFA・URI・埋め込みファイル入りのPDF
+fn risky_pdf() -> Vec<u8> {
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R /OpenAction 5 0 R /AcroForm << /XFA 9 0 R >> >>",
+        "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>",
+        "<< /Type /Page /Parent 2 0 R /AA << /O 6 0 R >> /Annots [7 0 R 8 0 R] >>",
+        "<</Type/Page/Parent 2 0 R>>",
+        "<< /S /J#61vaScript /JS (app.alert\\(1\\)) >>",
+        "<< /S /Launch /F (cmd.exe) >>",
+        "<< /Subtype /Link /A << /S /URI /URI (http://evil.example/a\\(b\\)?x=1) >> >>",
+        "<< /Subtype /Link /A << /S /URI /URI <68747470733A2F2F6865782E6578616D706C652F> >> >>",
+    ]
+    .map(|o| o.as_bytes().to_vec());
+    let mut objects = objects.to_vec();
+    objects.push(flate_object(
+        "/Type /ObjStm /N 2 /First 8",
+        "10 0 11 0 << /Type /Page >> << /S /URI /URI (http://evil.example/a\\(b\\)?x=1) /URI (https://packed.example/) /EmbeddedFiles << /Names [(a.exe) << /Type /EmbeddedFile >>] >> >>",
+    ));
+    pdf(&objects, "")
+}
+
+/// PDFを1件添付したメールを送信し、ログと添付のpdf解析結果を返す
+fn run_pdf(filename: &str, data: &[u8]) -> (String, Value) {
+    let body = multipart(&[attachment("application/pdf", Some(filename), &base64(data))]);
+    let (log, record) = run_one(
+        "",
+        &Message {
+            headers: MIXED,
+            body: &body,
+            ..Message::default()
+        },
+    );
+    (log, record["attachments"][0]["pdf"].clone())
+}
+
+/// ログに全行が含まれることを確認
+fn assert_logged(log: &str, lines: &[&str]) {
+    for line in lines {
+        assert!(log.contains(line), "{} がありません\n{}", line, log);
+    }
+}
+
+#[test]
+fn risky_pdf_keywords_are_counted() {
+    let (log, risky) = run_pdf("invoice.pdf", &risky_pdf());
+    // オブジェクトストリーム内のページ・URI・埋め込みファイルも数える
+    assert_eq!(risky["version"], "1.7");
+    assert_eq!(risky["pages"], 3);
+    assert_eq!(risky["encrypted"], false);
+    let k = &risky["keywords"];
+    assert_eq!(k["javascript"], 1); // #xxエスケープを展開して数える
+    assert_eq!(k["js"], 1);
+    assert_eq!(k["open_action"], 1);
+    assert_eq!(k["aa"], 1);
+    assert_eq!(k["launch"], 1);
+    assert_eq!(k["embedded_file"], 1);
+    assert_eq!(k["uri"], 7);
+    assert_eq!(k["acroform"], 1);
+    assert_eq!(k["xfa"], 1);
+    assert_eq!(risky["inflate_errors"], 0);
+    assert_logged(
+        &log,
+        &["[mail-parser] PDF(1): version=1.7 pages=3 /JavaScript=1 /JS=1 /OpenAction=1 /AA=1 /Launch=1 /EmbeddedFile=1 /URI=7 /AcroForm=1 /XFA=1 [JavaScript自動実行] [外部プログラム起動]"],
+    );
+}
+
+#[test]
+fn uri_actions_are_extracted_from_literal_hex_and_object_streams() {
+    let (log, risky) = run_pdf("invoice.pdf", &risky_pdf());
+    // 重複は1件にまとめ、出現順に並べる
+    assert_eq!(
+        risky["uris"],
+        serde_json::json!([
+            "http://evil.example/a(b)?x=1",
+            "https://hex.example/",
+            "https://packed.example/"
+        ])
+    );
+    assert_logged(
+        &log,
+        &[
+            "[mail-parser] PDF(1):   - URI http://evil.example/a(b)?x=1",
+            "[mail-parser] PDF(1):   - URI https://hex.example/",
+            "[mail-parser] PDF(1):   - URI https://packed.example/",
+        ],
+    );
+}
+
+#[test]
+fn pdf_without_keywords_reports_zero_counts() {
+    let plain = pdf(
+        &[
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            b"<< /Type /Page /Parent 2 0 R >>".to_vec(),
+        ],
+        "",
+    );
+    let (log, plain) = run_pdf("plain.pdf", &plain);
+    assert_eq!(plain["pages"], 1);
+    assert_eq!(plain["keywords"]["javascript"], 0);
+    assert_eq!(plain["keywords"]["uri"], 0);
+    assert_eq!(plain["uris"], Value::Array(vec![]));
+    assert_logged(&log, &["[mail-parser] PDF(1): version=1.7 pages=1\n"]);
+}
+
+#[test]
+fn encrypted_pdf_is_flagged() {
+    let encrypted = pdf(
+        &[
+            b"<< /Type /Catalog >>".to_vec(),
+            b"<< /Filter /Standard /V 2 >>".to_vec(),
+        ],
+        "/Encrypt 2 0 R ",
+    );
+    let (log, locked) = run_pdf("locked.pdf", &encrypted);
+    assert_eq!(locked["encrypted"], true);
+    assert_logged(
+        &log,
+        &["[mail-parser] PDF(1): version=1.7 pages=0 [暗号化]"],
+    );
+}
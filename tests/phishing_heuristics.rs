@@ -0,0 +1,290 @@
+// =========================
+// tests/phishing_heuristics.rs
+// フィッシング判定（表示名なりすまし・類似ドメイン・リンク文字列偽装）の結合テスト
+//
+// 【役割】
+// - 表示名に別アドレスを含むFromの検出とスコア（別ドメイン60・同じドメイン30）を確認
+// - Protected_domainsに対するホモグリフ（ASCII・punycode）・編集距離・埋め込みの検出を確認
+// - 類似ドメインはリダイレクタの外側のホストと転送先のホストの両方で検出されることを確認
+// - リンク文字列のURLとhrefの最終的な転送先のホストの不一致検出と、サブドメイン・既知のリダイレクタ経由の一致は対象外であることを確認
+// - 既知のリダイレクタ以外のurl=等の引数では一致とみなさないことを確認
+// =========================
+
+mod common;
+
+use common::{multipart, part, run_one, Message};
+use serde_json::Value;
+
+const PROTECTED: &str = "Protected_domains PayPal.com example.co.jp\n\
+                         Protected_domains microsoft.com paypal.com";
+
+/// 指標1件（種別, スコア, 検出場所, 実際の値, 装っている値, 補足）
+type Indicator = (String, u64, String, String, String, Value);
+
+/// Fromヘッダと本文パートを指定してメールを1通送信し、ログとフィッシング指標の一覧を返す
+fn run_phishing(from: &str, parts: &[String]) -> (String, Vec<Indicator>) {
+    let body = multipart(parts);
+    let (log, record) = run_one(
+        PROTECTED,
+        &Message {
+            mail: &["<attacker@evil.example>"],
+            headers: &[
+                ("From", from),
+                ("MIME-Version", "1.0"),
+                ("Content-Type", "multipart/alternative; boundary=\"XX\""),
+            ],
+            body: &body,
+            ..Message::default()
+        },
+    );
+    let indicators = record["phishing"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| {
+            (
+                x["kind"].as_str().unwrap().to_string(),
+                x["score"].as_u64().unwrap(),
+                x["location"].as_str().unwrap().to_string(),
+                x["actual"].as_str().unwrap().to_string(),
+                x["claimed"].as_str().unwrap().to_string(),
+                x["detail"].clone(),
+            )
+        })
+        .collect();
+    (log, indicators)
+}
+
+/// HTMLパート1件だけのメールを送信（Fromは正規のアドレス）
+fn run_html(html: &str) -> (String, Vec<Indicator>) {
+    run_phishing(
+        "<sender@example.org>",
+        &[part("text/html; charset=utf-8", html.as_bytes())],
+    )
+}
+
+/// 期待する指標1件
+fn ind(
+    kind: &str,
+    score: u64,
+    location: &str,
+    actual: &str,
+    claimed: &str,
+    detail: &str,
+) -> Indicator {
+    (
+        kind.to_string(),
+        score,
+        location.to_string(),
+        actual.to_string(),
+        claimed.to_string(),
+        Value::from(detail),
+    )
+}
+
+#[test]
+fn display_names_with_other_addresses_are_reported() {
+    let (log, indicators) = run_phishing(
+        "\"support@paypal.com\" <attacker@evil.example>, \"ceo@evil.example (CEO)\" <intern@evil.example>",
+        &[part("text/plain", b"hello\r\n")],
+    );
+    // 別ドメインは60、同じドメインは30
+    assert_eq!(
+        indicators,
+        vec![
+            ind(
+                "display_name_spoof",
+                60,
+                "From",
+                "attacker@evil.example",
+                "support@paypal.com",
+                "support@paypal.com"
+            ),
+            ind(
+                "display_name_spoof",
+                30,
+                "From",
+                "intern@evil.example",
+                "ceo@evil.example",
+                "ceo@evil.example (CEO)"
+            ),
+        ]
+    );
+    assert!(
+        log.contains("[phishing] フィッシング指標(1): display_name_spoof score=60 From actual=attacker@evil.example claimed=support@paypal.com (support@paypal.com)"),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn lookalike_domains_are_scored_by_method() {
+    let text = "返信はこちら: http://rnicrosoft.com/reply\r\n\
+        https://paypa1.com/login\r\n\
+        http://xn--pypal-4ve.com/\r\n\
+        https://login.exmaple.co.jp/\r\n\
+        https://paypal.com.secure-login.example/\r\n\
+        https://www.paypal.com/x https://paypa1.com/again\r\n";
+    let (log, indicators) = run_phishing(
+        "<sender@example.org>",
+        &[part("text/plain; charset=utf-8", text.as_bytes())],
+    );
+    // URLの抽出順、同じホスト・正規ドメインは除く
+    assert_eq!(
+        indicators,
+        vec![
+            ind(
+                "lookalike_domain",
+                70,
+                "URL(1)",
+                "rnicrosoft.com",
+                "microsoft.com",
+                "homoglyph"
+            ),
+            ind(
+                "lookalike_domain",
+                70,
+                "URL(2)",
+                "paypa1.com",
+                "paypal.com",
+                "homoglyph"
+            ),
+            ind(
+                "lookalike_domain",
+                80,
+                "URL(3)",
+                "pаypal.com",
+                "paypal.com",
+                "punycode"
+            ),
+            ind(
+                "lookalike_domain",
+                60,
+                "URL(4)",
+                "login.exmaple.co.jp",
+                "example.co.jp",
+                "edit_distance(1)"
+            ),
+            ind(
+                "lookalike_domain",
+                50,
+                "URL(5)",
+                "paypal.com.secure-login.example",
+                "paypal.com",
+                "embedded"
+            ),
+        ]
+    );
+    assert!(
+        log.contains("[phishing] フィッシング指標(3): lookalike_domain score=80 URL(3) actual=pаypal.com claimed=paypal.com (punycode)"),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn lookalike_hosts_are_checked_outside_and_inside_redirectors() {
+    let text = "https://paypa1.com/go?url=https%3A%2F%2Fwww.paypal.com%2F\r\n\
+        https://www.google.com/url?q=https%3A%2F%2Frnicrosoft.com%2F\r\n";
+    let (_, indicators) = run_phishing(
+        "<sender@example.org>",
+        &[part("text/plain", text.as_bytes())],
+    );
+    // 未知のホストのurl=引数は展開しないので外側の類似ドメインが残り、既知のリダイレクタは転送先も検査する
+    assert_eq!(
+        indicators,
+        vec![
+            ind(
+                "lookalike_domain",
+                70,
+                "URL(1)",
+                "paypa1.com",
+                "paypal.com",
+                "homoglyph"
+            ),
+            ind(
+                "lookalike_domain",
+                70,
+                "URL(2)",
+                "rnicrosoft.com",
+                "microsoft.com",
+                "homoglyph"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn link_text_urls_are_compared_with_the_final_href_host() {
+    let html = concat!(
+        "<html><body>",
+        "<a href=\"https://example.net/login\">https://www.example.org/signin</a>",
+        "<a href=\"https://example.net/\">ログイン</a>",
+        "<a href=\"https://www.example.org/x\">https://example.org/x</a>",
+        "<a href=\"https://nam01.safelinks.protection.outlook.com/?url=https%3A%2F%2Fexample.org%2F\">https://example.org/</a>",
+        "<a href=\"mailto:help@example.org\">https://example.org/</a>",
+        "<a href=\"https://evil.example/\"><span>www.example&#46;org</span></a>",
+        "</body></html>"
+    );
+    let (log, indicators) = run_html(html);
+    // リンク文字列がURLでないもの・サブドメイン・既知のリダイレクタ経由の一致・mailto:は対象外
+    assert_eq!(
+        indicators,
+        vec![
+            ind(
+                "link_text_mismatch",
+                50,
+                "part=1",
+                "https://example.net/login",
+                "https://www.example.org/signin",
+                "www.example.org → example.net"
+            ),
+            ind(
+                "link_text_mismatch",
+                50,
+                "part=1",
+                "https://evil.example/",
+                "www.example.org",
+                "www.example.org → evil.example"
+            ),
+        ]
+    );
+    assert!(
+        log.contains("[phishing] フィッシング指標(2): link_text_mismatch score=50 part=1 actual=https://evil.example/ claimed=www.example.org (www.example.org → evil.example)"),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn link_text_mismatch_is_not_hidden_by_redirect_parameters() {
+    let html = concat!(
+        "<html><body>",
+        "<a href=\"https://evil.example/r?url=https%3A%2F%2Fwww.example.org%2F\">https://www.example.org/</a>",
+        "<a href=\"https://nam01.safelinks.protection.outlook.com/?url=https%3A%2F%2Fevil.example%2F\">https://www.example.org/</a>",
+        "</body></html>"
+    );
+    let (_, indicators) = run_html(html);
+    // 未知のホストは展開しないので外側のホストで比較し、既知のリダイレクタは最終的な転送先で比較する
+    assert_eq!(
+        indicators,
+        vec![
+            ind(
+                "link_text_mismatch",
+                50,
+                "part=1",
+                "https://evil.example/r?url=https://www.example.org/",
+                "https://www.example.org/",
+                "www.example.org → evil.example"
+            ),
+            ind(
+                "link_text_mismatch",
+                50,
+                "part=1",
+                "https://nam01.safelinks.protection.outlook.com/?url=https://evil.example/",
+                "https://www.example.org/",
+                "www.example.org → nam01.safelinks.protection.outlook.com → evil.example"
+            ),
+        ]
+    );
+}
@@ -0,0 +1,119 @@
+// =========================
+// tests/policy_actions.rs
+// Milter応答（REJECT/TEMPFAIL/DISCARD/REPLYCODE）の結合テスト
+//
+// 【役割】
+// - Policy_rule設定に応じて各フェーズで正しい応答コードが返ることを確認
+// - SMFIR_REPLYCODEのペイロード書式（code xcode text\0）を確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, MilterServer};
+
+#[test]
+fn default_responses_are_continue_and_accept() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    assert_eq!(
+        client
+            .command(b'C', &args(&["mx.example.org", "4", "", "192.0.2.1"]))
+            .0,
+        b'c'
+    );
+    assert_eq!(client.command(b'H', &args(&["mx.example.org"])).0, b'c');
+    assert_eq!(client.command(b'M', &args(&["<a@example.org>"])).0, b'c');
+    assert_eq!(client.command(b'R', &args(&["<b@example.org>"])).0, b'c');
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["Subject", "ok"]));
+    assert_eq!(client.command(b'N', b"").0, b'c');
+    client.step(b'B', b"body\r\n");
+    assert_eq!(client.command(b'E', b"").0, b'a');
+}
+
+#[test]
+fn connect_rule_returns_custom_reply_code() {
+    let server = MilterServer::start(
+        "Policy_rule connect 198.51.100.* reply 554 5.7.1 Access denied (100% sure)",
+    );
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    let (cmd, payload) = client.command(b'C', &args(&["bad.example", "4", "", "198.51.100.7"]));
+    assert_eq!(cmd, b'y');
+    assert_eq!(payload, b"554 5.7.1 Access denied (100%% sure)\0");
+}
+
+#[test]
+fn helo_mail_and_rcpt_rules_reject_and_tempfail() {
+    let server = MilterServer::start(concat!(
+        "Policy_rule helo *.invalid reject\n",
+        "Policy_rule mail spammer@example.com reject\n",
+        "Policy_rule rcpt *@greylist.example tempfail\n",
+    ));
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    assert_eq!(
+        client
+            .command(b'C', &args(&["mx.example.org", "4", "", "192.0.2.1"]))
+            .0,
+        b'c'
+    );
+    assert_eq!(client.command(b'H', &args(&["host.invalid"])).0, b'r');
+    assert_eq!(client.command(b'H', &args(&["mx.example.org"])).0, b'c');
+    assert_eq!(
+        client.command(b'M', &args(&["<Spammer@Example.com>"])).0,
+        b'r'
+    );
+    assert_eq!(client.command(b'M', &args(&["<good@example.org>"])).0, b'c');
+    assert_eq!(
+        client.command(b'R', &args(&["<user@greylist.example>"])).0,
+        b't'
+    );
+    assert_eq!(client.command(b'R', &args(&["<user@example.org>"])).0, b'c');
+}
+
+#[test]
+fn header_rules_discard_at_eoh_and_reject_at_eom() {
+    let server = MilterServer::start(concat!(
+        "Policy_rule eoh X-Spam-Flag:*YES* discard\n",
+        "Policy_rule eom Subject:*invoice* reply 550 Message refused\n",
+    ));
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+
+    // 1通目: EOHで破棄
+    client.command(b'M', &args(&["<a@example.org>"]));
+    client.command(b'R', &args(&["<b@example.org>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["X-Spam-Flag", "YES"]));
+    assert_eq!(client.command(b'N', b"").0, b'd');
+    client.send(b'A', b"");
+
+    // 2通目: EOMで拒否（xcode省略）
+    client.command(b'M', &args(&["<a@example.org>"]));
+    client.command(b'R', &args(&["<b@example.org>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["Subject", "Your invoice"]));
+    assert_eq!(client.command(b'N', b"").0, b'c');
+    client.step(b'B', b"body\r\n");
+    let (cmd, payload) = client.command(b'E', b"");
+    assert_eq!(cmd, b'y');
+    assert_eq!(payload, b"550 Message refused\0");
+}
+
+#[test]
+fn invalid_rules_are_ignored() {
+    let server = MilterServer::start(concat!(
+        "Policy_rule mail * reply 250 not an error code\n",
+        "Policy_rule mail * reply 550 4.7.1 class mismatch\n",
+        "Policy_rule bogus * reject\n",
+    ));
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+    assert_eq!(client.command(b'M', &args(&["<a@example.org>"])).0, b'c');
+    let log = server.finish();
+    assert_eq!(log.matches("Policy_rule設定不正").count(), 3, "{}", log);
+}
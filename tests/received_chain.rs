@@ -0,0 +1,169 @@
+// =========================
+// tests/received_chain.rs
+// Receivedヘッダの中継経路解析の結合テスト
+//
+// 【役割】
+// - Postfix・Exim・Sendmail形式のReceivedヘッダからfrom/by・IP・プロトコル・TLS・id・for・日時を抽出できることを確認
+// - ホップ間の遅延（タイムゾーンの異なる日時の差）と、時計のずれ・未来の日時・経路の不連続・HELOのIP不一致・不正な形式の検出を確認
+// - JSON出力ではconnectの直後、ログでは[session] connect行の直後に出力されることを確認
+// - 各ホップの項目・フラグは、必要な前後のホップだけを並べた経路で個別に確認
+// =========================
+
+mod common;
+
+use common::{run, Message, RunOutput};
+use serde_json::{json, Value};
+
+const RECEIVED: [&str; 5] = [
+    // 1: 最終の受信サーバー（Postfix、TLS）
+    "from mail.sender.example (mail.sender.example [192.0.2.10])\r\n\t(using TLSv1.3 with cipher TLS_AES_256_GCM_SHA384 (256/256 bits))\r\n\tby mx.example.net (Postfix) with ESMTPS id 4ABC123\r\n\tfor <rcpt@example.net>; Fri, 16 Oct 2026 10:00:05 +0900 (JST)",
+    // 2: 送信側の投稿サーバー（Exim、SMTP認証）
+    "from [10.0.0.5] (helo=client.sender.example)\r\n\tby mail.sender.example with esmtpa (Exim 4.96)\r\n\tid 1xyz for rcpt@example.net; Fri, 16 Oct 2026 00:59:50 +0000",
+    // 3: 偽装されたホップ（HELOのIPと接続元IPが異なり、時刻も新しすぎる）
+    "from [203.0.113.9] (forged.example [198.51.100.7])\r\n\tby mx.forged.example with SMTP id F1; Fri, 16 Oct 2026 02:00:00 +0000",
+    // 4: 未来の日時（Sendmail形式、fromなし）
+    "by relay.example (8.15.2/8.15.2) id 42; Thu, 1 Jan 2099 00:00:00 +0000",
+    // 5: 不正な形式
+    "unknown garbage",
+];
+
+/// 指定したReceivedヘッダ（新しい順）付きのメールを1通送信
+fn run_received(values: &[&str]) -> RunOutput {
+    let mut headers: Vec<(&str, &str)> = values.iter().map(|v| ("Received", *v)).collect();
+    headers.push(("Subject", "hops"));
+    let out = run(
+        "",
+        &[Message {
+            mail: &["<sender@sender.example>"],
+            headers: &headers,
+            ..Message::default()
+        }],
+    );
+    assert_eq!(out.records.len(), 1, "{}", out.log);
+    out
+}
+
+/// 指定したReceivedヘッダを解析したホップの一覧
+fn hops(values: &[&str]) -> Vec<Value> {
+    let out = run_received(values);
+    let hops = out.records[0]["received"].as_array().unwrap().clone();
+    assert_eq!(hops.len(), values.len());
+    hops
+}
+
+#[test]
+fn postfix_hop_with_tls_and_delay_is_parsed() {
+    let hops = hops(&RECEIVED[..2]);
+    // TLS付きのESMTPS、1つ前のホップ（UTC表記）からの遅延15秒
+    let hop = &hops[0];
+    assert_eq!(hop["index"], 1);
+    assert_eq!(hop["from_host"], "mail.sender.example");
+    assert_eq!(hop["from_rdns"], "mail.sender.example");
+    assert_eq!(hop["from_ip"], "192.0.2.10");
+    assert_eq!(hop["by_host"], "mx.example.net");
+    assert_eq!(hop["protocol"], "ESMTPS");
+    assert_eq!(
+        hop["tls"],
+        "using TLSv1.3 with cipher TLS_AES_256_GCM_SHA384 (256/256 bits)"
+    );
+    assert_eq!(hop["encrypted"], true);
+    assert_eq!(hop["authenticated"], false);
+    assert_eq!(hop["id"], "4ABC123");
+    assert_eq!(hop["for_address"], "rcpt@example.net");
+    assert_eq!(hop["timestamp"], "2026-10-16T10:00:05+09:00");
+    assert_eq!(hop["delay"], 15);
+    assert_eq!(hop["flags"], json!([]));
+}
+
+#[test]
+fn exim_hop_with_authentication_is_parsed() {
+    let hops = hops(&RECEIVED[1..2]);
+    // ESMTPA（小文字表記）、IPリテラルのHELO名、helo=は逆引き名にしない
+    let hop = &hops[0];
+    assert_eq!(hop["from_host"], "[10.0.0.5]");
+    assert_eq!(hop["from_rdns"], Value::Null);
+    assert_eq!(hop["from_ip"], "10.0.0.5");
+    assert_eq!(hop["by_host"], "mail.sender.example");
+    assert_eq!(hop["protocol"], "ESMTPA");
+    assert_eq!(hop["tls"], Value::Null);
+    assert_eq!(hop["encrypted"], false);
+    assert_eq!(hop["authenticated"], true);
+    assert_eq!(hop["id"], "1xyz");
+    assert_eq!(hop["for_address"], "rcpt@example.net");
+    assert_eq!(hop["delay"], Value::Null); // 最も古いホップは遅延なし
+    assert_eq!(hop["flags"], json!([]));
+}
+
+#[test]
+fn clock_skew_and_chain_break_are_flagged() {
+    let hops = hops(&RECEIVED[1..3]);
+    // 1つ前のホップより古い日時、1つ前のby≠このホップのfrom
+    assert_eq!(hops[0]["delay"], -3610);
+    assert_eq!(hops[0]["flags"], json!(["clock_skew", "chain_break"]));
+}
+
+#[test]
+fn helo_ip_mismatch_is_flagged() {
+    let hops = hops(&RECEIVED[2..3]);
+    // HELOのIPと接続元IPの不一致
+    let hop = &hops[0];
+    assert_eq!(hop["from_rdns"], "forged.example");
+    assert_eq!(hop["from_ip"], "198.51.100.7");
+    assert_eq!(hop["flags"], json!(["helo_mismatch"]));
+}
+
+#[test]
+fn future_and_malformed_hops_are_flagged() {
+    let hops = hops(&RECEIVED[3..]);
+    // 未来の日時（Sendmail形式、fromなし。1つ前のホップは日時不明のため遅延なし）
+    let hop = &hops[0];
+    assert_eq!(hop["from_host"], Value::Null);
+    assert_eq!(hop["by_host"], "relay.example");
+    assert_eq!(hop["id"], "42");
+    assert_eq!(hop["delay"], Value::Null);
+    assert_eq!(hop["flags"], json!(["future_timestamp"]));
+
+    // from・by・日時なし
+    assert_eq!(hops[1]["flags"], json!(["malformed", "missing_timestamp"]));
+    assert_eq!(hops[1]["raw"], "unknown garbage");
+}
+
+#[test]
+fn received_chain_follows_connect_in_json_and_log() {
+    let out = run_received(&RECEIVED);
+
+    // JSON出力（connectの直後）
+    let text = std::fs::read_to_string(out.dir.join("messages.jsonl")).unwrap();
+    let line = text.lines().next().unwrap();
+    assert!(
+        line.find("\"connect\":").unwrap() < line.find("\"received\":").unwrap(),
+        "{}",
+        line
+    );
+    let hops = out.records[0]["received"].as_array().unwrap();
+    assert_eq!(
+        hops[2]["flags"],
+        json!(["helo_mismatch", "clock_skew", "chain_break"])
+    );
+
+    // ログ出力（[session] connect行の直後）
+    let log = &out.log;
+    let lines: Vec<&str> = log.lines().collect();
+    let connect = lines
+        .iter()
+        .position(|l| l.contains("[session] connect:"))
+        .unwrap();
+    assert!(
+        lines[connect + 1].ends_with("[received] 中継経路数: 5"),
+        "{}",
+        log
+    );
+    for line in [
+        "[received] hop(1): from=mail.sender.example mail.sender.example 192.0.2.10 by=mx.example.net - with=ESMTPS id=4ABC123 for=rcpt@example.net date=2026-10-16T10:00:05+09:00 delay=15s tls=(using TLSv1.3 with cipher TLS_AES_256_GCM_SHA384 (256/256 bits))\n",
+        "[received] hop(2): from=[10.0.0.5] - 10.0.0.5 by=mail.sender.example - with=ESMTPA id=1xyz for=rcpt@example.net date=2026-10-16T00:59:50+00:00 delay=-3610s [clock_skew] [chain_break]",
+        "[received] hop(4): from=- - - by=relay.example - id=42 date=2099-01-01T00:00:00+00:00 [future_timestamp]",
+        "[received] hop(5): from=- - - by=- - [malformed] [missing_timestamp]",
+    ] {
+        assert!(log.contains(line), "{} がありません\n{}", line, log);
+    }
+}
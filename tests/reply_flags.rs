@@ -0,0 +1,105 @@
+// =========================
+// tests/reply_flags.rs
+// 応答省略フラグ（SMFIP_NR_*）に従った応答有無の結合テスト
+//
+// 【役割】
+// - 合意したプロトコルフラグの組み合わせごとに、応答を待つコマンドへちょうど1回応答することを確認
+// - 応答省略を合意したコマンドには応答しない（余分な応答で同期がずれない）ことを確認
+// =========================
+
+mod common;
+
+use common::{args, MilterServer};
+
+const NR_HDR: u32 = 0x80;
+const NR_CONN: u32 = 0x1000;
+const NR_HELO: u32 = 0x2000;
+const NR_MAIL: u32 = 0x4000;
+const NR_RCPT: u32 = 0x8000;
+const NR_DATA: u32 = 0x1_0000;
+const NR_UNKN: u32 = 0x2_0000;
+const NR_EOH: u32 = 0x4_0000;
+const NR_BODY: u32 = 0x8_0000;
+
+/// 指定フラグを提示してOPTNEGから1通分を送り、応答有無と内容を検証
+/// - `expected_protocol`: サーバーが合意するはずのプロトコルフラグ
+fn run_session(offered_protocol: u32, expected_protocol: u32) {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    let (_, _, protocol) = client.optneg(6, 0, offered_protocol);
+    assert_eq!(protocol, expected_protocol, "protocol=0x{:08X}", protocol);
+
+    let steps: Vec<(u8, Vec<u8>)> = vec![
+        (b'D', args(&["C", "j", "mx.example.org"])),
+        (b'C', args(&["client.example.org", "4", "", "192.0.2.10"])),
+        (b'H', args(&["client.example.org"])),
+        (b'U', args(&["XFOO bar"])),
+        (b'D', args(&["M", "i", "ABC123"])),
+        (b'M', args(&["<sender@example.org>"])),
+        (b'R', args(&["<rcpt1@example.net>"])),
+        (b'R', args(&["<rcpt2@example.net>"])),
+        (b'T', Vec::new()),
+        (b'L', args(&["Subject", "flags"])),
+        (b'L', args(&["From", "sender@example.org"])),
+        (b'N', Vec::new()),
+        (b'B', b"chunk one\r\n".to_vec()),
+        (b'B', b"chunk two\r\n".to_vec()),
+    ];
+    for (cmd, payload) in &steps {
+        let expects = client.expects_reply(*cmd);
+        match client.step(*cmd, payload) {
+            Some((resp, _)) => assert_eq!(
+                resp, b'c',
+                "{}への応答がCONTINUEではありません",
+                *cmd as char
+            ),
+            None => assert!(!expects),
+        }
+    }
+    let (resp, _) = client.command(b'E', b"");
+    assert_eq!(resp, b'a', "BODYEOBへの最終応答がACCEPTではありません");
+    client.send(b'Q', b"");
+    let rest = client.read_until_closed();
+    assert!(rest.is_empty(), "余分な応答を受信: {:?}", rest);
+    server.finish();
+}
+
+#[test]
+fn replies_to_every_command_without_nr_flags() {
+    run_session(0, 0);
+}
+
+#[test]
+fn skips_replies_for_all_negotiable_nr_flags() {
+    let all_nr =
+        NR_HDR | NR_CONN | NR_HELO | NR_MAIL | NR_RCPT | NR_DATA | NR_UNKN | NR_EOH | NR_BODY;
+    // 判定を返すCONNECT/HELO/MAIL/RCPT/EOHの応答省略は合意しない
+    run_session(all_nr, NR_HDR | NR_DATA | NR_UNKN | NR_BODY);
+}
+
+#[test]
+fn skips_header_replies_only() {
+    run_session(NR_HDR, NR_HDR);
+}
+
+#[test]
+fn skips_body_and_unknown_replies() {
+    run_session(NR_BODY | NR_UNKN | NR_EOH, NR_BODY | NR_UNKN);
+}
+
+#[test]
+fn abort_and_macro_get_no_reply() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    client.send(b'D', &args(&["C", "j", "mx.example.org"]));
+    client.command(b'C', &args(&["client.example.org", "4", "", "192.0.2.10"]));
+    client.command(b'M', &args(&["<sender@example.org>"]));
+    client.send(b'A', b"");
+    // ABORTに応答していれば、次のMAILへの応答より先に読めてしまう
+    let (resp, _) = client.command(b'M', &args(&["<again@example.org>"]));
+    assert_eq!(resp, b'c');
+    client.send(b'Q', b"");
+    assert!(client.read_until_closed().is_empty());
+    server.finish();
+}
@@ -0,0 +1,153 @@
+// =========================
+// tests/session_reset.rs
+// SMFIC_ABORT / SMFIC_QUIT_NC によるセッション状態リセットの結合テスト
+//
+// 【役割】
+// - ABORT後の次メールに中断前のエンベロープ・ヘッダ・ボディが混入しないことを確認
+// - QUIT_NC後も同じソケットで新しい接続として処理が継続することを確認
+// =========================
+
+mod common;
+
+use common::{args, connect_and_helo, message_blocks, send_message, Message, MilterServer};
+
+#[test]
+fn abort_discards_transaction_but_keeps_connection_state() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "first.example.org", "helo.example.org");
+
+    // 1通目: ヘッダ途中でABORT（RSET相当）
+    client.command(b'M', &args(&["<stale@example.org>", "SIZE=10"]));
+    client.command(b'R', &args(&["<stale-rcpt@example.net>"]));
+    client.step(b'T', b"");
+    client.step(b'L', &args(&["X-Stale", "yes"]));
+    client.step(b'B', b"stale body\r\n");
+    client.send(b'A', b"");
+
+    // 2通目: 同じ接続で完走
+    let (eob, _) = send_message(
+        &mut client,
+        &Message {
+            mail: &["<fresh@example.org>"],
+            headers: &[("Subject", "second")],
+            body: b"fresh body\r\n",
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    let blocks = message_blocks(&log);
+    assert_eq!(blocks.len(), 1, "BODYEOBは1回のみのはず:\n{}", log);
+    let block = blocks[0];
+    assert!(
+        block.contains("helo.example.org"),
+        "HELO情報が保持されていません:\n{}",
+        block
+    );
+    assert!(
+        block.contains("mail from: <fresh@example.org>"),
+        "{}",
+        block
+    );
+    assert!(block.contains("Subject: second"), "{}", block);
+    assert!(block.contains("fresh body"), "{}", block);
+    assert!(
+        !block.contains("stale"),
+        "中断したメールが混入しています:\n{}",
+        block
+    );
+}
+
+#[test]
+fn quit_nc_resets_to_fresh_connection_on_same_socket() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+
+    // 1接続目
+    connect_and_helo(&mut client, "one.example.org", "helo-one.example.org");
+    let (eob, _) = send_message(
+        &mut client,
+        &Message {
+            mail: &["<one@example.org>"],
+            headers: &[("Subject", "first")],
+            body: b"body one\r\n",
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+    client.send(b'K', b"");
+
+    // 2接続目: 同じソケットでCONNECTからやり直し（応答が返れば接続は維持されている）
+    connect_and_helo(&mut client, "two.example.org", "helo-two.example.org");
+    let (eob, _) = send_message(
+        &mut client,
+        &Message {
+            mail: &["<two@example.org>"],
+            headers: &[("Subject", "second")],
+            body: b"body two\r\n",
+            ..Message::default()
+        },
+    );
+    assert_eq!(eob, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    let blocks = message_blocks(&log);
+    assert_eq!(blocks.len(), 2, "BODYEOBは2回のはず:\n{}", log);
+    assert!(blocks[0].contains("helo-one.example.org"), "{}", blocks[0]);
+    assert!(blocks[0].contains("Subject: first"), "{}", blocks[0]);
+    assert!(blocks[1].contains("two.example.org"), "{}", blocks[1]);
+    assert!(blocks[1].contains("helo-two.example.org"), "{}", blocks[1]);
+    assert!(!blocks[1].contains("one.example.org"), "{}", blocks[1]);
+    assert!(!blocks[1].contains("Subject: first"), "{}", blocks[1]);
+}
+
+#[test]
+fn consecutive_messages_do_not_share_headers() {
+    let server = MilterServer::start("");
+    let mut client = server.connect();
+    client.optneg(6, 0, 0);
+    connect_and_helo(&mut client, "mx.example.org", "mx.example.org");
+
+    for i in 1..=3 {
+        let from = format!("<sender{}@example.org>", i);
+        let subject = format!("message {}", i);
+        let (eob, _) = send_message(
+            &mut client,
+            &Message {
+                mail: &[&from],
+                headers: &[("Subject", &subject)],
+                body: b"hello\r\n",
+                ..Message::default()
+            },
+        );
+        assert_eq!(eob, b'a');
+    }
+    client.send(b'Q', b"");
+    client.wait_closed();
+
+    let log = server.finish();
+    let blocks = message_blocks(&log);
+    assert_eq!(blocks.len(), 3, "{}", log);
+    for (i, block) in blocks.iter().enumerate() {
+        let n = i + 1;
+        assert!(
+            block.contains(&format!("Subject: message {}", n)),
+            "{}",
+            block
+        );
+        assert_eq!(block.matches("Subject: message").count(), 1, "{}", block);
+        assert!(
+            block.contains(&format!("<sender{}@example.org>", n)),
+            "{}",
+            block
+        );
+    }
+}
@@ -0,0 +1,314 @@
+// =========================
+// tests/spf_evaluation.rs
+// SPF評価の結合テスト
+//
+// 【役割】
+// - CONNECTのファミリ・ポート・アドレスが型付きでJSON出力されることを確認（IPv4・Sendmail形式のIPv6）
+// - ゾーンファイルのSPFレコードでinclude/mx・redirect/exp・マクロ（exists）が評価されることを確認
+// - includeの循環がDNS問い合わせ回数の上限でpermerrorになることを確認
+// - ヌル送信者はHELO名で評価されること、ログ・Authentication-Resultsヘッダの出力を確認
+// - 自サーバのauthserv-idを名乗る受信済みのAuthentication-Resultsヘッダが削除され、自サーバの結果が先頭に挿入されることを確認
+// - Dns_resolver systemでも評価後に応答を返す（リゾルバの破棄でワーカースレッドが落ちない）ことを確認
+// =========================
+
+mod common;
+
+use common::{args, send_message, Message, MilterServer};
+use serde_json::Value;
+
+// SPFレコード等のゾーンファイル
+const ZONE: &str = "$ORIGIN example.com.\n\
+@ IN TXT \"v=spf1 include:_spf.example.com -all exp=explain.%{d}\"\n\
+@ IN MX 10 mail\n\
+_spf IN TXT \"v=spf1 mx:example.com -all\"\n\
+mail IN A 192.0.2.10\n\
+mail IN TXT \"v=spf1 ip6:2001:db8::/32 -all\"\n\
+explain IN TXT \"%{i} is not one of %{d}'s designated mail servers\"\n\
+redirect IN TXT \"v=spf1 redirect=example.com\"\n\
+$ORIGIN example.org.\n\
+macro IN TXT \"v=spf1 exists:%{ir}.%{l1r-}.allow.example.org -all\"\n\
+10.2.0.192.alice.allow IN A 127.0.0.2\n\
+loop.example.net. IN TXT \"v=spf1 include:loop.example.net -all\"\n";
+
+/// 1メール分の評価結果（Authentication-Resultsヘッダの値、削除したヘッダの出現番号、JSONレコード、ログ）
+struct Evaluated {
+    header: String,
+    removed: Vec<u32>,
+    record: Value,
+    log: String,
+}
+
+/// サーバーを起動し、CONNECT（ファミリ・ポート・アドレスを指定）・HELOの後に1メール送信して評価結果を返す
+fn evaluate(
+    resolver: &str,
+    family: u8,
+    port: u16,
+    address: &str,
+    helo: &str,
+    sender: &str,
+) -> Evaluated {
+    evaluate_with_headers(
+        resolver,
+        family,
+        port,
+        address,
+        helo,
+        sender,
+        &[("Subject", "spf")],
+    )
+}
+
+/// evaluateと同じ手順で、任意のヘッダを付けたメールを評価する
+fn evaluate_with_headers(
+    resolver: &str,
+    family: u8,
+    port: u16,
+    address: &str,
+    helo: &str,
+    sender: &str,
+    headers: &[(&str, &str)],
+) -> Evaluated {
+    let server = MilterServer::start(&format!(
+        "Json_output spf.jsonl\n\
+         Spf_verify yes\n\
+         Dns_resolver {}\n\
+         Authentication_results mx.example.net",
+        resolver
+    ));
+    std::fs::write(server.dir.join("spf.zone"), ZONE).unwrap();
+    let mut client = server.connect();
+    client.optneg(6, 0x11, 0); // SMFIF_ADDHDRS | SMFIF_CHGHDRS
+    let mut connect = args(&["client.example"]);
+    connect.push(family);
+    connect.extend_from_slice(&port.to_be_bytes());
+    connect.extend_from_slice(&args(&[address]));
+    assert_ne!(client.command(b'C', &connect).0, b'r');
+    assert_ne!(client.command(b'H', &args(&[helo])).0, b'r');
+    let (cmd, payload) = send_message(
+        &mut client,
+        &Message {
+            mail: &[sender],
+            headers,
+            body: b"hello\r\n",
+            ..Message::default()
+        },
+    );
+    // 偽装ヘッダの削除（SMFIR_CHGHEADER、空の値）→ 自サーバの結果の挿入（SMFIR_INSHEADER）
+    let (mut cmd, mut payload) = (cmd, payload);
+    let mut removed = Vec::new();
+    while cmd == b'm' {
+        removed.push(u32::from_be_bytes(payload[..4].try_into().unwrap()));
+        assert_eq!(&payload[4..], b"Authentication-Results\0\0");
+        (cmd, payload) = client.read_reply();
+    }
+    assert_eq!(cmd, b'i');
+    assert_eq!(&payload[..4], &[0, 0, 0, 0]); // 先頭に挿入
+    let header = String::from_utf8(payload[4..].to_vec()).unwrap();
+    assert_eq!(client.read_reply().0, b'h'); // X-MilterDecoder-Summary
+    assert_eq!(client.read_reply().0, b'a');
+    client.send(b'Q', b"");
+    client.wait_closed();
+    let dir = server.dir.clone();
+    let log = server.finish();
+    let text = std::fs::read_to_string(dir.join("spf.jsonl")).unwrap();
+    let record = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    Evaluated {
+        header,
+        removed,
+        record,
+        log,
+    }
+}
+
+/// ゾーンファイルのSPFレコードでIPv4の接続元を評価
+fn evaluate_v4(port: u16, address: &str, helo: &str, sender: &str) -> Evaluated {
+    evaluate("spf.zone", b'4', port, address, helo, sender)
+}
+
+/// Authentication-Resultsヘッダ（ヘッダ名・値のNUL区切り）とログ行を確認
+fn assert_reported(out: &Evaluated, method: &str, log_line: &str) {
+    assert_eq!(
+        out.header,
+        format!("Authentication-Results\0mx.example.net; {}\0", method)
+    );
+    assert!(
+        out.log.contains(log_line),
+        "{} がありません\n{}",
+        log_line,
+        out.log
+    );
+}
+
+#[test]
+fn include_with_mx_passes() {
+    let out = evaluate_v4(
+        25000,
+        "192.0.2.10",
+        "mail.example.com",
+        "<sender@example.com>",
+    );
+    // CONNECTの型付き出力
+    let connect = &out.record["connect"];
+    assert_eq!(connect["hostname"], "client.example");
+    assert_eq!(connect["family"], "inet");
+    assert_eq!(connect["port"], 25000);
+    assert_eq!(connect["address"], "192.0.2.10");
+
+    // include → mx（MXのAレコード）で一致
+    let spf = &out.record["spf"];
+    assert_eq!(spf["result"], "pass");
+    assert_eq!(spf["identity"], "mailfrom");
+    assert_eq!(spf["domain"], "example.com");
+    assert_eq!(spf["client_ip"], "192.0.2.10");
+    assert_eq!(spf["mechanism"], "include:_spf.example.com");
+    assert_eq!(spf["dns_lookups"], 2);
+    assert_reported(
+        &out,
+        "spf=pass smtp.mailfrom=sender@example.com",
+        "[spf] 結果: pass mailfrom=sender@example.com ip=192.0.2.10 mechanism=include:_spf.example.com lookups=2 void=0",
+    );
+}
+
+#[test]
+fn redirect_fails_with_the_target_explanation() {
+    let out = evaluate_v4(
+        25001,
+        "203.0.113.5",
+        "relay.example",
+        "<user@redirect.example.com>",
+    );
+    // redirect先の-allに一致し、redirect先のexp=で説明文を展開
+    let spf = &out.record["spf"];
+    assert_eq!(spf["result"], "fail");
+    assert_eq!(spf["mechanism"], "-all");
+    assert_eq!(spf["dns_lookups"], 3);
+    assert_eq!(
+        spf["explanation"],
+        "203.0.113.5 is not one of example.com's designated mail servers"
+    );
+    assert_reported(
+        &out,
+        "spf=fail smtp.mailfrom=user@redirect.example.com",
+        "[spf] 結果: fail mailfrom=user@redirect.example.com ip=203.0.113.5 mechanism=-all lookups=3 void=0 exp=\"203.0.113.5 is not one of example.com's designated mail servers\"",
+    );
+}
+
+#[test]
+fn exists_macros_are_expanded() {
+    let out = evaluate_v4(
+        25002,
+        "192.0.2.10",
+        "mail.example.org",
+        "<alice-trusted@macro.example.org>",
+    );
+    // %{ir}・%{l1r-}の展開
+    let spf = &out.record["spf"];
+    assert_eq!(spf["result"], "pass");
+    assert_eq!(spf["mechanism"], "exists:%{ir}.%{l1r-}.allow.example.org");
+    assert_eq!(
+        out.header,
+        "Authentication-Results\0mx.example.net; spf=pass smtp.mailfrom=alice-trusted@macro.example.org\0"
+    );
+}
+
+#[test]
+fn include_loop_hits_the_lookup_limit() {
+    let out = evaluate_v4(
+        25003,
+        "192.0.2.10",
+        "mail.example.net",
+        "<loop@loop.example.net>",
+    );
+    // includeの循環は11回目の問い合わせでpermerror
+    let spf = &out.record["spf"];
+    assert_eq!(spf["result"], "permerror");
+    assert_eq!(spf["reason"], "too many DNS lookups");
+    assert_eq!(spf["dns_lookups"], 11);
+    assert_reported(
+        &out,
+        "spf=permerror reason=\"too many DNS lookups\" smtp.mailfrom=loop@loop.example.net",
+        "[spf] 結果: permerror mailfrom=loop@loop.example.net ip=192.0.2.10 lookups=11 void=0 (too many DNS lookups)",
+    );
+}
+
+#[test]
+fn null_sender_is_checked_by_helo_over_ipv6() {
+    let out = evaluate(
+        "spf.zone",
+        b'6',
+        25004,
+        "IPv6:2001:db8::25",
+        "mail.example.com",
+        "<>",
+    );
+    // Sendmail形式のIPv6アドレス
+    assert_eq!(out.record["connect"]["family"], "inet6");
+    assert_eq!(out.record["connect"]["address"], "2001:db8::25");
+    // ヌル送信者はHELO名で評価
+    let spf = &out.record["spf"];
+    assert_eq!(spf["result"], "pass");
+    assert_eq!(spf["identity"], "helo");
+    assert_eq!(spf["sender"], "postmaster@mail.example.com");
+    assert_eq!(spf["mechanism"], "ip6:2001:db8::/32");
+    assert_reported(
+        &out,
+        "spf=pass smtp.helo=mail.example.com",
+        "[spf] 結果: pass helo=postmaster@mail.example.com ip=2001:db8::25 mechanism=ip6:2001:db8::/32 lookups=0 void=0",
+    );
+}
+
+#[test]
+fn system_resolver_is_dropped_without_stopping_the_session() {
+    // 検査対象のドメインが無いため問い合わせは発生しないが、システムのリゾルバの生成・破棄は行われる
+    let out = evaluate(
+        "system",
+        b'4',
+        25005,
+        "192.0.2.10",
+        "localhost",
+        "<user@localhost>",
+    );
+    assert_eq!(out.record["spf"]["result"], "none");
+    assert_eq!(out.record["spf"]["reason"], "no valid domain to check");
+    assert!(!out.log.contains("panicked"), "{}", out.log);
+}
+
+#[test]
+fn forged_results_with_our_authserv_id_are_removed() {
+    let out = evaluate_with_headers(
+        "spf.zone",
+        b'4',
+        25006,
+        "192.0.2.10",
+        "mail.example.com",
+        "<sender@example.com>",
+        &[
+            (
+                "Authentication-Results",
+                "mx.example.net; spf=pass smtp.mailfrom=forged@example.com",
+            ),
+            (
+                "Authentication-Results",
+                "relay.example.org; spf=fail smtp.mailfrom=sender@example.com",
+            ),
+            ("Subject", "spf"),
+            (
+                "Authentication-Results",
+                "(forged) MX.Example.NET 1; dkim=pass header.d=example.com",
+            ),
+        ],
+    );
+    // 同じauthserv-id（大文字小文字・コメント・版番号は無視）の1・3番目を末尾側から削除し、他サーバの2番目は残す
+    assert_eq!(out.removed, vec![3, 1]);
+    assert_reported(
+        &out,
+        "spf=pass smtp.mailfrom=sender@example.com",
+        "[auth] 偽装Authentication-Resultsを削除: 出現番号3",
+    );
+    assert!(
+        out.log
+            .contains("[auth] 偽装Authentication-Resultsを削除: 出現番号1"),
+        "{}",
+        out.log
+    );
+}
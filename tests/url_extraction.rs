@@ -0,0 +1,306 @@
+// =========================
+// tests/url_extraction.rs
+// URL抽出・正規化の結合テスト
+//
+// 【役割】
+// - text/plain本文（日本語に続くURL・末尾の句読点・括弧）、text/htmlのhref/src、PDF、Office文書からの抽出を確認
+// - punycodeのデコード・パーセントデコード・既定ポート除去・スキーム補完による正規化を確認
+// - 既知のリダイレクタ（Google・Facebook・Outlook Safe Links→Proofpointの入れ子）の転送先の取り出しと、外側のURLが主であることを確認
+// - 既知のリダイレクタ以外はurl=等の引数があっても展開しないことを確認
+// - Proofpoint v3の転送先のクエリと「*」置換文字の復元を確認
+// - URLらしい短い語が大量に並ぶ本文でも抽出が線形時間で終わることを確認
+// =========================
+
+mod common;
+
+use common::{multipart, part, run_one, Message, MIXED};
+use serde_json::{json, Value};
+use std::io::Write;
+
+/// リモートテンプレート参照入りの.docx
+fn docx() -> Vec<u8> {
+    let mut w = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    w.start_file("[Content_Types].xml", options).unwrap();
+    w.write_all(b"<Types/>").unwrap();
+    w.start_file("word/_rels/settings.xml.rels", options)
+        .unwrap();
+    w.write_all(
+        b"<Relationships><Relationship Id=\"rId1\" \
+          Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/attachedTemplate\" \
+          Target=\"https://xn--bcher-kva.example/t.dotm\" TargetMode=\"External\"/></Relationships>",
+    )
+    .unwrap();
+    w.finish().unwrap().into_inner()
+}
+
+/// パートを並べたメールを1通送信し、ログと抽出したURLの一覧を返す
+fn run_urls(parts: &[String]) -> (String, Vec<Value>) {
+    let body = multipart(parts);
+    let (log, record) = run_one(
+        "",
+        &Message {
+            headers: MIXED,
+            body: &body,
+            ..Message::default()
+        },
+    );
+    (log, record["urls"].as_array().unwrap().clone())
+}
+
+/// 抽出したURLを(抽出元, パート番号, 正規化URL, ホスト, 転送先)にする
+fn summary(url: &Value) -> (String, u64, String, String, Value) {
+    (
+        url["source"].as_str().unwrap().to_string(),
+        url["part"].as_u64().unwrap(),
+        url["normalized"].as_str().unwrap().to_string(),
+        url["host"].as_str().unwrap_or("").to_string(),
+        url["unwrapped"].clone(),
+    )
+}
+
+/// 期待するURL1件
+fn url(
+    source: &str,
+    part: u64,
+    normalized: &str,
+    host: &str,
+    unwrapped: Value,
+) -> (String, u64, String, String, Value) {
+    (
+        source.to_string(),
+        part,
+        normalized.to_string(),
+        host.to_string(),
+        unwrapped,
+    )
+}
+
+/// URL行の数と、ログに全行が含まれることを確認
+fn assert_url_log(log: &str, count: usize, lines: &[&str]) {
+    let url_lines = log.lines().filter(|l| l.contains("[url] URL(")).count();
+    assert_eq!(url_lines, count, "{}", log);
+    for line in lines {
+        assert!(log.contains(line), "{} がありません\n{}", line, log);
+    }
+}
+
+#[test]
+fn text_urls_are_extracted_and_normalized() {
+    let text = "詳しくはhttps://example.com/path%20a?x=1です。\r\n\
+        (see www.Example.org/x). 再掲: https://example.com/path%20a?x=1\r\n\
+        http://XN--E1AFMKFD.com:80/\r\n\
+        mailme@www.example.net";
+    let (log, urls) = run_urls(&[part("text/plain; charset=utf-8", text.as_bytes())]);
+    assert_eq!(
+        urls.iter().map(summary).collect::<Vec<_>>(),
+        vec![
+            url(
+                "text",
+                1,
+                "https://example.com/path a?x=1",
+                "example.com",
+                json!([])
+            ),
+            url(
+                "text",
+                1,
+                "http://www.example.org/x",
+                "www.example.org",
+                json!([])
+            ),
+            url("text", 1, "http://пример.com/", "пример.com", json!([])),
+        ]
+    );
+    // 抽出したままのURL（末尾の句読点・閉じ括弧は除去）
+    assert_eq!(urls[0]["url"], "https://example.com/path%20a?x=1");
+    assert_eq!(urls[1]["url"], "www.Example.org/x");
+    assert_url_log(
+        &log,
+        3,
+        &["[url] URL(1): source=text part=1 https://example.com/path%20a?x=1 → https://example.com/path a?x=1\n"],
+    );
+}
+
+#[test]
+fn html_links_are_extracted_except_comments_and_mailto() {
+    let html = "<html><body>\
+        <img src='//cdn.example/i.png'>\
+        <a href=\"mailto:a@example.org\">m</a>\
+        <!-- <a href=\"http://hidden.example/\"> -->\
+        <a HREF=\"http://quoted.example/?a=1&amp;b=2\">q</a>\
+        <a href=http://unquoted.example/p>u</a>\
+        </body></html>";
+    let (log, urls) = run_urls(&[part("text/html; charset=utf-8", html.as_bytes())]);
+    assert_eq!(
+        urls.iter().map(summary).collect::<Vec<_>>(),
+        vec![
+            url(
+                "html",
+                1,
+                "http://cdn.example/i.png",
+                "cdn.example",
+                json!([])
+            ),
+            url(
+                "html",
+                1,
+                "http://quoted.example/?a=1&b=2",
+                "quoted.example",
+                json!([])
+            ),
+            url(
+                "html",
+                1,
+                "http://unquoted.example/p",
+                "unquoted.example",
+                json!([])
+            ),
+        ]
+    );
+    // 実体参照は展開済み
+    assert_eq!(urls[1]["url"], "http://quoted.example/?a=1&b=2");
+    assert_url_log(&log, 3, &[]);
+    assert!(
+        log.lines()
+            .filter(|l| l.contains("[url] "))
+            .all(|l| !l.contains("hidden.example") && !l.contains("mailto:")),
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn pdf_and_office_urls_carry_their_part_numbers() {
+    let pdf = b"%PDF-1.4\n1 0 obj\n<< /S /URI /URI (https://pdf.example/doc) >>\nendobj\n";
+    let (log, urls) = run_urls(&[
+        part("text/plain", b"no links\r\n"),
+        part("application/pdf", pdf),
+        part(
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            &docx(),
+        ),
+    ]);
+    assert_eq!(
+        urls.iter().map(summary).collect::<Vec<_>>(),
+        vec![
+            url(
+                "pdf",
+                2,
+                "https://pdf.example/doc",
+                "pdf.example",
+                json!([])
+            ),
+            url(
+                "office",
+                3,
+                "https://bücher.example/t.dotm",
+                "bücher.example",
+                json!([])
+            ),
+        ]
+    );
+    assert_url_log(
+        &log,
+        2,
+        &["[url] URL(1): source=pdf part=2 https://pdf.example/doc\n"],
+    );
+}
+
+#[test]
+fn known_redirectors_are_unwrapped_keeping_the_outer_url() {
+    let text = "https://www.google.com/url?q=https%3A%2F%2Fevil.example%2Flogin&sa=D\r\n\
+        https://nam01.safelinks.protection.outlook.com/?url=https%3A%2F%2Furldefense.proofpoint.com%2Fv2%2Furl%3Fu%3Dhttps-3A__inner.example_a%26d%3DX&data=1\r\n";
+    let html = "<a href=\"https://l.facebook.com/l.php?u=https%3A%2F%2Fphish.example%2F&amp;h=AT\">click</a>";
+    let (log, urls) = run_urls(&[
+        part("text/plain", text.as_bytes()),
+        part("text/html", html.as_bytes()),
+    ]);
+    // 外側のURL・ホストが主で、転送先は外側から順にunwrappedへ
+    assert_eq!(
+        urls.iter().map(summary).collect::<Vec<_>>(),
+        vec![
+            url(
+                "text",
+                1,
+                "https://www.google.com/url?q=https://evil.example/login&sa=D",
+                "www.google.com",
+                json!([{"url": "https://evil.example/login", "host": "evil.example"}])
+            ),
+            url(
+                "text",
+                1,
+                "https://nam01.safelinks.protection.outlook.com/?url=https://urldefense.proofpoint.com/v2/url?u=https-3A__inner.example_a&d=X&data=1",
+                "nam01.safelinks.protection.outlook.com",
+                json!([
+                    {
+                        "url": "https://urldefense.proofpoint.com/v2/url?u=https-3A__inner.example_a&d=X",
+                        "host": "urldefense.proofpoint.com"
+                    },
+                    {"url": "https://inner.example/a", "host": "inner.example"}
+                ])
+            ),
+            url(
+                "html",
+                2,
+                "https://l.facebook.com/l.php?u=https://phish.example/&h=AT",
+                "l.facebook.com",
+                json!([{"url": "https://phish.example/", "host": "phish.example"}])
+            ),
+        ]
+    );
+    assert_url_log(
+        &log,
+        3,
+        &["[url] URL(1): source=text part=1 https://www.google.com/url?q=https%3A%2F%2Fevil.example%2Flogin&sa=D → https://www.google.com/url?q=https://evil.example/login&sa=D [転送先 https://evil.example/login]\n"],
+    );
+}
+
+#[test]
+fn unknown_hosts_with_url_parameters_are_not_unwrapped() {
+    let text = "https://evil.example/r?url=https%3A%2F%2Fwww.paypal.com%2F\r\n\
+        https://google.evil.example/url?q=https%3A%2F%2Fwww.paypal.com%2F\r\n\
+        https://www.google.com/search?q=https%3A%2F%2Fwww.paypal.com%2F\r\n";
+    let (log, urls) = run_urls(&[part("text/plain", text.as_bytes())]);
+    let hosts: Vec<&str> = urls.iter().map(|u| u["host"].as_str().unwrap()).collect();
+    assert_eq!(
+        hosts,
+        vec!["evil.example", "google.evil.example", "www.google.com"]
+    );
+    assert!(
+        urls.iter().all(|u| u["unwrapped"] == json!([])),
+        "{:?}",
+        urls
+    );
+    assert!(!log.contains("[転送先 "), "{}", log);
+}
+
+#[test]
+fn proofpoint_v3_restores_query_and_substituted_characters() {
+    // 「*」は置換文字（base64url）の「~」、「**A」は2文字分の「.+」
+    let text = "https://urldefense.com/v3/__https://inner.example/p*a?x=1&y=2__;fg!!ABC$\r\n\
+        https://urldefense.com/v3/__https://run.example/**Ab?q=1__;Lis!!X$\r\n";
+    let (_, urls) = run_urls(&[part("text/plain", text.as_bytes())]);
+    let unwrapped: Vec<&Value> = urls.iter().map(|u| &u["unwrapped"][0]["url"]).collect();
+    assert_eq!(
+        unwrapped,
+        vec![
+            &json!("https://inner.example/p~a?x=1&y=2"),
+            &json!("https://run.example/.+b?q=1"),
+        ]
+    );
+    assert_eq!(urls[0]["host"], "urldefense.com");
+    assert_eq!(urls[0]["unwrapped"][0]["host"], "inner.example");
+}
+
+#[test]
+fn large_bodies_of_url_like_tokens_are_scanned_quickly() {
+    let mut text = "www.a ".repeat(400_000); // 約2.4MB（いずれも短すぎてURLにはならない）
+    text.push_str("https://last.example/\r\n");
+    let started = std::time::Instant::now();
+    let (_, urls) = run_urls(&[part("text/plain", text.as_bytes())]);
+    let elapsed = started.elapsed();
+    assert!(elapsed.as_secs() < 20, "{:?}", elapsed);
+    assert_eq!(urls.len(), 1);
+    assert_eq!(urls[0]["normalized"], "https://last.example/");
+}